@@ -0,0 +1,158 @@
+// helpers for the legs of basket escrows made through make_basket. each leg is a mint held
+// in the escrow's ATA for it, under mint_a's token program. make, take and refund get every
+// leg as BASKET_LEG remaining accounts, [mint, vault, ATA of the maker or taker], in the
+// order of the escrow's basket_mints and ahead of any transfer hook accounts
+use anchor_lang::prelude::*;
+
+use anchor_spl::{
+    associated_token::{create_idempotent, get_associated_token_address_with_program_id, Create},
+    token_interface::{close_account, CloseAccount, Mint, TokenAccount, TransferChecked},
+};
+
+use crate::error::ErrorCode;
+use crate::extensions::transfer_checked_with_hook;
+use crate::pda::vault_pda;
+
+// remaining accounts per basket leg
+pub const BASKET_LEG: usize = 3;
+
+// the accounts of one leg, checked against the escrow
+pub struct BasketLeg<'info> {
+    pub mint: AccountInfo<'info>,
+    pub vault: AccountInfo<'info>,
+    pub ata: AccountInfo<'info>,
+    pub decimals: u8,
+}
+
+// the accounts of the instruction the legs move with. owner holds the ATAs the legs come
+// from in make and go to in take or refund
+pub struct BasketAccounts<'info> {
+    pub escrow: AccountInfo<'info>,
+    pub owner: AccountInfo<'info>,
+    pub payer: AccountInfo<'info>,
+    pub token_program: AccountInfo<'info>,
+    pub associated_token_program: AccountInfo<'info>,
+    pub system_program: AccountInfo<'info>,
+}
+
+impl<'info> BasketAccounts<'info> {
+    // the legs of `mints` off the front of the remaining accounts
+    pub fn legs(
+        &self,
+        mints: &[Pubkey],
+        accounts: &[AccountInfo<'info>],
+    ) -> Result<Vec<BasketLeg<'info>>> {
+        require!(
+            accounts.len() >= BASKET_LEG * mints.len(),
+            ErrorCode::BasketLegMismatch
+        );
+        let token_program = self.token_program.key();
+        mints
+            .iter()
+            .zip(accounts.chunks_exact(BASKET_LEG))
+            .map(|(mint, leg)| {
+                let (mint_info, vault, ata) = (&leg[0], &leg[1], &leg[2]);
+                require!(
+                    mint_info.key() == *mint
+                        && *mint_info.owner == token_program
+                        && vault.key() == vault_pda(self.escrow.key, mint, &token_program)
+                        && ata.key()
+                            == get_associated_token_address_with_program_id(
+                                self.owner.key,
+                                mint,
+                                &token_program,
+                            ),
+                    ErrorCode::BasketLegMismatch
+                );
+                let decimals =
+                    Mint::try_deserialize(&mut &mint_info.try_borrow_data()?[..])?.decimals;
+                Ok(BasketLeg {
+                    mint: mint_info.clone(),
+                    vault: vault.clone(),
+                    ata: ata.clone(),
+                    decimals,
+                })
+            })
+            .collect()
+    }
+
+    // creates the leg's vault unless someone already did, and moves `amount` into it from
+    // the owner's ATA
+    pub fn deposit(
+        &self,
+        leg: &BasketLeg<'info>,
+        amount: u64,
+        extra: &[AccountInfo<'info>],
+    ) -> Result<()> {
+        let accounts = Create {
+            payer: self.payer.clone(),
+            associated_token: leg.vault.clone(),
+            authority: self.escrow.clone(),
+            mint: leg.mint.clone(),
+            system_program: self.system_program.clone(),
+            token_program: self.token_program.clone(),
+        };
+        create_idempotent(CpiContext::new(
+            self.associated_token_program.clone(),
+            accounts,
+        ))?;
+
+        let accounts = TransferChecked {
+            from: leg.ata.clone(),
+            mint: leg.mint.clone(),
+            to: leg.vault.clone(),
+            authority: self.owner.clone(),
+        };
+        let cpi_ctx = CpiContext::new(self.token_program.clone(), accounts)
+            .with_remaining_accounts(extra.to_vec());
+        transfer_checked_with_hook(cpi_ctx, amount, leg.decimals)
+    }
+
+    // empties the leg's vault into the owner's ATA, created if it doesn't exist, and closes
+    // the vault into `rent_destination`. returns what the vault held, which tokens sent to
+    // the vault from outside can put above the leg's amount
+    pub fn release(
+        &self,
+        leg: &BasketLeg<'info>,
+        rent_destination: AccountInfo<'info>,
+        extra: &[AccountInfo<'info>],
+        signer_seeds: &[&[&[u8]]],
+    ) -> Result<u64> {
+        let accounts = Create {
+            payer: self.payer.clone(),
+            associated_token: leg.ata.clone(),
+            authority: self.owner.clone(),
+            mint: leg.mint.clone(),
+            system_program: self.system_program.clone(),
+            token_program: self.token_program.clone(),
+        };
+        create_idempotent(CpiContext::new(
+            self.associated_token_program.clone(),
+            accounts,
+        ))?;
+
+        let amount = TokenAccount::try_deserialize(&mut &leg.vault.try_borrow_data()?[..])?.amount;
+        let accounts = TransferChecked {
+            from: leg.vault.clone(),
+            mint: leg.mint.clone(),
+            to: leg.ata.clone(),
+            authority: self.escrow.clone(),
+        };
+        let cpi_ctx =
+            CpiContext::new_with_signer(self.token_program.clone(), accounts, signer_seeds)
+                .with_remaining_accounts(extra.to_vec());
+        transfer_checked_with_hook(cpi_ctx, amount, leg.decimals)?;
+
+        let accounts = CloseAccount {
+            account: leg.vault.clone(),
+            destination: rent_destination,
+            authority: self.escrow.clone(),
+        };
+        close_account(CpiContext::new_with_signer(
+            self.token_program.clone(),
+            accounts,
+            signer_seeds,
+        ))?;
+        Ok(amount)
+    }
+}
@@ -2,3 +2,98 @@ use anchor_lang::prelude::*;
 
 #[constant]
 pub const SEED: &str = "anchor";
+
+// lamports paid out of the escrow's rent to whoever cranks an expired escrow
+#[constant]
+pub const CRANK_BOUNTY: u64 = 100_000;
+
+// seconds after the expiry during which only the maker can refund, before the crank opens
+#[constant]
+pub const GRACE_PERIOD: i64 = 24 * 60 * 60;
+
+// seconds a commitment can be settled for, cut short by the escrow's expiry. the taker
+// can reclaim the payment afterwards
+#[constant]
+pub const COMMIT_WINDOW: i64 = 60 * 60;
+
+// layout version new escrows are made with, and migrate brings older escrows up to
+#[constant]
+pub const ESCROW_VERSION: u8 = 10;
+
+// byte offsets into an escrow account, discriminator included, for memcmp filters. the
+// leading fields follow from each other's sizes. status and namespace sit behind dozens of
+// fields, so offsets_match_the_serialized_escrow checks every offset against a serialized
+// escrow. u32 because the IDL has no usize.
+// the layout is only ever appended to, so these hold for escrows of every version. that is
+// also why status couldn't move up next to the mints: every field after it would shift in
+// existing escrows, which Account<Escrow> and migrate read at fixed positions. it comes
+// after the fields older escrows already had instead, a one byte EscrowStatus index.
+// namespace came with version 6, earlier escrows stop short of it
+#[constant]
+pub const OFFSET_SEED: u32 = 8;
+#[constant]
+pub const OFFSET_MAKER: u32 = OFFSET_SEED + 8;
+#[constant]
+pub const OFFSET_MINT_A: u32 = OFFSET_MAKER + 32;
+#[constant]
+pub const OFFSET_MINT_B: u32 = OFFSET_MINT_A + 32;
+#[constant]
+pub const OFFSET_STATUS: u32 = 446;
+#[constant]
+pub const OFFSET_NAMESPACE: u32 = 1165;
+
+// most mints make_basket sells besides mint_a. every leg adds its mint, vault and the
+// counterparty's account to make_basket, take and refund. take binds first, a legacy
+// transaction has no room for a fifth leg next to take's own accounts
+pub const MAX_BASKET_LEGS: usize = 4;
+
+// most payment options an escrow accepts next to mint_b, see MakeOptions::payment_options
+pub const MAX_PAYMENT_OPTIONS: usize = 3;
+
+// most open escrows a MakerIndex lists. every make lists its escrow, so this also caps a
+// maker's open escrows, a make beyond it fails with MakerIndexFull
+pub const MAX_INDEXED_ESCROWS: usize = 32;
+
+// maximum number of takers an escrow whitelist can hold
+pub const MAX_WHITELIST: usize = 5;
+
+// protocol fee on the mint_b a taker pays, in basis points. a new config starts with it
+#[constant]
+pub const FEE_BPS: u16 = 30;
+
+// share of a mint_b payment a front-end's referrer gets out of the maker's share, in
+// basis points. only paid when the take names a referrer
+#[constant]
+pub const REFERRAL_BPS: u16 = 50;
+
+// the protocol fee can never be configured above 10%
+#[constant]
+pub const MAX_FEE_BPS: u16 = 1_000;
+
+// slots that have to pass after make before an escrow can be taken, so bots
+// can't fill a new escrow in the block it was created in
+#[constant]
+pub const COOLDOWN_SLOTS: u64 = 10;
+
+// most escrows make_batch creates or take_batch takes in one call, six. the transaction
+// size is what binds: without a lookup table six make_batch escrows, at two accounts and
+// three u64s each, fit next to the maker's index, and a seventh doesn't since the index
+// joined the accounts. take_batch legs of distinct makers and mints run out of room
+// after two. every make_batch escrow also costs an account creation, an ATA creation and
+// a transfer, so batches need a raised compute unit limit. the "Make batch of MAX_BATCH
+// escrows fits one transaction" test logs the compute units per escrow and fails once a
+// full batch goes past the size limit or the 1.4M unit limit
+pub const MAX_BATCH: usize = 6;
+
+// Metaplex token metadata program, owner of the metadata accounts NFT escrows can check
+#[constant]
+pub const METADATA_PROGRAM_ID: Pubkey = pubkey!("metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s");
+
+// Pyth receiver program, owner of the price update accounts oracle priced escrows read
+#[constant]
+pub const PYTH_RECEIVER_ID: Pubkey = pubkey!("rec5EKMGg6MxZYaMdyBfgwp4d5rB9T1VQH5pJv5LtFJ");
+
+// widest spread an oracle priced escrow adds to the feed's price, and bound on the
+// premium or discount a pool priced escrow applies to the pool's price, in basis points
+#[constant]
+pub const MAX_SPREAD_BPS: u16 = 10_000;
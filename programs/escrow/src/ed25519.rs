@@ -0,0 +1,67 @@
+// checks signatures through the native Ed25519 program. the program verifies the
+// signatures of its instruction or fails the transaction, so the instruction only has
+// to be found in the instructions sysvar and its contents compared
+use anchor_lang::prelude::*;
+
+use anchor_lang::solana_program::{
+    ed25519_program,
+    sysvar::instructions::{load_current_index_checked, load_instruction_at_checked},
+};
+
+use crate::error::ErrorCode;
+
+// num_signatures and padding, then the offsets of each signature
+const HEADER_LEN: usize = 2;
+const OFFSETS_LEN: usize = 14;
+// the signature, public key and message are read from the Ed25519 instruction itself
+const THIS_INSTRUCTION: u16 = u16::MAX;
+
+// the instruction right before the current one has to be an Ed25519 instruction that
+// verified exactly `signature` by `signer` over `message`
+pub fn verify_ed25519(
+    instructions: &AccountInfo,
+    signer: &Pubkey,
+    message: &[u8],
+    signature: &[u8; 64],
+) -> Result<()> {
+    let current = load_current_index_checked(instructions)?;
+    let index = current
+        .checked_sub(1)
+        .ok_or(ErrorCode::InvalidQuoteSignature)?;
+    let ix = load_instruction_at_checked(index as usize, instructions)?;
+    require_keys_eq!(
+        ix.program_id,
+        ed25519_program::ID,
+        ErrorCode::InvalidQuoteSignature
+    );
+
+    let data = &ix.data;
+    require!(
+        data.len() >= HEADER_LEN + OFFSETS_LEN && data[0] == 1,
+        ErrorCode::InvalidQuoteSignature
+    );
+    let offsets: Vec<u16> = data[HEADER_LEN..HEADER_LEN + OFFSETS_LEN]
+        .chunks_exact(2)
+        .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+        .collect();
+    // signature, public key and message, each an offset and an instruction index, with
+    // the message size before its instruction index
+    let [sig_at, sig_ix, key_at, key_ix, msg_at, msg_len, msg_ix] = offsets[..] else {
+        return Err(ErrorCode::InvalidQuoteSignature.into());
+    };
+    require!(
+        [sig_ix, key_ix, msg_ix]
+            .iter()
+            .all(|&ix| ix == THIS_INSTRUCTION),
+        ErrorCode::InvalidQuoteSignature
+    );
+
+    let slice = |at: u16, len: usize| data.get(at as usize..at as usize + len);
+    require!(
+        slice(key_at, 32) == Some(signer.as_ref())
+            && slice(sig_at, 64) == Some(&signature[..])
+            && slice(msg_at, msg_len as usize) == Some(message),
+        ErrorCode::InvalidQuoteSignature
+    );
+    Ok(())
+}
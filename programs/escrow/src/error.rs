@@ -2,6 +2,234 @@ use anchor_lang::prelude::*;
 
 #[error_code]
 pub enum ErrorCode {
-    #[msg("Custom error message")]
-    CustomError,
+    #[msg("Escrow has expired")]
+    EscrowExpired,
+    #[msg("Amount must be greater than zero")]
+    ZeroAmount,
+    #[msg("Fill amount exceeds what is left in the escrow")]
+    FillExceedsRemaining,
+    #[msg("Fill is too small to release any tokens from the vault")]
+    FillTooSmall,
+    #[msg("Escrow has not expired yet")]
+    EscrowNotExpired,
+    #[msg("Escrow is paused by the maker")]
+    EscrowPaused,
+    #[msg("Escrow is reserved for a different taker")]
+    UnauthorizedTaker,
+    #[msg("Taker is not on the escrow whitelist")]
+    TakerNotWhitelisted,
+    #[msg("Whitelist has too many entries")]
+    WhitelistTooLong,
+    #[msg("Whitelist contains the same taker twice")]
+    DuplicateWhitelistEntry,
+    #[msg("Receive amount must be greater than zero")]
+    ZeroReceive,
+    #[msg("Escrow has already been partially filled")]
+    EscrowAlreadyFilled,
+    #[msg("Escrow deposit is native SOL, use the SOL instructions")]
+    DepositIsSol,
+    #[msg("Escrow deposit is not native SOL")]
+    DepositNotSol,
+    #[msg("Arithmetic overflow")]
+    ArithmeticOverflow,
+    #[msg("Protocol fee is above the maximum")]
+    FeeTooHigh,
+    #[msg("Withdraw amount exceeds what is left in the escrow")]
+    WithdrawExceedsRemaining,
+    #[msg("Auction needs a positive duration and a floor between 1 and receive")]
+    InvalidAuction,
+    #[msg("Auction escrows are priced by their curve and can't be re-priced")]
+    AuctionPriced,
+    #[msg("Vault balance doesn't match the escrow's remaining deposit")]
+    VaultBalanceMismatch,
+    #[msg("mint_a and mint_b must be different")]
+    IdenticalMints,
+    #[msg("Only the config admin can do this")]
+    UnauthorizedAdmin,
+    #[msg("Deposit amount must be greater than zero")]
+    ZeroDeposit,
+    #[msg("Fee collector accounts are required while the protocol charges a fee")]
+    MissingFeeAccount,
+    #[msg("Fill is below the escrow's minimum fill size")]
+    BelowMinimumFill,
+    #[msg("Signer is not the pending config admin")]
+    NotPendingAdmin,
+    #[msg("Protocol is paused, only refunds are allowed")]
+    ProtocolPaused,
+    #[msg("Escrow can't be taken this soon after it was made")]
+    CooldownNotElapsed,
+    #[msg("Transfer delivered a different amount than the transfer fee allows")]
+    TransferFeeMismatch,
+    #[msg("Transfer hook program can't be the escrow program")]
+    ReentrantTransferHook,
+    #[msg("Payment mint is not accepted by this escrow")]
+    UnsupportedPaymentMint,
+    #[msg("Mint is non-transferable and could never leave the vault")]
+    NonTransferableMint,
+    #[msg("Mint has a permanent delegate that could drain the vault")]
+    PermanentDelegateMint,
+    #[msg("Mint supports confidential transfers the escrow can't account for")]
+    ConfidentialTransferMint,
+    #[msg("Escrow does not accept native SOL payments")]
+    ReceiveNotSol,
+    #[msg("Native SOL payments need mint_b to be the wrapped SOL mint")]
+    MintBNotNative,
+    #[msg("Payment would leave the recipient below rent exemption")]
+    RecipientNotRentExempt,
+    #[msg("Escrow can't be refunded before its refund lock ends")]
+    RefundLocked,
+    #[msg("Maker token account is required unless mint_a is wrapped SOL")]
+    MakerAtaRequired,
+    #[msg("Batch seeds, receives, deposits and accounts don't line up")]
+    BatchLengthMismatch,
+    #[msg("Batch has more than MAX_BATCH escrows")]
+    BatchTooLarge,
+    #[msg("Batch account is not the escrow or vault its seed derives")]
+    InvalidBatchAccount,
+    #[msg("Escrow is a kept record of a finished trade")]
+    EscrowNotOpen,
+    #[msg("Escrow is still open, refund it instead")]
+    EscrowStillOpen,
+    #[msg("NFT escrows need a mint with 0 decimals, a supply of 1 and a deposit of 1")]
+    NotAnNft,
+    #[msg("Metadata account is not the Metaplex metadata of mint_a")]
+    InvalidMetadata,
+    #[msg("Fill is outside the price or amounts the taker agreed to")]
+    SlippageExceeded,
+    #[msg("pNFT escrows need the Token Metadata accounts as remaining accounts")]
+    MissingPnftAccounts,
+    #[msg("pNFTs only move through make, take and refund")]
+    ProgrammableNftUnsupported,
+    #[msg("Taker doesn't hold an NFT from the escrow's verified collection")]
+    TakerNotCollectionHolder,
+    #[msg("Gate token account is missing or isn't the taker's account of the gate mint")]
+    InvalidGateAccount,
+    #[msg("Taker holds less of the gate mint than the escrow requires")]
+    GateBalanceTooLow,
+    #[msg("Taker's mint_b account holds less than the take costs")]
+    InsufficientTakerFunds,
+    #[msg("Escrow's remaining deposit changed since the offer was made")]
+    OfferOutdated,
+    #[msg("The maker has no terms pending on this escrow")]
+    NoPendingTerms,
+    #[msg("Pending terms have expired")]
+    TermsExpired,
+    #[msg("Pending terms don't match the terms the taker agreed to")]
+    TermsMismatch,
+    #[msg("Basket accounts are missing, unexpected or don't match the escrow's second mint")]
+    BasketMismatch,
+    #[msg("Basket escrows only move through make, take and refund")]
+    BasketUnsupported,
+    #[msg("Vesting needs a positive duration and a fungible, single mint, token paid escrow")]
+    InvalidVesting,
+    #[msg("Vesting escrows only move through make, take, refund and claim_vested")]
+    VestingUnsupported,
+    #[msg("Escrow isn't vesting")]
+    EscrowNotVesting,
+    #[msg("Nothing has vested since the last claim")]
+    NothingToClaim,
+    #[msg("Maker can't take their own escrow, use refund or withdraw to get the deposit back")]
+    SelfTradeNotAllowed,
+    #[msg("Rent recipient doesn't match the escrow")]
+    RentRecipientMismatch,
+    #[msg("Quote has expired")]
+    QuoteExpired,
+    #[msg("Quote isn't signed by the maker")]
+    InvalidQuoteSignature,
+    #[msg("Unit price needs a non-zero numerator and denominator and no auction")]
+    InvalidUnitPrice,
+    #[msg("Escrow is priced per unit, its total price can't be set")]
+    UnitPriced,
+    #[msg("Unit prices aren't supported for this escrow")]
+    UnitPriceUnsupported,
+    #[msg("Oracle terms need a feed and its feed id, a max price age and a spread within bounds, and no other pricing")]
+    InvalidOracle,
+    #[msg("Price feed doesn't match the escrow's oracle")]
+    OracleMismatch,
+    #[msg("Price feed isn't a verified Pyth price update with a positive price")]
+    InvalidOraclePrice,
+    #[msg("Oracle price is older than the escrow allows")]
+    StaleOraclePrice,
+    #[msg("Oracle confidence interval is wider than the escrow allows")]
+    OracleConfidenceTooWide,
+    #[msg("Escrow is priced by its oracle")]
+    OraclePriced,
+    #[msg("Oracle and pool prices aren't supported for this escrow")]
+    OracleUnsupported,
+    #[msg("Pool terms need a pool, its program, reserve minimums and a premium within bounds, and no other pricing")]
+    InvalidPool,
+    #[msg("Pool accounts don't match the escrow's pool or mints")]
+    PoolMismatch,
+    #[msg("Pool reserves are below the escrow's minimums")]
+    PoolReservesTooLow,
+    #[msg("Escrow is still in its grace period after expiry")]
+    GracePeriodActive,
+    #[msg("Escrow needs accounts take_batch doesn't pass, use take")]
+    TakeBatchUnsupported,
+    #[msg("Batch contains the same seed twice")]
+    DuplicateBatchSeed,
+    #[msg("Account isn't an escrow layout migrate knows")]
+    InvalidEscrowLayout,
+    #[msg("Escrows don't trade each other's mints")]
+    CrossMismatch,
+    #[msg("Neither escrow covers what the other asks for")]
+    PricesDontCross,
+    #[msg("Escrow needs accounts cross doesn't pass")]
+    CrossUnsupported,
+    #[msg("A basket takes between one and MAX_BASKET_LEGS legs")]
+    BasketTooLarge,
+    #[msg("Basket leg accounts are missing or don't match the escrow's legs")]
+    BasketLegMismatch,
+    #[msg("Escrow needs accounts commit and settle don't pass")]
+    CommitUnsupported,
+    #[msg("Escrow changed since the commitment was made")]
+    CommitmentOutdated,
+    #[msg("Commitment deadline has passed, the taker can reclaim it")]
+    CommitmentExpired,
+    #[msg("Commitment can only be reclaimed once its deadline has passed")]
+    CommitmentActive,
+    #[msg("Only the maker or the committed taker can settle")]
+    UnauthorizedSettler,
+    #[msg("Escrow has no payment option at this index")]
+    InvalidPaymentIndex,
+    #[msg("Too many payment options, or one with a zero receive")]
+    InvalidPaymentOptions,
+    #[msg("A token account of the take is frozen")]
+    AccountFrozen,
+    #[msg("Escrow pays into its payment vault, which this instruction can't do")]
+    PaymentVaultUnsupported,
+    #[msg("Escrow pays into its payment vault, which is missing")]
+    PaymentVaultRequired,
+    #[msg("Maker index is full, compact it or finish an escrow first")]
+    MakerIndexFull,
+    #[msg("Escrow is still open or isn't the maker's escrow at this namespace and seed")]
+    EscrowStillIndexed,
+    #[msg("Escrows with a namespace can't receive to a vault")]
+    NamespaceUnsupported,
+    #[msg("Basket escrows only take a namespace out of the make options")]
+    InvalidBasketOptions,
+    #[msg("Signer is neither the escrow's maker nor its delegate")]
+    NotDelegate,
+    #[msg("Receive is below the escrow's price floor")]
+    BelowPriceFloor,
+    #[msg("Maker's mint_a account holds less than the deposit")]
+    InsufficientMakerFunds,
+    #[msg("Escrow still exists, its vault goes back through refund")]
+    EscrowNotOrphaned,
+    #[msg("Memo isn't valid UTF-8")]
+    InvalidMemo,
+    #[msg("mint_c account is missing or doesn't match MakeOptions::mint_c")]
+    MintCMismatch,
+    #[msg("Taker can't be the referrer of their own take")]
+    SelfReferral,
+    #[msg("Escrow is priced by its pool")]
+    PoolPriced,
+    #[msg("Escrow has an older layout, run migrate first")]
+    EscrowNotMigrated,
+    #[msg("Account isn't the maker's index")]
+    MakerIndexMismatch,
+    #[msg("Maker index has the seed only layout, run compact_maker_index first")]
+    MakerIndexOutdated,
+    #[msg("Escrow has a price floor, its mint_b can't change")]
+    PriceFloorSet,
 }
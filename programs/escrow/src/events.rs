@@ -0,0 +1,335 @@
+use anchor_lang::prelude::*;
+
+#[event]
+pub struct EscrowMade {
+    pub escrow: Pubkey,
+    pub maker: Pubkey,
+    pub seed: u64,
+    pub mint_a: Pubkey,
+    pub mint_b: Pubkey,
+    pub deposit: u64,
+    pub receive: u64,
+    // mint_b per mint_a in base units, the unit price of a unit priced escrow or
+    // receive / deposit
+    pub price_numerator: u64,
+    pub price_denominator: u64,
+}
+
+// take, take_partial and take_alt create the taker's mint_a account and the maker's
+// mint_b account when they are missing. the taker pays their rent, which is not part
+// of amount_b
+#[event]
+pub struct EscrowTaken {
+    pub escrow: Pubkey,
+    pub maker: Pubkey,
+    pub taker: Pubkey,
+    pub mint_a: Pubkey,
+    pub mint_b: Pubkey,
+    // mint_a sent from the vault to the taker
+    pub amount_a: u64,
+    // mint_b paid by the taker, the maker receives amount_b - fee
+    pub amount_b: u64,
+    // part of amount_b sent to the protocol fee collector
+    pub fee: u64,
+    // mint_a left in the vault, 0 once the escrow is closed
+    pub remaining: u64,
+    // part of amount_b that reached the maker's wallet as native lamports, 0 when the
+    // maker was paid in tokens
+    pub lamports: u64,
+}
+
+#[event]
+pub struct ReferralPaid {
+    pub escrow: Pubkey,
+    pub taker: Pubkey,
+    // owner of the mint_b account the referral went to
+    pub referrer: Pubkey,
+    pub mint_b: Pubkey,
+    // part of the taker's mint_b payment that went to the referrer instead of the maker
+    pub amount: u64,
+}
+
+#[event]
+pub struct VestedClaimed {
+    pub escrow: Pubkey,
+    pub taker: Pubkey,
+    pub mint_a: Pubkey,
+    // mint_a sent from the vault to the taker in this claim
+    pub amount: u64,
+    // mint_a claimed so far, the escrow is closed once it reaches deposit
+    pub claimed: u64,
+    pub deposit: u64,
+}
+
+#[event]
+pub struct EscrowRefunded {
+    pub escrow: Pubkey,
+    pub maker: Pubkey,
+    pub mint_a: Pubkey,
+    pub mint_b: Pubkey,
+    // mint_a returned from the vault to the maker
+    pub amount: u64,
+}
+
+#[event]
+pub struct EscrowCranked {
+    pub escrow: Pubkey,
+    pub maker: Pubkey,
+    pub cranker: Pubkey,
+    pub mint_a: Pubkey,
+    // mint_a returned from the vault to the maker
+    pub amount: u64,
+    // lamports paid to the cranker
+    pub bounty: u64,
+}
+
+#[event]
+pub struct EscrowRepriced {
+    pub escrow: Pubkey,
+    pub maker: Pubkey,
+    pub old_receive: u64,
+    pub new_receive: u64,
+}
+
+#[event]
+pub struct EscrowMintUpdated {
+    pub escrow: Pubkey,
+    pub maker: Pubkey,
+    pub old_mint_b: Pubkey,
+    pub new_mint_b: Pubkey,
+}
+
+#[event]
+pub struct EscrowToppedUp {
+    pub escrow: Pubkey,
+    pub maker: Pubkey,
+    // mint_a added to the vault
+    pub amount: u64,
+    // whether receive grew with the deposit or stayed fixed
+    pub scaled_receive: bool,
+    pub deposit: u64,
+    pub remaining: u64,
+    pub receive: u64,
+    pub remaining_receive: u64,
+}
+
+#[event]
+pub struct EscrowWithdrawn {
+    pub escrow: Pubkey,
+    pub maker: Pubkey,
+    pub mint_a: Pubkey,
+    // mint_a returned from the vault to the maker
+    pub amount: u64,
+    // zero once the escrow has been closed
+    pub remaining: u64,
+}
+
+#[event]
+pub struct AdminProposed {
+    pub admin: Pubkey,
+    // Pubkey::default() when the proposal cleared the pending slot
+    pub pending_admin: Pubkey,
+}
+
+#[event]
+pub struct AdminProposalCancelled {
+    pub admin: Pubkey,
+    pub pending_admin: Pubkey,
+}
+
+#[event]
+pub struct AdminAccepted {
+    pub old_admin: Pubkey,
+    pub new_admin: Pubkey,
+}
+
+#[event]
+pub struct PauseToggled {
+    pub admin: Pubkey,
+    pub paused: bool,
+}
+
+#[event]
+pub struct TermsProposed {
+    pub escrow: Pubkey,
+    pub maker: Pubkey,
+    pub taker: Pubkey,
+    // receive for the whole deposit, only open to taker
+    pub receive: u64,
+    // the terms lapse once the slot reaches it
+    pub expiry_slot: u64,
+}
+
+#[event]
+pub struct TermsAccepted {
+    pub escrow: Pubkey,
+    pub maker: Pubkey,
+    pub taker: Pubkey,
+    pub receive: u64,
+}
+
+// the maker withdrew terms that were still open
+#[event]
+pub struct TermsCleared {
+    pub escrow: Pubkey,
+    pub maker: Pubkey,
+    pub taker: Pubkey,
+    pub receive: u64,
+}
+
+// the maker cleared terms that had lapsed without the taker accepting them
+#[event]
+pub struct TermsExpired {
+    pub escrow: Pubkey,
+    pub maker: Pubkey,
+    pub taker: Pubkey,
+    pub receive: u64,
+}
+
+// a taker filled the escrow at a price the maker signed off chain
+#[event]
+pub struct QuoteAccepted {
+    pub escrow: Pubkey,
+    pub maker: Pubkey,
+    pub taker: Pubkey,
+    pub price: u64,
+    pub expiry: i64,
+}
+
+#[event]
+pub struct OfferMade {
+    pub escrow: Pubkey,
+    pub maker: Pubkey,
+    pub taker: Pubkey,
+    // mint_b locked in the offer vault
+    pub amount_b: u64,
+    // mint_a the bid is for, everything left in the escrow
+    pub remaining: u64,
+}
+
+#[event]
+pub struct OfferCancelled {
+    pub escrow: Pubkey,
+    pub taker: Pubkey,
+    // mint_b returned from the offer vault to the taker
+    pub amount_b: u64,
+}
+
+#[event]
+pub struct OfferAccepted {
+    pub escrow: Pubkey,
+    pub maker: Pubkey,
+    pub taker: Pubkey,
+    // mint_a sent from the vault to the taker
+    pub amount_a: u64,
+    // mint_b released from the offer vault, the maker receives amount_b - fee
+    pub amount_b: u64,
+    // part of amount_b sent to the protocol fee collector
+    pub fee: u64,
+}
+
+#[event]
+pub struct EscrowMigrated {
+    pub escrow: Pubkey,
+    pub maker: Pubkey,
+    // 0 for escrows from before versioning
+    pub old_version: u8,
+    pub version: u8,
+}
+
+#[event]
+pub struct EscrowsCrossed {
+    pub escrow_1: Pubkey,
+    pub escrow_2: Pubkey,
+    pub matcher: Pubkey,
+    pub mint_a: Pubkey,
+    pub mint_b: Pubkey,
+    // mint_a from escrow_1's vault to escrow_2's maker, fee_a included
+    pub amount_a: u64,
+    // mint_b from escrow_2's vault to escrow_1's maker, fee_b included
+    pub amount_b: u64,
+    pub fee_a: u64,
+    pub fee_b: u64,
+    // what was left of each deposit, sent to the matcher
+    pub surplus_a: u64,
+    pub surplus_b: u64,
+}
+
+// emitted by make_basket after EscrowMade, which covers mint_a
+#[event]
+pub struct BasketMade {
+    pub escrow: Pubkey,
+    pub maker: Pubkey,
+    pub mints: Vec<Pubkey>,
+    // what arrived in each leg's vault, in the order of mints
+    pub amounts: Vec<u64>,
+}
+
+#[event]
+pub struct CommitmentMade {
+    pub escrow: Pubkey,
+    pub maker: Pubkey,
+    pub taker: Pubkey,
+    pub amount_a: u64,
+    // mint_b owed for amount_a, locked in the commitment vault
+    pub amount_b: u64,
+    pub deadline: i64,
+}
+
+#[event]
+pub struct CommitmentSettled {
+    pub escrow: Pubkey,
+    pub maker: Pubkey,
+    pub taker: Pubkey,
+    // the maker or the taker, whoever settled
+    pub settler: Pubkey,
+    // mint_a sent from the vault to the taker
+    pub amount_a: u64,
+    // mint_b released from the commitment vault, the maker receives amount_b - fee
+    pub amount_b: u64,
+    pub fee: u64,
+    // amount of mint_a left in the vault after the settle
+    pub remaining: u64,
+}
+
+#[event]
+pub struct CommitmentReclaimed {
+    pub escrow: Pubkey,
+    pub taker: Pubkey,
+    pub amount_b: u64,
+}
+
+#[event]
+pub struct PaymentClaimed {
+    pub escrow: Pubkey,
+    pub maker: Pubkey,
+    pub mint_b: Pubkey,
+    pub amount_b: u64,
+}
+
+// the totals of the stats account after make, take or refund counted into them
+#[event]
+pub struct StatsUpdated {
+    pub escrows_made: u64,
+    pub escrows_taken: u64,
+    pub escrows_refunded: u64,
+    pub volume_a: u128,
+    pub volume_b: u128,
+}
+
+// delegate is Pubkey::default() when the maker revoked it
+#[event]
+pub struct DelegateApproved {
+    pub escrow: Pubkey,
+    pub maker: Pubkey,
+    pub old_delegate: Pubkey,
+    pub delegate: Pubkey,
+}
+
+#[event]
+pub struct OrphanVaultRecovered {
+    pub escrow: Pubkey,
+    pub maker: Pubkey,
+    pub mint_a: Pubkey,
+    pub amount: u64,
+}
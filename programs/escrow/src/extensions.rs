@@ -0,0 +1,126 @@
+// helpers for Token-2022 mint extensions. mints owned by the legacy token program
+// have no extensions, so every helper treats them as plain mints
+use anchor_lang::prelude::*;
+
+use anchor_spl::token_interface::{
+    spl_token_2022::{
+        self,
+        extension::{
+            transfer_fee::TransferFeeConfig, transfer_hook, BaseStateWithExtensions, ExtensionType,
+            StateWithExtensions,
+        },
+        onchain::invoke_transfer_checked,
+        state::Mint,
+    },
+    TokenAccount, TransferChecked,
+};
+
+use crate::error::ErrorCode;
+
+// fee withheld when `amount` of the mint is transferred in the current epoch
+pub fn transfer_fee(mint: &AccountInfo, amount: u64) -> Result<u64> {
+    with_transfer_fee(mint, |fee_config, epoch| {
+        fee_config.calculate_epoch_fee(epoch, amount)
+    })
+}
+
+// amount to send so that `net` arrives after the current epoch's transfer fee,
+// including when the fee is capped at its maximum
+pub fn gross_up(mint: &AccountInfo, net: u64) -> Result<u64> {
+    let fee = with_transfer_fee(mint, |fee_config, epoch| {
+        fee_config.calculate_inverse_epoch_fee(epoch, net)
+    })?;
+    net.checked_add(fee)
+        .ok_or(ErrorCode::ArithmeticOverflow.into())
+}
+
+// computes a fee with the mint's transfer fee config, mints without one charge nothing
+fn with_transfer_fee(
+    mint: &AccountInfo,
+    f: impl FnOnce(&TransferFeeConfig, u64) -> Option<u64>,
+) -> Result<u64> {
+    if mint.owner != &spl_token_2022::ID {
+        return Ok(0);
+    }
+
+    let data = mint.try_borrow_data()?;
+    let mint = StateWithExtensions::<Mint>::unpack(&data)?;
+    let Ok(fee_config) = mint.get_extension::<TransferFeeConfig>() else {
+        return Ok(0);
+    };
+    let fee = f(fee_config, Clock::get()?.epoch).ok_or(ErrorCode::ArithmeticOverflow)?;
+    Ok(fee)
+}
+
+// reloads `account` after a transfer into it and checks that exactly `expected` arrived
+pub fn check_received(
+    account: &mut InterfaceAccount<TokenAccount>,
+    before: u64,
+    expected: u64,
+) -> Result<()> {
+    account.reload()?;
+    require!(
+        account.amount.checked_sub(before) == Some(expected),
+        ErrorCode::TransferFeeMismatch
+    );
+    Ok(())
+}
+
+// transfer_checked that also works for transfer hook mints. the hook's extra accounts are
+// resolved from the ctx.remaining_accounts, which plain mints ignore
+pub fn transfer_checked_with_hook<'info>(
+    ctx: CpiContext<'_, '_, '_, 'info, TransferChecked<'info>>,
+    amount: u64,
+    decimals: u8,
+) -> Result<()> {
+    check_transfer_hook(&ctx.accounts.mint)?;
+    invoke_transfer_checked(
+        ctx.program.key,
+        ctx.accounts.from,
+        ctx.accounts.mint,
+        ctx.accounts.to,
+        ctx.accounts.authority,
+        &ctx.remaining_accounts,
+        amount,
+        decimals,
+        ctx.signer_seeds,
+    )
+    .map_err(Into::into)
+}
+
+// the runtime already rejects a hook calling back into the escrow through the token
+// program, this turns a mint pointing its hook at the escrow itself into a clear error
+fn check_transfer_hook(mint: &AccountInfo) -> Result<()> {
+    if mint.owner != &spl_token_2022::ID {
+        return Ok(());
+    }
+
+    let data = mint.try_borrow_data()?;
+    let mint = StateWithExtensions::<Mint>::unpack(&data)?;
+    require!(
+        transfer_hook::get_program_id(&mint) != Some(crate::ID),
+        ErrorCode::ReentrantTransferHook
+    );
+    Ok(())
+}
+
+// extensions that break the escrow. a non-transferable mint can never leave the vault,
+// a permanent delegate can drain it, and confidential balances can't be priced or checked
+pub fn check_mint_extensions(mint: &AccountInfo) -> Result<()> {
+    if mint.owner != &spl_token_2022::ID {
+        return Ok(());
+    }
+
+    let data = mint.try_borrow_data()?;
+    let mint = StateWithExtensions::<Mint>::unpack(&data)?;
+    for extension in mint.get_extension_types()? {
+        let error = match extension {
+            ExtensionType::NonTransferable => ErrorCode::NonTransferableMint,
+            ExtensionType::PermanentDelegate => ErrorCode::PermanentDelegateMint,
+            ExtensionType::ConfidentialTransferMint => ErrorCode::ConfidentialTransferMint,
+            _ => continue,
+        };
+        return Err(error.into());
+    }
+    Ok(())
+}
@@ -0,0 +1,99 @@
+use anchor_lang::prelude::*;
+
+use anchor_spl::{
+    associated_token::AssociatedToken,
+    token_interface::{
+        close_account, CloseAccount, Mint, TokenAccount, TokenInterface, TransferChecked,
+    },
+};
+
+use crate::extensions::transfer_checked_with_hook;
+
+// the maker sweeps what takes paid into the payment vault of an escrow made with
+// receive_to_vault. the escrow is only needed for its seeds, so this also works once it
+// is closed. the vault is closed, the next take creates it again
+#[derive(Accounts)]
+#[instruction(seed: u64)]
+pub struct ClaimPayment<'info> {
+    #[account(mut)]
+    pub maker: Signer<'info>,
+
+    #[account(
+        mint::token_program = token_program,
+    )]
+    pub mint_b: InterfaceAccount<'info, Mint>,
+
+    /// CHECK: the maker's escrow at `seed`, open, kept as a record or already closed.
+    /// only signs for the payment vault
+    #[account(
+        seeds = [b"escrow", maker.key().as_ref(), seed.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub escrow: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint_b,
+        associated_token::authority = escrow,
+        associated_token::token_program = token_program,
+    )]
+    pub payment_vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        init_if_needed,
+        payer = maker,
+        associated_token::mint = mint_b,
+        associated_token::authority = maker,
+        associated_token::token_program = token_program,
+    )]
+    pub maker_ata_b: InterfaceAccount<'info, TokenAccount>,
+
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> ClaimPayment<'info> {
+    // returns the mint_b sent to the maker
+    pub fn claim_payment(
+        &mut self,
+        seed: u64,
+        bumps: &ClaimPaymentBumps,
+        extra: &[AccountInfo<'info>],
+    ) -> Result<u64> {
+        let amount_b = self.payment_vault.amount;
+        let maker = self.maker.key();
+        let signer_seeds: [&[&[u8]]; 1] = [&[
+            b"escrow",
+            maker.as_ref(),
+            &seed.to_le_bytes()[..],
+            &[bumps.escrow],
+        ]];
+
+        let accounts = TransferChecked {
+            from: self.payment_vault.to_account_info(),
+            mint: self.mint_b.to_account_info(),
+            to: self.maker_ata_b.to_account_info(),
+            authority: self.escrow.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(
+            self.token_program.to_account_info(),
+            accounts,
+            &signer_seeds,
+        )
+        .with_remaining_accounts(extra.to_vec());
+        transfer_checked_with_hook(cpi_ctx, amount_b, self.mint_b.decimals)?;
+
+        let accounts = CloseAccount {
+            account: self.payment_vault.to_account_info(),
+            destination: self.maker.to_account_info(),
+            authority: self.escrow.to_account_info(),
+        };
+        close_account(CpiContext::new_with_signer(
+            self.token_program.to_account_info(),
+            accounts,
+            &signer_seeds,
+        ))?;
+        Ok(amount_b)
+    }
+}
@@ -0,0 +1,148 @@
+use anchor_lang::prelude::*;
+
+use anchor_spl::{
+    associated_token::AssociatedToken,
+    token_interface::{
+        close_account, CloseAccount, Mint, TokenAccount, TokenInterface, TransferChecked,
+    },
+};
+
+use crate::error::ErrorCode;
+use crate::extensions::transfer_checked_with_hook;
+use crate::state::{finish_escrow, namespace_seed, rent_destination, Escrow, EscrowStatus};
+
+#[derive(Accounts)]
+pub struct ClaimVested<'info> {
+    #[account(mut)]
+    pub taker: Signer<'info>,
+
+    // gets the rent of the vault and the escrow back with the last claim, unless make named
+    // a rent_recipient
+    #[account(mut)]
+    pub maker: SystemAccount<'info>,
+
+    #[account(
+        mint::token_program = token_program,
+    )]
+    pub mint_a: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        init_if_needed,
+        payer = taker,
+        associated_token::mint = mint_a,
+        associated_token::authority = taker,
+        associated_token::token_program = token_program,
+    )]
+    pub taker_ata_a: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        has_one = maker,
+        has_one = mint_a,
+        seeds = [
+            b"escrow",
+            maker.key().as_ref(),
+            escrow.namespace_seed(),
+            escrow.seed.to_le_bytes().as_ref(),
+        ],
+        bump = escrow.bump,
+        constraint = escrow.is_vesting() @ ErrorCode::EscrowNotVesting,
+        constraint = escrow.vest_taker == taker.key() @ ErrorCode::UnauthorizedTaker,
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint_a,
+        associated_token::authority = escrow,
+        associated_token::token_program = token_program,
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    // the escrow's rent_recipient, only needed when make named one
+    /// CHECK: only receives lamports, pinned to the escrow
+    #[account(mut, address = escrow.rent_recipient @ ErrorCode::RentRecipientMismatch)]
+    pub rent_recipient: Option<UncheckedAccount<'info>>,
+
+    // the maker's index of open escrows, the last claim drops the escrow from it
+    /// CHECK: only written by finish_escrow, when it holds an index
+    #[account(mut, seeds = [b"index", escrow.maker.as_ref()], bump)]
+    pub maker_index: UncheckedAccount<'info>,
+
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> ClaimVested<'info> {
+    fn rent_destination(&self) -> Result<AccountInfo<'info>> {
+        rent_destination(
+            &self.escrow,
+            self.maker.to_account_info(),
+            self.rent_recipient.as_ref(),
+        )
+    }
+
+    // returns the mint_a sent. the claim that reaches the whole deposit empties the vault,
+    // stray tokens included, and closes it with the escrow.
+    // `extra` holds the accounts a transfer hook on mint_a needs
+    pub fn claim_vested(&mut self, extra: &[AccountInfo<'info>]) -> Result<u64> {
+        let vested = self.escrow.vested(Clock::get()?.unix_timestamp)?;
+        let amount = vested - self.escrow.claimed_amount;
+        require!(amount > 0, ErrorCode::NothingToClaim);
+
+        let namespace = self.escrow.namespace;
+        let signer_seeds: [&[&[u8]]; 1] = [&[
+            b"escrow",
+            self.maker.to_account_info().key.as_ref(),
+            namespace_seed(&namespace),
+            &self.escrow.seed.to_le_bytes()[..],
+            &[self.escrow.bump],
+        ]];
+
+        let last_claim = vested == self.escrow.deposit;
+        let sent = if last_claim {
+            self.vault.amount
+        } else {
+            amount
+        };
+        let accounts = TransferChecked {
+            from: self.vault.to_account_info(),
+            mint: self.mint_a.to_account_info(),
+            to: self.taker_ata_a.to_account_info(),
+            authority: self.escrow.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(
+            self.token_program.to_account_info(),
+            accounts,
+            &signer_seeds,
+        )
+        .with_remaining_accounts(extra.to_vec());
+        transfer_checked_with_hook(cpi_ctx, sent, self.mint_a.decimals)?;
+
+        self.escrow.claimed_amount = vested;
+        if !last_claim {
+            return Ok(sent);
+        }
+
+        let destination = self.rent_destination()?;
+        let accounts = CloseAccount {
+            account: self.vault.to_account_info(),
+            destination: destination.clone(),
+            authority: self.escrow.to_account_info(),
+        };
+        close_account(CpiContext::new_with_signer(
+            self.token_program.to_account_info(),
+            accounts,
+            &signer_seeds,
+        ))?;
+        finish_escrow(
+            &mut self.escrow,
+            EscrowStatus::Completed,
+            self.taker.key(),
+            destination,
+            &self.maker_index,
+        )?;
+        Ok(sent)
+    }
+}
@@ -0,0 +1,20 @@
+use anchor_lang::prelude::*;
+
+use crate::CounterOffer;
+
+// counter offers hold no funds, their taker takes the rent back, also after the escrow is
+// gone. the escrow is only part of the seeds
+#[derive(Accounts)]
+pub struct CloseCounterOffer<'info> {
+    #[account(mut)]
+    pub taker: Signer<'info>,
+
+    #[account(
+        mut,
+        close = taker,
+        has_one = taker,
+        seeds = [b"offer", counter_offer.escrow.as_ref(), taker.key().as_ref()],
+        bump = counter_offer.bump,
+    )]
+    pub counter_offer: Account<'info, CounterOffer>,
+}
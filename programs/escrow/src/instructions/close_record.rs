@@ -0,0 +1,28 @@
+use anchor_lang::prelude::*;
+
+use crate::error::ErrorCode;
+use crate::Escrow;
+
+// escrows made with keep_record stay on chain after they finish, the maker can reclaim
+// the rent once the record is no longer needed
+#[derive(Accounts)]
+pub struct CloseRecord<'info> {
+    #[account(mut)]
+    pub maker: Signer<'info>,
+
+    #[account(
+        mut,
+        close = maker,
+        has_one = maker,
+        seeds = [
+            b"escrow",
+            maker.key().as_ref(),
+            escrow.namespace_seed(),
+            escrow.seed.to_le_bytes().as_ref(),
+        ],
+        bump = escrow.bump,
+        // a vesting escrow still holds the taker's deposit
+        constraint = !escrow.is_open() && !escrow.is_vesting() @ ErrorCode::EscrowStillOpen,
+    )]
+    pub escrow: Account<'info, Escrow>,
+}
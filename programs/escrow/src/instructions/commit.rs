@@ -0,0 +1,600 @@
+use anchor_lang::prelude::*;
+
+use anchor_spl::{
+    associated_token::AssociatedToken,
+    token_interface::{
+        close_account, CloseAccount, Mint, TokenAccount, TokenInterface, TransferChecked,
+    },
+};
+
+use crate::error::ErrorCode;
+use crate::extensions::transfer_checked_with_hook;
+use crate::state::{
+    finish_escrow, namespace_seed, protocol_fee, rent_destination, Commitment, Config, Escrow,
+    EscrowStatus, Stats,
+};
+use crate::COMMIT_WINDOW;
+
+// take in two steps. commit locks the taker's payment for `amount` of the escrow at
+// today's price, settle then swaps it for the deposit in a later transaction. the deposit
+// isn't set aside for the commitment: a take, top up or withdraw in between outdates it,
+// settling then fails with CommitmentOutdated and the taker reclaims the payment right away
+#[derive(Accounts)]
+pub struct Commit<'info> {
+    #[account(mut)]
+    pub taker: Signer<'info>,
+
+    #[account(
+        mint::token_program = token_program,
+    )]
+    pub mint_b: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint_b,
+        associated_token::authority = taker,
+        associated_token::token_program = token_program,
+    )]
+    pub taker_ata_b: InterfaceAccount<'info, TokenAccount>,
+
+    // only takers that could fill the escrow may commit to it. mut for the auction price
+    #[account(
+        mut,
+        has_one = mint_b,
+        seeds = [
+            b"escrow",
+            escrow.maker.key().as_ref(),
+            escrow.namespace_seed(),
+            escrow.seed.to_le_bytes().as_ref(),
+        ],
+        bump = escrow.bump,
+        constraint = escrow.is_open() @ ErrorCode::EscrowNotOpen,
+        constraint = !escrow.deposit_is_sol @ ErrorCode::DepositIsSol,
+        constraint = escrow.active @ ErrorCode::EscrowPaused,
+        constraint = escrow.is_allowed_taker(&taker.key()) @ ErrorCode::UnauthorizedTaker,
+        constraint = escrow.is_whitelisted(&taker.key()) @ ErrorCode::TakerNotWhitelisted,
+        constraint = !escrow.blocks_self_trade(&taker.key()) @ ErrorCode::SelfTradeNotAllowed,
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProtocolPaused,
+    )]
+    pub config: Account<'info, Config>,
+
+    // the taker pays the rent of both accounts and gets it back on settle or reclaim
+    #[account(
+        init,
+        payer = taker,
+        space = 8 + Commitment::INIT_SPACE,
+        seeds = [b"commitment", escrow.key().as_ref(), taker.key().as_ref()],
+        bump,
+    )]
+    pub commitment: Account<'info, Commitment>,
+
+    #[account(
+        init,
+        payer = taker,
+        associated_token::mint = mint_b,
+        associated_token::authority = commitment,
+        associated_token::token_program = token_program,
+    )]
+    pub commitment_vault: InterfaceAccount<'info, TokenAccount>,
+
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+// escrows that need take's optional accounts, or that gross up a transfer fee, can't be
+// committed to
+fn check_committable(escrow: &Escrow) -> Result<()> {
+    require!(
+        !escrow.is_basket()
+            && !escrow.is_programmable()
+            && !escrow.vests_deposit()
+            && !escrow.is_collection_gated()
+            && !escrow.is_mint_gated()
+            && !escrow.is_oracle_priced()
+            && !escrow.is_pool_priced()
+            && !escrow.gross_up_transfer_fee
+            && !escrow.receive_to_vault,
+        ErrorCode::CommitUnsupported
+    );
+    Ok(())
+}
+
+impl<'info> Commit<'info> {
+    // `extra` holds the accounts a transfer hook on mint_b needs
+    pub fn commit(
+        &mut self,
+        amount: u64,
+        bumps: &CommitBumps,
+        extra: &[AccountInfo<'info>],
+    ) -> Result<()> {
+        check_committable(&self.escrow)?;
+        let clock = Clock::get()?;
+        require!(
+            !self.escrow.is_expired(clock.unix_timestamp),
+            ErrorCode::EscrowExpired
+        );
+        require!(
+            !self.escrow.is_cooling_down(clock.slot),
+            ErrorCode::CooldownNotElapsed
+        );
+        self.escrow.apply_auction_price(clock.unix_timestamp)?;
+        let (amount_a, amount_b) = self.escrow.fill_for_amount_a(amount)?;
+        require!(
+            self.taker_ata_b.amount >= amount_b,
+            ErrorCode::InsufficientTakerFunds
+        );
+
+        // a mint_b transfer fee comes out of the maker's proceeds, like in take
+        let accounts = TransferChecked {
+            from: self.taker_ata_b.to_account_info(),
+            mint: self.mint_b.to_account_info(),
+            to: self.commitment_vault.to_account_info(),
+            authority: self.taker.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new(self.token_program.to_account_info(), accounts)
+            .with_remaining_accounts(extra.to_vec());
+        transfer_checked_with_hook(cpi_ctx, amount_b, self.mint_b.decimals)?;
+
+        // the commitment can't outlive the escrow
+        let window_end = clock.unix_timestamp.saturating_add(COMMIT_WINDOW);
+        let deadline = match self.escrow.expiry {
+            0 => window_end,
+            expiry => expiry.min(window_end),
+        };
+        self.commitment.set_inner(Commitment {
+            escrow: self.escrow.key(),
+            taker: self.taker.key(),
+            amount_a,
+            amount_b,
+            remaining: self.escrow.remaining,
+            deadline,
+            bump: bumps.commitment,
+        });
+        Ok(())
+    }
+}
+
+// the maker or the taker swaps a commitment for the deposit before its deadline. the
+// settler pays for whichever token accounts are missing, the taker gets the rent of the
+// commitment back
+#[derive(Accounts)]
+pub struct Settle<'info> {
+    #[account(
+        mut,
+        constraint = settler.key() == escrow.maker || settler.key() == commitment.taker
+            @ ErrorCode::UnauthorizedSettler,
+    )]
+    pub settler: Signer<'info>,
+
+    #[account(mut)]
+    pub maker: SystemAccount<'info>,
+    /// CHECK: only lamports are sent here, it is pinned to commitment.taker
+    #[account(mut)]
+    pub taker: UncheckedAccount<'info>,
+
+    #[account(
+        mint::token_program = token_program_a,
+    )]
+    pub mint_a: InterfaceAccount<'info, Mint>,
+    #[account(
+        mint::token_program = token_program_b,
+    )]
+    pub mint_b: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        init_if_needed,
+        payer = settler,
+        associated_token::mint = mint_b,
+        associated_token::authority = maker,
+        associated_token::token_program = token_program_b,
+    )]
+    pub maker_ata_b: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        init_if_needed,
+        payer = settler,
+        associated_token::mint = mint_a,
+        associated_token::authority = taker,
+        associated_token::token_program = token_program_a,
+    )]
+    pub taker_ata_a: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        has_one = maker,
+        has_one = mint_a,
+        has_one = mint_b,
+        seeds = [
+            b"escrow",
+            maker.key().as_ref(),
+            escrow.namespace_seed(),
+            escrow.seed.to_le_bytes().as_ref(),
+        ],
+        bump = escrow.bump,
+        constraint = escrow.is_open() @ ErrorCode::EscrowNotOpen,
+        constraint = escrow.active @ ErrorCode::EscrowPaused,
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint_a,
+        associated_token::authority = escrow,
+        associated_token::token_program = token_program_a,
+        constraint = vault.amount >= escrow.remaining @ ErrorCode::VaultBalanceMismatch,
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    // only needed when the vault holds more than the escrow records, like take's
+    #[account(
+        mut,
+        associated_token::mint = mint_a,
+        associated_token::authority = maker,
+        associated_token::token_program = token_program_a,
+    )]
+    pub maker_ata_a: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    // a take, top up or withdraw after the commit changes what it would buy
+    #[account(
+        mut,
+        close = taker,
+        has_one = escrow,
+        has_one = taker,
+        seeds = [b"commitment", escrow.key().as_ref(), taker.key().as_ref()],
+        bump = commitment.bump,
+        constraint = commitment.remaining == escrow.remaining @ ErrorCode::CommitmentOutdated,
+    )]
+    pub commitment: Account<'info, Commitment>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint_b,
+        associated_token::authority = commitment,
+        associated_token::token_program = token_program_b,
+    )]
+    pub commitment_vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProtocolPaused,
+    )]
+    pub config: Account<'info, Config>,
+
+    // adds the fill to the volumes, and the escrow to the taken ones on the last fill
+    #[account(
+        mut,
+        seeds = [b"stats"],
+        bump = stats.bump,
+    )]
+    pub stats: Option<Account<'info, Stats>>,
+
+    // the fee accounts can be left out while the config charges no fee
+    /// CHECK: only used as the authority of fee_collector_ata_b, pinned to the config
+    #[account(address = config.fee_collector)]
+    pub fee_collector: Option<UncheckedAccount<'info>>,
+
+    #[account(
+        init_if_needed,
+        payer = settler,
+        associated_token::mint = mint_b,
+        associated_token::authority = fee_collector,
+        associated_token::token_program = token_program_b,
+    )]
+    pub fee_collector_ata_b: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    // the escrow's rent_recipient, only needed when make named one
+    /// CHECK: only receives lamports, pinned to the escrow
+    #[account(mut, address = escrow.rent_recipient @ ErrorCode::RentRecipientMismatch)]
+    pub rent_recipient: Option<UncheckedAccount<'info>>,
+
+    // the maker's index of open escrows, the settle drops the escrow from it
+    /// CHECK: only written by finish_escrow, when it holds an index
+    #[account(mut, seeds = [b"index", escrow.maker.as_ref()], bump)]
+    pub maker_index: UncheckedAccount<'info>,
+
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub token_program_a: Interface<'info, TokenInterface>,
+    pub token_program_b: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> Settle<'info> {
+    fn rent_destination(&self) -> Result<AccountInfo<'info>> {
+        rent_destination(
+            &self.escrow,
+            self.maker.to_account_info(),
+            self.rent_recipient.as_ref(),
+        )
+    }
+
+    // the payment is released first and the deposit only leaves the vault once that went
+    // through. returns (mint_a sent, fee)
+    pub fn settle(&mut self, extra: &[AccountInfo<'info>]) -> Result<(u64, u64)> {
+        let now = Clock::get()?.unix_timestamp;
+        require!(now < self.commitment.deadline, ErrorCode::CommitmentExpired);
+        let fee = self.release_payment(extra)?;
+        let sent = self.release_deposit(now, extra)?;
+        Ok((sent, fee))
+    }
+
+    // whatever a mint_b transfer fee left of the payment goes to the maker, less the
+    // protocol fee on what was owed
+    fn release_payment(&mut self, extra: &[AccountInfo<'info>]) -> Result<u64> {
+        let escrow_key = self.escrow.key();
+        let taker_key = self.taker.key();
+        let commitment_seeds: [&[&[u8]]; 1] = [&[
+            b"commitment",
+            escrow_key.as_ref(),
+            taker_key.as_ref(),
+            &[self.commitment.bump],
+        ]];
+
+        let fee = protocol_fee(self.commitment.amount_b, self.config.fee_bps)?;
+        if fee > 0 {
+            let fee_collector_ata_b = self
+                .fee_collector_ata_b
+                .as_ref()
+                .ok_or(ErrorCode::MissingFeeAccount)?;
+            self.pay_from_commitment(
+                fee_collector_ata_b.to_account_info(),
+                fee,
+                extra,
+                &commitment_seeds,
+            )?;
+        }
+        let share = self
+            .commitment_vault
+            .amount
+            .checked_sub(fee)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.pay_from_commitment(
+            self.maker_ata_b.to_account_info(),
+            share,
+            extra,
+            &commitment_seeds,
+        )?;
+
+        let accounts = CloseAccount {
+            account: self.commitment_vault.to_account_info(),
+            destination: self.taker.to_account_info(),
+            authority: self.commitment.to_account_info(),
+        };
+        close_account(CpiContext::new_with_signer(
+            self.token_program_b.to_account_info(),
+            accounts,
+            &commitment_seeds,
+        ))?;
+        Ok(fee)
+    }
+
+    fn pay_from_commitment(
+        &self,
+        to: AccountInfo<'info>,
+        amount: u64,
+        extra: &[AccountInfo<'info>],
+        commitment_seeds: &[&[&[u8]]],
+    ) -> Result<()> {
+        let accounts = TransferChecked {
+            from: self.commitment_vault.to_account_info(),
+            mint: self.mint_b.to_account_info(),
+            to,
+            authority: self.commitment.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(
+            self.token_program_b.to_account_info(),
+            accounts,
+            commitment_seeds,
+        )
+        .with_remaining_accounts(extra.to_vec());
+        transfer_checked_with_hook(cpi_ctx, amount, self.mint_b.decimals)
+    }
+
+    // releases amount_a like a take, the last fill empties and closes the vault into the
+    // rent_destination
+    fn release_deposit(&mut self, now: i64, extra: &[AccountInfo<'info>]) -> Result<u64> {
+        let namespace = self.escrow.namespace;
+        let signer_seeds: [&[&[u8]]; 1] = [&[
+            b"escrow",
+            self.maker.key.as_ref(),
+            namespace_seed(&namespace),
+            &self.escrow.seed.to_le_bytes()[..],
+            &[self.escrow.bump],
+        ]];
+
+        let amount_a = self.commitment.amount_a;
+        let last_fill = amount_a == self.escrow.remaining;
+        let sent = amount_a;
+        let accounts = TransferChecked {
+            from: self.vault.to_account_info(),
+            mint: self.mint_a.to_account_info(),
+            to: self.taker_ata_a.to_account_info(),
+            authority: self.escrow.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(
+            self.token_program_a.to_account_info(),
+            accounts,
+            &signer_seeds,
+        )
+        .with_remaining_accounts(extra.to_vec());
+        transfer_checked_with_hook(cpi_ctx, sent, self.mint_a.decimals)?;
+
+        // an auction may have moved the price since the commit, which the taker doesn't pay
+        self.escrow.apply_auction_price(now)?;
+        self.escrow.remaining -= amount_a;
+        self.escrow.remaining_receive = self
+            .escrow
+            .remaining_receive
+            .saturating_sub(self.commitment.amount_b);
+        self.escrow.sync_unit_price()?;
+        if let Some(stats) = &mut self.stats {
+            stats.record_take(sent, self.commitment.amount_b, last_fill)?;
+        }
+        if !last_fill {
+            return Ok(sent);
+        }
+
+        // the vault's balance from before this fill
+        let excess = self.vault.amount - amount_a;
+        if excess > 0 {
+            self.sweep_excess(excess, extra, &signer_seeds)?;
+        }
+        let destination = self.rent_destination()?;
+        let accounts = CloseAccount {
+            account: self.vault.to_account_info(),
+            destination: destination.clone(),
+            authority: self.escrow.to_account_info(),
+        };
+        close_account(CpiContext::new_with_signer(
+            self.token_program_a.to_account_info(),
+            accounts,
+            &signer_seeds,
+        ))?;
+        self.escrow.remaining_receive = 0;
+        finish_escrow(
+            &mut self.escrow,
+            EscrowStatus::Completed,
+            self.taker.key(),
+            destination,
+            &self.maker_index,
+        )?;
+        Ok(sent)
+    }
+
+    // tokens sent to the vault from outside go back to the maker before it closes
+    fn sweep_excess(
+        &self,
+        excess: u64,
+        extra: &[AccountInfo<'info>],
+        signer_seeds: &[&[&[u8]]],
+    ) -> Result<()> {
+        let maker_ata_a = self
+            .maker_ata_a
+            .as_ref()
+            .ok_or(ErrorCode::MakerAtaRequired)?;
+        let accounts = TransferChecked {
+            from: self.vault.to_account_info(),
+            mint: self.mint_a.to_account_info(),
+            to: maker_ata_a.to_account_info(),
+            authority: self.escrow.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(
+            self.token_program_a.to_account_info(),
+            accounts,
+            signer_seeds,
+        )
+        .with_remaining_accounts(extra.to_vec());
+        transfer_checked_with_hook(cpi_ctx, excess, self.mint_a.decimals)
+    }
+}
+
+// the taker takes an unsettled payment back once the deadline has passed, also after the
+// escrow is gone. before the deadline only a commitment that can't settle any more comes
+// back, one whose escrow was closed or changed by a take, top up or withdraw
+#[derive(Accounts)]
+pub struct ReclaimCommitment<'info> {
+    #[account(mut)]
+    pub taker: Signer<'info>,
+
+    #[account(
+        mint::token_program = token_program,
+    )]
+    pub mint_b: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint_b,
+        associated_token::authority = taker,
+        associated_token::token_program = token_program,
+    )]
+    pub taker_ata_b: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        close = taker,
+        has_one = taker,
+        seeds = [b"commitment", commitment.escrow.as_ref(), taker.key().as_ref()],
+        bump = commitment.bump,
+    )]
+    pub commitment: Account<'info, Commitment>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint_b,
+        associated_token::authority = commitment,
+        associated_token::token_program = token_program,
+    )]
+    pub commitment_vault: InterfaceAccount<'info, TokenAccount>,
+
+    // the commitment's escrow, only needed to reclaim before the deadline
+    /// CHECK: pinned to the commitment, read in is_outdated
+    #[account(address = commitment.escrow)]
+    pub escrow: Option<UncheckedAccount<'info>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+impl<'info> ReclaimCommitment<'info> {
+    // returns the mint_b sent back to the taker
+    pub fn reclaim_commitment(&mut self, extra: &[AccountInfo<'info>]) -> Result<u64> {
+        require!(
+            Clock::get()?.unix_timestamp >= self.commitment.deadline || self.is_outdated(),
+            ErrorCode::CommitmentActive
+        );
+        let amount_b = self.commitment_vault.amount;
+        let commitment_seeds: [&[&[u8]]; 1] = [&[
+            b"commitment",
+            self.commitment.escrow.as_ref(),
+            self.commitment.taker.as_ref(),
+            &[self.commitment.bump],
+        ]];
+
+        let accounts = TransferChecked {
+            from: self.commitment_vault.to_account_info(),
+            mint: self.mint_b.to_account_info(),
+            to: self.taker_ata_b.to_account_info(),
+            authority: self.commitment.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(
+            self.token_program.to_account_info(),
+            accounts,
+            &commitment_seeds,
+        )
+        .with_remaining_accounts(extra.to_vec());
+        transfer_checked_with_hook(cpi_ctx, amount_b, self.mint_b.decimals)?;
+
+        let accounts = CloseAccount {
+            account: self.commitment_vault.to_account_info(),
+            destination: self.taker.to_account_info(),
+            authority: self.commitment.to_account_info(),
+        };
+        close_account(CpiContext::new_with_signer(
+            self.token_program.to_account_info(),
+            accounts,
+            &commitment_seeds,
+        ))?;
+        Ok(amount_b)
+    }
+    // the escrow is closed, no longer open, or at another remaining than settle expects
+    fn is_outdated(&self) -> bool {
+        let Some(escrow) = &self.escrow else {
+            return false;
+        };
+        if escrow.owner != &crate::ID {
+            return true;
+        }
+        let Ok(data) = escrow.try_borrow_data() else {
+            return true;
+        };
+        match Escrow::try_deserialize(&mut &data[..]) {
+            Ok(escrow) => !escrow.is_open() || escrow.remaining != self.commitment.remaining,
+            Err(_) => true,
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use anchor_lang::prelude::*;
+use anchor_lang::system_program::{transfer, Transfer};
+
+use crate::error::ErrorCode;
+use crate::pda::escrow_pda;
+use crate::{Escrow, IndexedEscrow, MakerIndex};
+
+// drops entries from the maker's index whose escrow finished without passing the index,
+// which every instruction finishing an escrow does now, so only escrows finished before
+// that or while the index had the seed only layout are left behind. every entry comes with
+// its escrow as a remaining account, in the same order, which has to be closed or no longer
+// open. an index of the seed only layout is grown to the current one first, its seeds all
+// sit at the zero namespace. an index left empty is closed into the maker
+#[derive(Accounts)]
+pub struct CompactMakerIndex<'info> {
+    #[account(mut)]
+    pub maker: Signer<'info>,
+
+    /// CHECK: loaded in compact, once it has the current layout
+    #[account(mut, seeds = [b"index", maker.key().as_ref()], bump)]
+    pub maker_index: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+// the layout of MakerIndex before it listed namespaces
+#[derive(AnchorDeserialize)]
+struct SeedOnlyIndex {
+    maker: Pubkey,
+    seeds: Vec<u64>,
+    bump: u8,
+}
+
+impl<'info> CompactMakerIndex<'info> {
+    pub fn compact(
+        &mut self,
+        entries: &[IndexedEscrow],
+        escrows: &[AccountInfo<'info>],
+    ) -> Result<()> {
+        require!(
+            entries.len() == escrows.len(),
+            ErrorCode::BatchLengthMismatch
+        );
+        self.grow_seed_only_index()?;
+
+        let info = self.maker_index.to_account_info();
+        let mut index = MakerIndex::try_deserialize(&mut &info.try_borrow_data()?[..])?;
+        for (entry, escrow) in entries.iter().zip(escrows) {
+            require_keys_eq!(
+                escrow.key(),
+                escrow_pda(&self.maker.key(), &entry.namespace, entry.seed).0,
+                ErrorCode::EscrowStillIndexed
+            );
+            // a closed escrow is handed back to the system program
+            if escrow.owner == &crate::ID {
+                let state = Escrow::try_deserialize(&mut &escrow.try_borrow_data()?[..])?;
+                require!(!state.is_open(), ErrorCode::EscrowStillIndexed);
+            }
+            index.remove(*entry);
+        }
+
+        if index.entries.is_empty() {
+            // what Account::close does
+            self.maker.add_lamports(info.lamports())?;
+            info.sub_lamports(info.lamports())?;
+            info.assign(&System::id());
+            return Ok(info.resize(0)?);
+        }
+        let mut data = info.try_borrow_mut_data()?;
+        index.try_serialize(&mut &mut data[..])
+    }
+
+    // rewrites an index of the seed only layout in the current one, the maker pays the rent
+    // of the larger account
+    fn grow_seed_only_index(&self) -> Result<()> {
+        let info = self.maker_index.to_account_info();
+        require_keys_eq!(*info.owner, crate::ID, ErrorCode::MakerIndexMismatch);
+        if info.data_len() != MakerIndex::SEED_ONLY_SPACE {
+            return Ok(());
+        }
+        let old = {
+            let data = info.try_borrow_data()?;
+            require!(
+                data[..8] == *MakerIndex::DISCRIMINATOR,
+                ErrorCode::MakerIndexMismatch
+            );
+            SeedOnlyIndex::deserialize(&mut &data[8..])?
+        };
+
+        let space = 8 + MakerIndex::INIT_SPACE;
+        let shortfall = Rent::get()?
+            .minimum_balance(space)
+            .saturating_sub(info.lamports());
+        if shortfall > 0 {
+            let accounts = Transfer {
+                from: self.maker.to_account_info(),
+                to: info.clone(),
+            };
+            transfer(
+                CpiContext::new(self.system_program.to_account_info(), accounts),
+                shortfall,
+            )?;
+        }
+        info.resize(space)?;
+
+        let index = MakerIndex {
+            maker: old.maker,
+            entries: old
+                .seeds
+                .into_iter()
+                .map(|seed| IndexedEscrow {
+                    namespace: [0; 8],
+                    seed,
+                })
+                .collect(),
+            bump: old.bump,
+        };
+        let mut data = info.try_borrow_mut_data()?;
+        index.try_serialize(&mut &mut data[..])
+    }
+}
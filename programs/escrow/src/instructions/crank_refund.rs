@@ -0,0 +1,305 @@
+use anchor_lang::prelude::*;
+
+use anchor_lang::system_program::{transfer, Transfer};
+use anchor_spl::{
+    associated_token::AssociatedToken,
+    token_interface::{
+        close_account, transfer_checked, CloseAccount, Mint, TokenAccount, TokenInterface,
+        TransferChecked,
+    },
+};
+
+use crate::error::ErrorCode;
+use crate::state::{finish_escrow, namespace_seed, rent_destination, Escrow, EscrowStatus, Stats};
+use crate::{CRANK_BOUNTY, GRACE_PERIOD};
+
+// anyone can return the deposit of an expired escrow to the maker and earn a bounty
+#[derive(Accounts)]
+pub struct CrankRefund<'info> {
+    #[account(mut)]
+    pub cranker: Signer<'info>,
+
+    #[account(mut)]
+    pub maker: SystemAccount<'info>,
+
+    #[account(
+        mint::token_program = token_program,
+    )]
+    pub mint_a: InterfaceAccount<'info, Mint>,
+
+    // tokens can only go back to the maker's ATA, the cranker can't redirect them
+    #[account(
+        mut,
+        associated_token::mint = mint_a,
+        associated_token::authority = maker,
+        associated_token::token_program = token_program,
+    )]
+    pub maker_ata_a: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        has_one = mint_a,
+        has_one = maker,
+        seeds = [
+            b"escrow",
+            maker.key().as_ref(),
+            escrow.namespace_seed(),
+            escrow.seed.to_le_bytes().as_ref(),
+        ],
+        bump = escrow.bump,
+        constraint = escrow.is_open() @ ErrorCode::EscrowNotOpen,
+        constraint = !escrow.deposit_is_sol @ ErrorCode::DepositIsSol,
+        constraint = !escrow.is_programmable() @ ErrorCode::ProgrammableNftUnsupported,
+        constraint = !escrow.is_basket() @ ErrorCode::BasketUnsupported,
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint_a,
+        associated_token::authority = escrow,
+        associated_token::token_program = token_program,
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    // the escrow's rent_recipient, only needed when make named one
+    /// CHECK: only receives lamports, pinned to the escrow
+    #[account(mut, address = escrow.rent_recipient @ ErrorCode::RentRecipientMismatch)]
+    pub rent_recipient: Option<UncheckedAccount<'info>>,
+
+    // counts the escrow as refunded
+    #[account(
+        mut,
+        seeds = [b"stats"],
+        bump = stats.bump,
+    )]
+    pub stats: Option<Account<'info, Stats>>,
+
+    // the maker's index of open escrows, the crank drops the escrow from it
+    /// CHECK: only written by finish_escrow, when it holds an index
+    #[account(mut, seeds = [b"index", escrow.maker.as_ref()], bump)]
+    pub maker_index: UncheckedAccount<'info>,
+
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+// escrows without an expiry can only be refunded by the maker, and so can expired ones
+// for GRACE_PERIOD. a refund lock running past that holds off the crank as well
+fn check_expired(escrow: &Escrow) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    require!(escrow.is_expired(now), ErrorCode::EscrowNotExpired);
+    require!(
+        now >= escrow.expiry.saturating_add(GRACE_PERIOD),
+        ErrorCode::GracePeriodActive
+    );
+    require!(!escrow.is_refund_locked(now), ErrorCode::RefundLocked);
+    Ok(())
+}
+
+// the bounty comes out of the escrow's lamports and the rest goes to `destination`, except
+// for the rent exempt minimum a kept record holds on to
+fn pay_bounty<'info>(
+    escrow: &mut Account<'info, Escrow>,
+    cranker: &Signer<'info>,
+    destination: AccountInfo<'info>,
+    maker_index: &AccountInfo<'info>,
+) -> Result<u64> {
+    let reserve = if escrow.keep_record {
+        Rent::get()?.minimum_balance(escrow.to_account_info().data_len())
+    } else {
+        0
+    };
+    let available = escrow.get_lamports().saturating_sub(reserve);
+    let bounty = CRANK_BOUNTY.min(available);
+    escrow.sub_lamports(bounty)?;
+    cranker.add_lamports(bounty)?;
+
+    if escrow.keep_record {
+        escrow.sub_lamports(available - bounty)?;
+        destination.add_lamports(available - bounty)?;
+    }
+    finish_escrow(
+        escrow,
+        EscrowStatus::Cancelled,
+        Pubkey::default(),
+        destination,
+        maker_index,
+    )?;
+    Ok(bounty)
+}
+
+impl<'info> CrankRefund<'info> {
+    fn rent_destination(&self) -> Result<AccountInfo<'info>> {
+        rent_destination(
+            &self.escrow,
+            self.maker.to_account_info(),
+            self.rent_recipient.as_ref(),
+        )
+    }
+
+    pub fn check_expired(&self) -> Result<()> {
+        check_expired(&self.escrow)
+    }
+
+    pub fn refund_and_close_vault(&mut self) -> Result<()> {
+        let namespace = self.escrow.namespace;
+        let signer_seeds: [&[&[u8]]; 1] = [&[
+            b"escrow",
+            self.maker.to_account_info().key.as_ref(),
+            namespace_seed(&namespace),
+            &self.escrow.seed.to_le_bytes()[..],
+            &[self.escrow.bump],
+        ]];
+
+        let transfer_accounts = TransferChecked {
+            from: self.vault.to_account_info(),
+            mint: self.mint_a.to_account_info(),
+            to: self.maker_ata_a.to_account_info(),
+            authority: self.escrow.to_account_info(),
+        };
+        let transfer_cpi_ctx = CpiContext::new_with_signer(
+            self.token_program.to_account_info(),
+            transfer_accounts,
+            &signer_seeds,
+        );
+
+        transfer_checked(transfer_cpi_ctx, self.vault.amount, self.mint_a.decimals)?;
+
+        // a kept record can't give up its own rent, the vault rent pays the bounty instead
+        let destination = if self.escrow.keep_record {
+            self.escrow.to_account_info()
+        } else {
+            self.rent_destination()?
+        };
+        let close_accounts = CloseAccount {
+            account: self.vault.to_account_info(),
+            destination,
+            authority: self.escrow.to_account_info(),
+        };
+
+        let close_cpi_ctx = CpiContext::new_with_signer(
+            self.token_program.to_account_info(),
+            close_accounts,
+            &signer_seeds,
+        );
+
+        close_account(close_cpi_ctx)?;
+        if let Some(stats) = &mut self.stats {
+            stats.record_refund()?;
+        }
+        Ok(())
+    }
+
+    pub fn pay_bounty(&mut self) -> Result<u64> {
+        let destination = self.rent_destination()?;
+        pay_bounty(
+            &mut self.escrow,
+            &self.cranker,
+            destination,
+            &self.maker_index,
+        )
+    }
+}
+
+// crank_refund for escrows whose deposit is native SOL held by the sol_vault PDA
+#[derive(Accounts)]
+pub struct CrankRefundSol<'info> {
+    #[account(mut)]
+    pub cranker: Signer<'info>,
+
+    #[account(mut)]
+    pub maker: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        has_one = maker,
+        seeds = [
+            b"escrow",
+            maker.key().as_ref(),
+            escrow.namespace_seed(),
+            escrow.seed.to_le_bytes().as_ref(),
+        ],
+        bump = escrow.bump,
+        constraint = escrow.is_open() @ ErrorCode::EscrowNotOpen,
+        constraint = escrow.deposit_is_sol @ ErrorCode::DepositNotSol,
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    #[account(
+        mut,
+        seeds = [b"sol_vault", escrow.key().as_ref()],
+        bump,
+    )]
+    pub sol_vault: SystemAccount<'info>,
+
+    // the escrow's rent_recipient, only needed when make named one
+    /// CHECK: only receives lamports, pinned to the escrow
+    #[account(mut, address = escrow.rent_recipient @ ErrorCode::RentRecipientMismatch)]
+    pub rent_recipient: Option<UncheckedAccount<'info>>,
+
+    // counts the escrow as refunded
+    #[account(
+        mut,
+        seeds = [b"stats"],
+        bump = stats.bump,
+    )]
+    pub stats: Option<Account<'info, Stats>>,
+
+    // the maker's index of open escrows, the crank drops the escrow from it
+    /// CHECK: only written by finish_escrow, when it holds an index
+    #[account(mut, seeds = [b"index", escrow.maker.as_ref()], bump)]
+    pub maker_index: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> CrankRefundSol<'info> {
+    fn rent_destination(&self) -> Result<AccountInfo<'info>> {
+        rent_destination(
+            &self.escrow,
+            self.maker.to_account_info(),
+            self.rent_recipient.as_ref(),
+        )
+    }
+
+    pub fn check_expired(&self) -> Result<()> {
+        check_expired(&self.escrow)
+    }
+
+    // returns the lamports sent back to the maker
+    pub fn refund(&mut self, bumps: &CrankRefundSolBumps) -> Result<u64> {
+        let escrow_key = self.escrow.key();
+        let signer_seeds: [&[&[u8]]; 1] =
+            [&[b"sol_vault", escrow_key.as_ref(), &[bumps.sol_vault]]];
+
+        let amount = self.sol_vault.lamports();
+        let accounts = Transfer {
+            from: self.sol_vault.to_account_info(),
+            to: self.maker.to_account_info(),
+        };
+        transfer(
+            CpiContext::new_with_signer(
+                self.system_program.to_account_info(),
+                accounts,
+                &signer_seeds,
+            ),
+            amount,
+        )?;
+        if let Some(stats) = &mut self.stats {
+            stats.record_refund()?;
+        }
+        Ok(amount)
+    }
+
+    pub fn pay_bounty(&mut self) -> Result<u64> {
+        let destination = self.rent_destination()?;
+        pay_bounty(
+            &mut self.escrow,
+            &self.cranker,
+            destination,
+            &self.maker_index,
+        )
+    }
+}
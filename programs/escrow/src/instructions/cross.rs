@@ -0,0 +1,414 @@
+use anchor_lang::prelude::*;
+
+use anchor_spl::{
+    associated_token::AssociatedToken,
+    token_interface::{
+        close_account, CloseAccount, Mint, TokenAccount, TokenInterface, TransferChecked,
+    },
+};
+
+use crate::error::ErrorCode;
+use crate::extensions::transfer_checked_with_hook;
+use crate::state::{
+    finish_escrow, namespace_seed, protocol_fee, rent_destination, Config, Escrow, EscrowStatus,
+    Stats,
+};
+
+// anyone can match escrow_1, selling mint_a for mint_b, against escrow_2, selling mint_b
+// for mint_a, once each deposit covers what the other maker asks for. each maker is paid
+// what their escrow still asks, less the protocol fee, out of the other escrow's vault.
+// what is left over of either deposit is the matcher's reward. tokens sent to a vault from
+// outside aren't part of the deposit and go back to that vault's maker
+#[event_cpi]
+#[derive(Accounts)]
+pub struct Cross<'info> {
+    #[account(mut)]
+    pub matcher: Signer<'info>,
+
+    #[account(mut)]
+    pub maker_1: SystemAccount<'info>,
+    #[account(mut)]
+    pub maker_2: SystemAccount<'info>,
+
+    #[account(
+        mint::token_program = token_program_a,
+    )]
+    pub mint_a: InterfaceAccount<'info, Mint>,
+    #[account(
+        mint::token_program = token_program_b,
+    )]
+    pub mint_b: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"escrow",
+            maker_1.key().as_ref(),
+            escrow_1.namespace_seed(),
+            escrow_1.seed.to_le_bytes().as_ref(),
+        ],
+        bump = escrow_1.bump,
+        constraint = escrow_1.mint_a == mint_a.key() && escrow_1.mint_b == mint_b.key()
+            @ ErrorCode::CrossMismatch,
+        constraint = escrow_1.is_open() @ ErrorCode::EscrowNotOpen,
+        constraint = !escrow_1.deposit_is_sol @ ErrorCode::DepositIsSol,
+        constraint = escrow_1.active @ ErrorCode::EscrowPaused,
+    )]
+    pub escrow_1: Account<'info, Escrow>,
+    #[account(
+        mut,
+        seeds = [
+            b"escrow",
+            maker_2.key().as_ref(),
+            escrow_2.namespace_seed(),
+            escrow_2.seed.to_le_bytes().as_ref(),
+        ],
+        bump = escrow_2.bump,
+        constraint = escrow_2.mint_a == mint_b.key() && escrow_2.mint_b == mint_a.key()
+            @ ErrorCode::CrossMismatch,
+        constraint = escrow_2.is_open() @ ErrorCode::EscrowNotOpen,
+        constraint = !escrow_2.deposit_is_sol @ ErrorCode::DepositIsSol,
+        constraint = escrow_2.active @ ErrorCode::EscrowPaused,
+    )]
+    pub escrow_2: Account<'info, Escrow>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint_a,
+        associated_token::authority = escrow_1,
+        associated_token::token_program = token_program_a,
+        constraint = vault_1.amount >= escrow_1.remaining @ ErrorCode::VaultBalanceMismatch,
+    )]
+    pub vault_1: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = mint_b,
+        associated_token::authority = escrow_2,
+        associated_token::token_program = token_program_b,
+        constraint = vault_2.amount >= escrow_2.remaining @ ErrorCode::VaultBalanceMismatch,
+    )]
+    pub vault_2: InterfaceAccount<'info, TokenAccount>,
+
+    // the makers are only ever paid into their own ATAs, the matcher can't redirect them
+    #[account(
+        mut,
+        associated_token::mint = mint_b,
+        associated_token::authority = maker_1,
+        associated_token::token_program = token_program_b,
+    )]
+    pub maker_1_ata_b: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = mint_a,
+        associated_token::authority = maker_2,
+        associated_token::token_program = token_program_a,
+    )]
+    pub maker_2_ata_a: InterfaceAccount<'info, TokenAccount>,
+    // only needed when a vault holds more than its escrow records, like take's maker_ata_a
+    #[account(
+        mut,
+        associated_token::mint = mint_a,
+        associated_token::authority = maker_1,
+        associated_token::token_program = token_program_a,
+    )]
+    pub maker_1_ata_a: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = mint_b,
+        associated_token::authority = maker_2,
+        associated_token::token_program = token_program_b,
+    )]
+    pub maker_2_ata_b: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    // the matcher's reward, created at the matcher's expense
+    #[account(
+        init_if_needed,
+        payer = matcher,
+        associated_token::mint = mint_a,
+        associated_token::authority = matcher,
+        associated_token::token_program = token_program_a,
+    )]
+    pub matcher_ata_a: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        init_if_needed,
+        payer = matcher,
+        associated_token::mint = mint_b,
+        associated_token::authority = matcher,
+        associated_token::token_program = token_program_b,
+    )]
+    pub matcher_ata_b: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProtocolPaused,
+    )]
+    pub config: Account<'info, Config>,
+
+    // adds both fills to the volumes and both escrows to the taken ones
+    #[account(
+        mut,
+        seeds = [b"stats"],
+        bump = stats.bump,
+    )]
+    pub stats: Option<Account<'info, Stats>>,
+
+    // the fee accounts can be left out while the config charges no fee
+    /// CHECK: only used as the authority of the fee collector ATAs, pinned to the config
+    #[account(address = config.fee_collector)]
+    pub fee_collector: Option<UncheckedAccount<'info>>,
+    #[account(
+        init_if_needed,
+        payer = matcher,
+        associated_token::mint = mint_a,
+        associated_token::authority = fee_collector,
+        associated_token::token_program = token_program_a,
+    )]
+    pub fee_collector_ata_a: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        init_if_needed,
+        payer = matcher,
+        associated_token::mint = mint_b,
+        associated_token::authority = fee_collector,
+        associated_token::token_program = token_program_b,
+    )]
+    pub fee_collector_ata_b: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    // each escrow's rent_recipient, only needed when its make named one
+    /// CHECK: only receives lamports, pinned to escrow_1
+    #[account(mut, address = escrow_1.rent_recipient @ ErrorCode::RentRecipientMismatch)]
+    pub rent_recipient_1: Option<UncheckedAccount<'info>>,
+    /// CHECK: only receives lamports, pinned to escrow_2
+    #[account(mut, address = escrow_2.rent_recipient @ ErrorCode::RentRecipientMismatch)]
+    pub rent_recipient_2: Option<UncheckedAccount<'info>>,
+
+    // the makers' indexes of open escrows, the cross drops both escrows from them
+    /// CHECK: maker_1's index, only written by finish_escrow, when it holds an index
+    #[account(mut, seeds = [b"index", escrow_1.maker.as_ref()], bump)]
+    pub maker_index_1: UncheckedAccount<'info>,
+    /// CHECK: maker_2's index, only written by finish_escrow, when it holds an index
+    #[account(mut, seeds = [b"index", escrow_2.maker.as_ref()], bump)]
+    pub maker_index_2: UncheckedAccount<'info>,
+
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub token_program_a: Interface<'info, TokenInterface>,
+    pub token_program_b: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+// what cross moved. amount_a went to maker_2 and amount_b to maker_1, fees included
+pub struct Crossed {
+    pub amount_a: u64,
+    pub amount_b: u64,
+    pub fee_a: u64,
+    pub fee_b: u64,
+    pub surplus_a: u64,
+    pub surplus_b: u64,
+}
+
+// each maker takes the other escrow, so each has to be a taker the other escrow accepts.
+// escrows that need take's optional accounts or gross up a transfer fee can't be crossed
+fn check_crossable(escrow: &Escrow, counterparty: &Pubkey, clock: &Clock) -> Result<()> {
+    require!(
+        escrow.is_allowed_taker(counterparty),
+        ErrorCode::UnauthorizedTaker
+    );
+    require!(
+        escrow.is_whitelisted(counterparty),
+        ErrorCode::TakerNotWhitelisted
+    );
+    require!(
+        !escrow.blocks_self_trade(counterparty),
+        ErrorCode::SelfTradeNotAllowed
+    );
+    require!(
+        !escrow.is_expired(clock.unix_timestamp),
+        ErrorCode::EscrowExpired
+    );
+    require!(
+        !escrow.is_cooling_down(clock.slot),
+        ErrorCode::CooldownNotElapsed
+    );
+    require!(
+        !escrow.is_basket()
+            && !escrow.is_programmable()
+            && !escrow.vests_deposit()
+            && !escrow.is_collection_gated()
+            && !escrow.is_mint_gated()
+            && !escrow.is_oracle_priced()
+            && !escrow.is_pool_priced()
+            && !escrow.gross_up_transfer_fee
+            && !escrow.receive_to_vault,
+        ErrorCode::CrossUnsupported
+    );
+    Ok(())
+}
+
+impl<'info> Cross<'info> {
+    pub fn check_escrows(&self) -> Result<()> {
+        let clock = Clock::get()?;
+        check_crossable(&self.escrow_1, &self.maker_2.key(), &clock)?;
+        check_crossable(&self.escrow_2, &self.maker_1.key(), &clock)
+    }
+
+    // both escrows are filled completely, at the prices they ask right now
+    pub fn cross(&mut self) -> Result<Crossed> {
+        let now = Clock::get()?.unix_timestamp;
+        self.escrow_1.apply_auction_price(now)?;
+        self.escrow_2.apply_auction_price(now)?;
+        // escrow_1 asks amount_b for vault_1, escrow_2 asks amount_a for vault_2
+        let (amount_a, amount_b) = (
+            self.escrow_2.remaining_receive,
+            self.escrow_1.remaining_receive,
+        );
+        require!(
+            self.escrow_1.remaining >= amount_a && self.escrow_2.remaining >= amount_b,
+            ErrorCode::PricesDontCross
+        );
+        let fee_a = protocol_fee(amount_a, self.config.fee_bps)?;
+        let fee_b = protocol_fee(amount_b, self.config.fee_bps)?;
+        let surplus_a = self.escrow_1.remaining - amount_a;
+        let surplus_b = self.escrow_2.remaining - amount_b;
+
+        let mut payouts_a = vec![
+            (self.maker_2_ata_a.to_account_info(), amount_a - fee_a),
+            (self.matcher_ata_a.to_account_info(), surplus_a),
+        ];
+        let mut payouts_b = vec![
+            (self.maker_1_ata_b.to_account_info(), amount_b - fee_b),
+            (self.matcher_ata_b.to_account_info(), surplus_b),
+        ];
+        for (fee, ata, payouts) in [
+            (fee_a, &self.fee_collector_ata_a, &mut payouts_a),
+            (fee_b, &self.fee_collector_ata_b, &mut payouts_b),
+        ] {
+            if fee > 0 {
+                let ata = ata.as_ref().ok_or(ErrorCode::MissingFeeAccount)?;
+                payouts.push((ata.to_account_info(), fee));
+            }
+        }
+        // tokens sent to a vault from outside go back to its maker. the vault amounts are still
+        // the balances from before the cross
+        for (excess, ata, payouts) in [
+            (
+                self.vault_1.amount - self.escrow_1.remaining,
+                &self.maker_1_ata_a,
+                &mut payouts_a,
+            ),
+            (
+                self.vault_2.amount - self.escrow_2.remaining,
+                &self.maker_2_ata_b,
+                &mut payouts_b,
+            ),
+        ] {
+            if excess > 0 {
+                let ata = ata.as_ref().ok_or(ErrorCode::MakerAtaRequired)?;
+                payouts.push((ata.to_account_info(), excess));
+            }
+        }
+        let destination_1 = rent_destination(
+            &self.escrow_1,
+            self.maker_1.to_account_info(),
+            self.rent_recipient_1.as_ref(),
+        )?;
+        let destination_2 = rent_destination(
+            &self.escrow_2,
+            self.maker_2.to_account_info(),
+            self.rent_recipient_2.as_ref(),
+        )?;
+        release_vault(
+            &self.escrow_1,
+            &self.vault_1,
+            &self.mint_a,
+            self.maker_1.to_account_info(),
+            destination_1.clone(),
+            &self.token_program_a,
+            &payouts_a,
+        )?;
+        release_vault(
+            &self.escrow_2,
+            &self.vault_2,
+            &self.mint_b,
+            self.maker_2.to_account_info(),
+            destination_2.clone(),
+            &self.token_program_b,
+            &payouts_b,
+        )?;
+
+        for escrow in [&mut self.escrow_1, &mut self.escrow_2] {
+            escrow.remaining = 0;
+            escrow.remaining_receive = 0;
+        }
+        finish_escrow(
+            &mut self.escrow_1,
+            EscrowStatus::Completed,
+            self.maker_2.key(),
+            destination_1,
+            &self.maker_index_1,
+        )?;
+        finish_escrow(
+            &mut self.escrow_2,
+            EscrowStatus::Completed,
+            self.maker_1.key(),
+            destination_2,
+            &self.maker_index_2,
+        )?;
+        // each escrow's deposit went out for what the other one paid
+        if let Some(stats) = &mut self.stats {
+            stats.record_take(amount_a, amount_b, true)?;
+            stats.record_take(amount_b, amount_a, true)?;
+        }
+        Ok(Crossed {
+            amount_a,
+            amount_b,
+            fee_a,
+            fee_b,
+            surplus_a,
+            surplus_b,
+        })
+    }
+}
+
+// empties an escrow's vault into `payouts`, skipping zero amounts, and closes it into
+// `destination`
+fn release_vault<'info>(
+    escrow: &Account<'info, Escrow>,
+    vault: &InterfaceAccount<'info, TokenAccount>,
+    mint: &InterfaceAccount<'info, Mint>,
+    maker: AccountInfo<'info>,
+    destination: AccountInfo<'info>,
+    token_program: &Interface<'info, TokenInterface>,
+    payouts: &[(AccountInfo<'info>, u64)],
+) -> Result<()> {
+    let namespace = escrow.namespace;
+    let signer_seeds: [&[&[u8]]; 1] = [&[
+        b"escrow",
+        maker.key.as_ref(),
+        namespace_seed(&namespace),
+        &escrow.seed.to_le_bytes()[..],
+        &[escrow.bump],
+    ]];
+
+    for (to, amount) in payouts.iter().filter(|(_, amount)| *amount > 0) {
+        let accounts = TransferChecked {
+            from: vault.to_account_info(),
+            mint: mint.to_account_info(),
+            to: to.clone(),
+            authority: escrow.to_account_info(),
+        };
+        let cpi_ctx =
+            CpiContext::new_with_signer(token_program.to_account_info(), accounts, &signer_seeds);
+        transfer_checked_with_hook(cpi_ctx, *amount, mint.decimals)?;
+    }
+
+    let accounts = CloseAccount {
+        account: vault.to_account_info(),
+        destination,
+        authority: escrow.to_account_info(),
+    };
+    close_account(CpiContext::new_with_signer(
+        token_program.to_account_info(),
+        accounts,
+        &signer_seeds,
+    ))
+}
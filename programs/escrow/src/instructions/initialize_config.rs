@@ -0,0 +1,53 @@
+use anchor_lang::prelude::*;
+
+use crate::{Config, Stats, FEE_BPS};
+
+// the config is a singleton, a second initialize fails because the account already exists.
+// the stats account is created along with it
+#[derive(Accounts)]
+pub struct InitializeConfig<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        init,
+        payer = admin,
+        seeds = [b"config"],
+        space = 8 + Config::INIT_SPACE,
+        bump,
+    )]
+    pub config: Account<'info, Config>,
+
+    #[account(
+        init,
+        payer = admin,
+        seeds = [b"stats"],
+        space = 8 + Stats::INIT_SPACE,
+        bump,
+    )]
+    pub stats: Account<'info, Stats>,
+
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> InitializeConfig<'info> {
+    pub fn initialize_config(
+        &mut self,
+        fee_collector: Pubkey,
+        bumps: &InitializeConfigBumps,
+    ) -> Result<()> {
+        self.config.set_inner(Config {
+            admin: self.admin.key(),
+            fee_collector,
+            bump: bumps.config,
+            fee_bps: FEE_BPS,
+            paused: false,
+            pending_admin: Pubkey::default(),
+        });
+        self.stats.set_inner(Stats {
+            bump: bumps.stats,
+            ..Default::default()
+        });
+        Ok(())
+    }
+}
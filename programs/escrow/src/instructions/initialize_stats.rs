@@ -0,0 +1,32 @@
+use anchor_lang::prelude::*;
+
+use crate::Stats;
+
+// creates the stats account for a config initialized before stats existed. anyone can pay
+// for it, a second call fails because the account already exists
+#[derive(Accounts)]
+pub struct InitializeStats<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        init,
+        payer = payer,
+        seeds = [b"stats"],
+        space = 8 + Stats::INIT_SPACE,
+        bump,
+    )]
+    pub stats: Account<'info, Stats>,
+
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> InitializeStats<'info> {
+    pub fn initialize_stats(&mut self, bumps: &InitializeStatsBumps) -> Result<()> {
+        self.stats.set_inner(Stats {
+            bump: bumps.stats,
+            ..Default::default()
+        });
+        Ok(())
+    }
+}
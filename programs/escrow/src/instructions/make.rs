@@ -2,68 +2,471 @@ use anchor_lang::prelude::*;
 
 use anchor_spl::{
     associated_token::AssociatedToken,
-    token_interface::{transfer_checked, Mint, TokenAccount, TokenInterface, TransferChecked},
+    token::spl_token::native_mint,
+    token_interface::{Mint, TokenAccount, TokenInterface, TransferChecked},
 };
 
 // crate is wrap modules.
-use crate::Escrow;
+use crate::basket::{BasketAccounts, BASKET_LEG};
+use crate::error::ErrorCode;
+use crate::extensions::{check_mint_extensions, transfer_checked_with_hook, transfer_fee};
+use crate::metadata::{check_metadata, token_standard, PROGRAMMABLE_NON_FUNGIBLE};
+use crate::native::wrap_sol;
+use crate::pnft::{transfer_pnft, PnftAccounts, PnftTransfer};
+use crate::state::{memo_str, namespace_seed, unit_payment, EscrowKind, PriceMode};
+use crate::{
+    is_paused, Escrow, MakerIndex, Stats, ESCROW_VERSION, MAX_BASKET_LEGS, MAX_PAYMENT_OPTIONS,
+    MAX_SPREAD_BPS,
+};
+
+// optional terms of an escrow. the defaults create an escrow anyone can take at any time
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Default)]
+pub struct MakeOptions {
+    // unix timestamp after which the escrow can no longer be taken. 0 means no expiry
+    pub expiry: i64,
+    // reserve the escrow for a single counterparty
+    pub allowed_taker: Option<Pubkey>,
+    // up to MAX_WHITELIST takers allowed to fill the escrow, empty means anyone
+    pub whitelist: Vec<Pubkey>,
+    // dutch auction, the price falls linearly from receive to floor_receive over
+    // decay_duration seconds from start_time. a zero duration keeps the price fixed
+    pub floor_receive: u64,
+    // 0 starts the auction when the escrow is made
+    pub start_time: i64,
+    pub decay_duration: i64,
+    // smallest partial fill in mint_a, 0 allows any size
+    pub min_fill: u64,
+    // the taker pays the mint_b transfer fee on top instead of the maker netting less
+    pub gross_up_transfer_fee: bool,
+    // second mint take_alt accepts, receive_c is its price for the whole deposit
+    pub mint_c: Option<Pubkey>,
+    pub receive_c: u64,
+    // mint_b must be wrapped SOL, takers can then pay lamports through take_native
+    pub receive_sol: bool,
+    // commits the maker to leave the deposit in the escrow until this unix timestamp.
+    // 0 allows refunds at any time
+    pub refund_locked_until: i64,
+    // keep the escrow account with its final status instead of closing it once it is
+    // taken or refunded. close_record reclaims the rent later
+    pub keep_record: bool,
+    // sell a single NFT. mint_a must have 0 decimals and a supply of 1, and the deposit
+    // must be 1. make also checks the metadata account if one is passed, which is
+    // required for pNFTs
+    pub nft: bool,
+    // only holders of an NFT verified as part of this collection can take the escrow
+    pub gate_collection: Option<Pubkey>,
+    // only takers holding at least gate_min_balance of this mint can take the escrow
+    pub gate_mint: Option<Pubkey>,
+    pub gate_min_balance: u64,
+    // basket escrow of one leg, deposit_2 of the mint whose leg accounts lead the
+    // remaining accounts, see basket.rs. 0 sells mint_a alone. baskets are only filled whole
+    pub deposit_2: u64,
+    // the taker pays in full and the deposit vests to them over this many seconds from
+    // the take, claimed through claim_vested. 0 hands it over in the take
+    pub vest_duration: i64,
+    // lets the maker take the escrow themselves, which is rejected by default
+    pub allow_self_fill: bool,
+    // gets the rent back when take or refund close the escrow, e.g. a treasury that
+    // funds the maker's listings. None returns it to the maker
+    pub rent_recipient: Option<Pubkey>,
+    // price fills at price_numerator / price_denominator mint_b per mint_a instead of a
+    // receive for the whole deposit. both count base units, so 1.5 mint_b per mint_a with
+    // 9 and 6 decimals is 1_500_000_000 / 1_000_000. make then ignores receive, 0 for both
+    // prices the whole deposit at receive
+    pub price_numerator: u64,
+    pub price_denominator: u64,
+    // Pyth price feed that prices the deposit at each take, see Escrow::oracle. the feed
+    // quotes whole mint_b per whole mint_a, e.g. a token in USDC. receive only stands in
+    // until a take prices the escrow
+    pub oracle: Option<Pubkey>,
+    pub spread_bps: u16,
+    pub max_price_age: u64,
+    pub max_conf_bps: u16,
+    // AMM pool that prices the deposit at each take, see Escrow::pool. the minimum
+    // reserves keep thin pools that are cheap to move from setting the price. receive
+    // only stands in until a take prices the escrow.
+    // a spot price has no time averaging: a taker can move the pool within the take's
+    // transaction and buy at that price, paying only the pool's swap fees. set the
+    // reserves well above what the deposit is worth and a premium that covers the fees,
+    // and prefer an oracle where the pair has one
+    pub pool: Option<Pubkey>,
+    pub pool_program: Pubkey,
+    pub pool_premium_bps: i16,
+    pub min_reserve_a: u64,
+    pub min_reserve_b: u64,
+    // up to MAX_PAYMENT_OPTIONS more mints takers can pay in through take_with_payment,
+    // each at its own receive for the whole deposit, e.g. USDC and USDT next to SOL
+    pub payment_options: Vec<PaymentOption>,
+    // pay the maker's share into the escrow's mint_b account, swept by claim_payment, so
+    // fills don't depend on the maker's own account, e.g. for a DAO
+    pub receive_to_vault: bool,
+    // goes into the escrow's seeds next to the maker and the seed, so escrows of different
+    // front-ends never collide on an address. all zeros leaves it out, see namespace_seed
+    pub namespace: [u8; 8],
+    // the maker can never reprice the escrow below this receive, which bounds what a
+    // leaked maker key can give away. 0 leaves the price unbounded
+    pub min_receive_floor: u64,
+    // label shown by front-ends, e.g. "OTC deal #42". UTF-8 padded with trailing zeros,
+    // all zeros for no memo
+    pub memo: [u8; 32],
+    // Pyth feed id of `oracle`, e.g. SOL/USD, required with it
+    pub oracle_feed_id: [u8; 32],
+}
+
+// a mint take_with_payment accepts and its price for the whole deposit
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Default)]
+pub struct PaymentOption {
+    pub mint: Pubkey,
+    pub receive: u64,
+}
+
+// an escrow has to offer something and ask for something, zero on either side is spam
+pub fn check_terms(receive: u64, deposit: u64) -> Result<()> {
+    require!(deposit > 0, ErrorCode::ZeroDeposit);
+    require!(receive > 0, ErrorCode::ZeroReceive);
+    Ok(())
+}
+
+// the metadata account is optional, but if given it has to be the mint's Metaplex
+// metadata: the PDA of the mint, owned by the metadata program and naming the mint
+pub fn check_nft(
+    mint: &InterfaceAccount<Mint>,
+    deposit: u64,
+    metadata: Option<&UncheckedAccount>,
+) -> Result<()> {
+    require!(
+        mint.decimals == 0 && mint.supply == 1 && deposit == 1,
+        ErrorCode::NotAnNft
+    );
+
+    if let Some(metadata) = metadata {
+        check_metadata(metadata, &mint.key())?;
+        // key byte, update authority, then the mint
+        let data = metadata.try_borrow_data()?;
+        require!(
+            data.len() >= 65 && data[33..65] == mint.key().to_bytes(),
+            ErrorCode::InvalidMetadata
+        );
+    }
+    Ok(())
+}
+
+impl MakeOptions {
+    // make_basket takes the default options apart from the namespace
+    pub fn check_basket(&self) -> Result<()> {
+        let defaults = MakeOptions {
+            namespace: self.namespace,
+            ..Default::default()
+        };
+        require!(*self == defaults, ErrorCode::InvalidBasketOptions);
+        Ok(())
+    }
+
+    pub fn is_unit_priced(&self) -> bool {
+        self.price_numerator != 0 || self.price_denominator != 0
+    }
+
+    pub fn apply(&self, escrow: &mut Escrow) -> Result<()> {
+        escrow.expiry = self.expiry;
+        escrow.allowed_taker = self.allowed_taker.unwrap_or_default();
+        escrow.set_whitelist(&self.whitelist)?;
+        escrow.min_fill = self.min_fill;
+        escrow.gross_up_transfer_fee = self.gross_up_transfer_fee;
+        escrow.refund_locked_until = self.refund_locked_until;
+        escrow.keep_record = self.keep_record;
+        escrow.allow_self_fill = self.allow_self_fill;
+        escrow.rent_recipient = self.rent_recipient.unwrap_or_default();
+
+        if let Some(oracle) = self.oracle {
+            require!(
+                oracle != Pubkey::default()
+                    && self.oracle_feed_id != [0; 32]
+                    && self.max_price_age > 0
+                    && self.spread_bps <= MAX_SPREAD_BPS
+                    && self.decay_duration == 0
+                    && !self.is_unit_priced()
+                    && !self.receive_sol,
+                ErrorCode::InvalidOracle
+            );
+            escrow.oracle = oracle;
+            escrow.oracle_feed_id = self.oracle_feed_id;
+            escrow.spread_bps = self.spread_bps;
+            escrow.max_price_age = self.max_price_age;
+            escrow.max_conf_bps = self.max_conf_bps;
+        }
+
+        if let Some(pool) = self.pool {
+            require!(
+                pool != Pubkey::default()
+                    && self.pool_program != Pubkey::default()
+                    && self.min_reserve_a > 0
+                    && self.min_reserve_b > 0
+                    && self.pool_premium_bps.unsigned_abs() < MAX_SPREAD_BPS
+                    && self.oracle.is_none()
+                    && self.decay_duration == 0
+                    && !self.is_unit_priced()
+                    && !self.receive_sol,
+                ErrorCode::InvalidPool
+            );
+            escrow.pool = pool;
+            escrow.pool_program = self.pool_program;
+            escrow.pool_premium_bps = self.pool_premium_bps;
+            escrow.min_reserve_a = self.min_reserve_a;
+            escrow.min_reserve_b = self.min_reserve_b;
+        }
+
+        if self.is_unit_priced() {
+            // an auction moves the price of the whole deposit, which a unit price fixes
+            require!(self.decay_duration == 0, ErrorCode::InvalidUnitPrice);
+            escrow.price_mode = PriceMode::PerUnit;
+            escrow.price_numerator = self.price_numerator;
+            escrow.price_denominator = self.price_denominator;
+            escrow.sync_unit_price()?;
+        }
+        escrow.gate_collection = self.gate_collection.unwrap_or_default();
+
+        if self.vest_duration != 0 {
+            // take_native and make_offer release the deposit in one go
+            require!(
+                self.vest_duration > 0 && !self.nft && !self.receive_sol && self.deposit_2 == 0,
+                ErrorCode::InvalidVesting
+            );
+            escrow.vest_duration = self.vest_duration;
+        }
+
+        if let Some(gate_mint) = self.gate_mint {
+            // a zero minimum would let anyone with an empty account through
+            require!(self.gate_min_balance > 0, ErrorCode::ZeroAmount);
+            escrow.gate_mint = gate_mint;
+            escrow.gate_min_balance = self.gate_min_balance;
+        }
+
+        if let Some(mint_c) = self.mint_c {
+            require!(self.receive_c > 0, ErrorCode::ZeroReceive);
+            require!(
+                mint_c != escrow.mint_a && mint_c != escrow.mint_b,
+                ErrorCode::IdenticalMints
+            );
+            escrow.mint_c = mint_c;
+            escrow.receive_c = self.receive_c;
+        }
+
+        require!(
+            self.payment_options.len() <= MAX_PAYMENT_OPTIONS
+                && self.payment_options.iter().all(|option| option.receive > 0),
+            ErrorCode::InvalidPaymentOptions
+        );
+        for (i, option) in self.payment_options.iter().enumerate() {
+            require!(
+                option.mint != escrow.mint_a
+                    && option.mint != escrow.mint_b
+                    && option.mint != escrow.mint_c
+                    && !escrow.payment_mints[..i].contains(&option.mint),
+                ErrorCode::IdenticalMints
+            );
+            escrow.payment_mints[i] = option.mint;
+            escrow.payment_receives[i] = option.receive;
+        }
+        escrow.payment_len = self.payment_options.len() as u8;
+
+        // take_native pays the maker's wallet directly
+        require!(
+            !self.receive_to_vault || !self.receive_sol,
+            ErrorCode::PaymentVaultUnsupported
+        );
+        escrow.receive_to_vault = self.receive_to_vault;
+
+        // claim_payment finds the escrow by maker and seed alone
+        require!(
+            self.namespace == [0; 8] || !self.receive_to_vault,
+            ErrorCode::NamespaceUnsupported
+        );
+        escrow.namespace = self.namespace;
+
+        require!(
+            self.min_receive_floor <= escrow.receive,
+            ErrorCode::BelowPriceFloor
+        );
+        escrow.min_receive_floor = self.min_receive_floor;
+
+        require!(memo_str(&self.memo).is_some(), ErrorCode::InvalidMemo);
+        escrow.memo = self.memo;
+
+        if self.receive_sol {
+            require!(escrow.mint_b == native_mint::ID, ErrorCode::MintBNotNative);
+            escrow.receive_is_sol = true;
+        }
+
+        if self.decay_duration != 0 {
+            require!(
+                self.decay_duration > 0
+                    && self.floor_receive > 0
+                    && self.floor_receive <= escrow.receive,
+                ErrorCode::InvalidAuction
+            );
+            escrow.start_receive = escrow.receive;
+            escrow.floor_receive = self.floor_receive;
+            escrow.start_time = match self.start_time {
+                0 => Clock::get()?.unix_timestamp,
+                start_time => start_time,
+            };
+            escrow.decay_duration = self.decay_duration;
+        }
+        Ok(())
+    }
+}
 
+#[event_cpi]
 #[derive(Accounts)]
-// instruction seed is used to create a unique escrow account for each transaction
-#[instruction(seed: u64)]
+// instruction seed is used to create a unique escrow account for each transaction. the
+// namespace of the options goes into the escrow's seeds along with it
+#[instruction(seed: u64, _receive: u64, _deposit: u64, options: MakeOptions)]
 pub struct Make<'info> {
     #[account(mut)]
     pub maker: Signer<'info>,
 
+    // new escrows can't be made while the protocol is paused. unchecked rather than an
+    // Account so makes keep working on a deployment that hasn't initialized its config,
+    // but the PDA is always passed and the pause holds as soon as the config exists
+    /// CHECK: must be the config PDA, is_paused reads the pause off its data
+    #[account(
+        seeds = [b"config"],
+        bump,
+        constraint = !is_paused(&config) @ ErrorCode::ProtocolPaused,
+    )]
+    pub config: UncheckedAccount<'info>,
+
+    // counts the escrow as made
+    #[account(
+        mut,
+        seeds = [b"stats"],
+        bump = stats.bump,
+    )]
+    pub stats: Option<Account<'info, Stats>>,
+
     // mint::token_program is used to verify that the mint accounts are owned by the SPL Token program
     // forgery token accounts are not possible
     #[account(
-        mint::token_program = token_program,
+        mint::token_program = token_program_a,
     )]
     // it verifies program ownership and deserializes the underlying data into a Rust type
     // We can use the InterfaceAccount wrapper with the Mint or TokenAccount types from the anchor_spl::token_interface crate we mentioned.
     pub mint_a: InterfaceAccount<'info, Mint>,
+    // swapping a mint for itself makes no sense and would let the vault and payment collapse into one mint
     #[account(
-        mint::token_program = token_program,
+        mint::token_program = token_program_b,
+        constraint = mint_b.key() != mint_a.key() @ ErrorCode::IdenticalMints,
     )]
     pub mint_b: InterfaceAccount<'info, Mint>,
 
     // if you want to create ata, you use associated_token::constraints
     // existing ATA is used, so it is not init
+    // for wrapped SOL mint_a it may hold less than the deposit, see wrap_shortfall
     #[account(
         mut,
         associated_token::mint = mint_a, // mint account
         associated_token::authority = maker, // Sets the authority (owner) of the token account who has permission to transfer or burn tokens.
-        associated_token::token_program = token_program,
+        associated_token::token_program = token_program_a,
     )]
     pub maker_ata_a: InterfaceAccount<'info, TokenAccount>,
     // https://www.anchor-lang.com/docs/tokens/basics/create-token-account#associated_token-constraints
     #[account(
         init,
         payer = maker,
-        seeds = [b"escrow", maker.key().as_ref(), seed.to_le_bytes().as_ref()],
+        seeds = [
+            b"escrow",
+            maker.key().as_ref(),
+            namespace_seed(&options.namespace),
+            seed.to_le_bytes().as_ref(),
+        ],
         space = 8 + Escrow::INIT_SPACE,
         bump,
     )]
     pub escrow: Account<'info, Escrow>,
 
-    // vault is escrow's token account. escrow account holds the tokens deposited by the maker
+    // vault is escrow's token account. escrow account holds the tokens deposited by the maker.
+    // anyone can create the ATA ahead of make, so an existing one is taken as it is. tokens
+    // already in it go back to the maker with the last take or the refund
     #[account(
-        init,
+        init_if_needed,
         payer = maker,
         associated_token::mint = mint_a,
         associated_token::authority = escrow,
-        associated_token::token_program = token_program,
+        associated_token::token_program = token_program_a,
     )]
     pub vault: InterfaceAccount<'info, TokenAccount>,
 
+    // Metaplex metadata of mint_a, only read for NFT escrows. its token standard tells
+    // pNFTs apart, they need the accounts in pnft::PnftAccounts as remaining accounts
+    /// CHECK: checked against the metadata PDA of mint_a in check_nft
+    pub metadata: Option<UncheckedAccount<'info>>,
+
+    // alternative payment mint, only passed when MakeOptions::mint_c is set so its
+    // extensions are checked like mint_b's
+    pub mint_c: Option<InterfaceAccount<'info, Mint>>,
+
+    // the maker's index of open escrows, created on first use
+    #[account(
+        init_if_needed,
+        payer = maker,
+        space = 8 + MakerIndex::INIT_SPACE,
+        seeds = [b"index", maker.key().as_ref()],
+        bump,
+    )]
+    pub maker_index: Account<'info, MakerIndex>,
+
     pub associated_token_program: Program<'info, AssociatedToken>,
-    pub token_program: Interface<'info, TokenInterface>,
+    // the mints may live under different token programs, e.g. a legacy mint_a and a Token-2022 mint_b
+    pub token_program_a: Interface<'info, TokenInterface>,
+    pub token_program_b: Interface<'info, TokenInterface>,
     pub system_program: Program<'info, System>,
 }
 
 impl<'info> Make<'info> {
-    pub fn init_escrow(&mut self, seed: u64, receive: u64, bumps: &MakeBumps) -> Result<()> {
+    pub fn init_escrow(
+        &mut self,
+        seed: u64,
+        receive: u64,
+        deposit: u64,
+        options: &MakeOptions,
+        bumps: &MakeBumps,
+    ) -> Result<()> {
+        // a unit priced escrow works out the price of the whole deposit itself
+        let receive = if options.is_unit_priced() {
+            unit_payment(deposit, options.price_numerator, options.price_denominator)?
+        } else {
+            receive
+        };
+        check_terms(receive, deposit)?;
+        check_mint_extensions(&self.mint_a.to_account_info())?;
+        check_mint_extensions(&self.mint_b.to_account_info())?;
+        match (options.mint_c, &self.mint_c) {
+            (Some(key), Some(mint_c)) if mint_c.key() == key => {
+                check_mint_extensions(&mint_c.to_account_info())?
+            }
+            (None, None) => {}
+            _ => return Err(ErrorCode::MintCMismatch.into()),
+        }
+        let kind = if options.nft {
+            check_nft(&self.mint_a, deposit, self.metadata.as_ref())?;
+            match &self.metadata {
+                Some(metadata) if token_standard(metadata)? == Some(PROGRAMMABLE_NON_FUNGIBLE) => {
+                    EscrowKind::ProgrammableNft
+                }
+                _ => EscrowKind::Nft,
+            }
+        } else {
+            EscrowKind::Fungible
+        };
+        // take_native and withdraw only move plain tokens
+        require!(
+            kind != EscrowKind::ProgrammableNft || !options.receive_sol,
+            ErrorCode::ProgrammableNftUnsupported
+        );
+        require!(
+            options.deposit_2 == 0 || (!options.nft && !options.receive_sol),
+            ErrorCode::BasketUnsupported
+        );
         // set_innter is used to set the inner data of the escrow account
         self.escrow.set_inner(Escrow {
             seed,
@@ -72,11 +475,52 @@ impl<'info> Make<'info> {
             mint_b: self.mint_b.key(),
             receive,
             bump: bumps.escrow,
+            deposit,
+            remaining: deposit,
+            remaining_receive: receive,
+            active: true,
+            created_slot: Clock::get()?.slot,
+            version: ESCROW_VERSION,
+            kind,
+            ..Default::default()
         });
-        Ok(())
+        options.apply(&mut self.escrow)?;
+        if let Some(stats) = &mut self.stats {
+            stats.record_make()?;
+        }
+        self.maker_index
+            .list(self.maker.key(), bumps.maker_index, &self.escrow)
+    }
+
+    // when mint_a is wrapped SOL the maker doesn't have to wrap beforehand, whatever their
+    // ATA lacks of the deposit is wrapped from their lamports
+    pub fn wrap_shortfall(&mut self, deposit: u64) -> Result<()> {
+        if self.mint_a.key() != native_mint::ID || self.maker_ata_a.amount >= deposit {
+            return Ok(());
+        }
+        wrap_sol(
+            self.maker.to_account_info(),
+            self.maker_ata_a.to_account_info(),
+            deposit - self.maker_ata_a.amount,
+            self.system_program.to_account_info(),
+            self.token_program_a.to_account_info(),
+        )
     }
 
-    pub fn deposit(&mut self, deposit: u64) -> Result<()> {
+    // a transfer fee mint delivers less than `deposit` to the vault, the escrow only
+    // offers what actually arrived. returns that amount
+    // `extra` holds the accounts a transfer hook on mint_a needs, or the pNFT accounts
+    pub fn deposit(&mut self, deposit: u64, extra: &[AccountInfo<'info>]) -> Result<u64> {
+        // fails here rather than in the token program. wrap_shortfall tops up wrapped SOL
+        require!(
+            self.mint_a.key() == native_mint::ID || self.maker_ata_a.amount >= deposit,
+            ErrorCode::InsufficientMakerFunds
+        );
+        if self.escrow.is_programmable() {
+            return self.deposit_pnft(deposit, extra);
+        }
+        self.wrap_shortfall(deposit)?;
+
         // Transfer is deprecated, use transfer_checked instead in token 2022
         let transfer_accounts = TransferChecked {
             from: self.maker_ata_a.to_account_info(),
@@ -85,8 +529,91 @@ impl<'info> Make<'info> {
             authority: self.maker.to_account_info(),
         };
 
-        let cpi_ctx = CpiContext::new(self.token_program.to_account_info(), transfer_accounts);
-        transfer_checked(cpi_ctx, deposit, self.mint_a.decimals)?;
-        Ok(())
+        let cpi_ctx = CpiContext::new(self.token_program_a.to_account_info(), transfer_accounts)
+            .with_remaining_accounts(extra.to_vec());
+        transfer_checked_with_hook(cpi_ctx, deposit, self.mint_a.decimals)?;
+
+        let received = deposit - transfer_fee(&self.mint_a.to_account_info(), deposit)?;
+        require!(received > 0, ErrorCode::ZeroDeposit);
+        self.escrow.deposit = received;
+        self.escrow.remaining = received;
+        self.escrow.sync_unit_price()?;
+        // a vesting escrow has a single taker, so only whole fills pass
+        if self.escrow.vests_deposit() {
+            self.escrow.min_fill = received;
+        }
+        Ok(received)
+    }
+    // moves the legs of make_basket, or make's deposit_2, into vaults of their own.
+    // `extra` starts with the leg accounts, see basket.rs. returns what arrived of each leg
+    pub fn deposit_legs(&mut self, legs: &[u64], extra: &[AccountInfo<'info>]) -> Result<Vec<u64>> {
+        require!(
+            (1..=MAX_BASKET_LEGS).contains(&legs.len()),
+            ErrorCode::BasketTooLarge
+        );
+        require!(
+            extra.len() >= BASKET_LEG * legs.len(),
+            ErrorCode::BasketLegMismatch
+        );
+        let mints: Vec<Pubkey> = extra
+            .chunks_exact(BASKET_LEG)
+            .take(legs.len())
+            .map(|leg| leg[0].key())
+            .collect();
+        for (i, mint) in mints.iter().enumerate() {
+            require!(
+                *mint != self.mint_a.key()
+                    && *mint != self.mint_b.key()
+                    && !mints[..i].contains(mint),
+                ErrorCode::IdenticalMints
+            );
+        }
+
+        let accounts = self.basket_accounts();
+        let mut received = Vec::with_capacity(legs.len());
+        for (i, (leg, amount)) in accounts.legs(&mints, extra)?.iter().zip(legs).enumerate() {
+            require!(*amount > 0, ErrorCode::ZeroDeposit);
+            check_mint_extensions(&leg.mint)?;
+            accounts.deposit(leg, *amount, extra)?;
+            let arrived = amount - transfer_fee(&leg.mint, *amount)?;
+            require!(arrived > 0, ErrorCode::ZeroDeposit);
+            self.escrow.basket_mints[i] = leg.mint.key();
+            self.escrow.basket_amounts[i] = arrived;
+            received.push(arrived);
+        }
+        self.escrow.basket_len = legs.len() as u8;
+        // partial fills would have to split the legs too, so only whole fills pass
+        self.escrow.min_fill = self.escrow.deposit;
+        Ok(received)
+    }
+
+    fn basket_accounts(&self) -> BasketAccounts<'info> {
+        BasketAccounts {
+            escrow: self.escrow.to_account_info(),
+            owner: self.maker.to_account_info(),
+            payer: self.maker.to_account_info(),
+            token_program: self.token_program_a.to_account_info(),
+            associated_token_program: self.associated_token_program.to_account_info(),
+            system_program: self.system_program.to_account_info(),
+        }
+    }
+
+    // pNFTs have no transfer fee, the whole deposit arrives
+    fn deposit_pnft(&mut self, deposit: u64, extra: &[AccountInfo<'info>]) -> Result<u64> {
+        let pnft = PnftAccounts::from_remaining(extra, &self.mint_a.key())?;
+        let transfer = PnftTransfer {
+            token: self.maker_ata_a.to_account_info(),
+            token_owner: self.maker.to_account_info(),
+            destination: self.vault.to_account_info(),
+            destination_owner: self.escrow.to_account_info(),
+            mint: self.mint_a.to_account_info(),
+            authority: self.maker.to_account_info(),
+            payer: self.maker.to_account_info(),
+            system_program: self.system_program.to_account_info(),
+            token_program: self.token_program_a.to_account_info(),
+            associated_token_program: self.associated_token_program.to_account_info(),
+        };
+        transfer_pnft(transfer, &pnft, deposit, &[])?;
+        Ok(deposit)
     }
 }
@@ -0,0 +1,158 @@
+use anchor_lang::prelude::*;
+
+use anchor_spl::{
+    associated_token::AssociatedToken,
+    token_interface::{Mint, TokenAccount, TokenInterface, TransferChecked},
+};
+
+use crate::error::ErrorCode;
+use crate::extensions::{check_mint_extensions, transfer_checked_with_hook, transfer_fee};
+use crate::{check_terms, is_paused, Escrow, MakerIndex, MakerNonce, Stats, ESCROW_VERSION};
+
+// make with the seed taken from the maker's nonce instead of picked by the client, so
+// makes never collide on a seed. the escrow gets the default MakeOptions
+#[event_cpi]
+#[derive(Accounts)]
+pub struct MakeAuto<'info> {
+    #[account(mut)]
+    pub maker: Signer<'info>,
+
+    // checked for the pause the same way as Make's
+    /// CHECK: must be the config PDA, is_paused reads the pause off its data
+    #[account(
+        seeds = [b"config"],
+        bump,
+        constraint = !is_paused(&config) @ ErrorCode::ProtocolPaused,
+    )]
+    pub config: UncheckedAccount<'info>,
+
+    // counts the escrow as made
+    #[account(
+        mut,
+        seeds = [b"stats"],
+        bump = stats.bump,
+    )]
+    pub stats: Option<Account<'info, Stats>>,
+
+    #[account(
+        init_if_needed,
+        payer = maker,
+        space = 8 + MakerNonce::INIT_SPACE,
+        seeds = [b"nonce", maker.key().as_ref()],
+        bump,
+    )]
+    pub maker_nonce: Account<'info, MakerNonce>,
+
+    #[account(
+        mint::token_program = token_program_a,
+    )]
+    pub mint_a: InterfaceAccount<'info, Mint>,
+    #[account(
+        mint::token_program = token_program_b,
+        constraint = mint_b.key() != mint_a.key() @ ErrorCode::IdenticalMints,
+    )]
+    pub mint_b: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint_a,
+        associated_token::authority = maker,
+        associated_token::token_program = token_program_a,
+    )]
+    pub maker_ata_a: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        init,
+        payer = maker,
+        seeds = [b"escrow", maker.key().as_ref(), maker_nonce.next_seed.to_le_bytes().as_ref()],
+        space = 8 + Escrow::INIT_SPACE,
+        bump,
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    // an existing vault is taken as it is, like in Make
+    #[account(
+        init_if_needed,
+        payer = maker,
+        associated_token::mint = mint_a,
+        associated_token::authority = escrow,
+        associated_token::token_program = token_program_a,
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    // the maker's index of open escrows, created on first use
+    #[account(
+        init_if_needed,
+        payer = maker,
+        space = 8 + MakerIndex::INIT_SPACE,
+        seeds = [b"index", maker.key().as_ref()],
+        bump,
+    )]
+    pub maker_index: Account<'info, MakerIndex>,
+
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub token_program_a: Interface<'info, TokenInterface>,
+    pub token_program_b: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> MakeAuto<'info> {
+    // writes the escrow at the nonce's seed, counts the nonce up and moves the deposit in.
+    // returns (seed, what arrived of the deposit)
+    pub fn make(
+        &mut self,
+        receive: u64,
+        deposit: u64,
+        bumps: &MakeAutoBumps,
+        extra: &[AccountInfo<'info>],
+    ) -> Result<(u64, u64)> {
+        check_terms(receive, deposit)?;
+        require!(
+            self.maker_ata_a.amount >= deposit,
+            ErrorCode::InsufficientMakerFunds
+        );
+        check_mint_extensions(&self.mint_a.to_account_info())?;
+        check_mint_extensions(&self.mint_b.to_account_info())?;
+
+        let seed = self.maker_nonce.next_seed;
+        self.maker_nonce.set_inner(MakerNonce {
+            maker: self.maker.key(),
+            next_seed: seed.checked_add(1).ok_or(ErrorCode::ArithmeticOverflow)?,
+            bump: bumps.maker_nonce,
+        });
+
+        let accounts = TransferChecked {
+            from: self.maker_ata_a.to_account_info(),
+            mint: self.mint_a.to_account_info(),
+            to: self.vault.to_account_info(),
+            authority: self.maker.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new(self.token_program_a.to_account_info(), accounts)
+            .with_remaining_accounts(extra.to_vec());
+        transfer_checked_with_hook(cpi_ctx, deposit, self.mint_a.decimals)?;
+        let received = deposit - transfer_fee(&self.mint_a.to_account_info(), deposit)?;
+        require!(received > 0, ErrorCode::ZeroDeposit);
+
+        self.escrow.set_inner(Escrow {
+            seed,
+            maker: self.maker.key(),
+            mint_a: self.mint_a.key(),
+            mint_b: self.mint_b.key(),
+            receive,
+            bump: bumps.escrow,
+            deposit: received,
+            remaining: received,
+            remaining_receive: receive,
+            active: true,
+            created_slot: Clock::get()?.slot,
+            version: ESCROW_VERSION,
+            ..Default::default()
+        });
+        if let Some(stats) = &mut self.stats {
+            stats.record_make()?;
+        }
+        self.maker_index
+            .list(self.maker.key(), bumps.maker_index, &self.escrow)?;
+        Ok((seed, received))
+    }
+}
@@ -0,0 +1,193 @@
+use anchor_lang::prelude::*;
+
+use anchor_spl::{
+    associated_token::{create_idempotent, AssociatedToken, Create},
+    token_interface::{Mint, TokenAccount, TokenInterface, TransferChecked},
+};
+
+use crate::error::ErrorCode;
+use crate::extensions::{check_mint_extensions, transfer_checked_with_hook, transfer_fee};
+use crate::pda::{escrow_pda, vault_pda};
+use crate::{
+    check_terms, create_pda_account, is_paused, Escrow, MakerIndex, Stats, ESCROW_VERSION,
+    MAX_BATCH,
+};
+
+// make for several escrows on the same pair of mints. the escrows and their vaults come
+// in as remaining accounts, [escrow, vault] per escrow in the order of `seeds`.
+// every escrow gets the default MakeOptions
+#[event_cpi]
+#[derive(Accounts)]
+pub struct MakeBatch<'info> {
+    #[account(mut)]
+    pub maker: Signer<'info>,
+
+    // checked for the pause the same way as Make's
+    /// CHECK: must be the config PDA, is_paused reads the pause off its data
+    #[account(
+        seeds = [b"config"],
+        bump,
+        constraint = !is_paused(&config) @ ErrorCode::ProtocolPaused,
+    )]
+    pub config: UncheckedAccount<'info>,
+
+    // counts every escrow as made
+    #[account(
+        mut,
+        seeds = [b"stats"],
+        bump = stats.bump,
+    )]
+    pub stats: Option<Account<'info, Stats>>,
+
+    #[account(
+        mint::token_program = token_program_a,
+    )]
+    pub mint_a: InterfaceAccount<'info, Mint>,
+    #[account(
+        mint::token_program = token_program_b,
+        constraint = mint_b.key() != mint_a.key() @ ErrorCode::IdenticalMints,
+    )]
+    pub mint_b: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint_a,
+        associated_token::authority = maker,
+        associated_token::token_program = token_program_a,
+    )]
+    pub maker_ata_a: InterfaceAccount<'info, TokenAccount>,
+
+    // the maker's index of open escrows, created on first use
+    #[account(
+        init_if_needed,
+        payer = maker,
+        space = 8 + MakerIndex::INIT_SPACE,
+        seeds = [b"index", maker.key().as_ref()],
+        bump,
+    )]
+    pub maker_index: Account<'info, MakerIndex>,
+
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub token_program_a: Interface<'info, TokenInterface>,
+    pub token_program_b: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+// what make_batch reports for each escrow it made
+pub struct BatchEntry {
+    pub escrow: Pubkey,
+    pub seed: u64,
+    pub deposit: u64,
+    pub receive: u64,
+}
+
+impl<'info> MakeBatch<'info> {
+    pub fn check_batch(
+        &self,
+        seeds: &[u64],
+        receives: &[u64],
+        deposits: &[u64],
+        accounts: &[AccountInfo<'info>],
+    ) -> Result<()> {
+        require!(
+            receives.len() == seeds.len()
+                && deposits.len() == seeds.len()
+                && accounts.len() == 2 * seeds.len(),
+            ErrorCode::BatchLengthMismatch
+        );
+        require!(seeds.len() <= MAX_BATCH, ErrorCode::BatchTooLarge);
+        // a repeated seed would only fail later, when its escrow is created a second time
+        for (i, seed) in seeds.iter().enumerate() {
+            require!(!seeds[..i].contains(seed), ErrorCode::DuplicateBatchSeed);
+        }
+        check_mint_extensions(&self.mint_a.to_account_info())?;
+        check_mint_extensions(&self.mint_b.to_account_info())
+    }
+
+    // does what the account constraints of make do for a single escrow: the escrow PDA is
+    // created and written, the vault ATA created unless it exists, then the deposit moved in.
+    // the escrow is listed in the maker's index last
+    pub fn make_one(
+        &mut self,
+        seed: u64,
+        receive: u64,
+        deposit: u64,
+        escrow: &AccountInfo<'info>,
+        vault: &AccountInfo<'info>,
+        maker_index_bump: u8,
+    ) -> Result<BatchEntry> {
+        check_terms(receive, deposit)?;
+        let maker = self.maker.key();
+        let (escrow_key, bump) = escrow_pda(&maker, &[0; 8], seed);
+        require_keys_eq!(escrow.key(), escrow_key, ErrorCode::InvalidBatchAccount);
+        require_keys_eq!(
+            vault.key(),
+            vault_pda(&escrow_key, &self.mint_a.key(), &self.token_program_a.key()),
+            ErrorCode::InvalidBatchAccount
+        );
+
+        let signer_seeds: [&[&[u8]]; 1] =
+            [&[b"escrow", maker.as_ref(), &seed.to_le_bytes()[..], &[bump]]];
+        create_pda_account(
+            self.maker.to_account_info(),
+            escrow.clone(),
+            8 + Escrow::INIT_SPACE,
+            &crate::ID,
+            &signer_seeds,
+            self.system_program.to_account_info(),
+        )?;
+
+        let accounts = Create {
+            payer: self.maker.to_account_info(),
+            associated_token: vault.clone(),
+            authority: escrow.clone(),
+            mint: self.mint_a.to_account_info(),
+            system_program: self.system_program.to_account_info(),
+            token_program: self.token_program_a.to_account_info(),
+        };
+        create_idempotent(CpiContext::new(
+            self.associated_token_program.to_account_info(),
+            accounts,
+        ))?;
+
+        // a transfer fee mint delivers less than `deposit`, the escrow offers what arrived
+        let accounts = TransferChecked {
+            from: self.maker_ata_a.to_account_info(),
+            mint: self.mint_a.to_account_info(),
+            to: vault.clone(),
+            authority: self.maker.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new(self.token_program_a.to_account_info(), accounts);
+        transfer_checked_with_hook(cpi_ctx, deposit, self.mint_a.decimals)?;
+        let received = deposit - transfer_fee(&self.mint_a.to_account_info(), deposit)?;
+        require!(received > 0, ErrorCode::ZeroDeposit);
+
+        let state = Escrow {
+            seed,
+            maker,
+            mint_a: self.mint_a.key(),
+            mint_b: self.mint_b.key(),
+            receive,
+            bump,
+            deposit: received,
+            remaining: received,
+            remaining_receive: receive,
+            active: true,
+            created_slot: Clock::get()?.slot,
+            version: ESCROW_VERSION,
+            ..Default::default()
+        };
+        state.try_serialize(&mut &mut escrow.try_borrow_mut_data()?[..])?;
+        if let Some(stats) = &mut self.stats {
+            stats.record_make()?;
+        }
+        self.maker_index.list(maker, maker_index_bump, &state)?;
+
+        Ok(BatchEntry {
+            escrow: escrow_key,
+            seed,
+            deposit: received,
+            receive,
+        })
+    }
+}
@@ -0,0 +1,147 @@
+use anchor_lang::prelude::*;
+
+use anchor_lang::system_program::{transfer, Transfer};
+use anchor_spl::token_interface::{Mint, TokenInterface};
+
+use crate::error::ErrorCode;
+use crate::extensions::check_mint_extensions;
+use crate::state::namespace_seed;
+use crate::{check_terms, is_paused, Escrow, MakeOptions, MakerIndex, Stats, ESCROW_VERSION};
+
+// same as make, but the maker deposits native SOL instead of mint_a
+#[derive(Accounts)]
+#[instruction(seed: u64, _receive: u64, _deposit: u64, options: MakeOptions)]
+pub struct MakeSol<'info> {
+    #[account(mut)]
+    pub maker: Signer<'info>,
+
+    // checked for the pause the same way as Make's
+    /// CHECK: must be the config PDA, is_paused reads the pause off its data
+    #[account(
+        seeds = [b"config"],
+        bump,
+        constraint = !is_paused(&config) @ ErrorCode::ProtocolPaused,
+    )]
+    pub config: UncheckedAccount<'info>,
+
+    // counts the escrow as made
+    #[account(
+        mut,
+        seeds = [b"stats"],
+        bump = stats.bump,
+    )]
+    pub stats: Option<Account<'info, Stats>>,
+
+    #[account(
+        mint::token_program = token_program,
+    )]
+    pub mint_b: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        init,
+        payer = maker,
+        seeds = [
+            b"escrow",
+            maker.key().as_ref(),
+            namespace_seed(&options.namespace),
+            seed.to_le_bytes().as_ref(),
+        ],
+        space = 8 + Escrow::INIT_SPACE,
+        bump,
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    // system owned account without data. only the program can sign for it,
+    // so the lamports can only leave through take_sol or refund_sol
+    #[account(
+        mut,
+        seeds = [b"sol_vault", escrow.key().as_ref()],
+        bump,
+    )]
+    pub sol_vault: SystemAccount<'info>,
+
+    // the maker's index of open escrows, created on first use
+    #[account(
+        init_if_needed,
+        payer = maker,
+        space = 8 + MakerIndex::INIT_SPACE,
+        seeds = [b"index", maker.key().as_ref()],
+        bump,
+    )]
+    pub maker_index: Account<'info, MakerIndex>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> MakeSol<'info> {
+    pub fn init_escrow(
+        &mut self,
+        seed: u64,
+        receive: u64,
+        deposit: u64,
+        options: &MakeOptions,
+        bumps: &MakeSolBumps,
+    ) -> Result<()> {
+        check_terms(receive, deposit)?;
+        check_mint_extensions(&self.mint_b.to_account_info())?;
+        // take_alt and take_with_payment only settle token escrows
+        require!(
+            options.mint_c.is_none() && options.payment_options.is_empty(),
+            ErrorCode::UnsupportedPaymentMint
+        );
+        // take_sol pays the maker's own account
+        require!(
+            !options.receive_to_vault,
+            ErrorCode::PaymentVaultUnsupported
+        );
+        // take_native releases mint_a from a token vault, SOL deposits trade SOL for mint_b
+        require!(!options.receive_sol, ErrorCode::UnsupportedPaymentMint);
+        require!(!options.nft, ErrorCode::NotAnNft);
+        // take_sol has no accounts to prove a collection holding
+        require!(
+            options.gate_collection.is_none(),
+            ErrorCode::TakerNotCollectionHolder
+        );
+        require!(options.gate_mint.is_none(), ErrorCode::InvalidGateAccount);
+        require!(options.deposit_2 == 0, ErrorCode::BasketUnsupported);
+        require!(options.vest_duration == 0, ErrorCode::VestingUnsupported);
+        require!(!options.is_unit_priced(), ErrorCode::UnitPriceUnsupported);
+        require!(
+            options.oracle.is_none() && options.pool.is_none(),
+            ErrorCode::OracleUnsupported
+        );
+        // mint_a stays Pubkey::default(), deposit_is_sol marks the SOL side
+        self.escrow.set_inner(Escrow {
+            seed,
+            maker: self.maker.key(),
+            mint_b: self.mint_b.key(),
+            receive,
+            bump: bumps.escrow,
+            deposit,
+            remaining: deposit,
+            remaining_receive: receive,
+            active: true,
+            created_slot: Clock::get()?.slot,
+            version: ESCROW_VERSION,
+            deposit_is_sol: true,
+            ..Default::default()
+        });
+        options.apply(&mut self.escrow)?;
+        if let Some(stats) = &mut self.stats {
+            stats.record_make()?;
+        }
+        self.maker_index
+            .list(self.maker.key(), bumps.maker_index, &self.escrow)
+    }
+
+    pub fn deposit(&mut self, deposit: u64) -> Result<()> {
+        let transfer_accounts = Transfer {
+            from: self.maker.to_account_info(),
+            to: self.sol_vault.to_account_info(),
+        };
+
+        let cpi_ctx = CpiContext::new(self.system_program.to_account_info(), transfer_accounts);
+        transfer(cpi_ctx, deposit)
+    }
+}
@@ -0,0 +1,147 @@
+use anchor_lang::prelude::*;
+
+use anchor_spl::{
+    associated_token::AssociatedToken,
+    token::spl_token::native_mint,
+    token_interface::{Mint, TokenAccount, TokenInterface},
+};
+
+use crate::error::ErrorCode;
+use crate::extensions::check_mint_extensions;
+use crate::native::wrap_sol;
+use crate::state::namespace_seed;
+use crate::{check_terms, is_paused, Escrow, MakeOptions, MakerIndex, Stats, ESCROW_VERSION};
+
+// same as make with wrapped SOL as mint_a, but the maker's lamports are wrapped straight
+// into the vault so no wSOL account is needed. the escrow is then taken like any other
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(seed: u64, _receive: u64, _deposit: u64, options: MakeOptions)]
+pub struct MakeWithSol<'info> {
+    #[account(mut)]
+    pub maker: Signer<'info>,
+
+    // checked for the pause the same way as Make's
+    /// CHECK: must be the config PDA, is_paused reads the pause off its data
+    #[account(
+        seeds = [b"config"],
+        bump,
+        constraint = !is_paused(&config) @ ErrorCode::ProtocolPaused,
+    )]
+    pub config: UncheckedAccount<'info>,
+
+    // counts the escrow as made
+    #[account(
+        mut,
+        seeds = [b"stats"],
+        bump = stats.bump,
+    )]
+    pub stats: Option<Account<'info, Stats>>,
+
+    #[account(
+        address = native_mint::ID,
+        mint::token_program = token_program_a,
+    )]
+    pub mint_a: InterfaceAccount<'info, Mint>,
+    #[account(
+        mint::token_program = token_program_b,
+        constraint = mint_b.key() != mint_a.key() @ ErrorCode::IdenticalMints,
+    )]
+    pub mint_b: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        init,
+        payer = maker,
+        seeds = [
+            b"escrow",
+            maker.key().as_ref(),
+            namespace_seed(&options.namespace),
+            seed.to_le_bytes().as_ref(),
+        ],
+        space = 8 + Escrow::INIT_SPACE,
+        bump,
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    // the maker pays the rent exempt minimum on creation, the wrapped amount is
+    // whatever the vault holds on top of it
+    #[account(
+        init,
+        payer = maker,
+        associated_token::mint = mint_a,
+        associated_token::authority = escrow,
+        associated_token::token_program = token_program_a,
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    // the maker's index of open escrows, created on first use
+    #[account(
+        init_if_needed,
+        payer = maker,
+        space = 8 + MakerIndex::INIT_SPACE,
+        seeds = [b"index", maker.key().as_ref()],
+        bump,
+    )]
+    pub maker_index: Account<'info, MakerIndex>,
+
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub token_program_a: Interface<'info, TokenInterface>,
+    pub token_program_b: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> MakeWithSol<'info> {
+    pub fn init_escrow(
+        &mut self,
+        seed: u64,
+        receive: u64,
+        lamports: u64,
+        options: &MakeOptions,
+        bumps: &MakeWithSolBumps,
+    ) -> Result<()> {
+        check_terms(receive, lamports)?;
+        require!(!options.nft, ErrorCode::NotAnNft);
+        // the second basket mint needs make's accounts
+        require!(options.deposit_2 == 0, ErrorCode::BasketUnsupported);
+        // so does mint_c, whose extensions are checked there
+        require!(options.mint_c.is_none(), ErrorCode::MintCMismatch);
+        require!(options.vest_duration == 0, ErrorCode::VestingUnsupported);
+        require!(!options.is_unit_priced(), ErrorCode::UnitPriceUnsupported);
+        require!(
+            options.oracle.is_none() && options.pool.is_none(),
+            ErrorCode::OracleUnsupported
+        );
+        check_mint_extensions(&self.mint_b.to_account_info())?;
+        self.escrow.set_inner(Escrow {
+            seed,
+            maker: self.maker.key(),
+            mint_a: self.mint_a.key(),
+            mint_b: self.mint_b.key(),
+            receive,
+            bump: bumps.escrow,
+            deposit: lamports,
+            remaining: lamports,
+            remaining_receive: receive,
+            active: true,
+            created_slot: Clock::get()?.slot,
+            version: ESCROW_VERSION,
+            ..Default::default()
+        });
+        options.apply(&mut self.escrow)?;
+        if let Some(stats) = &mut self.stats {
+            stats.record_make()?;
+        }
+        self.maker_index
+            .list(self.maker.key(), bumps.maker_index, &self.escrow)
+    }
+
+    pub fn deposit(&mut self, lamports: u64) -> Result<()> {
+        wrap_sol(
+            self.maker.to_account_info(),
+            self.vault.to_account_info(),
+            lamports,
+            self.system_program.to_account_info(),
+            self.token_program_a.to_account_info(),
+        )
+    }
+}
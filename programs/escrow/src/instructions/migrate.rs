@@ -0,0 +1,133 @@
+use anchor_lang::prelude::*;
+
+use anchor_lang::system_program::{transfer, Transfer};
+use anchor_spl::token_interface::TokenAccount;
+
+use crate::error::ErrorCode;
+use crate::pda::vault_pda;
+use crate::state::namespace_seed;
+use crate::{Escrow, ESCROW_VERSION, OFFSET_MAKER, OFFSET_MINT_A, OFFSET_NAMESPACE, OFFSET_SEED};
+
+// ends of the fields older layouts stop short of, discriminator included. fields are only
+// ever appended, so the length of an old escrow tells which of them it has
+const BUMP_AT: usize = 120;
+// the first layout ended with the bump
+const MIN_LEN: usize = BUMP_AT + 1;
+const DEPOSIT_END: usize = 137;
+const ACTIVE_END: usize = 154;
+// the published offsets, as indexes into the data
+const SEED_AT: usize = OFFSET_SEED as usize;
+const MAKER_AT: usize = OFFSET_MAKER as usize;
+const MINT_A_AT: usize = OFFSET_MINT_A as usize;
+const NAMESPACE_AT: usize = OFFSET_NAMESPACE as usize;
+
+// grows an escrow made by an earlier version of the program to the current layout, so
+// Account<Escrow> can load it again. running it on a current escrow does nothing
+#[derive(Accounts)]
+pub struct Migrate<'info> {
+    #[account(mut)]
+    pub maker: Signer<'info>,
+
+    /// CHECK: an escrow of any layout, which Account<Escrow> can't load. the
+    /// discriminator, maker and seeds are checked in migrate
+    #[account(mut, owner = crate::ID)]
+    pub escrow: UncheckedAccount<'info>,
+
+    // only needed for escrows from before partial fills, whose deposit is what the vault holds
+    pub vault: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> Migrate<'info> {
+    // returns the version the escrow had, None when it was already current
+    pub fn migrate(&mut self) -> Result<Option<u8>> {
+        let info = self.escrow.to_account_info();
+        let old_len = info.data_len();
+        let space = 8 + Escrow::INIT_SPACE;
+        let (mint_a, seed, bump) = {
+            let data = info.try_borrow_data()?;
+            require!(
+                (MIN_LEN..=space).contains(&old_len) && data[..8] == *Escrow::DISCRIMINATOR,
+                ErrorCode::InvalidEscrowLayout
+            );
+            require!(
+                data[MAKER_AT..MINT_A_AT] == self.maker.key().to_bytes(),
+                anchor_lang::error::ErrorCode::ConstraintHasOne
+            );
+            (
+                Pubkey::try_from(&data[MINT_A_AT..MINT_A_AT + 32]).unwrap(),
+                u64::from_le_bytes(data[SEED_AT..MAKER_AT].try_into().unwrap()),
+                data[BUMP_AT],
+            )
+        };
+        // escrows from version 6 on have a namespace, older ones sit at the zero namespace
+        let namespace: [u8; 8] = if old_len >= NAMESPACE_AT + 8 {
+            info.try_borrow_data()?[NAMESPACE_AT..NAMESPACE_AT + 8]
+                .try_into()
+                .unwrap()
+        } else {
+            [0; 8]
+        };
+        let escrow_key = Pubkey::create_program_address(
+            &[
+                b"escrow",
+                self.maker.key().as_ref(),
+                namespace_seed(&namespace),
+                &seed.to_le_bytes(),
+                &[bump],
+            ],
+            &crate::ID,
+        )
+        .map_err(|_| anchor_lang::error::ErrorCode::ConstraintSeeds)?;
+        require_keys_eq!(
+            escrow_key,
+            info.key(),
+            anchor_lang::error::ErrorCode::ConstraintSeeds
+        );
+
+        if old_len < space {
+            let shortfall = Rent::get()?
+                .minimum_balance(space)
+                .saturating_sub(info.lamports());
+            if shortfall > 0 {
+                let accounts = Transfer {
+                    from: self.maker.to_account_info(),
+                    to: info.clone(),
+                };
+                transfer(
+                    CpiContext::new(self.system_program.to_account_info(), accounts),
+                    shortfall,
+                )?;
+            }
+            // the new fields start out zeroed, which is their default
+            info.resize(space)?;
+        }
+
+        // the discriminator was checked above, the version is what migrate is about
+        let mut escrow = Escrow::try_deserialize_unchecked(&mut &info.try_borrow_data()?[..])?;
+        if escrow.version == ESCROW_VERSION {
+            return Ok(None);
+        }
+        // before partial fills the vault held the whole deposit until the take closed it
+        if old_len < DEPOSIT_END {
+            let vault = self.vault.as_ref().ok_or(ErrorCode::InvalidEscrowLayout)?;
+            require_keys_eq!(
+                vault.key(),
+                vault_pda(&escrow_key, &mint_a, vault.to_account_info().owner),
+                ErrorCode::InvalidEscrowLayout
+            );
+            escrow.deposit = vault.amount;
+            escrow.remaining = vault.amount;
+            escrow.remaining_receive = escrow.receive;
+        }
+        // escrows were always live before the maker could pause them
+        if old_len < ACTIVE_END {
+            escrow.active = true;
+        }
+        let old_version = escrow.version;
+        escrow.version = ESCROW_VERSION;
+        escrow.try_serialize(&mut &mut info.try_borrow_mut_data()?[..])?;
+        Ok(Some(old_version))
+    }
+}
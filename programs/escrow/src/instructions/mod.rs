@@ -1,7 +1,61 @@
+pub mod claim_payment;
+pub mod claim_vested;
+pub mod close_counter_offer;
+pub mod close_record;
+pub mod commit;
+pub mod compact_maker_index;
+pub mod crank_refund;
+pub mod cross;
+pub mod initialize_config;
+pub mod initialize_stats;
 pub mod make;
+pub mod make_auto;
+pub mod make_batch;
+pub mod make_sol;
+pub mod make_with_sol;
+pub mod migrate;
+pub mod negotiate;
+pub mod quote;
+pub mod recover_orphan_vault;
 pub mod refund;
+pub mod refund_sol;
+pub mod set_active;
 pub mod take;
+pub mod take_batch;
+pub mod take_native;
+pub mod take_sol;
+pub mod top_up;
+pub mod update;
+pub mod update_config;
+pub mod withdraw;
 
+pub use claim_payment::*;
+pub use claim_vested::*;
+pub use close_counter_offer::*;
+pub use close_record::*;
+pub use commit::*;
+pub use compact_maker_index::*;
+pub use crank_refund::*;
+pub use cross::*;
+pub use initialize_config::*;
+pub use initialize_stats::*;
 pub use make::*;
+pub use make_auto::*;
+pub use make_batch::*;
+pub use make_sol::*;
+pub use make_with_sol::*;
+pub use migrate::*;
+pub use negotiate::*;
+pub use quote::*;
+pub use recover_orphan_vault::*;
 pub use refund::*;
+pub use refund_sol::*;
+pub use set_active::*;
 pub use take::*;
+pub use take_batch::*;
+pub use take_native::*;
+pub use take_sol::*;
+pub use top_up::*;
+pub use update::*;
+pub use update_config::*;
+pub use withdraw::*;
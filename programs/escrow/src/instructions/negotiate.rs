@@ -0,0 +1,515 @@
+use anchor_lang::prelude::*;
+
+use anchor_spl::{
+    associated_token::AssociatedToken,
+    token_interface::{
+        close_account, CloseAccount, Mint, TokenAccount, TokenInterface, TransferChecked,
+    },
+};
+
+use crate::error::ErrorCode;
+use crate::extensions::transfer_checked_with_hook;
+use crate::state::{
+    finish_escrow, namespace_seed, protocol_fee, rent_destination, Config, Escrow, EscrowStatus,
+    Offer, Stats,
+};
+
+#[derive(Accounts)]
+pub struct MakeOffer<'info> {
+    #[account(mut)]
+    pub taker: Signer<'info>,
+
+    #[account(
+        mint::token_program = token_program,
+    )]
+    pub mint_b: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint_b,
+        associated_token::authority = taker,
+        associated_token::token_program = token_program,
+    )]
+    pub taker_ata_b: InterfaceAccount<'info, TokenAccount>,
+
+    // only takers that could fill the escrow may bid on it
+    #[account(
+        has_one = mint_b,
+        seeds = [
+            b"escrow",
+            escrow.maker.key().as_ref(),
+            escrow.namespace_seed(),
+            escrow.seed.to_le_bytes().as_ref(),
+        ],
+        bump = escrow.bump,
+        constraint = escrow.is_open() @ ErrorCode::EscrowNotOpen,
+        constraint = !escrow.deposit_is_sol @ ErrorCode::DepositIsSol,
+        constraint = escrow.active @ ErrorCode::EscrowPaused,
+        constraint = escrow.is_allowed_taker(&taker.key()) @ ErrorCode::UnauthorizedTaker,
+        constraint = escrow.is_whitelisted(&taker.key()) @ ErrorCode::TakerNotWhitelisted,
+        constraint = !escrow.blocks_self_trade(&taker.key()) @ ErrorCode::SelfTradeNotAllowed,
+        constraint = !escrow.is_programmable() @ ErrorCode::ProgrammableNftUnsupported,
+        // the gate holdings are only checked by the token takes
+        constraint = !escrow.is_collection_gated() @ ErrorCode::TakerNotCollectionHolder,
+        constraint = !escrow.is_mint_gated() @ ErrorCode::InvalidGateAccount,
+        constraint = !escrow.is_basket() @ ErrorCode::BasketUnsupported,
+        constraint = !escrow.vests_deposit() @ ErrorCode::VestingUnsupported,
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    // the taker pays the rent of both accounts and gets it back on accept or cancel
+    #[account(
+        init,
+        payer = taker,
+        space = 8 + Offer::INIT_SPACE,
+        seeds = [b"offer", escrow.key().as_ref(), taker.key().as_ref()],
+        bump,
+    )]
+    pub offer: Account<'info, Offer>,
+
+    #[account(
+        init,
+        payer = taker,
+        associated_token::mint = mint_b,
+        associated_token::authority = offer,
+        associated_token::token_program = token_program,
+    )]
+    pub offer_vault: InterfaceAccount<'info, TokenAccount>,
+
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> MakeOffer<'info> {
+    // `extra` holds the accounts a transfer hook on mint_b needs
+    pub fn make_offer(
+        &mut self,
+        amount_b: u64,
+        bumps: &MakeOfferBumps,
+        extra: &[AccountInfo<'info>],
+    ) -> Result<()> {
+        require!(amount_b > 0, ErrorCode::ZeroAmount);
+        require!(
+            !self.escrow.is_expired(Clock::get()?.unix_timestamp),
+            ErrorCode::EscrowExpired
+        );
+        require!(
+            self.taker_ata_b.amount >= amount_b,
+            ErrorCode::InsufficientTakerFunds
+        );
+
+        let accounts = TransferChecked {
+            from: self.taker_ata_b.to_account_info(),
+            mint: self.mint_b.to_account_info(),
+            to: self.offer_vault.to_account_info(),
+            authority: self.taker.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new(self.token_program.to_account_info(), accounts)
+            .with_remaining_accounts(extra.to_vec());
+        transfer_checked_with_hook(cpi_ctx, amount_b, self.mint_b.decimals)?;
+        // a mint_b transfer fee comes out of the bid
+        self.offer_vault.reload()?;
+
+        self.offer.set_inner(Offer {
+            escrow: self.escrow.key(),
+            taker: self.taker.key(),
+            amount_b: self.offer_vault.amount,
+            remaining: self.escrow.remaining,
+            bump: bumps.offer,
+        });
+        Ok(())
+    }
+}
+
+// works whether or not the escrow is still around, so bids outlive the escrow they were
+// made on
+#[derive(Accounts)]
+pub struct CancelOffer<'info> {
+    #[account(mut)]
+    pub taker: Signer<'info>,
+
+    #[account(
+        mint::token_program = token_program,
+    )]
+    pub mint_b: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint_b,
+        associated_token::authority = taker,
+        associated_token::token_program = token_program,
+    )]
+    pub taker_ata_b: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        close = taker,
+        has_one = taker,
+        seeds = [b"offer", offer.escrow.as_ref(), taker.key().as_ref()],
+        bump = offer.bump,
+    )]
+    pub offer: Account<'info, Offer>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint_b,
+        associated_token::authority = offer,
+        associated_token::token_program = token_program,
+    )]
+    pub offer_vault: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+impl<'info> CancelOffer<'info> {
+    // returns the mint_b sent back to the taker
+    pub fn cancel_offer(&mut self, extra: &[AccountInfo<'info>]) -> Result<u64> {
+        let amount_b = self.offer_vault.amount;
+        let offer_seeds: [&[&[u8]]; 1] = [&[
+            b"offer",
+            self.offer.escrow.as_ref(),
+            self.offer.taker.as_ref(),
+            &[self.offer.bump],
+        ]];
+
+        let accounts = TransferChecked {
+            from: self.offer_vault.to_account_info(),
+            mint: self.mint_b.to_account_info(),
+            to: self.taker_ata_b.to_account_info(),
+            authority: self.offer.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(
+            self.token_program.to_account_info(),
+            accounts,
+            &offer_seeds,
+        )
+        .with_remaining_accounts(extra.to_vec());
+        transfer_checked_with_hook(cpi_ctx, amount_b, self.mint_b.decimals)?;
+
+        let accounts = CloseAccount {
+            account: self.offer_vault.to_account_info(),
+            destination: self.taker.to_account_info(),
+            authority: self.offer.to_account_info(),
+        };
+        close_account(CpiContext::new_with_signer(
+            self.token_program.to_account_info(),
+            accounts,
+            &offer_seeds,
+        ))?;
+        Ok(amount_b)
+    }
+}
+
+#[derive(Accounts)]
+pub struct AcceptOffer<'info> {
+    // the maker, or the delegate the maker approved through approve_delegate, see the
+    // escrow's constraints
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    // receives the bid, and the rent unless the escrow names a rent_recipient
+    /// CHECK: pinned to the escrow through has_one
+    #[account(mut)]
+    pub maker: UncheckedAccount<'info>,
+
+    // receives the rent of the offer and its vault back
+    /// CHECK: only lamports are sent here, it is pinned to offer.taker
+    #[account(mut)]
+    pub taker: UncheckedAccount<'info>,
+
+    #[account(
+        mint::token_program = token_program_a,
+    )]
+    pub mint_a: InterfaceAccount<'info, Mint>,
+    #[account(
+        mint::token_program = token_program_b,
+    )]
+    pub mint_b: InterfaceAccount<'info, Mint>,
+
+    // whoever accepts pays for whichever of the two accounts is missing
+    #[account(
+        init_if_needed,
+        payer = authority,
+        associated_token::mint = mint_b,
+        associated_token::authority = maker,
+        associated_token::token_program = token_program_b,
+    )]
+    pub maker_ata_b: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        init_if_needed,
+        payer = authority,
+        associated_token::mint = mint_a,
+        associated_token::authority = taker,
+        associated_token::token_program = token_program_a,
+    )]
+    pub taker_ata_a: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        has_one = maker,
+        has_one = mint_a,
+        has_one = mint_b,
+        seeds = [
+            b"escrow",
+            maker.key().as_ref(),
+            escrow.namespace_seed(),
+            escrow.seed.to_le_bytes().as_ref(),
+        ],
+        bump = escrow.bump,
+        constraint = escrow.is_open() @ ErrorCode::EscrowNotOpen,
+        constraint = authority.key() == maker.key()
+            || escrow.is_delegate(&authority.key()) @ ErrorCode::NotDelegate,
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint_a,
+        associated_token::authority = escrow,
+        associated_token::token_program = token_program_a,
+        constraint = vault.amount >= escrow.remaining @ ErrorCode::VaultBalanceMismatch,
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    // only needed when the vault holds more than the escrow records, like take's
+    #[account(
+        mut,
+        associated_token::mint = mint_a,
+        associated_token::authority = maker,
+        associated_token::token_program = token_program_a,
+    )]
+    pub maker_ata_a: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    // a take or top up after the bid changes what it would buy
+    #[account(
+        mut,
+        close = taker,
+        has_one = escrow,
+        has_one = taker,
+        seeds = [b"offer", escrow.key().as_ref(), taker.key().as_ref()],
+        bump = offer.bump,
+        constraint = offer.remaining == escrow.remaining @ ErrorCode::OfferOutdated,
+    )]
+    pub offer: Account<'info, Offer>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint_b,
+        associated_token::authority = offer,
+        associated_token::token_program = token_program_b,
+    )]
+    pub offer_vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProtocolPaused,
+    )]
+    pub config: Account<'info, Config>,
+
+    // adds the fill to the volumes and the escrow to the taken ones
+    #[account(
+        mut,
+        seeds = [b"stats"],
+        bump = stats.bump,
+    )]
+    pub stats: Option<Account<'info, Stats>>,
+
+    // the fee accounts can be left out while the config charges no fee
+    /// CHECK: only used as the authority of fee_collector_ata_b, pinned to the config
+    #[account(address = config.fee_collector)]
+    pub fee_collector: Option<UncheckedAccount<'info>>,
+
+    #[account(
+        init_if_needed,
+        payer = authority,
+        associated_token::mint = mint_b,
+        associated_token::authority = fee_collector,
+        associated_token::token_program = token_program_b,
+    )]
+    pub fee_collector_ata_b: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    // the escrow's rent_recipient, only needed when make named one
+    /// CHECK: only receives lamports, pinned to the escrow
+    #[account(mut, address = escrow.rent_recipient @ ErrorCode::RentRecipientMismatch)]
+    pub rent_recipient: Option<UncheckedAccount<'info>>,
+
+    // the maker's index of open escrows, accepting drops the escrow from it
+    /// CHECK: only written by finish_escrow, when it holds an index
+    #[account(mut, seeds = [b"index", escrow.maker.as_ref()], bump)]
+    pub maker_index: UncheckedAccount<'info>,
+
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub token_program_a: Interface<'info, TokenInterface>,
+    pub token_program_b: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> AcceptOffer<'info> {
+    fn rent_destination(&self) -> Result<AccountInfo<'info>> {
+        rent_destination(
+            &self.escrow,
+            self.maker.to_account_info(),
+            self.rent_recipient.as_ref(),
+        )
+    }
+
+    // the bid pays for everything left in the vault. the bid is released first and the
+    // deposit only leaves the vault once that went through. returns (mint_a sent, mint_b
+    // released, fee)
+    pub fn accept_offer(&mut self, extra: &[AccountInfo<'info>]) -> Result<(u64, u64, u64)> {
+        let amount_b = self.offer_vault.amount;
+        let fee = self.release_bid(amount_b, extra)?;
+        let amount_a = self.release_deposit(extra)?;
+        if let Some(stats) = &mut self.stats {
+            stats.record_take(amount_a, amount_b, true)?;
+        }
+        Ok((amount_a, amount_b, fee))
+    }
+
+    fn release_bid(&mut self, amount_b: u64, extra: &[AccountInfo<'info>]) -> Result<u64> {
+        let escrow_key = self.escrow.key();
+        let taker_key = self.taker.key();
+        let offer_seeds: [&[&[u8]]; 1] = [&[
+            b"offer",
+            escrow_key.as_ref(),
+            taker_key.as_ref(),
+            &[self.offer.bump],
+        ]];
+
+        let fee = protocol_fee(amount_b, self.config.fee_bps)?;
+        if fee > 0 {
+            let fee_collector_ata_b = self
+                .fee_collector_ata_b
+                .as_ref()
+                .ok_or(ErrorCode::MissingFeeAccount)?;
+            self.pay_from_offer(
+                fee_collector_ata_b.to_account_info(),
+                fee,
+                extra,
+                &offer_seeds,
+            )?;
+        }
+        self.pay_from_offer(
+            self.maker_ata_b.to_account_info(),
+            amount_b - fee,
+            extra,
+            &offer_seeds,
+        )?;
+
+        let accounts = CloseAccount {
+            account: self.offer_vault.to_account_info(),
+            destination: self.taker.to_account_info(),
+            authority: self.offer.to_account_info(),
+        };
+        close_account(CpiContext::new_with_signer(
+            self.token_program_b.to_account_info(),
+            accounts,
+            &offer_seeds,
+        ))?;
+        Ok(fee)
+    }
+
+    fn pay_from_offer(
+        &self,
+        to: AccountInfo<'info>,
+        amount: u64,
+        extra: &[AccountInfo<'info>],
+        offer_seeds: &[&[&[u8]]],
+    ) -> Result<()> {
+        let accounts = TransferChecked {
+            from: self.offer_vault.to_account_info(),
+            mint: self.mint_b.to_account_info(),
+            to,
+            authority: self.offer.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(
+            self.token_program_b.to_account_info(),
+            accounts,
+            offer_seeds,
+        )
+        .with_remaining_accounts(extra.to_vec());
+        transfer_checked_with_hook(cpi_ctx, amount, self.mint_b.decimals)
+    }
+
+    fn release_deposit(&mut self, extra: &[AccountInfo<'info>]) -> Result<u64> {
+        let namespace = self.escrow.namespace;
+        let signer_seeds: [&[&[u8]]; 1] = [&[
+            b"escrow",
+            self.escrow.maker.as_ref(),
+            namespace_seed(&namespace),
+            &self.escrow.seed.to_le_bytes()[..],
+            &[self.escrow.bump],
+        ]];
+
+        let amount_a = self.escrow.remaining;
+        let accounts = TransferChecked {
+            from: self.vault.to_account_info(),
+            mint: self.mint_a.to_account_info(),
+            to: self.taker_ata_a.to_account_info(),
+            authority: self.escrow.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(
+            self.token_program_a.to_account_info(),
+            accounts,
+            &signer_seeds,
+        )
+        .with_remaining_accounts(extra.to_vec());
+        transfer_checked_with_hook(cpi_ctx, amount_a, self.mint_a.decimals)?;
+
+        let excess = self.vault.amount - amount_a;
+        if excess > 0 {
+            self.sweep_excess(excess, extra, &signer_seeds)?;
+        }
+        let destination = self.rent_destination()?;
+        let accounts = CloseAccount {
+            account: self.vault.to_account_info(),
+            destination: destination.clone(),
+            authority: self.escrow.to_account_info(),
+        };
+        close_account(CpiContext::new_with_signer(
+            self.token_program_a.to_account_info(),
+            accounts,
+            &signer_seeds,
+        ))?;
+
+        self.escrow.remaining = 0;
+        self.escrow.remaining_receive = 0;
+        finish_escrow(
+            &mut self.escrow,
+            EscrowStatus::Completed,
+            self.taker.key(),
+            destination,
+            &self.maker_index,
+        )?;
+        Ok(amount_a)
+    }
+
+    // tokens sent to the vault from outside go back to the maker before it closes
+    fn sweep_excess(
+        &self,
+        excess: u64,
+        extra: &[AccountInfo<'info>],
+        signer_seeds: &[&[&[u8]]],
+    ) -> Result<()> {
+        let maker_ata_a = self
+            .maker_ata_a
+            .as_ref()
+            .ok_or(ErrorCode::MakerAtaRequired)?;
+        let accounts = TransferChecked {
+            from: self.vault.to_account_info(),
+            mint: self.mint_a.to_account_info(),
+            to: maker_ata_a.to_account_info(),
+            authority: self.escrow.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(
+            self.token_program_a.to_account_info(),
+            accounts,
+            signer_seeds,
+        )
+        .with_remaining_accounts(extra.to_vec());
+        transfer_checked_with_hook(cpi_ctx, excess, self.mint_a.decimals)
+    }
+}
@@ -0,0 +1,76 @@
+use anchor_lang::prelude::*;
+
+use anchor_spl::token_interface::TokenAccount;
+
+use crate::error::ErrorCode;
+use crate::{Config, Escrow};
+
+// read only, clients simulate it and read the quote from the return data
+#[derive(Accounts)]
+pub struct Quote<'info> {
+    #[account(
+        seeds = [
+            b"escrow",
+            escrow.maker.key().as_ref(),
+            escrow.namespace_seed(),
+            escrow.seed.to_le_bytes().as_ref(),
+        ],
+        bump = escrow.bump,
+        constraint = escrow.is_open() @ ErrorCode::EscrowNotOpen,
+        constraint = !escrow.deposit_is_sol @ ErrorCode::DepositIsSol,
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    #[account(
+        constraint = vault.owner == escrow.key(),
+        constraint = vault.mint == escrow.mint_a,
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+    )]
+    pub config: Account<'info, Config>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct EscrowQuote {
+    // price of the whole deposit right now, follows the auction curve if there is one
+    pub receive: u64,
+    // mint_b needed to take everything that is left
+    pub remaining_receive: u64,
+    pub vault_balance: u64,
+    // whether `taker` could take right now. false once the escrow or the protocol is
+    // paused, the escrow expired or is still cooling down, or the taker isn't let in
+    pub takeable: bool,
+    // mint_b per mint_a in base units, see Escrow::base_unit_price
+    pub price_numerator: u64,
+    pub price_denominator: u64,
+}
+
+impl<'info> Quote<'info> {
+    pub fn quote(&self, taker: &Pubkey) -> Result<EscrowQuote> {
+        let clock = Clock::get()?;
+        let now = clock.unix_timestamp;
+        // price the escrow like a take would, without writing the account
+        let mut escrow = (*self.escrow).clone();
+        escrow.apply_auction_price(now)?;
+
+        let (price_numerator, price_denominator) = escrow.base_unit_price();
+        Ok(EscrowQuote {
+            receive: escrow.receive,
+            remaining_receive: escrow.remaining_receive,
+            vault_balance: self.vault.amount,
+            takeable: escrow.active
+                && !escrow.is_expired(now)
+                && !self.config.paused
+                && !escrow.is_cooling_down(clock.slot)
+                && escrow.is_allowed_taker(taker)
+                && escrow.is_whitelisted(taker)
+                && !escrow.blocks_self_trade(taker),
+            price_numerator,
+            price_denominator,
+        })
+    }
+}
@@ -0,0 +1,111 @@
+use anchor_lang::prelude::*;
+
+use anchor_spl::{
+    associated_token::AssociatedToken,
+    token_interface::{
+        close_account, CloseAccount, Mint, TokenAccount, TokenInterface, TransferChecked,
+    },
+};
+
+use crate::error::ErrorCode;
+use crate::extensions::transfer_checked_with_hook;
+use crate::state::namespace_seed;
+
+// returns what is left in the vault of an escrow that no longer exists to the maker. only
+// the maker the escrow was derived from can sign, and the tokens and the vault's rent only
+// ever go to them
+#[derive(Accounts)]
+#[instruction(seed: u64, namespace: [u8; 8])]
+pub struct RecoverOrphanVault<'info> {
+    #[account(mut)]
+    pub maker: Signer<'info>,
+
+    #[account(
+        mint::token_program = token_program,
+    )]
+    pub mint_a: InterfaceAccount<'info, Mint>,
+
+    /// CHECK: the address of the maker's escrow at `namespace` and `seed`, which has to be
+    /// closed. only signs for the vault
+    #[account(
+        seeds = [
+            b"escrow",
+            maker.key().as_ref(),
+            namespace_seed(&namespace),
+            seed.to_le_bytes().as_ref(),
+        ],
+        bump,
+        constraint = escrow.data_is_empty() @ ErrorCode::EscrowNotOrphaned,
+    )]
+    pub escrow: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint_a,
+        associated_token::authority = escrow,
+        associated_token::token_program = token_program,
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        init_if_needed,
+        payer = maker,
+        associated_token::mint = mint_a,
+        associated_token::authority = maker,
+        associated_token::token_program = token_program,
+    )]
+    pub maker_ata_a: InterfaceAccount<'info, TokenAccount>,
+
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> RecoverOrphanVault<'info> {
+    // returns the mint_a sent to the maker
+    pub fn recover(
+        &mut self,
+        seed: u64,
+        namespace: [u8; 8],
+        bumps: &RecoverOrphanVaultBumps,
+        extra: &[AccountInfo<'info>],
+    ) -> Result<u64> {
+        let amount = self.vault.amount;
+        let maker = self.maker.key();
+        let signer_seeds: [&[&[u8]]; 1] = [&[
+            b"escrow",
+            maker.as_ref(),
+            namespace_seed(&namespace),
+            &seed.to_le_bytes()[..],
+            &[bumps.escrow],
+        ]];
+
+        if amount > 0 {
+            let accounts = TransferChecked {
+                from: self.vault.to_account_info(),
+                mint: self.mint_a.to_account_info(),
+                to: self.maker_ata_a.to_account_info(),
+                authority: self.escrow.to_account_info(),
+            };
+            let cpi_ctx = CpiContext::new_with_signer(
+                self.token_program.to_account_info(),
+                accounts,
+                &signer_seeds,
+            )
+            .with_remaining_accounts(extra.to_vec());
+            transfer_checked_with_hook(cpi_ctx, amount, self.mint_a.decimals)?;
+        }
+
+        let accounts = CloseAccount {
+            account: self.vault.to_account_info(),
+            destination: self.maker.to_account_info(),
+            authority: self.escrow.to_account_info(),
+        };
+        close_account(CpiContext::new_with_signer(
+            self.token_program.to_account_info(),
+            accounts,
+            &signer_seeds,
+        ))?;
+        Ok(amount)
+    }
+}
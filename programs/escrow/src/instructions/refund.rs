@@ -1,38 +1,50 @@
 use anchor_lang::prelude::*;
 
-use crate::Escrow;
+use crate::basket::BasketAccounts;
+use crate::error::ErrorCode;
+use crate::extensions::transfer_checked_with_hook;
+use crate::pnft::{transfer_pnft, PnftAccounts, PnftTransfer};
+use crate::state::{finish_escrow, namespace_seed, rent_destination, Escrow, EscrowStatus, Stats};
 use anchor_spl::{
     associated_token::AssociatedToken,
+    token::spl_token::native_mint,
     token_interface::{
-        close_account, transfer_checked, CloseAccount, Mint, TokenAccount, TokenInterface,
-        TransferChecked,
+        close_account, CloseAccount, Mint, TokenAccount, TokenInterface, TransferChecked,
     },
 };
 
+#[event_cpi]
 #[derive(Accounts)]
 pub struct Refund<'info> {
     #[account(mut)]
     pub maker: Signer<'info>,
 
     #[account(
-        mint::token_program = token_program,
+        mint::token_program = token_program_a,
     )]
     pub mint_a: InterfaceAccount<'info, Mint>,
 
+    // wrapped SOL vaults are unwrapped into the maker's wallet, so only other mints need it
     #[account(
         mut,
         associated_token::mint = mint_a,
         associated_token::authority = maker,
-        associated_token::token_program = token_program,
+        associated_token::token_program = token_program_a,
     )]
-    pub maker_ata_a: InterfaceAccount<'info, TokenAccount>,
+    pub maker_ata_a: Option<InterfaceAccount<'info, TokenAccount>>,
     #[account(
         mut,
-        close = maker,
         has_one = mint_a,
         has_one = maker,
-        seeds = [b"escrow", maker.key().as_ref(), escrow.seed.to_le_bytes().as_ref()],
+        seeds = [
+            b"escrow",
+            maker.key().as_ref(),
+            escrow.namespace_seed(),
+            escrow.seed.to_le_bytes().as_ref(),
+        ],
         bump = escrow.bump,
+        constraint = escrow.is_open() @ ErrorCode::EscrowNotOpen,
+        constraint = !escrow.deposit_is_sol @ ErrorCode::DepositIsSol,
     )]
     pub escrow: Account<'info, Escrow>,
 
@@ -40,17 +52,50 @@ pub struct Refund<'info> {
         mut,
         associated_token::mint = mint_a,
         associated_token::authority = escrow,
-        associated_token::token_program = token_program,
+        associated_token::token_program = token_program_a,
     )]
     pub vault: InterfaceAccount<'info, TokenAccount>,
 
+    // counts the escrow as refunded
+    #[account(
+        mut,
+        seeds = [b"stats"],
+        bump = stats.bump,
+    )]
+    pub stats: Option<Account<'info, Stats>>,
+
+    // the escrow's rent_recipient, only needed when make named one
+    /// CHECK: only receives lamports, pinned to the escrow
+    #[account(mut, address = escrow.rent_recipient @ ErrorCode::RentRecipientMismatch)]
+    pub rent_recipient: Option<UncheckedAccount<'info>>,
+
+    // the maker's index of open escrows, the refund drops the escrow from it
+    /// CHECK: only written by finish_escrow, when it holds an index
+    #[account(mut, seeds = [b"index", maker.key().as_ref()], bump)]
+    pub maker_index: UncheckedAccount<'info>,
+
     pub associated_token_program: Program<'info, AssociatedToken>,
-    pub token_program: Interface<'info, TokenInterface>,
+    pub token_program_a: Interface<'info, TokenInterface>,
     pub system_program: Program<'info, System>,
 }
 
 impl<'info> Refund<'info> {
-    pub fn refund_and_close_vault(&mut self) -> Result<()> {
+    fn rent_destination(&self) -> Result<AccountInfo<'info>> {
+        rent_destination(
+            &self.escrow,
+            self.maker.to_account_info(),
+            self.rent_recipient.as_ref(),
+        )
+    }
+
+    // `extra` holds the accounts a transfer hook on mint_a needs, or the pNFT accounts
+    pub fn refund_and_close_vault(&mut self, extra: &[AccountInfo<'info>]) -> Result<()> {
+        require!(
+            !self.escrow.is_refund_locked(Clock::get()?.unix_timestamp),
+            ErrorCode::RefundLocked
+        );
+
+        let namespace = self.escrow.namespace;
         let signer_seeds: [&[&[u8]]; 1] = [&[
             b"escrow",
             // key() is a method that returns the public key of the account
@@ -58,6 +103,7 @@ impl<'info> Refund<'info> {
             // key() returns a Pubkey. key is directed reference. key.as_ref() returns a byte slice
             self.maker.to_account_info().key.as_ref(),
             // to_le_bytes() converts the u64 to a byte array
+            namespace_seed(&namespace),
             &self.escrow.seed.to_le_bytes()[..],
             // self.escrow.seed -> u64
             // to_le_bytes() converts the u64 to a byte array
@@ -65,32 +111,103 @@ impl<'info> Refund<'info> {
             &[self.escrow.bump],
         ]];
 
-        let transfer_accounts = TransferChecked {
-            from: self.vault.to_account_info(),
-            mint: self.mint_a.to_account_info(),
-            to: self.maker_ata_a.to_account_info(),
-            authority: self.escrow.to_account_info(),
-        };
-        let transfer_cpi_ctx = CpiContext::new_with_signer(
-            self.token_program.to_account_info(),
-            transfer_accounts,
-            &signer_seeds,
-        );
+        // closing a native account hands over the wrapped lamports along with the rent
+        if self.mint_a.key() != native_mint::ID {
+            let maker_ata_a = self
+                .maker_ata_a
+                .as_ref()
+                .ok_or(ErrorCode::MakerAtaRequired)?;
+            if self.escrow.is_programmable() {
+                let pnft = PnftAccounts::from_remaining(extra, &self.mint_a.key())?;
+                let transfer = PnftTransfer {
+                    token: self.vault.to_account_info(),
+                    token_owner: self.escrow.to_account_info(),
+                    destination: maker_ata_a.to_account_info(),
+                    destination_owner: self.maker.to_account_info(),
+                    mint: self.mint_a.to_account_info(),
+                    authority: self.escrow.to_account_info(),
+                    payer: self.maker.to_account_info(),
+                    system_program: self.system_program.to_account_info(),
+                    token_program: self.token_program_a.to_account_info(),
+                    associated_token_program: self.associated_token_program.to_account_info(),
+                };
+                transfer_pnft(transfer, &pnft, self.vault.amount, &signer_seeds)?;
+            } else {
+                let transfer_accounts = TransferChecked {
+                    from: self.vault.to_account_info(),
+                    mint: self.mint_a.to_account_info(),
+                    to: maker_ata_a.to_account_info(),
+                    authority: self.escrow.to_account_info(),
+                };
+                let transfer_cpi_ctx = CpiContext::new_with_signer(
+                    self.token_program_a.to_account_info(),
+                    transfer_accounts,
+                    &signer_seeds,
+                )
+                .with_remaining_accounts(extra.to_vec());
 
-        transfer_checked(transfer_cpi_ctx, self.vault.amount, self.mint_a.decimals)?;
+                transfer_checked_with_hook(
+                    transfer_cpi_ctx,
+                    self.vault.amount,
+                    self.mint_a.decimals,
+                )?;
+            }
+        }
 
-        let close_accpounts = CloseAccount {
-            account: self.vault.to_account_info(),
-            destination: self.maker.to_account_info(),
-            authority: self.escrow.to_account_info(),
+        // a native vault still holds the deposit, which only the maker gets back
+        let destination = if self.mint_a.key() == native_mint::ID {
+            self.maker.to_account_info()
+        } else {
+            self.rent_destination()?
         };
+        // guarded in case Token Metadata already closed the emptied pNFT vault
+        if self.vault.to_account_info().lamports() > 0 {
+            let close_accpounts = CloseAccount {
+                account: self.vault.to_account_info(),
+                destination,
+                authority: self.escrow.to_account_info(),
+            };
 
-        let close_cpi_ctx = CpiContext::new_with_signer(
-            self.token_program.to_account_info(),
-            close_accpounts,
-            &signer_seeds,
-        );
+            let close_cpi_ctx = CpiContext::new_with_signer(
+                self.token_program_a.to_account_info(),
+                close_accpounts,
+                &signer_seeds,
+            );
+
+            close_account(close_cpi_ctx)?;
+        }
+        self.escrow.fold_second_mint();
+        if self.escrow.basket_len > 0 {
+            self.refund_legs(extra, &signer_seeds)?;
+        }
+        if let Some(stats) = &mut self.stats {
+            stats.record_refund()?;
+        }
+        let destination = self.rent_destination()?;
+        finish_escrow(
+            &mut self.escrow,
+            EscrowStatus::Refunded,
+            Pubkey::default(),
+            destination,
+            &self.maker_index,
+        )
+    }
 
-        close_account(close_cpi_ctx)
+    // every basket leg goes back to the maker, whose ATAs are recreated if they were closed
+    // since. the leg accounts lead `extra`, see basket.rs
+    fn refund_legs(&self, extra: &[AccountInfo<'info>], signer_seeds: &[&[&[u8]]]) -> Result<()> {
+        let accounts = BasketAccounts {
+            escrow: self.escrow.to_account_info(),
+            owner: self.maker.to_account_info(),
+            payer: self.maker.to_account_info(),
+            token_program: self.token_program_a.to_account_info(),
+            associated_token_program: self.associated_token_program.to_account_info(),
+            system_program: self.system_program.to_account_info(),
+        };
+        let destination = self.rent_destination()?;
+        for leg in accounts.legs(self.escrow.basket_mints(), extra)? {
+            accounts.release(&leg, destination.clone(), extra, signer_seeds)?;
+        }
+        Ok(())
     }
 }
@@ -0,0 +1,99 @@
+use anchor_lang::prelude::*;
+
+use anchor_lang::system_program::{transfer, Transfer};
+
+use crate::error::ErrorCode;
+use crate::state::{finish_escrow, rent_destination, Escrow, EscrowStatus, Stats};
+
+#[derive(Accounts)]
+pub struct RefundSol<'info> {
+    #[account(mut)]
+    pub maker: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = maker,
+        seeds = [
+            b"escrow",
+            maker.key().as_ref(),
+            escrow.namespace_seed(),
+            escrow.seed.to_le_bytes().as_ref(),
+        ],
+        bump = escrow.bump,
+        constraint = escrow.is_open() @ ErrorCode::EscrowNotOpen,
+        constraint = escrow.deposit_is_sol @ ErrorCode::DepositNotSol,
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    #[account(
+        mut,
+        seeds = [b"sol_vault", escrow.key().as_ref()],
+        bump,
+    )]
+    pub sol_vault: SystemAccount<'info>,
+
+    // counts the escrow as refunded
+    #[account(
+        mut,
+        seeds = [b"stats"],
+        bump = stats.bump,
+    )]
+    pub stats: Option<Account<'info, Stats>>,
+
+    // the escrow's rent_recipient, only needed when make_sol named one
+    /// CHECK: only receives lamports, pinned to the escrow
+    #[account(mut, address = escrow.rent_recipient @ ErrorCode::RentRecipientMismatch)]
+    pub rent_recipient: Option<UncheckedAccount<'info>>,
+
+    // the maker's index of open escrows, the refund drops the escrow from it
+    /// CHECK: only written by finish_escrow, when it holds an index
+    #[account(mut, seeds = [b"index", escrow.maker.as_ref()], bump)]
+    pub maker_index: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> RefundSol<'info> {
+    fn rent_destination(&self) -> Result<AccountInfo<'info>> {
+        rent_destination(
+            &self.escrow,
+            self.maker.to_account_info(),
+            self.rent_recipient.as_ref(),
+        )
+    }
+
+    pub fn refund(&mut self, bumps: &RefundSolBumps) -> Result<u64> {
+        require!(
+            !self.escrow.is_refund_locked(Clock::get()?.unix_timestamp),
+            ErrorCode::RefundLocked
+        );
+
+        let escrow_key = self.escrow.key();
+        let signer_seeds: [&[&[u8]]; 1] =
+            [&[b"sol_vault", escrow_key.as_ref(), &[bumps.sol_vault]]];
+
+        let amount = self.sol_vault.lamports();
+        let transfer_accounts = Transfer {
+            from: self.sol_vault.to_account_info(),
+            to: self.maker.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(
+            self.system_program.to_account_info(),
+            transfer_accounts,
+            &signer_seeds,
+        );
+        transfer(cpi_ctx, amount)?;
+        if let Some(stats) = &mut self.stats {
+            stats.record_refund()?;
+        }
+        let destination = self.rent_destination()?;
+        finish_escrow(
+            &mut self.escrow,
+            EscrowStatus::Refunded,
+            Pubkey::default(),
+            destination,
+            &self.maker_index,
+        )?;
+        Ok(amount)
+    }
+}
@@ -0,0 +1,31 @@
+use anchor_lang::prelude::*;
+
+use crate::error::ErrorCode;
+use crate::Escrow;
+
+#[derive(Accounts)]
+pub struct SetActive<'info> {
+    pub maker: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = maker,
+        seeds = [
+            b"escrow",
+            maker.key().as_ref(),
+            escrow.namespace_seed(),
+            escrow.seed.to_le_bytes().as_ref(),
+        ],
+        bump = escrow.bump,
+        constraint = escrow.is_open() @ ErrorCode::EscrowNotOpen,
+    )]
+    pub escrow: Account<'info, Escrow>,
+}
+
+impl<'info> SetActive<'info> {
+    // pausing only blocks take, the deposit stays in the vault
+    pub fn set_active(&mut self, active: bool) -> Result<()> {
+        self.escrow.active = active;
+        Ok(())
+    }
+}
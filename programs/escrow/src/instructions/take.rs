@@ -1,15 +1,32 @@
 use anchor_lang::prelude::*;
 
+use anchor_lang::solana_program::program_pack::Pack;
 use anchor_spl::{
     associated_token::AssociatedToken,
+    token::spl_token::{self, native_mint},
     token_interface::{
-        close_account, transfer_checked, CloseAccount, Mint, TokenAccount, TokenInterface,
-        TransferChecked,
+        close_account, initialize_account3, CloseAccount, InitializeAccount3, Mint, TokenAccount,
+        TokenInterface, TransferChecked,
     },
 };
 
-use crate::state::Escrow;
+use crate::basket::BasketAccounts;
+use crate::ed25519::verify_ed25519;
+use crate::error::ErrorCode;
+use crate::events::{EscrowTaken, ReferralPaid};
+use crate::extensions::{check_received, gross_up, transfer_checked_with_hook, transfer_fee};
+use crate::math::mul_div_floor;
+use crate::metadata::{check_metadata, verified_collection};
+use crate::pnft::{transfer_pnft, PnftAccounts, PnftTransfer};
+use crate::pool::read_reserves;
+use crate::pyth::read_price;
+use crate::state::{
+    create_pda_account, finish_escrow, namespace_seed, protocol_fee, rent_destination, Config,
+    Escrow, EscrowStatus, Stats,
+};
+use crate::REFERRAL_BPS;
 
+#[event_cpi]
 #[derive(Accounts)]
 // #[instruction(seed: u64)]
 pub struct Take<'info> {
@@ -18,33 +35,38 @@ pub struct Take<'info> {
     // normal solana account.
     // pass the account as a SystemAccount to the instruction
     // only receives the account, not the authority
-    // you input the maker's public key here
+    // you input the maker's public key here, has_one on the escrow pins it to the real one
     #[account(mut)]
     pub maker: SystemAccount<'info>,
 
     #[account(
-        mint::token_program = token_program,
+        mint::token_program = token_program_a,
     )]
     pub mint_a: InterfaceAccount<'info, Mint>,
     #[account(
-        mint::token_program = token_program,
+        mint::token_program = token_program_b,
     )]
     pub mint_b: InterfaceAccount<'info, Mint>,
 
+    // created at the taker's expense if the maker never held mint_b, left out when a
+    // wrapped SOL payment is unwrapped through unwrap_b
     #[account(
         init_if_needed,
         payer = taker,
         associated_token::mint = mint_b,
         associated_token::authority = maker,
-        associated_token::token_program = token_program,
+        associated_token::token_program = token_program_b,
     )]
-    pub maker_ata_b: InterfaceAccount<'info, TokenAccount>,
+    pub maker_ata_b: Option<InterfaceAccount<'info, TokenAccount>>,
 
+    // created for takers buying a mint they never held. an existing account still has
+    // to be the taker's ATA for mint_a
     #[account(
-        mut,
+        init_if_needed,
+        payer = taker,
         associated_token::mint = mint_a,
         associated_token::authority = taker,
-        associated_token::token_program = token_program,
+        associated_token::token_program = token_program_a,
     )]
     pub taker_ata_a: InterfaceAccount<'info, TokenAccount>,
 
@@ -52,81 +74,823 @@ pub struct Take<'info> {
         mut,
         associated_token::mint = mint_b,
         associated_token::authority = taker,
-        associated_token::token_program = token_program,
+        associated_token::token_program = token_program_b,
     )]
     pub taker_ata_b: InterfaceAccount<'info, TokenAccount>,
 
     #[account(
         mut,
-        seeds = [b"escrow", escrow.maker.key().as_ref(), escrow.seed.to_le_bytes().as_ref()],
+        seeds = [
+            b"escrow",
+            escrow.maker.key().as_ref(),
+            escrow.namespace_seed(),
+            escrow.seed.to_le_bytes().as_ref(),
+        ],
         bump = escrow.bump,
-        close = maker
+        has_one = maker,
+        constraint = escrow.is_open() @ ErrorCode::EscrowNotOpen,
+        constraint = !escrow.deposit_is_sol @ ErrorCode::DepositIsSol,
+        constraint = escrow.active @ ErrorCode::EscrowPaused,
+        constraint = escrow.is_allowed_taker(&taker.key()) @ ErrorCode::UnauthorizedTaker,
+        constraint = escrow.is_whitelisted(&taker.key()) @ ErrorCode::TakerNotWhitelisted,
+        constraint = !escrow.blocks_self_trade(&taker.key()) @ ErrorCode::SelfTradeNotAllowed,
     )]
     pub escrow: Account<'info, Escrow>,
 
+    // the vault has to hold at least what the escrow records, a short vault would hand the
+    // taker less than the escrow advertises. anyone can send tokens to it though, whatever
+    // sits on top goes back to the maker on the last fill
     #[account(
         mut,
         associated_token::mint = mint_a,
         associated_token::authority = escrow,
-        associated_token::token_program = token_program,
+        associated_token::token_program = token_program_a,
+        constraint = vault.amount >= escrow.remaining @ ErrorCode::VaultBalanceMismatch,
     )]
     pub vault: InterfaceAccount<'info, TokenAccount>,
 
+    // only needed on the last fill of a vault holding more than the escrow records
+    #[account(
+        mut,
+        associated_token::mint = mint_a,
+        associated_token::authority = maker,
+        associated_token::token_program = token_program_a,
+    )]
+    pub maker_ata_a: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProtocolPaused,
+    )]
+    pub config: Account<'info, Config>,
+
+    // adds the fill to the volumes, and the escrow to the taken ones on the last fill
+    #[account(
+        mut,
+        seeds = [b"stats"],
+        bump = stats.bump,
+    )]
+    pub stats: Option<Account<'info, Stats>>,
+
+    // the fee accounts can be left out while the config charges no fee
+    /// CHECK: only used as the authority of fee_collector_ata_b, pinned to the config
+    #[account(address = config.fee_collector)]
+    pub fee_collector: Option<UncheckedAccount<'info>>,
+
+    #[account(
+        init_if_needed,
+        payer = taker,
+        associated_token::mint = mint_b,
+        associated_token::authority = fee_collector,
+        associated_token::token_program = token_program_b,
+    )]
+    pub fee_collector_ata_b: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    // a front-end's referrer, paid REFERRAL_BPS of the mint_b out of the maker's share.
+    // leaving both out pays the maker in full. the taker picks it, so naming themselves
+    // would be a discount at the maker's expense
+    /// CHECK: only used as the authority of referrer_ata_b
+    #[account(constraint = referrer.key() != taker.key() @ ErrorCode::SelfReferral)]
+    pub referrer: Option<UncheckedAccount<'info>>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint_b,
+        associated_token::authority = referrer,
+        associated_token::token_program = token_program_b,
+    )]
+    pub referrer_ata_b: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    // scratch wrapped SOL account the maker's share passes through to reach the maker's
+    // wallet as lamports. it is created and closed within the take
+    /// CHECK: created and closed by the program at its seeds
+    #[account(
+        mut,
+        seeds = [b"unwrap", escrow.key().as_ref()],
+        bump,
+    )]
+    pub unwrap_b: Option<UncheckedAccount<'info>>,
+
+    // the escrow's own mint_b account, paid instead of maker_ata_b when the escrow was made
+    // with receive_to_vault. created at the taker's expense like maker_ata_b
+    #[account(
+        init_if_needed,
+        payer = taker,
+        associated_token::mint = mint_b,
+        associated_token::authority = escrow,
+        associated_token::token_program = token_program_b,
+    )]
+    pub payment_vault: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    // the taker's NFT from the escrow's gate collection and its Metaplex metadata,
+    // only needed when the escrow is collection gated
+    pub gate_token: Option<InterfaceAccount<'info, TokenAccount>>,
+    /// CHECK: checked against the metadata PDA of gate_token's mint in check_collection_gate
+    pub gate_metadata: Option<UncheckedAccount<'info>>,
+
+    // the taker's account of the escrow's gate mint, only needed when the escrow is mint gated
+    pub gate_mint_token: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    // the escrow's rent_recipient, only needed when make named one
+    /// CHECK: only receives lamports, pinned to the escrow
+    #[account(mut, address = escrow.rent_recipient @ ErrorCode::RentRecipientMismatch)]
+    pub rent_recipient: Option<UncheckedAccount<'info>>,
+
+    // the escrow's Pyth price feed, only needed for oracle priced escrows
+    /// CHECK: pinned to the escrow, read by pyth::read_price
+    #[account(address = escrow.oracle @ ErrorCode::OracleMismatch)]
+    pub price_feed: Option<UncheckedAccount<'info>>,
+
+    // the escrow's AMM pool and the vaults it names, only needed for pool priced escrows
+    /// CHECK: pinned to the escrow, its owner checked in apply_pool_price
+    #[account(address = escrow.pool @ ErrorCode::PoolMismatch)]
+    pub pool: Option<UncheckedAccount<'info>>,
+    pub pool_vault_0: Option<InterfaceAccount<'info, TokenAccount>>,
+    pub pool_vault_1: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    // only needed by take_with_quote, to find the Ed25519 instruction ahead of it
+    /// CHECK: the instructions sysvar
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub sysvar_instructions: Option<UncheckedAccount<'info>>,
+
+    // the maker's index of open escrows, the last fill drops the escrow from it
+    /// CHECK: only written by finish_escrow, when it holds an index
+    #[account(mut, seeds = [b"index", escrow.maker.as_ref()], bump)]
+    pub maker_index: UncheckedAccount<'info>,
+
     pub associated_token_program: Program<'info, AssociatedToken>,
-    pub token_program: Interface<'info, TokenInterface>,
+    // the mints may live under different token programs, e.g. a legacy mint_a and a Token-2022 mint_b
+    pub token_program_a: Interface<'info, TokenInterface>,
+    pub token_program_b: Interface<'info, TokenInterface>,
     pub system_program: Program<'info, System>,
 }
 
+// the events every take handler ends with: the fill, the referral it paid if any and the
+// new stats totals. amount_b is what the taker paid in the mint_b account's mint. emit_cpi!
+// needs the context, so this takes it instead of the accounts
+pub fn emit_taken<'info>(
+    ctx: &Context<'_, '_, '_, 'info, Take<'info>>,
+    amount_a: u64,
+    amount_b: u64,
+    fee: u64,
+    referral: u64,
+) -> Result<()> {
+    let event = EscrowTaken {
+        escrow: ctx.accounts.escrow.key(),
+        maker: ctx.accounts.maker.key(),
+        taker: ctx.accounts.taker.key(),
+        mint_a: ctx.accounts.mint_a.key(),
+        mint_b: ctx.accounts.mint_b.key(),
+        amount_a,
+        amount_b,
+        fee,
+        remaining: ctx.accounts.escrow.remaining,
+        lamports: ctx.accounts.unwrapped(amount_b, fee, referral),
+    };
+    emit!(event);
+    emit_cpi!(event);
+    if let Some(event) = ctx.accounts.referral_paid(referral) {
+        emit!(event);
+        emit_cpi!(event);
+    }
+    if let Some(stats) = &ctx.accounts.stats {
+        let event = stats.updated();
+        emit!(event);
+        emit_cpi!(event);
+    }
+    Ok(())
+}
+
 impl<'info> Take<'info> {
-    pub fn deposit(&mut self) -> Result<()> {
+    fn rent_destination(&self) -> Result<AccountInfo<'info>> {
+        rent_destination(
+            &self.escrow,
+            self.maker.to_account_info(),
+            self.rent_recipient.as_ref(),
+        )
+    }
+
+    // refund does not check the expiry, so the maker can always get the deposit back
+    pub fn check_expiry(&self) -> Result<()> {
+        require!(
+            !self.escrow.is_expired(Clock::get()?.unix_timestamp),
+            ErrorCode::EscrowExpired
+        );
+        Ok(())
+    }
+
+    pub fn check_cooldown(&self) -> Result<()> {
+        require!(
+            !self.escrow.is_cooling_down(Clock::get()?.slot),
+            ErrorCode::CooldownNotElapsed
+        );
+        Ok(())
+    }
+
+    // prices the whole deposit at the oracle's current price, marked up by the spread.
+    // runs next to apply_auction_price, make keeps auctions, oracles and pools apart
+    pub fn apply_oracle_price(&mut self) -> Result<()> {
+        if !self.escrow.is_oracle_priced() {
+            return Ok(());
+        }
+        let feed = self.price_feed.as_ref().ok_or(ErrorCode::OracleMismatch)?;
+        let price = read_price(feed)?;
+        require!(
+            price.feed_id == self.escrow.oracle_feed_id,
+            ErrorCode::OracleMismatch
+        );
+        let age = Clock::get()?
+            .unix_timestamp
+            .saturating_sub(price.publish_time);
+        require!(
+            age <= self.escrow.max_price_age as i64,
+            ErrorCode::StaleOraclePrice
+        );
+        require!(
+            self.escrow.max_conf_bps == 0 || price.conf_bps()? <= self.escrow.max_conf_bps as u64,
+            ErrorCode::OracleConfidenceTooWide
+        );
+
+        let receive = price.payment(
+            self.escrow.deposit,
+            self.mint_a.decimals,
+            self.mint_b.decimals,
+            self.escrow.spread_bps,
+        )?;
+        require!(receive > 0, ErrorCode::ZeroReceive);
+        self.escrow.reprice(receive)?;
+        Ok(())
+    }
+
+    // prices the whole deposit at the pool's spot price, moved by the maker's premium.
+    // the pool's reserves have to meet the escrow's minimums, which make moving the price
+    // within the take's transaction costlier but not impossible, see MakeOptions::pool
+    pub fn apply_pool_price(&mut self) -> Result<()> {
+        if !self.escrow.is_pool_priced() {
+            return Ok(());
+        }
+        let (Some(pool), Some(vault_0), Some(vault_1)) =
+            (&self.pool, &self.pool_vault_0, &self.pool_vault_1)
+        else {
+            return Err(ErrorCode::PoolMismatch.into());
+        };
+        require_keys_eq!(
+            *pool.owner,
+            self.escrow.pool_program,
+            ErrorCode::PoolMismatch
+        );
+        let reserves = read_reserves(
+            pool,
+            vault_0,
+            vault_1,
+            &self.escrow.mint_a,
+            &self.escrow.mint_b,
+        )?;
+        require!(
+            reserves.reserve_a >= self.escrow.min_reserve_a
+                && reserves.reserve_b >= self.escrow.min_reserve_b,
+            ErrorCode::PoolReservesTooLow
+        );
+
+        let receive = reserves.payment(self.escrow.deposit, self.escrow.pool_premium_bps)?;
+        require!(receive > 0, ErrorCode::ZeroReceive);
+        self.escrow.reprice(receive)?;
+        Ok(())
+    }
+
+    // the maker can reprice between the taker signing and the take landing. runs after
+    // apply_auction_price, so an auction is judged by the price it is filled at. 0 leaves
+    // the price unbounded, like the fill bounds
+    pub fn check_slippage(&self, max_receive: u64) -> Result<()> {
+        require!(
+            max_receive == 0 || self.escrow.receive <= max_receive,
+            ErrorCode::SlippageExceeded
+        );
+        Ok(())
+    }
+
+    // bounds on this fill rather than on the price of the whole escrow. the taker receives
+    // amount_a less a mint_a transfer fee and pays amount_b before a grossed up mint_b
+    // transfer fee. 0 leaves either side unbounded
+    pub fn check_fill_bounds(
+        &self,
+        amount_a: u64,
+        amount_b: u64,
+        expected_receive_a: u64,
+        max_pay_b: u64,
+    ) -> Result<()> {
+        let received = amount_a - transfer_fee(&self.mint_a.to_account_info(), amount_a)?;
+        require!(
+            expected_receive_a == 0 || received >= expected_receive_a,
+            ErrorCode::SlippageExceeded
+        );
+        require!(
+            max_pay_b == 0 || amount_b <= max_pay_b,
+            ErrorCode::SlippageExceeded
+        );
+        Ok(())
+    }
+
+    // take_with_quote replaces the price of the whole deposit with one the maker signed
+    // off chain over (escrow, mint_b, created_slot, remaining, price, expiry). the Ed25519
+    // instruction before the take carries the signature. the take fills everything that
+    // is left and so finishes the escrow, which spends the quote. remaining keeps it from
+    // an escrow a fill, top up or withdraw changed since, created_slot from a later escrow
+    // at the same address. the floor binds a signed price like any other the maker sets
+    pub fn check_quote(&self, price: u64, expiry: i64, signature: &[u8; 64]) -> Result<()> {
+        require!(price > 0, ErrorCode::ZeroReceive);
+        require!(
+            price >= self.escrow.min_receive_floor,
+            ErrorCode::BelowPriceFloor
+        );
+        require!(!self.escrow.is_auction(), ErrorCode::AuctionPriced);
+        require!(!self.escrow.is_unit_priced(), ErrorCode::UnitPriced);
+        require!(
+            Clock::get()?.unix_timestamp < expiry,
+            ErrorCode::QuoteExpired
+        );
+        let instructions = self
+            .sysvar_instructions
+            .as_ref()
+            .ok_or(ErrorCode::InvalidQuoteSignature)?;
+
+        let mut message = self.escrow.key().to_bytes().to_vec();
+        message.extend_from_slice(self.escrow.mint_b.as_ref());
+        message.extend_from_slice(&self.escrow.created_slot.to_le_bytes());
+        message.extend_from_slice(&self.escrow.remaining.to_le_bytes());
+        message.extend_from_slice(&price.to_le_bytes());
+        message.extend_from_slice(&expiry.to_le_bytes());
+        verify_ed25519(instructions, &self.escrow.maker, &message, signature)
+    }
+
+    // take_with_terms repeats the terms it agrees to, so a maker can't swap them out
+    // between the taker signing and the take landing
+    pub fn check_terms(&self, receive: u64) -> Result<()> {
+        require!(self.escrow.has_pending_terms(), ErrorCode::NoPendingTerms);
+        require_keys_eq!(
+            self.taker.key(),
+            self.escrow.terms_taker,
+            ErrorCode::UnauthorizedTaker
+        );
+        require!(
+            !self.escrow.terms_expired(Clock::get()?.slot),
+            ErrorCode::TermsExpired
+        );
+        require!(
+            receive == self.escrow.terms_receive,
+            ErrorCode::TermsMismatch
+        );
+        // the terms price the whole deposit, a fill since the proposal locked the price
+        require!(!self.escrow.is_filled(), ErrorCode::EscrowAlreadyFilled);
+        Ok(())
+    }
+
+    // the taker has to own the NFT, a delegate or a freeze on the account doesn't change
+    // that. pNFTs are always frozen
+    pub fn check_collection_gate(&self) -> Result<()> {
+        if !self.escrow.is_collection_gated() {
+            return Ok(());
+        }
+        let (Some(gate_token), Some(gate_metadata)) = (&self.gate_token, &self.gate_metadata)
+        else {
+            return Err(ErrorCode::TakerNotCollectionHolder.into());
+        };
+        require!(
+            gate_token.owner == self.taker.key() && gate_token.amount == 1,
+            ErrorCode::TakerNotCollectionHolder
+        );
+        // a wrong or unreadable metadata account fails like a missing holding
+        check_metadata(gate_metadata, &gate_token.mint)
+            .map_err(|_| ErrorCode::TakerNotCollectionHolder)?;
+        let collection =
+            verified_collection(gate_metadata).map_err(|_| ErrorCode::TakerNotCollectionHolder)?;
+        require!(
+            collection == Some(self.escrow.gate_collection),
+            ErrorCode::TakerNotCollectionHolder
+        );
+        Ok(())
+    }
+
+    pub fn check_mint_gate(&self) -> Result<()> {
+        if !self.escrow.is_mint_gated() {
+            return Ok(());
+        }
+        let gate_mint_token = self
+            .gate_mint_token
+            .as_ref()
+            .ok_or(ErrorCode::InvalidGateAccount)?;
+        require!(
+            gate_mint_token.mint == self.escrow.gate_mint
+                && gate_mint_token.owner == self.taker.key(),
+            ErrorCode::InvalidGateAccount
+        );
+        require!(
+            gate_mint_token.amount >= self.escrow.gate_min_balance,
+            ErrorCode::GateBalanceTooLow
+        );
+        Ok(())
+    }
+
+    // a frozen maker or taker account would fail the transfer CPIs halfway, so it is
+    // named up front. pNFT token accounts stay frozen between transfers, Token Metadata
+    // thaws the taker's for the transfer
+    pub fn check_frozen(&self) -> Result<()> {
+        let maker_frozen = self
+            .maker_ata_b
+            .as_ref()
+            .is_some_and(|maker_ata_b| maker_ata_b.is_frozen());
+        let taker_frozen = !self.escrow.is_programmable() && self.taker_ata_a.is_frozen();
+        require!(!maker_frozen && !taker_frozen, ErrorCode::AccountFrozen);
+        Ok(())
+    }
+
+    // take_alt reuses these accounts with mint_b set to the payment mint, the other
+    // takes only settle in the escrow's mint_b
+    pub fn check_payment_mint(&self) -> Result<()> {
+        require!(
+            self.mint_b.key() == self.escrow.mint_b,
+            ErrorCode::UnsupportedPaymentMint
+        );
+        Ok(())
+    }
+
+    // the maker is paid first and mint_a only leaves the vault once that transfer went
+    // through. a failing CPI aborts the instruction, which rolls back every earlier
+    // transfer with it. returns (mint_a sent, fee, referral)
+    pub fn fill(
+        &mut self,
+        amount_a: u64,
+        amount_b: u64,
+        extra: &[AccountInfo<'info>],
+    ) -> Result<(u64, u64, u64)> {
+        let (fee, referral) = self.settle(amount_b, extra)?;
+        self.escrow.remaining_receive -= amount_b;
+        let sent = self.withdraw_and_close_vault(amount_a, extra)?;
+        self.record_take(sent, amount_b)?;
+        Ok((sent, fee, referral))
+    }
+
+    // take_alt pays for everything that is left, in whichever accepted mint mint_b holds
+    pub fn fill_all(
+        &mut self,
+        amount_b: u64,
+        extra: &[AccountInfo<'info>],
+    ) -> Result<(u64, u64, u64)> {
+        let (fee, referral) = self.settle(amount_b, extra)?;
+        let sent = self.withdraw_and_close_vault(self.escrow.remaining, extra)?;
+        self.record_take(sent, amount_b)?;
+        Ok((sent, fee, referral))
+    }
+
+    // take_with_payment pays in one of the escrow's payment options, which mint_b holds
+    pub fn fill_with_payment(
+        &mut self,
+        amount_a: u64,
+        payment: u64,
+        extra: &[AccountInfo<'info>],
+    ) -> Result<(u64, u64, u64)> {
+        let (fee, referral) = self.settle(payment, extra)?;
+        self.escrow.release_receive(amount_a)?;
+        let sent = self.withdraw_and_close_vault(amount_a, extra)?;
+        self.record_take(sent, payment)?;
+        Ok((sent, fee, referral))
+    }
+
+    fn record_take(&mut self, amount_a: u64, amount_b: u64) -> Result<()> {
+        let completed = self.escrow.remaining == 0;
+        match &mut self.stats {
+            Some(stats) => stats.record_take(amount_a, amount_b, completed),
+            None => Ok(()),
+        }
+    }
+
+    // the taker pays `amount` in total, the protocol fee and the referral are split off
+    // before the maker is paid. a mint_b transfer fee comes out of the maker's share unless
+    // the escrow grosses it up. returns (fee, referral)
+    fn settle(&mut self, amount: u64, extra: &[AccountInfo<'info>]) -> Result<(u64, u64)> {
+        // fail with a named error instead of the token program's. a grossed up transfer
+        // fee comes on top and is still left to the token program
+        require!(
+            self.taker_ata_b.amount >= amount,
+            ErrorCode::InsufficientTakerFunds
+        );
+        let fee = protocol_fee(amount, self.config.fee_bps)?;
+        if fee > 0 {
+            let fee_collector_ata_b = self
+                .fee_collector_ata_b
+                .as_ref()
+                .ok_or(ErrorCode::MissingFeeAccount)?;
+            self.pay(fee_collector_ata_b.to_account_info(), fee, extra)?;
+        }
+
+        // REFERRAL_BPS is below 10_000 - MAX_FEE_BPS, so fee + referral never exceeds amount
+        let referral = match &self.referrer_ata_b {
+            Some(referrer_ata_b) => {
+                let referral = mul_div_floor(amount, REFERRAL_BPS as u64, 10_000)?;
+                if referral > 0 {
+                    self.pay(referrer_ata_b.to_account_info(), referral, extra)?;
+                }
+                referral
+            }
+            None => 0,
+        };
+
+        let share = amount - fee - referral;
+        if let Some(unwrap_b) = &self.unwrap_b {
+            require!(
+                !self.escrow.receive_to_vault,
+                ErrorCode::PaymentVaultUnsupported
+            );
+            self.pay_unwrapped(unwrap_b.to_account_info(), share, extra)?;
+            return Ok((fee, referral));
+        }
+
+        let mint_b = self.mint_b.to_account_info();
+        let (sent, expected) = if self.escrow.gross_up_transfer_fee {
+            (gross_up(&mint_b, share)?, share)
+        } else {
+            (share, share - transfer_fee(&mint_b, share)?)
+        };
+        let receive_to_vault = self.escrow.receive_to_vault;
+        let maker_ata_b = if receive_to_vault {
+            self.payment_vault
+                .as_mut()
+                .ok_or(ErrorCode::PaymentVaultRequired)?
+        } else {
+            self.maker_ata_b
+                .as_mut()
+                .ok_or(ErrorCode::MakerAtaRequired)?
+        };
+        // reloaded first in case the maker is also the fee collector
+        maker_ata_b.reload()?;
+        let before = maker_ata_b.amount;
+        let to = maker_ata_b.to_account_info();
+        self.pay(to, sent, extra)?;
+        let maker_ata_b = if receive_to_vault {
+            self.payment_vault.as_mut()
+        } else {
+            self.maker_ata_b.as_mut()
+        };
+        if let Some(maker_ata_b) = maker_ata_b {
+            check_received(maker_ata_b, before, expected)?;
+        }
+        Ok((fee, referral))
+    }
+
+    // lamports the maker received in the wallet instead of tokens, out of what the taker paid
+    fn unwrapped(&self, amount_b: u64, fee: u64, referral: u64) -> u64 {
+        if self.unwrap_b.is_some() {
+            amount_b - fee - referral
+        } else {
+            0
+        }
+    }
+
+    // None when the take paid no referral
+    fn referral_paid(&self, amount: u64) -> Option<ReferralPaid> {
+        let referrer_ata_b = self.referrer_ata_b.as_ref().filter(|_| amount > 0)?;
+        Some(ReferralPaid {
+            escrow: self.escrow.key(),
+            taker: self.taker.key(),
+            referrer: referrer_ata_b.owner,
+            mint_b: self.mint_b.key(),
+            amount,
+        })
+    }
+
+    // the taker's wrapped SOL goes into unwrap_b, which is closed into the escrow and the
+    // lamports handed on: `amount` to the maker, the rent back to the taker who put it up
+    fn pay_unwrapped(
+        &mut self,
+        unwrap_b: AccountInfo<'info>,
+        amount: u64,
+        extra: &[AccountInfo<'info>],
+    ) -> Result<()> {
+        require_keys_eq!(
+            self.mint_b.key(),
+            native_mint::ID,
+            ErrorCode::MintBNotNative
+        );
+        let escrow_key = self.escrow.key();
+        let (_, bump) = Pubkey::find_program_address(&[b"unwrap", escrow_key.as_ref()], &crate::ID);
+        let unwrap_seeds: [&[&[u8]]; 1] = [&[b"unwrap", escrow_key.as_ref(), &[bump]]];
+        let namespace = self.escrow.namespace;
+        let escrow_seeds: [&[&[u8]]; 1] = [&[
+            b"escrow",
+            self.escrow.maker.as_ref(),
+            namespace_seed(&namespace),
+            &self.escrow.seed.to_le_bytes()[..],
+            &[self.escrow.bump],
+        ]];
+
+        create_pda_account(
+            self.taker.to_account_info(),
+            unwrap_b.clone(),
+            spl_token::state::Account::LEN,
+            &self.token_program_b.key(),
+            &unwrap_seeds,
+            self.system_program.to_account_info(),
+        )?;
+        // the taker gets back whatever the account held, lamports sent to it beforehand too
+        let rent = unwrap_b.lamports();
+        let accounts = InitializeAccount3 {
+            account: unwrap_b.clone(),
+            mint: self.mint_b.to_account_info(),
+            authority: self.escrow.to_account_info(),
+        };
+        initialize_account3(CpiContext::new(
+            self.token_program_b.to_account_info(),
+            accounts,
+        ))?;
+
+        self.pay(unwrap_b.clone(), amount, extra)?;
+
+        let accounts = CloseAccount {
+            account: unwrap_b,
+            destination: self.escrow.to_account_info(),
+            authority: self.escrow.to_account_info(),
+        };
+        close_account(CpiContext::new_with_signer(
+            self.token_program_b.to_account_info(),
+            accounts,
+            &escrow_seeds,
+        ))?;
+
+        self.escrow.sub_lamports(amount + rent)?;
+        self.maker.add_lamports(amount)?;
+        self.taker.add_lamports(rent)?;
+        Ok(())
+    }
+
+    fn pay(&self, to: AccountInfo<'info>, amount: u64, extra: &[AccountInfo<'info>]) -> Result<()> {
         let transfer_accounts = TransferChecked {
             from: self.taker_ata_b.to_account_info(),
             mint: self.mint_b.to_account_info(),
-            to: self.maker_ata_b.to_account_info(),
+            to,
             authority: self.taker.to_account_info(),
         };
 
-        let cpi_ctx = CpiContext::new(self.token_program.to_account_info(), transfer_accounts);
-        transfer_checked(cpi_ctx, self.escrow.receive, self.mint_b.decimals)?;
+        let cpi_ctx = CpiContext::new(self.token_program_b.to_account_info(), transfer_accounts)
+            .with_remaining_accounts(extra.to_vec());
+        transfer_checked_with_hook(cpi_ctx, amount, self.mint_b.decimals)
+    }
+
+    // the deposit stays in the vault for claim_vested, nothing is sent to the taker yet.
+    // min_fill makes this the one and only fill
+    fn start_vesting(&mut self, amount: u64) -> Result<u64> {
+        require!(amount == self.escrow.remaining, ErrorCode::BelowMinimumFill);
+        self.escrow.remaining = 0;
+        self.escrow.status = EscrowStatus::Vesting;
+        self.escrow.vest_start = Clock::get()?.unix_timestamp;
+        self.escrow.vest_taker = self.taker.key();
+        Ok(0)
+    }
 
+    // sends every basket leg to the taker and closes its vault. the leg accounts lead
+    // `extra`, see basket.rs. tokens sent to a leg's vault from outside go along with it
+    fn release_legs(&self, extra: &[AccountInfo<'info>], signer_seeds: &[&[&[u8]]]) -> Result<()> {
+        let accounts = BasketAccounts {
+            escrow: self.escrow.to_account_info(),
+            owner: self.taker.to_account_info(),
+            payer: self.taker.to_account_info(),
+            token_program: self.token_program_a.to_account_info(),
+            associated_token_program: self.associated_token_program.to_account_info(),
+            system_program: self.system_program.to_account_info(),
+        };
+        let destination = self.rent_destination()?;
+        let legs = accounts.legs(self.escrow.basket_mints(), extra)?;
+        for (leg, amount) in legs.iter().zip(self.escrow.basket_amounts) {
+            let released = accounts.release(leg, destination.clone(), extra, signer_seeds)?;
+            require!(released >= amount, ErrorCode::BasketLegMismatch);
+        }
         Ok(())
     }
 
-    pub fn withdraw_and_close_vault(&mut self) -> Result<()> {
+    // returns the mint_a actually sent, none for a vesting escrow. the last fill empties
+    // the vault so that it can be closed.
+    // `extra` holds the accounts transfer hooks on either mint need, or the pNFT accounts
+    fn withdraw_and_close_vault(
+        &mut self,
+        amount: u64,
+        extra: &[AccountInfo<'info>],
+    ) -> Result<u64> {
+        if self.escrow.vests_deposit() {
+            return self.start_vesting(amount);
+        }
+        let namespace = self.escrow.namespace;
         let signer_seeds: [&[&[u8]]; 1] = [&[
             b"escrow",
             self.maker.to_account_info().key.as_ref(),
+            namespace_seed(&namespace),
             &self.escrow.seed.to_le_bytes()[..],
             &[self.escrow.bump],
         ]];
 
+        let last_fill = amount == self.escrow.remaining;
+        let sent = amount;
+        // the vault only holds the deposit, so a mint_a transfer fee can't be grossed up
+        // and always comes out of what the taker receives
+        let expected = sent - transfer_fee(&self.mint_a.to_account_info(), sent)?;
+        let before = self.taker_ata_a.amount;
+        if self.escrow.is_programmable() {
+            let pnft = PnftAccounts::from_remaining(extra, &self.mint_a.key())?;
+            let transfer = PnftTransfer {
+                token: self.vault.to_account_info(),
+                token_owner: self.escrow.to_account_info(),
+                destination: self.taker_ata_a.to_account_info(),
+                destination_owner: self.taker.to_account_info(),
+                mint: self.mint_a.to_account_info(),
+                authority: self.escrow.to_account_info(),
+                payer: self.taker.to_account_info(),
+                system_program: self.system_program.to_account_info(),
+                token_program: self.token_program_a.to_account_info(),
+                associated_token_program: self.associated_token_program.to_account_info(),
+            };
+            transfer_pnft(transfer, &pnft, sent, &signer_seeds)?;
+        } else {
+            let accounts = TransferChecked {
+                from: self.vault.to_account_info(),
+                mint: self.mint_a.to_account_info(),
+                to: self.taker_ata_a.to_account_info(),
+                authority: self.escrow.to_account_info(),
+            };
+            let cpi_ctx = CpiContext::new_with_signer(
+                self.token_program_a.to_account_info(),
+                accounts,
+                &signer_seeds,
+            )
+            .with_remaining_accounts(extra.to_vec());
+            transfer_checked_with_hook(cpi_ctx, sent, self.mint_a.decimals)?;
+        }
+        check_received(&mut self.taker_ata_a, before, expected)?;
+
+        self.escrow.remaining -= amount;
+        self.escrow.sync_unit_price()?;
+        if !last_fill {
+            return Ok(sent);
+        }
+        self.escrow.fold_second_mint();
+        if self.escrow.basket_len > 0 {
+            self.release_legs(extra, &signer_seeds)?;
+        }
+        // the vault's balance from before this fill
+        let excess = self.vault.amount - amount;
+        if excess > 0 {
+            self.sweep_excess(excess, extra, &signer_seeds)?;
+        }
+
+        // guarded in case Token Metadata already closed the emptied pNFT vault
+        if self.vault.to_account_info().lamports() > 0 {
+            let accounts = CloseAccount {
+                account: self.vault.to_account_info(),
+                destination: self.rent_destination()?,
+                authority: self.escrow.to_account_info(),
+            };
+
+            let ctx = CpiContext::new_with_signer(
+                self.token_program_a.to_account_info(),
+                accounts,
+                &signer_seeds,
+            );
+
+            close_account(ctx)?;
+        }
+
+        // escrow is no longer closed by the constraint since partial fills keep it open
+        let destination = self.rent_destination()?;
+        finish_escrow(
+            &mut self.escrow,
+            EscrowStatus::Completed,
+            self.taker.key(),
+            destination,
+            &self.maker_index,
+        )?;
+        Ok(sent)
+    }
+
+    fn sweep_excess(
+        &self,
+        excess: u64,
+        extra: &[AccountInfo<'info>],
+        signer_seeds: &[&[&[u8]]],
+    ) -> Result<()> {
+        let maker_ata_a = self
+            .maker_ata_a
+            .as_ref()
+            .ok_or(ErrorCode::MakerAtaRequired)?;
         let accounts = TransferChecked {
             from: self.vault.to_account_info(),
             mint: self.mint_a.to_account_info(),
-            to: self.taker_ata_a.to_account_info(),
+            to: maker_ata_a.to_account_info(),
             authority: self.escrow.to_account_info(),
         };
-
         let cpi_ctx = CpiContext::new_with_signer(
-            self.token_program.to_account_info(),
+            self.token_program_a.to_account_info(),
             accounts,
-            &signer_seeds,
-        );
-
-        transfer_checked(cpi_ctx, self.vault.amount, self.mint_a.decimals)?;
-
-        let accounts = CloseAccount {
-            account: self.vault.to_account_info(),
-            destination: self.maker.to_account_info(),
-            authority: self.escrow.to_account_info(),
-        };
-
-        let ctx = CpiContext::new_with_signer(
-            self.token_program.to_account_info(),
-            accounts,
-            &signer_seeds,
-        );
-
-        close_account(ctx)
+            signer_seeds,
+        )
+        .with_remaining_accounts(extra.to_vec());
+        transfer_checked_with_hook(cpi_ctx, excess, self.mint_a.decimals)
     }
 }
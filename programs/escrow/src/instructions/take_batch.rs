@@ -0,0 +1,325 @@
+use anchor_lang::prelude::*;
+
+use anchor_spl::{
+    associated_token::get_associated_token_address_with_program_id,
+    token_interface::{
+        close_account, CloseAccount, Mint, TokenAccount, TokenInterface, TransferChecked,
+    },
+};
+
+use crate::error::ErrorCode;
+use crate::extensions::{gross_up, transfer_checked_with_hook};
+use crate::pda::{maker_index_pda, vault_pda};
+use crate::state::{
+    finish_escrow, namespace_seed, protocol_fee, rent_destination, Config, Escrow, EscrowStatus,
+    Stats,
+};
+use crate::MAX_BATCH;
+
+// remaining accounts per escrow in take_batch
+pub const TAKE_BATCH_LEG: usize = 12;
+
+// take for several escrows at once, across makers and mints. each escrow comes in as
+// TAKE_BATCH_LEG remaining accounts in the order of `amounts`:
+// [escrow, vault, maker, mint_a, mint_b, maker_ata_a, maker_ata_b, taker_ata_a,
+// taker_ata_b, fee_collector_ata_b, rent_recipient, maker_index], with the maker again as
+// rent_recipient when make named none. maker_index is the maker's index PDA, see MakerIndex.
+// maker_ata_a only has to exist when the last fill sweeps an excess, every other ATA has
+// to exist already. every mint has to live under `token_program`, without a transfer
+// hook. a failing leg fails the instruction, so no leg is taken on its own
+#[event_cpi]
+#[derive(Accounts)]
+pub struct TakeBatch<'info> {
+    #[account(mut)]
+    pub taker: Signer<'info>,
+
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProtocolPaused,
+    )]
+    pub config: Account<'info, Config>,
+
+    // adds every fill to the volumes, and each escrow to the taken ones on its last fill
+    #[account(
+        mut,
+        seeds = [b"stats"],
+        bump = stats.bump,
+    )]
+    pub stats: Option<Account<'info, Stats>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+// what take_batch reports for each escrow it took
+pub struct BatchFill {
+    pub escrow: Pubkey,
+    pub maker: Pubkey,
+    pub mint_a: Pubkey,
+    pub mint_b: Pubkey,
+    pub amount_a: u64,
+    pub amount_b: u64,
+    pub fee: u64,
+    pub remaining: u64,
+}
+
+impl<'info> TakeBatch<'info> {
+    pub fn check_batch(
+        &self,
+        amounts: &[u64],
+        max_pay_b: &[u64],
+        accounts: &[AccountInfo<'info>],
+    ) -> Result<()> {
+        require!(
+            max_pay_b.len() == amounts.len() && accounts.len() == TAKE_BATCH_LEG * amounts.len(),
+            ErrorCode::BatchLengthMismatch
+        );
+        require!(amounts.len() <= MAX_BATCH, ErrorCode::BatchTooLarge);
+        Ok(())
+    }
+
+    // does what the account constraints and take do for a single escrow. escrows that
+    // need take's optional accounts are left to take
+    pub fn take_one(
+        &mut self,
+        amount: u64,
+        max_pay_b: u64,
+        accounts: &'info [AccountInfo<'info>],
+    ) -> Result<BatchFill> {
+        let [escrow, vault, maker, mint_a, mint_b, maker_ata_a, maker_ata_b, taker_ata_a, taker_ata_b, fee_collector_ata_b, rent_recipient, maker_index] =
+            accounts
+        else {
+            return Err(ErrorCode::BatchLengthMismatch.into());
+        };
+        // owner and discriminator are checked here. escrows only ever exist at their PDA
+        let mut escrow_state = Account::<Escrow>::try_from(escrow)?;
+        self.check_escrow(&escrow_state)?;
+
+        let mint_a_state = InterfaceAccount::<Mint>::try_from(mint_a)?;
+        let mint_b_state = InterfaceAccount::<Mint>::try_from(mint_b)?;
+        let token_program = self.token_program.key();
+        require!(
+            maker.key() == escrow_state.maker
+                && mint_a.key() == escrow_state.mint_a
+                && mint_b.key() == escrow_state.mint_b
+                && *mint_a.owner == token_program
+                && *mint_b.owner == token_program,
+            ErrorCode::InvalidBatchAccount
+        );
+        let ata = |owner: &Pubkey, mint: &AccountInfo| {
+            get_associated_token_address_with_program_id(owner, &mint.key(), &token_program)
+        };
+        require!(
+            vault.key() == vault_pda(&escrow.key(), &mint_a.key(), &token_program)
+                && maker_ata_b.key() == ata(&maker.key(), mint_b)
+                && taker_ata_a.key() == ata(&self.taker.key(), mint_a)
+                && taker_ata_b.key() == ata(&self.taker.key(), mint_b),
+            ErrorCode::InvalidBatchAccount
+        );
+        // like take's vault constraint, whatever sits on top goes back to the maker
+        let vault_state = InterfaceAccount::<TokenAccount>::try_from(vault)?;
+        require!(
+            vault_state.amount >= escrow_state.remaining,
+            ErrorCode::VaultBalanceMismatch
+        );
+
+        escrow_state.apply_auction_price(Clock::get()?.unix_timestamp)?;
+        let (amount_a, amount_b) = escrow_state.fill_for_amount_a(amount)?;
+        require!(
+            max_pay_b == 0 || amount_b <= max_pay_b,
+            ErrorCode::SlippageExceeded
+        );
+        require!(
+            InterfaceAccount::<TokenAccount>::try_from(taker_ata_b)?.amount >= amount_b,
+            ErrorCode::InsufficientTakerFunds
+        );
+
+        // mint_a only leaves the vault once the payment went through, like in take
+        let fee = protocol_fee(amount_b, self.config.fee_bps)?;
+        if fee > 0 {
+            require_keys_eq!(
+                fee_collector_ata_b.key(),
+                ata(&self.config.fee_collector, mint_b),
+                ErrorCode::InvalidBatchAccount
+            );
+            self.transfer(
+                taker_ata_b,
+                &mint_b_state,
+                fee_collector_ata_b,
+                self.taker.to_account_info(),
+                fee,
+                &[],
+            )?;
+        }
+        let share = amount_b - fee;
+        let sent_b = if escrow_state.gross_up_transfer_fee {
+            gross_up(mint_b, share)?
+        } else {
+            share
+        };
+        self.transfer(
+            taker_ata_b,
+            &mint_b_state,
+            maker_ata_b,
+            self.taker.to_account_info(),
+            sent_b,
+            &[],
+        )?;
+
+        let namespace = escrow_state.namespace;
+        let signer_seeds: [&[&[u8]]; 1] = [&[
+            b"escrow",
+            maker.key.as_ref(),
+            namespace_seed(&namespace),
+            &escrow_state.seed.to_le_bytes()[..],
+            &[escrow_state.bump],
+        ]];
+        let last_fill = amount_a == escrow_state.remaining;
+        self.transfer(
+            vault,
+            &mint_a_state,
+            taker_ata_a,
+            escrow.clone(),
+            amount_a,
+            &signer_seeds,
+        )?;
+
+        escrow_state.remaining_receive -= amount_b;
+        escrow_state.remaining -= amount_a;
+        escrow_state.sync_unit_price()?;
+        if let Some(stats) = &mut self.stats {
+            stats.record_take(amount_a, amount_b, last_fill)?;
+        }
+        let remaining = escrow_state.remaining;
+        if last_fill {
+            let excess = vault_state.amount - amount_a;
+            if excess > 0 {
+                require_keys_eq!(
+                    maker_ata_a.key(),
+                    ata(&maker.key(), mint_a),
+                    ErrorCode::InvalidBatchAccount
+                );
+                self.transfer(
+                    vault,
+                    &mint_a_state,
+                    maker_ata_a,
+                    escrow.clone(),
+                    excess,
+                    &signer_seeds,
+                )?;
+            }
+
+            // like take's rent_recipient account, only checked when make named one
+            let rent_recipient = UncheckedAccount::try_from(rent_recipient);
+            if escrow_state.rent_recipient != Pubkey::default() {
+                require_keys_eq!(
+                    rent_recipient.key(),
+                    escrow_state.rent_recipient,
+                    ErrorCode::RentRecipientMismatch
+                );
+            }
+            let destination =
+                rent_destination(&escrow_state, maker.clone(), Some(&rent_recipient))?;
+            let accounts = CloseAccount {
+                account: vault.clone(),
+                destination: destination.clone(),
+                authority: escrow.clone(),
+            };
+            close_account(CpiContext::new_with_signer(
+                self.token_program.to_account_info(),
+                accounts,
+                &signer_seeds,
+            ))?;
+            // checked on the last fill only, the one that drops the escrow from the index
+            require_keys_eq!(
+                maker_index.key(),
+                maker_index_pda(&maker.key()).0,
+                ErrorCode::InvalidBatchAccount
+            );
+            finish_escrow(
+                &mut escrow_state,
+                EscrowStatus::Completed,
+                self.taker.key(),
+                destination,
+                maker_index,
+            )?;
+        }
+        // written back now, a later leg may load the same escrow again
+        escrow_state.exit(&crate::ID)?;
+
+        Ok(BatchFill {
+            escrow: escrow.key(),
+            maker: maker.key(),
+            mint_a: mint_a.key(),
+            mint_b: mint_b.key(),
+            amount_a,
+            amount_b,
+            fee,
+            remaining,
+        })
+    }
+
+    // the checks of take's escrow constraint and handler that don't need extra accounts
+    fn check_escrow(&self, escrow: &Escrow) -> Result<()> {
+        let taker = self.taker.key();
+        let clock = Clock::get()?;
+        require!(escrow.is_open(), ErrorCode::EscrowNotOpen);
+        require!(!escrow.deposit_is_sol, ErrorCode::DepositIsSol);
+        require!(escrow.active, ErrorCode::EscrowPaused);
+        require!(
+            escrow.is_allowed_taker(&taker),
+            ErrorCode::UnauthorizedTaker
+        );
+        require!(
+            escrow.is_whitelisted(&taker),
+            ErrorCode::TakerNotWhitelisted
+        );
+        require!(
+            !escrow.blocks_self_trade(&taker),
+            ErrorCode::SelfTradeNotAllowed
+        );
+        require!(
+            !escrow.is_expired(clock.unix_timestamp),
+            ErrorCode::EscrowExpired
+        );
+        require!(
+            !escrow.is_cooling_down(clock.slot),
+            ErrorCode::CooldownNotElapsed
+        );
+        require!(
+            !escrow.is_basket()
+                && !escrow.is_programmable()
+                && !escrow.vests_deposit()
+                && !escrow.is_collection_gated()
+                && !escrow.is_mint_gated()
+                && !escrow.is_oracle_priced()
+                && !escrow.is_pool_priced()
+                && !escrow.receive_to_vault,
+            ErrorCode::TakeBatchUnsupported
+        );
+        Ok(())
+    }
+
+    fn transfer(
+        &self,
+        from: &AccountInfo<'info>,
+        mint: &InterfaceAccount<'info, Mint>,
+        to: &AccountInfo<'info>,
+        authority: AccountInfo<'info>,
+        amount: u64,
+        signer_seeds: &[&[&[u8]]],
+    ) -> Result<()> {
+        let accounts = TransferChecked {
+            from: from.clone(),
+            mint: mint.to_account_info(),
+            to: to.clone(),
+            authority,
+        };
+        let cpi_ctx = CpiContext::new_with_signer(
+            self.token_program.to_account_info(),
+            accounts,
+            signer_seeds,
+        );
+        transfer_checked_with_hook(cpi_ctx, amount, mint.decimals)
+    }
+}
@@ -0,0 +1,264 @@
+use anchor_lang::prelude::*;
+
+use anchor_lang::system_program::{transfer as system_transfer, Transfer};
+use anchor_spl::{
+    associated_token::AssociatedToken,
+    token_interface::{
+        close_account, CloseAccount, Mint, TokenAccount, TokenInterface, TransferChecked,
+    },
+};
+
+use crate::error::ErrorCode;
+use crate::extensions::{check_received, transfer_checked_with_hook, transfer_fee};
+use crate::state::{
+    finish_escrow, namespace_seed, protocol_fee, rent_destination, Config, Escrow, EscrowStatus,
+    Stats,
+};
+
+// token escrows asking for wrapped SOL can also be paid in plain lamports, so the taker
+// needs no wSOL account. like take_sol the escrow is filled in one go
+#[event_cpi]
+#[derive(Accounts)]
+pub struct TakeNative<'info> {
+    #[account(mut)]
+    pub taker: Signer<'info>,
+
+    // may be a PDA owned by another program, lamports can be credited to any account
+    /// CHECK: only receives lamports, pinned to the escrow's maker
+    #[account(mut, address = escrow.maker)]
+    pub maker: UncheckedAccount<'info>,
+
+    #[account(
+        mint::token_program = token_program_a,
+    )]
+    pub mint_a: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        init_if_needed,
+        payer = taker,
+        associated_token::mint = mint_a,
+        associated_token::authority = taker,
+        associated_token::token_program = token_program_a,
+    )]
+    pub taker_ata_a: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        has_one = mint_a,
+        seeds = [
+            b"escrow",
+            escrow.maker.key().as_ref(),
+            escrow.namespace_seed(),
+            escrow.seed.to_le_bytes().as_ref(),
+        ],
+        bump = escrow.bump,
+        constraint = escrow.is_open() @ ErrorCode::EscrowNotOpen,
+        constraint = !escrow.deposit_is_sol @ ErrorCode::DepositIsSol,
+        constraint = escrow.receive_is_sol @ ErrorCode::ReceiveNotSol,
+        constraint = escrow.active @ ErrorCode::EscrowPaused,
+        constraint = escrow.is_allowed_taker(&taker.key()) @ ErrorCode::UnauthorizedTaker,
+        constraint = escrow.is_whitelisted(&taker.key()) @ ErrorCode::TakerNotWhitelisted,
+        constraint = !escrow.blocks_self_trade(&taker.key()) @ ErrorCode::SelfTradeNotAllowed,
+        // the gate holdings are only checked by the token takes
+        constraint = !escrow.is_collection_gated() @ ErrorCode::TakerNotCollectionHolder,
+        constraint = !escrow.is_mint_gated() @ ErrorCode::InvalidGateAccount,
+        constraint = !escrow.is_basket() @ ErrorCode::BasketUnsupported,
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint_a,
+        associated_token::authority = escrow,
+        associated_token::token_program = token_program_a,
+        constraint = vault.amount >= escrow.remaining @ ErrorCode::VaultBalanceMismatch,
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    // only needed when the vault holds more than the escrow records, like take's
+    #[account(
+        mut,
+        associated_token::mint = mint_a,
+        associated_token::authority = maker,
+        associated_token::token_program = token_program_a,
+    )]
+    pub maker_ata_a: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProtocolPaused,
+    )]
+    pub config: Account<'info, Config>,
+
+    // adds the fill to the volumes and the escrow to the taken ones
+    #[account(
+        mut,
+        seeds = [b"stats"],
+        bump = stats.bump,
+    )]
+    pub stats: Option<Account<'info, Stats>>,
+
+    // can be left out while the config charges no fee
+    /// CHECK: only receives the fee in lamports, pinned to the config
+    #[account(mut, address = config.fee_collector)]
+    pub fee_collector: Option<UncheckedAccount<'info>>,
+
+    // the escrow's rent_recipient, only needed when make named one
+    /// CHECK: only receives lamports, pinned to the escrow
+    #[account(mut, address = escrow.rent_recipient @ ErrorCode::RentRecipientMismatch)]
+    pub rent_recipient: Option<UncheckedAccount<'info>>,
+
+    // the maker's index of open escrows, the take drops the escrow from it
+    /// CHECK: only written by finish_escrow, when it holds an index
+    #[account(mut, seeds = [b"index", escrow.maker.as_ref()], bump)]
+    pub maker_index: UncheckedAccount<'info>,
+
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub token_program_a: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> TakeNative<'info> {
+    fn rent_destination(&self) -> Result<AccountInfo<'info>> {
+        rent_destination(
+            &self.escrow,
+            self.maker.to_account_info(),
+            self.rent_recipient.as_ref(),
+        )
+    }
+
+    pub fn check_expiry(&self) -> Result<()> {
+        require!(
+            !self.escrow.is_expired(Clock::get()?.unix_timestamp),
+            ErrorCode::EscrowExpired
+        );
+        Ok(())
+    }
+
+    pub fn check_cooldown(&self) -> Result<()> {
+        require!(
+            !self.escrow.is_cooling_down(Clock::get()?.slot),
+            ErrorCode::CooldownNotElapsed
+        );
+        Ok(())
+    }
+
+    // the lamport payment always covers everything that is left, so the escrow counts as taken
+    pub fn record_take(&mut self, amount_a: u64, amount_b: u64) -> Result<()> {
+        match &mut self.stats {
+            Some(stats) => stats.record_take(amount_a, amount_b, true),
+            None => Ok(()),
+        }
+    }
+
+    // the taker pays everything that is still owed in lamports, with the same fee split as
+    // take. the maker is paid before any mint_a leaves the vault. returns (paid, fee)
+    pub fn deposit(&mut self) -> Result<(u64, u64)> {
+        let amount = self.escrow.remaining_receive;
+        let fee = protocol_fee(amount, self.config.fee_bps)?;
+        if fee > 0 {
+            let fee_collector = self
+                .fee_collector
+                .as_ref()
+                .ok_or(ErrorCode::MissingFeeAccount)?;
+            self.pay(fee_collector.to_account_info(), fee)?;
+        }
+        self.pay(self.maker.to_account_info(), amount - fee)?;
+        self.escrow.remaining_receive = 0;
+        Ok((amount, fee))
+    }
+
+    // the runtime rejects a transfer leaving an account below rent exemption, checked
+    // here first so the taker gets a clear error instead
+    fn pay(&self, to: AccountInfo<'info>, amount: u64) -> Result<()> {
+        let minimum = Rent::get()?.minimum_balance(to.data_len());
+        require!(
+            to.lamports().saturating_add(amount) >= minimum,
+            ErrorCode::RecipientNotRentExempt
+        );
+
+        let transfer_accounts = Transfer {
+            from: self.taker.to_account_info(),
+            to,
+        };
+        let cpi_ctx = CpiContext::new(self.system_program.to_account_info(), transfer_accounts);
+        system_transfer(cpi_ctx, amount)
+    }
+
+    // sends the deposit to the taker and closes the vault along with the escrow, their rent
+    // goes to the escrow's rent_recipient or else the maker
+    pub fn withdraw_and_close_vault(&mut self, extra: &[AccountInfo<'info>]) -> Result<u64> {
+        let namespace = self.escrow.namespace;
+        let signer_seeds: [&[&[u8]]; 1] = [&[
+            b"escrow",
+            self.maker.to_account_info().key.as_ref(),
+            namespace_seed(&namespace),
+            &self.escrow.seed.to_le_bytes()[..],
+            &[self.escrow.bump],
+        ]];
+
+        let accounts = TransferChecked {
+            from: self.vault.to_account_info(),
+            mint: self.mint_a.to_account_info(),
+            to: self.taker_ata_a.to_account_info(),
+            authority: self.escrow.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(
+            self.token_program_a.to_account_info(),
+            accounts,
+            &signer_seeds,
+        )
+        .with_remaining_accounts(extra.to_vec());
+
+        let sent = self.escrow.remaining;
+        let expected = sent - transfer_fee(&self.mint_a.to_account_info(), sent)?;
+        let before = self.taker_ata_a.amount;
+        transfer_checked_with_hook(cpi_ctx, sent, self.mint_a.decimals)?;
+        check_received(&mut self.taker_ata_a, before, expected)?;
+        self.escrow.remaining = 0;
+
+        // tokens sent to the vault from outside go back to the maker
+        let excess = self.vault.amount - sent;
+        if excess > 0 {
+            let maker_ata_a = self
+                .maker_ata_a
+                .as_ref()
+                .ok_or(ErrorCode::MakerAtaRequired)?;
+            let accounts = TransferChecked {
+                from: self.vault.to_account_info(),
+                mint: self.mint_a.to_account_info(),
+                to: maker_ata_a.to_account_info(),
+                authority: self.escrow.to_account_info(),
+            };
+            let cpi_ctx = CpiContext::new_with_signer(
+                self.token_program_a.to_account_info(),
+                accounts,
+                &signer_seeds,
+            )
+            .with_remaining_accounts(extra.to_vec());
+            transfer_checked_with_hook(cpi_ctx, excess, self.mint_a.decimals)?;
+        }
+
+        let accounts = CloseAccount {
+            account: self.vault.to_account_info(),
+            destination: self.rent_destination()?,
+            authority: self.escrow.to_account_info(),
+        };
+        let ctx = CpiContext::new_with_signer(
+            self.token_program_a.to_account_info(),
+            accounts,
+            &signer_seeds,
+        );
+        close_account(ctx)?;
+        let destination = self.rent_destination()?;
+        finish_escrow(
+            &mut self.escrow,
+            EscrowStatus::Completed,
+            self.taker.key(),
+            destination,
+            &self.maker_index,
+        )?;
+        Ok(sent)
+    }
+}
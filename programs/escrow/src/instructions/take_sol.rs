@@ -0,0 +1,224 @@
+use anchor_lang::prelude::*;
+
+use anchor_lang::system_program::{transfer as system_transfer, Transfer};
+use anchor_spl::{
+    associated_token::AssociatedToken,
+    token_interface::{transfer_checked, Mint, TokenAccount, TokenInterface, TransferChecked},
+};
+
+use crate::error::ErrorCode;
+use crate::extensions::{check_received, gross_up, transfer_fee};
+use crate::state::{
+    finish_escrow, protocol_fee, rent_destination, Config, Escrow, EscrowStatus, Stats,
+};
+
+// SOL escrows are filled in one go. a partial withdrawal could leave the
+// sol_vault below the rent exempt minimum of a system account
+#[derive(Accounts)]
+pub struct TakeSol<'info> {
+    #[account(mut)]
+    pub taker: Signer<'info>,
+    #[account(mut)]
+    pub maker: SystemAccount<'info>,
+
+    #[account(
+        mint::token_program = token_program,
+    )]
+    pub mint_b: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        init_if_needed,
+        payer = taker,
+        associated_token::mint = mint_b,
+        associated_token::authority = maker,
+        associated_token::token_program = token_program,
+    )]
+    pub maker_ata_b: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint_b,
+        associated_token::authority = taker,
+        associated_token::token_program = token_program,
+    )]
+    pub taker_ata_b: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        has_one = maker,
+        has_one = mint_b,
+        seeds = [
+            b"escrow",
+            maker.key().as_ref(),
+            escrow.namespace_seed(),
+            escrow.seed.to_le_bytes().as_ref(),
+        ],
+        bump = escrow.bump,
+        constraint = escrow.is_open() @ ErrorCode::EscrowNotOpen,
+        constraint = escrow.deposit_is_sol @ ErrorCode::DepositNotSol,
+        constraint = escrow.active @ ErrorCode::EscrowPaused,
+        constraint = escrow.is_allowed_taker(&taker.key()) @ ErrorCode::UnauthorizedTaker,
+        constraint = escrow.is_whitelisted(&taker.key()) @ ErrorCode::TakerNotWhitelisted,
+        constraint = !escrow.blocks_self_trade(&taker.key()) @ ErrorCode::SelfTradeNotAllowed,
+        // the gate holdings are only checked by the token takes
+        constraint = !escrow.is_collection_gated() @ ErrorCode::TakerNotCollectionHolder,
+        constraint = !escrow.is_mint_gated() @ ErrorCode::InvalidGateAccount,
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    #[account(
+        mut,
+        seeds = [b"sol_vault", escrow.key().as_ref()],
+        bump,
+    )]
+    pub sol_vault: SystemAccount<'info>,
+
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        constraint = !config.paused @ ErrorCode::ProtocolPaused,
+    )]
+    pub config: Account<'info, Config>,
+
+    // adds the fill to the volumes and the escrow to the taken ones
+    #[account(
+        mut,
+        seeds = [b"stats"],
+        bump = stats.bump,
+    )]
+    pub stats: Option<Account<'info, Stats>>,
+
+    // the fee accounts can be left out while the config charges no fee
+    /// CHECK: only used as the authority of fee_collector_ata_b, pinned to the config
+    #[account(address = config.fee_collector)]
+    pub fee_collector: Option<UncheckedAccount<'info>>,
+
+    #[account(
+        init_if_needed,
+        payer = taker,
+        associated_token::mint = mint_b,
+        associated_token::authority = fee_collector,
+        associated_token::token_program = token_program,
+    )]
+    pub fee_collector_ata_b: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    // the escrow's rent_recipient, only needed when make_sol named one
+    /// CHECK: only receives lamports, pinned to the escrow
+    #[account(mut, address = escrow.rent_recipient @ ErrorCode::RentRecipientMismatch)]
+    pub rent_recipient: Option<UncheckedAccount<'info>>,
+
+    // the maker's index of open escrows, the take drops the escrow from it
+    /// CHECK: only written by finish_escrow, when it holds an index
+    #[account(mut, seeds = [b"index", escrow.maker.as_ref()], bump)]
+    pub maker_index: UncheckedAccount<'info>,
+
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> TakeSol<'info> {
+    fn rent_destination(&self) -> Result<AccountInfo<'info>> {
+        rent_destination(
+            &self.escrow,
+            self.maker.to_account_info(),
+            self.rent_recipient.as_ref(),
+        )
+    }
+
+    pub fn check_expiry(&self) -> Result<()> {
+        require!(
+            !self.escrow.is_expired(Clock::get()?.unix_timestamp),
+            ErrorCode::EscrowExpired
+        );
+        Ok(())
+    }
+
+    pub fn check_cooldown(&self) -> Result<()> {
+        require!(
+            !self.escrow.is_cooling_down(Clock::get()?.slot),
+            ErrorCode::CooldownNotElapsed
+        );
+        Ok(())
+    }
+
+    // the take empties the sol_vault, so the escrow counts as taken. lamports count as mint_a
+    pub fn record_take(&mut self, amount_a: u64, amount_b: u64) -> Result<()> {
+        match &mut self.stats {
+            Some(stats) => stats.record_take(amount_a, amount_b, true),
+            None => Ok(()),
+        }
+    }
+
+    // same fee split as take, the taker pays everything that is still owed.
+    // returns (paid, fee)
+    pub fn deposit(&mut self) -> Result<(u64, u64)> {
+        let amount = self.escrow.remaining_receive;
+        let fee = protocol_fee(amount, self.config.fee_bps)?;
+        if fee > 0 {
+            let fee_collector_ata_b = self
+                .fee_collector_ata_b
+                .as_ref()
+                .ok_or(ErrorCode::MissingFeeAccount)?;
+            self.pay(fee_collector_ata_b.to_account_info(), fee)?;
+        }
+
+        // same mint_b transfer fee handling as take
+        let share = amount - fee;
+        let mint_b = self.mint_b.to_account_info();
+        let (sent, expected) = if self.escrow.gross_up_transfer_fee {
+            (gross_up(&mint_b, share)?, share)
+        } else {
+            (share, share - transfer_fee(&mint_b, share)?)
+        };
+        self.maker_ata_b.reload()?;
+        let before = self.maker_ata_b.amount;
+        self.pay(self.maker_ata_b.to_account_info(), sent)?;
+        check_received(&mut self.maker_ata_b, before, expected)?;
+        Ok((amount, fee))
+    }
+
+    fn pay(&self, to: AccountInfo<'info>, amount: u64) -> Result<()> {
+        let transfer_accounts = TransferChecked {
+            from: self.taker_ata_b.to_account_info(),
+            mint: self.mint_b.to_account_info(),
+            to,
+            authority: self.taker.to_account_info(),
+        };
+
+        let cpi_ctx = CpiContext::new(self.token_program.to_account_info(), transfer_accounts);
+        transfer_checked(cpi_ctx, amount, self.mint_b.decimals)
+    }
+
+    // empties the sol_vault and closes the escrow, its rent goes to the escrow's
+    // rent_recipient or else the maker
+    pub fn withdraw(&mut self, bumps: &TakeSolBumps) -> Result<u64> {
+        let escrow_key = self.escrow.key();
+        let signer_seeds: [&[&[u8]]; 1] =
+            [&[b"sol_vault", escrow_key.as_ref(), &[bumps.sol_vault]]];
+
+        let amount = self.sol_vault.lamports();
+        let transfer_accounts = Transfer {
+            from: self.sol_vault.to_account_info(),
+            to: self.taker.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(
+            self.system_program.to_account_info(),
+            transfer_accounts,
+            &signer_seeds,
+        );
+        system_transfer(cpi_ctx, amount)?;
+
+        self.escrow.remaining = 0;
+        self.escrow.remaining_receive = 0;
+        let destination = self.rent_destination()?;
+        finish_escrow(
+            &mut self.escrow,
+            EscrowStatus::Completed,
+            self.taker.key(),
+            destination,
+            &self.maker_index,
+        )?;
+        Ok(amount)
+    }
+}
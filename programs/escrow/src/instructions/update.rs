@@ -0,0 +1,148 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::spl_token::native_mint;
+use anchor_spl::token_interface::Mint;
+
+use crate::error::ErrorCode;
+use crate::extensions::check_mint_extensions;
+use crate::Escrow;
+
+#[derive(Accounts)]
+pub struct Update<'info> {
+    pub maker: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = maker,
+        seeds = [
+            b"escrow",
+            maker.key().as_ref(),
+            escrow.namespace_seed(),
+            escrow.seed.to_le_bytes().as_ref(),
+        ],
+        bump = escrow.bump,
+        constraint = escrow.is_open() @ ErrorCode::EscrowNotOpen,
+    )]
+    pub escrow: Account<'info, Escrow>,
+}
+
+impl<'info> Update<'info> {
+    // only the asking price changes, the vault and deposit stay untouched.
+    // once a taker has filled part of the escrow the price is locked in
+    pub fn update_receive(&mut self, new_receive: u64) -> Result<u64> {
+        require!(new_receive > 0, ErrorCode::ZeroReceive);
+        require!(
+            new_receive >= self.escrow.min_receive_floor,
+            ErrorCode::BelowPriceFloor
+        );
+        require!(!self.escrow.is_filled(), ErrorCode::EscrowAlreadyFilled);
+        require!(!self.escrow.is_auction(), ErrorCode::AuctionPriced);
+        require!(!self.escrow.is_unit_priced(), ErrorCode::UnitPriced);
+        require!(!self.escrow.is_oracle_priced(), ErrorCode::OraclePriced);
+        require!(!self.escrow.is_pool_priced(), ErrorCode::PoolPriced);
+
+        let old_receive = self.escrow.receive;
+        self.escrow.reprice(new_receive)?;
+        Ok(old_receive)
+    }
+
+    // offers `taker` the whole deposit for `receive` during the next `valid_slots` slots.
+    // proposing again replaces the pending terms. the public price stays as it is
+    pub fn propose_terms(&mut self, receive: u64, taker: Pubkey, valid_slots: u64) -> Result<()> {
+        require!(receive > 0, ErrorCode::ZeroReceive);
+        require!(
+            receive >= self.escrow.min_receive_floor,
+            ErrorCode::BelowPriceFloor
+        );
+        require!(valid_slots > 0, ErrorCode::ZeroAmount);
+        require!(!self.escrow.deposit_is_sol, ErrorCode::DepositIsSol);
+        require!(!self.escrow.is_filled(), ErrorCode::EscrowAlreadyFilled);
+        require!(!self.escrow.is_auction(), ErrorCode::AuctionPriced);
+        require!(!self.escrow.is_unit_priced(), ErrorCode::UnitPriced);
+        require!(
+            self.escrow.is_allowed_taker(&taker) && self.escrow.is_whitelisted(&taker),
+            ErrorCode::UnauthorizedTaker
+        );
+
+        self.escrow.terms_taker = taker;
+        self.escrow.terms_receive = receive;
+        self.escrow.terms_expiry_slot = Clock::get()?.slot.saturating_add(valid_slots);
+        Ok(())
+    }
+
+    // returns the terms that were pending and whether they had already lapsed
+    pub fn clear_terms(&mut self) -> Result<(Pubkey, u64, bool)> {
+        require!(self.escrow.has_pending_terms(), ErrorCode::NoPendingTerms);
+
+        let expired = self.escrow.terms_expired(Clock::get()?.slot);
+        let (taker, receive) = (self.escrow.terms_taker, self.escrow.terms_receive);
+        self.escrow.clear_terms();
+        Ok((taker, receive, expired))
+    }
+
+    // lets `delegate` accept offers on the escrow for the maker, at whatever they bid, so
+    // an automated flow can settle it without the maker's key. the proceeds still go to the
+    // maker. Pubkey::default() revokes it. returns the delegate it replaced
+    pub fn approve_delegate(&mut self, delegate: Pubkey) -> Result<Pubkey> {
+        let old_delegate = self.escrow.delegate;
+        self.escrow.delegate = delegate;
+        Ok(old_delegate)
+    }
+
+    // takes and whitelist updates both write the escrow, so they are never processed
+    // in parallel. a take landing after the update is checked against the new list
+    pub fn set_whitelist(&mut self, takers: Vec<Pubkey>) -> Result<()> {
+        self.escrow.set_whitelist(&takers)
+    }
+}
+
+// update_mint_b needs the new mint itself, to check it like make checks mint_b
+#[derive(Accounts)]
+pub struct UpdateMintB<'info> {
+    pub maker: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = maker,
+        seeds = [
+            b"escrow",
+            maker.key().as_ref(),
+            escrow.namespace_seed(),
+            escrow.seed.to_le_bytes().as_ref(),
+        ],
+        bump = escrow.bump,
+        constraint = escrow.is_open() @ ErrorCode::EscrowNotOpen,
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    pub new_mint_b: InterfaceAccount<'info, Mint>,
+}
+
+impl<'info> UpdateMintB<'info> {
+    // fixes a wrong mint_b while nothing has been paid in it. the asking amounts stay,
+    // they are read in the new mint's units from here on. oracles and pools price one
+    // pair, so those escrows have to be remade. so do escrows with a price floor, which is
+    // only worth anything in the mint it was set in
+    pub fn update_mint_b(&mut self) -> Result<Pubkey> {
+        let new_mint_b = self.new_mint_b.key();
+        require!(!self.escrow.is_filled(), ErrorCode::EscrowAlreadyFilled);
+        require!(self.escrow.min_receive_floor == 0, ErrorCode::PriceFloorSet);
+        require!(!self.escrow.is_oracle_priced(), ErrorCode::OraclePriced);
+        require!(!self.escrow.is_pool_priced(), ErrorCode::PoolPriced);
+        require!(
+            new_mint_b != self.escrow.mint_a
+                && new_mint_b != self.escrow.mint_a2
+                && !self.escrow.basket_mints().contains(&new_mint_b)
+                && new_mint_b != self.escrow.mint_c
+                && !self.escrow.payment_mints().contains(&new_mint_b),
+            ErrorCode::IdenticalMints
+        );
+        if self.escrow.receive_is_sol {
+            require!(new_mint_b == native_mint::ID, ErrorCode::MintBNotNative);
+        }
+        check_mint_extensions(&self.new_mint_b.to_account_info())?;
+
+        let old_mint_b = self.escrow.mint_b;
+        self.escrow.mint_b = new_mint_b;
+        Ok(old_mint_b)
+    }
+}
@@ -0,0 +1,84 @@
+use anchor_lang::prelude::*;
+
+use crate::error::ErrorCode;
+use crate::{Config, MAX_FEE_BPS};
+
+// fields left as None keep their current value
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct ConfigUpdate {
+    pub fee_bps: Option<u16>,
+    pub fee_collector: Option<Pubkey>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateConfig<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = admin @ ErrorCode::UnauthorizedAdmin,
+        seeds = [b"config"],
+        bump = config.bump,
+    )]
+    pub config: Account<'info, Config>,
+}
+
+impl<'info> UpdateConfig<'info> {
+    pub fn update_config(&mut self, update: ConfigUpdate) -> Result<()> {
+        if let Some(fee_bps) = update.fee_bps {
+            require!(fee_bps <= MAX_FEE_BPS, ErrorCode::FeeTooHigh);
+            self.config.fee_bps = fee_bps;
+        }
+        if let Some(fee_collector) = update.fee_collector {
+            self.config.fee_collector = fee_collector;
+        }
+        Ok(())
+    }
+
+    // the admin only changes once the new key signs accept_admin, so a typo can't
+    // lock everyone out. proposing the current admin clears the pending slot
+    pub fn propose_admin(&mut self, new_admin: Pubkey) -> Result<()> {
+        self.config.pending_admin = if new_admin == self.config.admin {
+            Pubkey::default()
+        } else {
+            new_admin
+        };
+        Ok(())
+    }
+
+    // refunds and cranks don't load the config, so a pause never traps deposits
+    pub fn set_paused(&mut self, paused: bool) -> Result<()> {
+        self.config.paused = paused;
+        Ok(())
+    }
+
+    pub fn cancel_pending_admin(&mut self) -> Result<Pubkey> {
+        let pending_admin = self.config.pending_admin;
+        self.config.pending_admin = Pubkey::default();
+        Ok(pending_admin)
+    }
+}
+
+#[derive(Accounts)]
+pub struct AcceptAdmin<'info> {
+    pub pending_admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = config.bump,
+        constraint = config.pending_admin != Pubkey::default()
+            && config.pending_admin == pending_admin.key() @ ErrorCode::NotPendingAdmin,
+    )]
+    pub config: Account<'info, Config>,
+}
+
+impl<'info> AcceptAdmin<'info> {
+    // returns the previous admin
+    pub fn accept_admin(&mut self) -> Result<Pubkey> {
+        let old_admin = self.config.admin;
+        self.config.admin = self.pending_admin.key();
+        self.config.pending_admin = Pubkey::default();
+        Ok(old_admin)
+    }
+}
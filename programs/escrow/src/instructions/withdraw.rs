@@ -0,0 +1,155 @@
+use anchor_lang::prelude::*;
+
+use anchor_spl::token_interface::{
+    close_account, transfer_checked, CloseAccount, Mint, TokenAccount, TokenInterface,
+    TransferChecked,
+};
+
+use crate::error::ErrorCode;
+use crate::state::{finish_escrow, namespace_seed, rent_destination, Escrow, EscrowStatus, Stats};
+
+#[derive(Accounts)]
+pub struct Withdraw<'info> {
+    #[account(mut)]
+    pub maker: Signer<'info>,
+
+    #[account(
+        mint::token_program = token_program,
+    )]
+    pub mint_a: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint_a,
+        associated_token::authority = maker,
+        associated_token::token_program = token_program,
+    )]
+    pub maker_ata_a: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        has_one = maker,
+        has_one = mint_a,
+        seeds = [
+            b"escrow",
+            maker.key().as_ref(),
+            escrow.namespace_seed(),
+            escrow.seed.to_le_bytes().as_ref(),
+        ],
+        bump = escrow.bump,
+        constraint = escrow.is_open() @ ErrorCode::EscrowNotOpen,
+        constraint = !escrow.deposit_is_sol @ ErrorCode::DepositIsSol,
+        constraint = !escrow.is_programmable() @ ErrorCode::ProgrammableNftUnsupported,
+        constraint = !escrow.is_basket() @ ErrorCode::BasketUnsupported,
+    )]
+    pub escrow: Account<'info, Escrow>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint_a,
+        associated_token::authority = escrow,
+        associated_token::token_program = token_program,
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    // the escrow's rent_recipient, only needed when make named one
+    /// CHECK: only receives lamports, pinned to the escrow
+    #[account(mut, address = escrow.rent_recipient @ ErrorCode::RentRecipientMismatch)]
+    pub rent_recipient: Option<UncheckedAccount<'info>>,
+
+    // counts the escrow as refunded once the whole deposit is withdrawn
+    #[account(
+        mut,
+        seeds = [b"stats"],
+        bump = stats.bump,
+    )]
+    pub stats: Option<Account<'info, Stats>>,
+
+    // the maker's index of open escrows, withdrawing the whole deposit drops the escrow from it
+    /// CHECK: only written by finish_escrow, when it holds an index
+    #[account(mut, seeds = [b"index", escrow.maker.as_ref()], bump)]
+    pub maker_index: UncheckedAccount<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+impl<'info> Withdraw<'info> {
+    fn rent_destination(&self) -> Result<AccountInfo<'info>> {
+        rent_destination(
+            &self.escrow,
+            self.maker.to_account_info(),
+            self.rent_recipient.as_ref(),
+        )
+    }
+
+    // takes and withdrawals both write the escrow, so they never run in parallel.
+    // a withdrawal landing after a take is checked against what the take left
+    pub fn withdraw(&mut self, amount: u64) -> Result<()> {
+        // pulling part of the deposit breaks the commitment just like a refund
+        require!(
+            !self.escrow.is_refund_locked(Clock::get()?.unix_timestamp),
+            ErrorCode::RefundLocked
+        );
+        self.escrow.withdraw(amount)?;
+
+        let namespace = self.escrow.namespace;
+        let signer_seeds: [&[&[u8]]; 1] = [&[
+            b"escrow",
+            self.maker.to_account_info().key.as_ref(),
+            namespace_seed(&namespace),
+            &self.escrow.seed.to_le_bytes()[..],
+            &[self.escrow.bump],
+        ]];
+
+        self.transfer_to_maker(amount, &signer_seeds)?;
+
+        // withdrawing everything that is left is a refund
+        if self.escrow.remaining > 0 {
+            return Ok(());
+        }
+        // tokens sent to the vault from outside go back to the maker too, or the close fails
+        self.vault.reload()?;
+        if self.vault.amount > 0 {
+            self.transfer_to_maker(self.vault.amount, &signer_seeds)?;
+        }
+
+        let destination = self.rent_destination()?;
+        let close_accounts = CloseAccount {
+            account: self.vault.to_account_info(),
+            destination: destination.clone(),
+            authority: self.escrow.to_account_info(),
+        };
+        let close_cpi_ctx = CpiContext::new_with_signer(
+            self.token_program.to_account_info(),
+            close_accounts,
+            &signer_seeds,
+        );
+
+        close_account(close_cpi_ctx)?;
+        if let Some(stats) = &mut self.stats {
+            stats.record_refund()?;
+        }
+        finish_escrow(
+            &mut self.escrow,
+            EscrowStatus::Refunded,
+            Pubkey::default(),
+            destination,
+            &self.maker_index,
+        )
+    }
+
+    fn transfer_to_maker(&self, amount: u64, signer_seeds: &[&[&[u8]]]) -> Result<()> {
+        let transfer_accounts = TransferChecked {
+            from: self.vault.to_account_info(),
+            mint: self.mint_a.to_account_info(),
+            to: self.maker_ata_a.to_account_info(),
+            authority: self.escrow.to_account_info(),
+        };
+        let transfer_cpi_ctx = CpiContext::new_with_signer(
+            self.token_program.to_account_info(),
+            transfer_accounts,
+            signer_seeds,
+        );
+        transfer_checked(transfer_cpi_ctx, amount, self.mint_a.decimals)
+    }
+}
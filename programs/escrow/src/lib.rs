@@ -1,14 +1,27 @@
 #![allow(unexpected_cfgs)]
 #![allow(deprecated)]
+pub mod basket; // basket.rs
 pub mod constants; // constants.rs
+pub mod ed25519; // ed25519.rs
 pub mod error; // error.rs
+pub mod events; // events.rs
+pub mod extensions; // extensions.rs
 pub mod instructions; // instructions/*
+pub mod math; // math.rs
+pub mod metadata; // metadata.rs
+pub mod native; // native.rs
+pub mod pda; // pda.rs
+pub mod pnft; // pnft.rs
+pub mod pool; // pool.rs
+pub mod pyth; // pyth.rs
 pub mod state; // state/*
 
 use anchor_lang::prelude::*;
 
 pub use constants::*;
+pub use events::*;
 pub use instructions::*;
+pub use pda::*;
 pub use state::*;
 
 declare_id!("AFsE5ZUWMy2rNDa6rvaYjBVwM93hdpcxKiamgi5dUt8b");
@@ -18,22 +31,979 @@ pub mod escrow {
 
     use super::*;
 
-    pub fn make(ctx: Context<Make>, seed: u64, receive: u64, deposit: u64) -> Result<()> {
-        ctx.accounts.init_escrow(seed, receive, &ctx.bumps)?;
+    pub fn initialize_config(ctx: Context<InitializeConfig>, fee_collector: Pubkey) -> Result<()> {
+        ctx.accounts.initialize_config(fee_collector, &ctx.bumps)
+    }
+
+    // for a config initialized before the stats account existed
+    pub fn initialize_stats(ctx: Context<InitializeStats>) -> Result<()> {
+        ctx.accounts.initialize_stats(&ctx.bumps)
+    }
+
+    pub fn update_config(ctx: Context<UpdateConfig>, update: ConfigUpdate) -> Result<()> {
+        ctx.accounts.update_config(update)
+    }
+
+    pub fn set_paused(ctx: Context<UpdateConfig>, paused: bool) -> Result<()> {
+        ctx.accounts.set_paused(paused)?;
+
+        emit!(PauseToggled {
+            admin: ctx.accounts.admin.key(),
+            paused,
+        });
+        Ok(())
+    }
+
+    pub fn propose_admin(ctx: Context<UpdateConfig>, new_admin: Pubkey) -> Result<()> {
+        ctx.accounts.propose_admin(new_admin)?;
+
+        emit!(AdminProposed {
+            admin: ctx.accounts.admin.key(),
+            pending_admin: ctx.accounts.config.pending_admin,
+        });
+        Ok(())
+    }
+
+    pub fn cancel_pending_admin(ctx: Context<UpdateConfig>) -> Result<()> {
+        let pending_admin = ctx.accounts.cancel_pending_admin()?;
+
+        emit!(AdminProposalCancelled {
+            admin: ctx.accounts.admin.key(),
+            pending_admin,
+        });
+        Ok(())
+    }
+
+    pub fn accept_admin(ctx: Context<AcceptAdmin>) -> Result<()> {
+        let old_admin = ctx.accounts.accept_admin()?;
+
+        emit!(AdminAccepted {
+            old_admin,
+            new_admin: ctx.accounts.pending_admin.key(),
+        });
+        Ok(())
+    }
+
+    // remaining accounts are forwarded to transfer hooks, see extensions::transfer_checked_with_hook.
+    // pNFT escrows pass the Token Metadata accounts instead, see pnft::PnftAccounts
+    pub fn make<'info>(
+        ctx: Context<'_, '_, '_, 'info, Make<'info>>,
+        seed: u64,
+        receive: u64,
+        deposit: u64,
+        options: MakeOptions,
+    ) -> Result<()> {
+        ctx.accounts
+            .init_escrow(seed, receive, deposit, &options, &ctx.bumps)?;
+        let deposit = ctx.accounts.deposit(deposit, ctx.remaining_accounts)?;
+        // a second mint is a basket of one leg
+        if options.deposit_2 > 0 {
+            ctx.accounts
+                .deposit_legs(&[options.deposit_2], ctx.remaining_accounts)?;
+        }
+
+        let (price_numerator, price_denominator) = ctx.accounts.escrow.base_unit_price();
+        let event = EscrowMade {
+            escrow: ctx.accounts.escrow.key(),
+            maker: ctx.accounts.maker.key(),
+            seed,
+            mint_a: ctx.accounts.mint_a.key(),
+            mint_b: ctx.accounts.mint_b.key(),
+            deposit,
+            // a unit priced escrow ignores the receive it was made with
+            receive: ctx.accounts.escrow.receive,
+            price_numerator,
+            price_denominator,
+        };
+        // logged for existing clients, and sent as a self CPI that log truncation can't drop
+        emit!(event);
+        emit_cpi!(event);
+        if let Some(stats) = &ctx.accounts.stats {
+            let event = stats.updated();
+            emit!(event);
+            emit_cpi!(event);
+        }
+        Ok(())
+    }
+
+    // make for a basket of mint_a and up to MAX_BASKET_LEGS more mints, sold whole for
+    // receive. legs holds the amount of each further mint, whose accounts lead the remaining
+    // accounts as described in basket.rs. take and refund then need the same leg accounts.
+    // like make_batch it takes the default MakeOptions, which leaves room for the legs. only
+    // the namespace can be set
+    pub fn make_basket<'info>(
+        ctx: Context<'_, '_, '_, 'info, Make<'info>>,
+        seed: u64,
+        receive: u64,
+        deposit: u64,
+        options: MakeOptions,
+        legs: Vec<u64>,
+    ) -> Result<()> {
+        options.check_basket()?;
+        ctx.accounts
+            .init_escrow(seed, receive, deposit, &options, &ctx.bumps)?;
+        let deposit = ctx.accounts.deposit(deposit, ctx.remaining_accounts)?;
+        let amounts = ctx.accounts.deposit_legs(&legs, ctx.remaining_accounts)?;
+
+        let (price_numerator, price_denominator) = ctx.accounts.escrow.base_unit_price();
+        let event = EscrowMade {
+            escrow: ctx.accounts.escrow.key(),
+            maker: ctx.accounts.maker.key(),
+            seed,
+            mint_a: ctx.accounts.mint_a.key(),
+            mint_b: ctx.accounts.mint_b.key(),
+            deposit,
+            receive: ctx.accounts.escrow.receive,
+            price_numerator,
+            price_denominator,
+        };
+        emit!(event);
+        emit_cpi!(event);
+        let event = BasketMade {
+            escrow: ctx.accounts.escrow.key(),
+            maker: ctx.accounts.maker.key(),
+            mints: ctx.accounts.escrow.basket_mints().to_vec(),
+            amounts,
+        };
+        emit!(event);
+        emit_cpi!(event);
+        if let Some(stats) = &ctx.accounts.stats {
+            let event = stats.updated();
+            emit!(event);
+            emit_cpi!(event);
+        }
+        Ok(())
+    }
+
+    // amount is how much mint_a the taker wants out of the vault, max_receive the highest
+    // price for the whole deposit the taker accepts. expected_receive_a and max_pay_b bound
+    // this fill itself. 0 leaves any of the three out.
+    // the escrow and vault are closed once the remaining amount hits zero
+    pub fn take<'info>(
+        ctx: Context<'_, '_, '_, 'info, Take<'info>>,
+        amount: u64,
+        max_receive: u64,
+        expected_receive_a: u64,
+        max_pay_b: u64,
+    ) -> Result<()> {
+        ctx.accounts.check_payment_mint()?;
+        ctx.accounts.check_expiry()?;
+        ctx.accounts.check_cooldown()?;
+        ctx.accounts.check_frozen()?;
+        ctx.accounts.check_collection_gate()?;
+        ctx.accounts.check_mint_gate()?;
+        ctx.accounts
+            .escrow
+            .apply_auction_price(Clock::get()?.unix_timestamp)?;
+        ctx.accounts.apply_oracle_price()?;
+        ctx.accounts.apply_pool_price()?;
+        ctx.accounts.check_slippage(max_receive)?;
+        let (amount_a, amount_b) = ctx.accounts.escrow.fill_for_amount_a(amount)?;
+        ctx.accounts
+            .check_fill_bounds(amount_a, amount_b, expected_receive_a, max_pay_b)?;
+        let (amount_a, fee, referral) =
+            ctx.accounts
+                .fill(amount_a, amount_b, ctx.remaining_accounts)?;
+
+        emit_taken(&ctx, amount_a, amount_b, fee, referral)
+    }
+
+    // the taker named in the pending terms takes the whole deposit at the terms' receive,
+    // which has to be repeated as `receive`
+    pub fn take_with_terms<'info>(
+        ctx: Context<'_, '_, '_, 'info, Take<'info>>,
+        receive: u64,
+    ) -> Result<()> {
+        ctx.accounts.check_payment_mint()?;
+        ctx.accounts.check_expiry()?;
+        ctx.accounts.check_cooldown()?;
+        ctx.accounts.check_frozen()?;
+        ctx.accounts.check_collection_gate()?;
+        ctx.accounts.check_mint_gate()?;
+        ctx.accounts.check_terms(receive)?;
+        ctx.accounts.escrow.reprice(receive)?;
+        ctx.accounts.escrow.clear_terms();
+        let (amount_a, amount_b) = (
+            ctx.accounts.escrow.remaining,
+            ctx.accounts.escrow.remaining_receive,
+        );
+        let (amount_a, fee, referral) =
+            ctx.accounts
+                .fill(amount_a, amount_b, ctx.remaining_accounts)?;
+
+        emit!(TermsAccepted {
+            escrow: ctx.accounts.escrow.key(),
+            maker: ctx.accounts.maker.key(),
+            taker: ctx.accounts.taker.key(),
+            receive,
+        });
+        emit_taken(&ctx, amount_a, amount_b, fee, referral)
+    }
+
+    // fills everything that is left at a price the maker signed off chain, see check_quote
+    pub fn take_with_quote<'info>(
+        ctx: Context<'_, '_, '_, 'info, Take<'info>>,
+        price: u64,
+        expiry: i64,
+        signature: [u8; 64],
+    ) -> Result<()> {
+        ctx.accounts.check_payment_mint()?;
+        ctx.accounts.check_expiry()?;
+        ctx.accounts.check_cooldown()?;
+        ctx.accounts.check_frozen()?;
+        ctx.accounts.check_collection_gate()?;
+        ctx.accounts.check_mint_gate()?;
+        ctx.accounts.check_quote(price, expiry, &signature)?;
+        ctx.accounts.escrow.reprice(price)?;
+        let (amount_a, amount_b) = (
+            ctx.accounts.escrow.remaining,
+            ctx.accounts.escrow.remaining_receive,
+        );
+        let (amount_a, fee, referral) =
+            ctx.accounts
+                .fill(amount_a, amount_b, ctx.remaining_accounts)?;
+
+        emit!(QuoteAccepted {
+            escrow: ctx.accounts.escrow.key(),
+            maker: ctx.accounts.maker.key(),
+            taker: ctx.accounts.taker.key(),
+            price,
+            expiry,
+        });
+        emit_taken(&ctx, amount_a, amount_b, fee, referral)
+    }
+
+    // amount is how much mint_b the taker pays to the maker
+    pub fn take_partial<'info>(
+        ctx: Context<'_, '_, '_, 'info, Take<'info>>,
+        amount: u64,
+    ) -> Result<()> {
+        ctx.accounts.check_payment_mint()?;
+        ctx.accounts.check_expiry()?;
+        ctx.accounts.check_cooldown()?;
+        ctx.accounts.check_frozen()?;
+        ctx.accounts.check_collection_gate()?;
+        ctx.accounts.check_mint_gate()?;
+        ctx.accounts
+            .escrow
+            .apply_auction_price(Clock::get()?.unix_timestamp)?;
+        ctx.accounts.apply_oracle_price()?;
+        ctx.accounts.apply_pool_price()?;
+        let (amount_a, amount_b) = ctx.accounts.escrow.fill_for_amount_b(amount)?;
+        let (amount_a, fee, referral) =
+            ctx.accounts
+                .fill(amount_a, amount_b, ctx.remaining_accounts)?;
+
+        emit_taken(&ctx, amount_a, amount_b, fee, referral)
+    }
+
+    // fills everything that is left, paid either in mint_b at the current price or in the
+    // escrow's mint_c at receive_c. the mint_b account carries the payment mint
+    pub fn take_alt<'info>(ctx: Context<'_, '_, '_, 'info, Take<'info>>) -> Result<()> {
+        ctx.accounts.check_expiry()?;
+        ctx.accounts.check_cooldown()?;
+        ctx.accounts.check_frozen()?;
+        ctx.accounts.check_collection_gate()?;
+        ctx.accounts.check_mint_gate()?;
+        ctx.accounts
+            .escrow
+            .apply_auction_price(Clock::get()?.unix_timestamp)?;
+        ctx.accounts.apply_oracle_price()?;
+        ctx.accounts.apply_pool_price()?;
+        let amount_b = ctx
+            .accounts
+            .escrow
+            .full_payment(&ctx.accounts.mint_b.key())?;
+        let (amount_a, fee, referral) = ctx.accounts.fill_all(amount_b, ctx.remaining_accounts)?;
+
+        emit_taken(&ctx, amount_a, amount_b, fee, referral)
+    }
+
+    // fills `amount` of mint_a paid in the escrow's payment option at `index`, whose mint
+    // the mint_b account carries. the option's receive prices the whole deposit
+    pub fn take_with_payment<'info>(
+        ctx: Context<'_, '_, '_, 'info, Take<'info>>,
+        index: u8,
+        amount: u64,
+    ) -> Result<()> {
+        ctx.accounts.check_expiry()?;
+        ctx.accounts.check_cooldown()?;
+        ctx.accounts.check_frozen()?;
+        ctx.accounts.check_collection_gate()?;
+        ctx.accounts.check_mint_gate()?;
+        let (amount_a, payment) =
+            ctx.accounts
+                .escrow
+                .fill_with_payment(index, &ctx.accounts.mint_b.key(), amount)?;
+        let (amount_a, fee, referral) =
+            ctx.accounts
+                .fill_with_payment(amount_a, payment, ctx.remaining_accounts)?;
+
+        emit_taken(&ctx, amount_a, payment, fee, referral)
+    }
+
+    // the taker of a vesting escrow collects what has unlocked since the last claim
+    pub fn claim_vested<'info>(ctx: Context<'_, '_, '_, 'info, ClaimVested<'info>>) -> Result<()> {
+        let escrow = ctx.accounts.escrow.key();
+        let amount = ctx.accounts.claim_vested(ctx.remaining_accounts)?;
+
+        emit!(VestedClaimed {
+            escrow,
+            taker: ctx.accounts.taker.key(),
+            mint_a: ctx.accounts.mint_a.key(),
+            amount,
+            claimed: ctx.accounts.escrow.claimed_amount,
+            deposit: ctx.accounts.escrow.deposit,
+        });
+        Ok(())
+    }
+
+    // the event reports the recorded remaining deposit, stray tokens sent to the vault
+    // are returned to the maker as well but not counted
+    pub fn refund<'info>(ctx: Context<'_, '_, '_, 'info, Refund<'info>>) -> Result<()> {
+        let amount = ctx.accounts.escrow.remaining;
+        ctx.accounts
+            .refund_and_close_vault(ctx.remaining_accounts)?;
+
+        let event = EscrowRefunded {
+            escrow: ctx.accounts.escrow.key(),
+            maker: ctx.accounts.maker.key(),
+            mint_a: ctx.accounts.mint_a.key(),
+            mint_b: ctx.accounts.escrow.mint_b,
+            amount,
+        };
+        emit!(event);
+        emit_cpi!(event);
+        if let Some(stats) = &ctx.accounts.stats {
+            let event = stats.updated();
+            emit!(event);
+            emit_cpi!(event);
+        }
+        Ok(())
+    }
+
+    // one take per amount, see TakeBatch for the remaining accounts of each escrow.
+    // max_pay_b bounds each fill like take's, 0 leaves it unbounded
+    pub fn take_batch<'info>(
+        ctx: Context<'_, '_, 'info, 'info, TakeBatch<'info>>,
+        amounts: Vec<u64>,
+        max_pay_b: Vec<u64>,
+    ) -> Result<()> {
+        ctx.accounts
+            .check_batch(&amounts, &max_pay_b, ctx.remaining_accounts)?;
+        for (i, leg) in ctx.remaining_accounts.chunks(TAKE_BATCH_LEG).enumerate() {
+            let filled = ctx.accounts.take_one(amounts[i], max_pay_b[i], leg)?;
+
+            let event = EscrowTaken {
+                escrow: filled.escrow,
+                maker: filled.maker,
+                taker: ctx.accounts.taker.key(),
+                mint_a: filled.mint_a,
+                mint_b: filled.mint_b,
+                amount_a: filled.amount_a,
+                amount_b: filled.amount_b,
+                fee: filled.fee,
+                remaining: filled.remaining,
+                lamports: 0,
+            };
+            emit!(event);
+            emit_cpi!(event);
+        }
+        if let Some(stats) = &ctx.accounts.stats {
+            let event = stats.updated();
+            emit!(event);
+            emit_cpi!(event);
+        }
+        Ok(())
+    }
+
+    // one make per seed, remaining accounts hold [escrow, vault] for each of them
+    pub fn make_batch<'info>(
+        ctx: Context<'_, '_, '_, 'info, MakeBatch<'info>>,
+        seeds: Vec<u64>,
+        receives: Vec<u64>,
+        deposits: Vec<u64>,
+    ) -> Result<()> {
+        ctx.accounts
+            .check_batch(&seeds, &receives, &deposits, ctx.remaining_accounts)?;
+        for (i, pair) in ctx.remaining_accounts.chunks(2).enumerate() {
+            let made = ctx.accounts.make_one(
+                seeds[i],
+                receives[i],
+                deposits[i],
+                &pair[0],
+                &pair[1],
+                ctx.bumps.maker_index,
+            )?;
+
+            let event = EscrowMade {
+                escrow: made.escrow,
+                maker: ctx.accounts.maker.key(),
+                seed: made.seed,
+                mint_a: ctx.accounts.mint_a.key(),
+                mint_b: ctx.accounts.mint_b.key(),
+                deposit: made.deposit,
+                receive: made.receive,
+                price_numerator: made.receive,
+                price_denominator: made.deposit,
+            };
+            emit!(event);
+            emit_cpi!(event);
+        }
+        if let Some(stats) = &ctx.accounts.stats {
+            let event = stats.updated();
+            emit!(event);
+            emit_cpi!(event);
+        }
+        Ok(())
+    }
+
+    // make at the next seed of the maker's nonce, see MakeAuto
+    pub fn make_auto<'info>(
+        ctx: Context<'_, '_, '_, 'info, MakeAuto<'info>>,
+        receive: u64,
+        deposit: u64,
+    ) -> Result<()> {
+        let (seed, deposit) =
+            ctx.accounts
+                .make(receive, deposit, &ctx.bumps, ctx.remaining_accounts)?;
+
+        let event = EscrowMade {
+            escrow: ctx.accounts.escrow.key(),
+            maker: ctx.accounts.maker.key(),
+            seed,
+            mint_a: ctx.accounts.mint_a.key(),
+            mint_b: ctx.accounts.mint_b.key(),
+            deposit,
+            receive,
+            price_numerator: receive,
+            price_denominator: deposit,
+        };
+        emit!(event);
+        emit_cpi!(event);
+        if let Some(stats) = &ctx.accounts.stats {
+            let event = stats.updated();
+            emit!(event);
+            emit_cpi!(event);
+        }
+        Ok(())
+    }
+
+    // wraps the maker's lamports into a wrapped SOL vault, the escrow is a token escrow from
+    // then on and reports the native mint as mint_a
+    pub fn make_with_sol(
+        ctx: Context<MakeWithSol>,
+        seed: u64,
+        receive: u64,
+        lamports: u64,
+        options: MakeOptions,
+    ) -> Result<()> {
+        ctx.accounts
+            .init_escrow(seed, receive, lamports, &options, &ctx.bumps)?;
+        ctx.accounts.deposit(lamports)?;
+
+        let event = EscrowMade {
+            escrow: ctx.accounts.escrow.key(),
+            maker: ctx.accounts.maker.key(),
+            seed,
+            mint_a: ctx.accounts.mint_a.key(),
+            mint_b: ctx.accounts.mint_b.key(),
+            deposit: lamports,
+            receive,
+            price_numerator: receive,
+            price_denominator: lamports,
+        };
+        emit!(event);
+        emit_cpi!(event);
+        if let Some(stats) = &ctx.accounts.stats {
+            let event = stats.updated();
+            emit!(event);
+            emit_cpi!(event);
+        }
+        Ok(())
+    }
+
+    // native SOL variants. mint_a is reported as Pubkey::default()
+    pub fn make_sol(
+        ctx: Context<MakeSol>,
+        seed: u64,
+        receive: u64,
+        deposit: u64,
+        options: MakeOptions,
+    ) -> Result<()> {
+        ctx.accounts
+            .init_escrow(seed, receive, deposit, &options, &ctx.bumps)?;
         ctx.accounts.deposit(deposit)?;
 
+        emit!(EscrowMade {
+            escrow: ctx.accounts.escrow.key(),
+            maker: ctx.accounts.maker.key(),
+            seed,
+            mint_a: Pubkey::default(),
+            mint_b: ctx.accounts.mint_b.key(),
+            deposit,
+            receive,
+            price_numerator: receive,
+            price_denominator: deposit,
+        });
+        if let Some(stats) = &ctx.accounts.stats {
+            emit!(stats.updated());
+        }
+        Ok(())
+    }
+
+    pub fn take_sol(ctx: Context<TakeSol>) -> Result<()> {
+        ctx.accounts.check_expiry()?;
+        ctx.accounts.check_cooldown()?;
+        ctx.accounts
+            .escrow
+            .apply_auction_price(Clock::get()?.unix_timestamp)?;
+        let (amount_b, fee) = ctx.accounts.deposit()?;
+        let amount_a = ctx.accounts.withdraw(&ctx.bumps)?;
+        ctx.accounts.record_take(amount_a, amount_b)?;
+
+        emit!(EscrowTaken {
+            escrow: ctx.accounts.escrow.key(),
+            maker: ctx.accounts.maker.key(),
+            taker: ctx.accounts.taker.key(),
+            mint_a: Pubkey::default(),
+            mint_b: ctx.accounts.mint_b.key(),
+            amount_a,
+            amount_b,
+            fee,
+            remaining: 0,
+            lamports: 0,
+        });
+        if let Some(stats) = &ctx.accounts.stats {
+            emit!(stats.updated());
+        }
+        Ok(())
+    }
+
+    // lamport payment for a token escrow made with receive_sol, fills everything that is left
+    pub fn take_native<'info>(ctx: Context<'_, '_, '_, 'info, TakeNative<'info>>) -> Result<()> {
+        ctx.accounts.check_expiry()?;
+        ctx.accounts.check_cooldown()?;
+        ctx.accounts
+            .escrow
+            .apply_auction_price(Clock::get()?.unix_timestamp)?;
+        let (amount_b, fee) = ctx.accounts.deposit()?;
+        let amount_a = ctx
+            .accounts
+            .withdraw_and_close_vault(ctx.remaining_accounts)?;
+        ctx.accounts.record_take(amount_a, amount_b)?;
+
+        let event = EscrowTaken {
+            escrow: ctx.accounts.escrow.key(),
+            maker: ctx.accounts.maker.key(),
+            taker: ctx.accounts.taker.key(),
+            mint_a: ctx.accounts.mint_a.key(),
+            mint_b: ctx.accounts.escrow.mint_b,
+            amount_a,
+            amount_b,
+            fee,
+            remaining: 0,
+            lamports: amount_b - fee,
+        };
+        emit!(event);
+        emit_cpi!(event);
+        if let Some(stats) = &ctx.accounts.stats {
+            let event = stats.updated();
+            emit!(event);
+            emit_cpi!(event);
+        }
+        Ok(())
+    }
+
+    pub fn refund_sol(ctx: Context<RefundSol>) -> Result<()> {
+        let amount = ctx.accounts.refund(&ctx.bumps)?;
+
+        emit!(EscrowRefunded {
+            escrow: ctx.accounts.escrow.key(),
+            maker: ctx.accounts.maker.key(),
+            mint_a: Pubkey::default(),
+            mint_b: ctx.accounts.escrow.mint_b,
+            amount,
+        });
+        if let Some(stats) = &ctx.accounts.stats {
+            emit!(stats.updated());
+        }
+        Ok(())
+    }
+
+    // returns part of the deposit to the maker, the escrow stays open until it is empty
+    pub fn withdraw(ctx: Context<Withdraw>, amount: u64) -> Result<()> {
+        ctx.accounts.withdraw(amount)?;
+
+        emit!(EscrowWithdrawn {
+            escrow: ctx.accounts.escrow.key(),
+            maker: ctx.accounts.maker.key(),
+            mint_a: ctx.accounts.mint_a.key(),
+            amount,
+            remaining: ctx.accounts.escrow.remaining,
+        });
+        // only withdrawing the whole deposit counts, as a refund
+        if let (Some(stats), 0) = (&ctx.accounts.stats, ctx.accounts.escrow.remaining) {
+            emit!(stats.updated());
+        }
+        Ok(())
+    }
+
+    // the close constraint does all the work
+    pub fn close_record(_ctx: Context<CloseRecord>) -> Result<()> {
+        Ok(())
+    }
+
+    // drops finished escrows from the maker's index, see CompactMakerIndex
+    pub fn compact_maker_index<'info>(
+        ctx: Context<'_, '_, '_, 'info, CompactMakerIndex<'info>>,
+        entries: Vec<IndexedEscrow>,
+    ) -> Result<()> {
+        ctx.accounts.compact(&entries, ctx.remaining_accounts)
+    }
+
+    pub fn set_active(ctx: Context<SetActive>, active: bool) -> Result<()> {
+        ctx.accounts.set_active(active)
+    }
+
+    pub fn update_receive(ctx: Context<Update>, new_receive: u64) -> Result<()> {
+        let old_receive = ctx.accounts.update_receive(new_receive)?;
+
+        emit!(EscrowRepriced {
+            escrow: ctx.accounts.escrow.key(),
+            maker: ctx.accounts.maker.key(),
+            old_receive,
+            new_receive,
+        });
+        Ok(())
+    }
+
+    // adds mint_a to an open escrow. scale_receive keeps the price per token in every
+    // payment mint, otherwise receive, receive_c and the payment options stay fixed and the
+    // extra deposit improves the taker's price
+    pub fn top_up(ctx: Context<TopUp>, amount: u64, scale_receive: bool) -> Result<()> {
+        let amount = ctx.accounts.top_up(amount, scale_receive)?;
+
+        let escrow = &ctx.accounts.escrow;
+        emit!(EscrowToppedUp {
+            escrow: escrow.key(),
+            maker: ctx.accounts.maker.key(),
+            amount,
+            scaled_receive: scale_receive,
+            deposit: escrow.deposit,
+            remaining: escrow.remaining,
+            receive: escrow.receive,
+            remaining_receive: escrow.remaining_receive,
+        });
+        Ok(())
+    }
+
+    pub fn set_whitelist(ctx: Context<Update>, takers: Vec<Pubkey>) -> Result<()> {
+        ctx.accounts.set_whitelist(takers)
+    }
+
+    // grows an escrow of an earlier layout to the current one, see Migrate
+    pub fn migrate(ctx: Context<Migrate>) -> Result<()> {
+        if let Some(old_version) = ctx.accounts.migrate()? {
+            emit!(EscrowMigrated {
+                escrow: ctx.accounts.escrow.key(),
+                maker: ctx.accounts.maker.key(),
+                old_version,
+                version: ESCROW_VERSION,
+            });
+        }
         Ok(())
     }
 
-    pub fn take(ctx: Context<Take>) -> Result<()> {
-        ctx.accounts.deposit()?;
-        ctx.accounts.withdraw_and_close_vault()?;
+    pub fn update_mint_b(ctx: Context<UpdateMintB>) -> Result<()> {
+        let old_mint_b = ctx.accounts.update_mint_b()?;
 
+        emit!(EscrowMintUpdated {
+            escrow: ctx.accounts.escrow.key(),
+            maker: ctx.accounts.maker.key(),
+            old_mint_b,
+            new_mint_b: ctx.accounts.new_mint_b.key(),
+        });
         Ok(())
     }
 
-    pub fn refund(ctx: Context<Refund>) -> Result<()> {
+    pub fn propose_terms(
+        ctx: Context<Update>,
+        receive: u64,
+        taker: Pubkey,
+        valid_slots: u64,
+    ) -> Result<()> {
+        ctx.accounts.propose_terms(receive, taker, valid_slots)?;
+
+        emit!(TermsProposed {
+            escrow: ctx.accounts.escrow.key(),
+            maker: ctx.accounts.maker.key(),
+            taker,
+            receive,
+            expiry_slot: ctx.accounts.escrow.terms_expiry_slot,
+        });
+        Ok(())
+    }
+
+    // lapsed terms are reported as expired, open ones as cleared
+    pub fn clear_terms(ctx: Context<Update>) -> Result<()> {
+        let (taker, receive, expired) = ctx.accounts.clear_terms()?;
+
+        let escrow = ctx.accounts.escrow.key();
+        let maker = ctx.accounts.maker.key();
+        if expired {
+            emit!(TermsExpired {
+                escrow,
+                maker,
+                taker,
+                receive,
+            });
+        } else {
+            emit!(TermsCleared {
+                escrow,
+                maker,
+                taker,
+                receive,
+            });
+        }
+        Ok(())
+    }
+
+    // the delegate accepts offers for the maker, Pubkey::default() revokes it
+    pub fn approve_delegate(ctx: Context<Update>, delegate: Pubkey) -> Result<()> {
+        let old_delegate = ctx.accounts.approve_delegate(delegate)?;
+
+        emit!(DelegateApproved {
+            escrow: ctx.accounts.escrow.key(),
+            maker: ctx.accounts.maker.key(),
+            old_delegate,
+            delegate,
+        });
+        Ok(())
+    }
+
+    // a taker locks amount_b of mint_b as a bid for everything left in the escrow
+    pub fn make_offer<'info>(
+        ctx: Context<'_, '_, '_, 'info, MakeOffer<'info>>,
+        amount_b: u64,
+    ) -> Result<()> {
+        ctx.accounts
+            .make_offer(amount_b, &ctx.bumps, ctx.remaining_accounts)?;
+
+        emit!(OfferMade {
+            escrow: ctx.accounts.escrow.key(),
+            maker: ctx.accounts.escrow.maker,
+            taker: ctx.accounts.taker.key(),
+            amount_b: ctx.accounts.offer.amount_b,
+            remaining: ctx.accounts.offer.remaining,
+        });
+        Ok(())
+    }
+
+    // the taker takes the bid back along with the rent, also after the escrow is gone
+    pub fn cancel_offer<'info>(ctx: Context<'_, '_, '_, 'info, CancelOffer<'info>>) -> Result<()> {
+        let amount_b = ctx.accounts.cancel_offer(ctx.remaining_accounts)?;
+
+        emit!(OfferCancelled {
+            escrow: ctx.accounts.offer.escrow,
+            taker: ctx.accounts.taker.key(),
+            amount_b,
+        });
+        Ok(())
+    }
+
+    // the close constraint does all the work
+    pub fn close_counter_offer(_ctx: Context<CloseCounterOffer>) -> Result<()> {
+        Ok(())
+    }
+
+    // the maker, or the maker's delegate, settles the escrow with one bid. other bids stay
+    // open until cancelled
+    pub fn accept_offer<'info>(ctx: Context<'_, '_, '_, 'info, AcceptOffer<'info>>) -> Result<()> {
+        let escrow = ctx.accounts.escrow.key();
+        let (amount_a, amount_b, fee) = ctx.accounts.accept_offer(ctx.remaining_accounts)?;
+
+        emit!(OfferAccepted {
+            escrow,
+            maker: ctx.accounts.maker.key(),
+            taker: ctx.accounts.taker.key(),
+            amount_a,
+            amount_b,
+            fee,
+        });
+        if let Some(stats) = &ctx.accounts.stats {
+            emit!(stats.updated());
+        }
+        Ok(())
+    }
+
+    // the taker locks the payment for `amount` of mint_a at the current price. settle
+    // swaps it for the deposit until the deadline, reclaim_commitment returns it after
+    pub fn commit<'info>(
+        ctx: Context<'_, '_, '_, 'info, Commit<'info>>,
+        amount: u64,
+    ) -> Result<()> {
+        ctx.accounts
+            .commit(amount, &ctx.bumps, ctx.remaining_accounts)?;
+
+        emit!(CommitmentMade {
+            escrow: ctx.accounts.escrow.key(),
+            maker: ctx.accounts.escrow.maker,
+            taker: ctx.accounts.taker.key(),
+            amount_a: ctx.accounts.commitment.amount_a,
+            amount_b: ctx.accounts.commitment.amount_b,
+            deadline: ctx.accounts.commitment.deadline,
+        });
+        Ok(())
+    }
+
+    // either party completes a commitment, see Settle
+    pub fn settle<'info>(ctx: Context<'_, '_, '_, 'info, Settle<'info>>) -> Result<()> {
+        let escrow = ctx.accounts.escrow.key();
+        let remaining = ctx.accounts.commitment.remaining - ctx.accounts.commitment.amount_a;
+        let (amount_a, fee) = ctx.accounts.settle(ctx.remaining_accounts)?;
+
+        emit!(CommitmentSettled {
+            escrow,
+            maker: ctx.accounts.maker.key(),
+            taker: ctx.accounts.taker.key(),
+            settler: ctx.accounts.settler.key(),
+            amount_a,
+            amount_b: ctx.accounts.commitment.amount_b,
+            fee,
+            remaining,
+        });
+        if let Some(stats) = &ctx.accounts.stats {
+            emit!(stats.updated());
+        }
+        Ok(())
+    }
+
+    // the taker takes an unsettled payment back along with the rent once the deadline
+    // passed, or earlier once the commitment is outdated
+    pub fn reclaim_commitment<'info>(
+        ctx: Context<'_, '_, '_, 'info, ReclaimCommitment<'info>>,
+    ) -> Result<()> {
+        let amount_b = ctx.accounts.reclaim_commitment(ctx.remaining_accounts)?;
+
+        emit!(CommitmentReclaimed {
+            escrow: ctx.accounts.commitment.escrow,
+            taker: ctx.accounts.taker.key(),
+            amount_b,
+        });
+        Ok(())
+    }
+
+    // the maker of an escrow made with receive_to_vault collects the payments of its takes
+    pub fn claim_payment<'info>(
+        ctx: Context<'_, '_, '_, 'info, ClaimPayment<'info>>,
+        seed: u64,
+    ) -> Result<()> {
+        let amount_b = ctx
+            .accounts
+            .claim_payment(seed, &ctx.bumps, ctx.remaining_accounts)?;
+
+        emit!(PaymentClaimed {
+            escrow: ctx.accounts.escrow.key(),
+            maker: ctx.accounts.maker.key(),
+            mint_b: ctx.accounts.mint_b.key(),
+            amount_b,
+        });
+        Ok(())
+    }
+
+    // the maker empties and closes the vault of an escrow that was closed without it
+    pub fn recover_orphan_vault<'info>(
+        ctx: Context<'_, '_, '_, 'info, RecoverOrphanVault<'info>>,
+        seed: u64,
+        namespace: [u8; 8],
+    ) -> Result<()> {
+        let amount = ctx
+            .accounts
+            .recover(seed, namespace, &ctx.bumps, ctx.remaining_accounts)?;
+
+        emit!(OrphanVaultRecovered {
+            escrow: ctx.accounts.escrow.key(),
+            maker: ctx.accounts.maker.key(),
+            mint_a: ctx.accounts.mint_a.key(),
+            amount,
+        });
+        Ok(())
+    }
+
+    // anchor writes the returned quote into the transaction return data.
+    // takeable is answered for `taker`, nothing has to sign for it
+    pub fn quote(ctx: Context<Quote>, taker: Pubkey) -> Result<EscrowQuote> {
+        ctx.accounts.quote(&taker)
+    }
+
+    // permissionless match of two escrows trading the same pair, see Cross
+    pub fn cross(ctx: Context<Cross>) -> Result<()> {
+        ctx.accounts.check_escrows()?;
+        let crossed = ctx.accounts.cross()?;
+
+        let event = EscrowsCrossed {
+            escrow_1: ctx.accounts.escrow_1.key(),
+            escrow_2: ctx.accounts.escrow_2.key(),
+            matcher: ctx.accounts.matcher.key(),
+            mint_a: ctx.accounts.mint_a.key(),
+            mint_b: ctx.accounts.mint_b.key(),
+            amount_a: crossed.amount_a,
+            amount_b: crossed.amount_b,
+            fee_a: crossed.fee_a,
+            fee_b: crossed.fee_b,
+            surplus_a: crossed.surplus_a,
+            surplus_b: crossed.surplus_b,
+        };
+        emit!(event);
+        emit_cpi!(event);
+        if let Some(stats) = &ctx.accounts.stats {
+            let event = stats.updated();
+            emit!(event);
+            emit_cpi!(event);
+        }
+        Ok(())
+    }
+
+    // permissionless refund of an escrow GRACE_PERIOD past its expiry, the caller earns
+    // CRANK_BOUNTY lamports
+    pub fn crank_refund(ctx: Context<CrankRefund>) -> Result<()> {
+        ctx.accounts.check_expired()?;
+        let amount = ctx.accounts.vault.amount;
         ctx.accounts.refund_and_close_vault()?;
+        let bounty = ctx.accounts.pay_bounty()?;
+
+        emit!(EscrowCranked {
+            escrow: ctx.accounts.escrow.key(),
+            maker: ctx.accounts.maker.key(),
+            cranker: ctx.accounts.cranker.key(),
+            mint_a: ctx.accounts.mint_a.key(),
+            amount,
+            bounty,
+        });
+        if let Some(stats) = &ctx.accounts.stats {
+            emit!(stats.updated());
+        }
+        Ok(())
+    }
+
+    // crank_refund for escrows made with make_sol
+    pub fn crank_refund_sol(ctx: Context<CrankRefundSol>) -> Result<()> {
+        ctx.accounts.check_expired()?;
+        let amount = ctx.accounts.refund(&ctx.bumps)?;
+        let bounty = ctx.accounts.pay_bounty()?;
+
+        emit!(EscrowCranked {
+            escrow: ctx.accounts.escrow.key(),
+            maker: ctx.accounts.maker.key(),
+            cranker: ctx.accounts.cranker.key(),
+            mint_a: Pubkey::default(),
+            amount,
+            bounty,
+        });
+        if let Some(stats) = &ctx.accounts.stats {
+            emit!(stats.updated());
+        }
         Ok(())
     }
 }
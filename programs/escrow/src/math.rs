@@ -0,0 +1,32 @@
+// overflow safe proportional math for amounts, fees and prices. products are taken in
+// u128, and a result that doesn't fit in u64 or a zero denominator fails with
+// ArithmeticOverflow instead of panicking
+use anchor_lang::prelude::*;
+
+use crate::error::ErrorCode;
+
+// amount * numerator / denominator, rounded down
+pub fn mul_div_floor(amount: u64, numerator: u64, denominator: u64) -> Result<u64> {
+    let product = (amount as u128)
+        .checked_mul(numerator as u128)
+        .ok_or(ErrorCode::ArithmeticOverflow)?;
+    let quotient = product
+        .checked_div(denominator as u128)
+        .ok_or(ErrorCode::ArithmeticOverflow)?;
+    u64::try_from(quotient).map_err(|_| ErrorCode::ArithmeticOverflow.into())
+}
+
+// mul_div_floor rounded up
+pub fn mul_div_ceil(amount: u64, numerator: u64, denominator: u64) -> Result<u64> {
+    let product = (amount as u128)
+        .checked_mul(numerator as u128)
+        .ok_or(ErrorCode::ArithmeticOverflow)?;
+    u64::try_from(checked_div_ceil(product, denominator as u128)?)
+        .map_err(|_| ErrorCode::ArithmeticOverflow.into())
+}
+
+// numerator / denominator rounded up, ArithmeticOverflow for a zero denominator
+pub fn checked_div_ceil(numerator: u128, denominator: u128) -> Result<u128> {
+    require!(denominator > 0, ErrorCode::ArithmeticOverflow);
+    Ok(numerator.div_ceil(denominator))
+}
@@ -0,0 +1,86 @@
+// readers for Metaplex metadata accounts. the fields after the mint sit behind the
+// variable length name, symbol, uri and creators, so everything before them is skipped
+use anchor_lang::prelude::*;
+
+use crate::error::ErrorCode;
+use crate::pda::metadata_pda;
+use crate::METADATA_PROGRAM_ID;
+
+// TokenStandard::ProgrammableNonFungible
+pub const PROGRAMMABLE_NON_FUNGIBLE: u8 = 4;
+
+// the metadata has to be the Metaplex metadata of `mint`
+pub fn check_metadata(metadata: &AccountInfo, mint: &Pubkey) -> Result<()> {
+    require_keys_eq!(
+        metadata.key(),
+        metadata_pda(mint).0,
+        ErrorCode::InvalidMetadata
+    );
+    require_keys_eq!(
+        *metadata.owner,
+        METADATA_PROGRAM_ID,
+        ErrorCode::InvalidMetadata
+    );
+    Ok(())
+}
+
+pub fn token_standard(metadata: &AccountInfo) -> Result<Option<u8>> {
+    let data = metadata.try_borrow_data()?;
+    let at = token_standard_offset(&data).ok_or(ErrorCode::InvalidMetadata)?;
+    Ok(read_option(&data, at, 1)
+        .ok_or(ErrorCode::InvalidMetadata)?
+        .map(|standard| standard[0]))
+}
+
+// the collection the metadata names, only if the collection's authority verified it
+pub fn verified_collection(metadata: &AccountInfo) -> Result<Option<Pubkey>> {
+    let data = metadata.try_borrow_data()?;
+    let at = token_standard_offset(&data).ok_or(ErrorCode::InvalidMetadata)?;
+    // the token standard option comes first
+    let at = at + option_len(&data, at, 1).ok_or(ErrorCode::InvalidMetadata)?;
+    // Collection { verified: bool, key: Pubkey }
+    let collection = read_option(&data, at, 33).ok_or(ErrorCode::InvalidMetadata)?;
+    Ok(collection
+        .filter(|collection| collection[0] == 1)
+        .map(|collection| Pubkey::try_from(&collection[1..]).unwrap()))
+}
+
+// None when the data ends early
+fn token_standard_offset(data: &[u8]) -> Option<usize> {
+    let len_at = |at: usize| -> Option<usize> {
+        Some(u32::from_le_bytes(data.get(at..at + 4)?.try_into().ok()?) as usize)
+    };
+    // key, update authority, mint
+    let mut at = 65;
+    // name, symbol, uri
+    for _ in 0..3 {
+        at += 4 + len_at(at)?;
+    }
+    // seller fee basis points
+    at += 2;
+    // creators, 34 bytes each
+    at += match data.get(at)? {
+        1 => 5 + 34 * len_at(at + 1)?,
+        _ => 1,
+    };
+    // primary sale happened, is mutable
+    at += 2;
+    // edition nonce
+    Some(at + option_len(data, at, 1)?)
+}
+
+// bytes a borsh Option with a `len` byte value takes up at `at`
+fn option_len(data: &[u8], at: usize, len: usize) -> Option<usize> {
+    match data.get(at)? {
+        1 => Some(1 + len),
+        _ => Some(1),
+    }
+}
+
+// the `len` bytes of a borsh Option at `at`, Some(None) when it is empty
+fn read_option(data: &[u8], at: usize, len: usize) -> Option<Option<&[u8]>> {
+    match data.get(at)? {
+        1 => Some(Some(data.get(at + 1..at + 1 + len)?)),
+        _ => Some(None),
+    }
+}
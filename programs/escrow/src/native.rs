@@ -0,0 +1,24 @@
+// helpers for wrapped SOL. a native token account holds its balance as lamports
+use anchor_lang::prelude::*;
+
+use anchor_lang::system_program::{transfer, Transfer};
+use anchor_spl::token_interface::{sync_native, SyncNative};
+
+// wraps `lamports` of `from` into the native token account `to`. lamports sent to a
+// native token account only count once sync_native has run
+pub fn wrap_sol<'info>(
+    from: AccountInfo<'info>,
+    to: AccountInfo<'info>,
+    lamports: u64,
+    system_program: AccountInfo<'info>,
+    token_program: AccountInfo<'info>,
+) -> Result<()> {
+    let transfer_accounts = Transfer {
+        from,
+        to: to.clone(),
+    };
+    transfer(CpiContext::new(system_program, transfer_accounts), lamports)?;
+
+    let sync_accounts = SyncNative { account: to };
+    sync_native(CpiContext::new(token_program, sync_accounts))
+}
@@ -0,0 +1,105 @@
+// offline derivation of every address the program derives, using the same seeds as
+// the accounts structs. on-chain code keeps its seeds in the constraints
+use anchor_lang::prelude::*;
+use anchor_spl::associated_token::get_associated_token_address_with_program_id;
+
+use crate::state::namespace_seed;
+use crate::METADATA_PROGRAM_ID;
+
+// [b"escrow", maker, namespace, seed as little endian bytes]. the zero namespace is left
+// out of the seeds, see state::namespace_seed
+pub fn escrow_pda(maker: &Pubkey, namespace: &[u8; 8], seed: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            b"escrow",
+            maker.as_ref(),
+            namespace_seed(namespace),
+            &seed.to_le_bytes(),
+        ],
+        &crate::ID,
+    )
+}
+
+// the escrow's associated token account for mint_a under the mint's token program
+pub fn vault_pda(escrow: &Pubkey, mint_a: &Pubkey, token_program: &Pubkey) -> Pubkey {
+    get_associated_token_address_with_program_id(escrow, mint_a, token_program)
+}
+
+// lamport vault of a SOL escrow
+pub fn sol_vault_pda(escrow: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"sol_vault", escrow.as_ref()], &crate::ID)
+}
+
+// seed counter of make_auto, [b"nonce", maker]
+pub fn maker_nonce_pda(maker: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"nonce", maker.as_ref()], &crate::ID)
+}
+
+// open escrows of a maker, [b"index", maker]
+pub fn maker_index_pda(maker: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"index", maker.as_ref()], &crate::ID)
+}
+
+pub fn config_pda() -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"config"], &crate::ID)
+}
+
+// Metaplex metadata of `mint`, [b"metadata", metadata program, mint]
+pub fn metadata_pda(mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"metadata", METADATA_PROGRAM_ID.as_ref(), mint.as_ref()],
+        &METADATA_PROGRAM_ID,
+    )
+}
+
+// master edition of `mint`, the freeze authority of a pNFT's token accounts
+pub fn edition_pda(mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            b"metadata",
+            METADATA_PROGRAM_ID.as_ref(),
+            mint.as_ref(),
+            b"edition",
+        ],
+        &METADATA_PROGRAM_ID,
+    )
+}
+
+// Token Metadata's record of the pNFT held in `token`, e.g. a vault
+pub fn token_record_pda(mint: &Pubkey, token: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            b"metadata",
+            METADATA_PROGRAM_ID.as_ref(),
+            mint.as_ref(),
+            b"token_record",
+            token.as_ref(),
+        ],
+        &METADATA_PROGRAM_ID,
+    )
+}
+
+pub fn offer_pda(escrow: &Pubkey, taker: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"offer", escrow.as_ref(), taker.as_ref()], &crate::ID)
+}
+
+// the offer's associated token account for mint_b, holding the taker's bid
+pub fn offer_vault_pda(offer: &Pubkey, mint_b: &Pubkey, token_program: &Pubkey) -> Pubkey {
+    get_associated_token_address_with_program_id(offer, mint_b, token_program)
+}
+
+pub fn commitment_pda(escrow: &Pubkey, taker: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"commitment", escrow.as_ref(), taker.as_ref()],
+        &crate::ID,
+    )
+}
+
+// the commitment's associated token account for mint_b, holding the taker's payment
+pub fn commitment_vault_pda(
+    commitment: &Pubkey,
+    mint_b: &Pubkey,
+    token_program: &Pubkey,
+) -> Pubkey {
+    get_associated_token_address_with_program_id(commitment, mint_b, token_program)
+}
@@ -0,0 +1,136 @@
+// helpers for Metaplex programmable NFTs. their token accounts stay frozen between
+// transfers, so only Token Metadata's Transfer instruction can move them
+use anchor_lang::prelude::*;
+
+use anchor_lang::solana_program::{
+    instruction::{AccountMeta, Instruction},
+    program::invoke_signed,
+};
+
+use crate::error::ErrorCode;
+use crate::metadata::check_metadata;
+use crate::METADATA_PROGRAM_ID;
+
+// MetadataInstruction::Transfer, followed by TransferArgs::V1
+const TRANSFER: [u8; 2] = [49, 0];
+
+// what a pNFT transfer needs besides the accounts of the instruction itself. they come
+// in as remaining accounts in this order, the rules accounts only for pNFTs with a rule set
+pub struct PnftAccounts<'info> {
+    pub metadata: AccountInfo<'info>,
+    pub edition: AccountInfo<'info>,
+    pub token_record: AccountInfo<'info>,
+    pub destination_token_record: AccountInfo<'info>,
+    pub sysvar_instructions: AccountInfo<'info>,
+    pub token_metadata_program: AccountInfo<'info>,
+    pub authorization_rules_program: Option<AccountInfo<'info>>,
+    pub authorization_rules: Option<AccountInfo<'info>>,
+}
+
+impl<'info> PnftAccounts<'info> {
+    // Token Metadata checks the edition, token records and rules itself. the metadata and
+    // the program are checked here, the escrow signs for the vault in this CPI
+    pub fn from_remaining(accounts: &[AccountInfo<'info>], mint: &Pubkey) -> Result<Self> {
+        let (rules_program, rules) = match accounts.len() {
+            6 => (None, None),
+            8 => (Some(accounts[6].clone()), Some(accounts[7].clone())),
+            _ => return Err(ErrorCode::MissingPnftAccounts.into()),
+        };
+        check_metadata(&accounts[0], mint)?;
+        require_keys_eq!(
+            accounts[5].key(),
+            METADATA_PROGRAM_ID,
+            ErrorCode::InvalidMetadata
+        );
+        Ok(Self {
+            metadata: accounts[0].clone(),
+            edition: accounts[1].clone(),
+            token_record: accounts[2].clone(),
+            destination_token_record: accounts[3].clone(),
+            sysvar_instructions: accounts[4].clone(),
+            token_metadata_program: accounts[5].clone(),
+            authorization_rules_program: rules_program,
+            authorization_rules: rules,
+        })
+    }
+}
+
+// the accounts of a transfer the instructions already hold
+pub struct PnftTransfer<'info> {
+    pub token: AccountInfo<'info>,
+    pub token_owner: AccountInfo<'info>,
+    pub destination: AccountInfo<'info>,
+    pub destination_owner: AccountInfo<'info>,
+    pub mint: AccountInfo<'info>,
+    pub authority: AccountInfo<'info>,
+    pub payer: AccountInfo<'info>,
+    pub system_program: AccountInfo<'info>,
+    pub token_program: AccountInfo<'info>,
+    pub associated_token_program: AccountInfo<'info>,
+}
+
+// Token Metadata's Transfer, which thaws the source, moves `amount` and freezes the
+// destination again. missing optional accounts are passed as the program id
+pub fn transfer_pnft<'info>(
+    transfer: PnftTransfer<'info>,
+    pnft: &PnftAccounts<'info>,
+    amount: u64,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<()> {
+    let program = pnft.token_metadata_program.clone();
+    let rules_program = pnft
+        .authorization_rules_program
+        .clone()
+        .unwrap_or_else(|| program.clone());
+    let rules = pnft
+        .authorization_rules
+        .clone()
+        .unwrap_or_else(|| program.clone());
+    let accounts = [
+        (transfer.token, true),
+        (transfer.token_owner, false),
+        (transfer.destination, true),
+        (transfer.destination_owner, false),
+        (transfer.mint, false),
+        (pnft.metadata.clone(), true),
+        (pnft.edition.clone(), false),
+        (pnft.token_record.clone(), true),
+        (pnft.destination_token_record.clone(), true),
+        (transfer.authority, false),
+        (transfer.payer, true),
+        (transfer.system_program, false),
+        (pnft.sysvar_instructions.clone(), false),
+        (transfer.token_program, false),
+        (transfer.associated_token_program, false),
+        (rules_program, false),
+        (rules, false),
+    ];
+    let metas = accounts
+        .iter()
+        .enumerate()
+        .map(|(i, (account, writable))| {
+            // the authority and the payer sign, at positions 9 and 10
+            let signer = i == 9 || i == 10;
+            if *writable {
+                AccountMeta::new(account.key(), signer)
+            } else {
+                AccountMeta::new_readonly(account.key(), signer)
+            }
+        })
+        .collect();
+
+    // TransferArgs::V1 { amount, authorization_data: None }
+    let mut data = TRANSFER.to_vec();
+    data.extend_from_slice(&amount.to_le_bytes());
+    data.push(0);
+
+    let instruction = Instruction {
+        program_id: METADATA_PROGRAM_ID,
+        accounts: metas,
+        data,
+    };
+    let mut infos: Vec<AccountInfo<'info>> =
+        accounts.into_iter().map(|(account, _)| account).collect();
+    infos.push(program);
+    invoke_signed(&instruction, &infos, signer_seeds).map_err(Into::into)
+}
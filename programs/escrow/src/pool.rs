@@ -0,0 +1,78 @@
+// reader for constant product pools laid out like Raydium CP-Swap's PoolState. the
+// reserves are what the pool's vaults hold, less the fees the pool owes elsewhere
+use anchor_lang::prelude::*;
+
+use anchor_spl::token_interface::TokenAccount;
+
+use crate::error::ErrorCode;
+use crate::math::checked_div_ceil;
+
+const VAULT_0_AT: usize = 72;
+const VAULT_1_AT: usize = 104;
+const MINT_0_AT: usize = 168;
+const MINT_1_AT: usize = 200;
+// protocol fees, then fund fees, of token 0 and token 1
+const FEES_AT: usize = 341;
+const POOL_LEN: usize = FEES_AT + 32;
+
+// the pool's reserves of mint_a and mint_b, in base units
+pub struct PoolReserves {
+    pub reserve_a: u64,
+    pub reserve_b: u64,
+}
+
+// the pool has to trade exactly mint_a against mint_b, in either order, through the two
+// vaults it names
+pub fn read_reserves(
+    pool: &AccountInfo,
+    vault_0: &InterfaceAccount<TokenAccount>,
+    vault_1: &InterfaceAccount<TokenAccount>,
+    mint_a: &Pubkey,
+    mint_b: &Pubkey,
+) -> Result<PoolReserves> {
+    let data = pool.try_borrow_data()?;
+    require!(data.len() >= POOL_LEN, ErrorCode::PoolMismatch);
+    let key = |at: usize| Pubkey::try_from(&data[at..at + 32]).unwrap();
+    let fees = |at: usize| u64::from_le_bytes(data[at..at + 8].try_into().unwrap());
+    require!(
+        key(VAULT_0_AT) == vault_0.key() && key(VAULT_1_AT) == vault_1.key(),
+        ErrorCode::PoolMismatch
+    );
+    let reserve_0 = vault_0
+        .amount
+        .saturating_sub(fees(FEES_AT))
+        .saturating_sub(fees(FEES_AT + 16));
+    let reserve_1 = vault_1
+        .amount
+        .saturating_sub(fees(FEES_AT + 8))
+        .saturating_sub(fees(FEES_AT + 24));
+
+    let (mint_0, mint_1) = (key(MINT_0_AT), key(MINT_1_AT));
+    let (reserve_a, reserve_b) = if (mint_0, mint_1) == (*mint_a, *mint_b) {
+        (reserve_0, reserve_1)
+    } else if (mint_0, mint_1) == (*mint_b, *mint_a) {
+        (reserve_1, reserve_0)
+    } else {
+        return Err(ErrorCode::PoolMismatch.into());
+    };
+    Ok(PoolReserves {
+        reserve_a,
+        reserve_b,
+    })
+}
+
+impl PoolReserves {
+    // mint_b for `amount` of mint_a at the pool's spot price, moved by `premium_bps`.
+    // a discount is a negative premium. rounds up in the maker's favour
+    pub fn payment(&self, amount: u64, premium_bps: i16) -> Result<u64> {
+        let numerator = (amount as u128)
+            .checked_mul(self.reserve_b as u128)
+            .and_then(|value| value.checked_mul((10_000 + premium_bps as i32) as u128))
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        let denominator = (self.reserve_a as u128)
+            .checked_mul(10_000)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        u64::try_from(checked_div_ceil(numerator, denominator)?)
+            .map_err(|_| ErrorCode::ArithmeticOverflow.into())
+    }
+}
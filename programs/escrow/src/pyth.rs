@@ -0,0 +1,86 @@
+// reader for Pyth price feeds, the PriceUpdateV2 accounts the Pyth receiver program
+// posts. only fully verified updates are read, which fixes where the price sits
+use anchor_lang::prelude::*;
+
+use anchor_lang::solana_program::hash::hash;
+
+use crate::error::ErrorCode;
+use crate::math::checked_div_ceil;
+use crate::PYTH_RECEIVER_ID;
+
+// VerificationLevel::Full, right after the discriminator and the write authority
+const FULL_VERIFICATION: u8 = 1;
+const VERIFICATION_AT: usize = 40;
+// the feed id comes before the price
+const FEED_ID_AT: usize = VERIFICATION_AT + 1;
+const PRICE_AT: usize = FEED_ID_AT + 32;
+
+pub struct OraclePrice {
+    pub feed_id: [u8; 32],
+    pub price: i64,
+    pub conf: u64,
+    pub exponent: i32,
+    pub publish_time: i64,
+}
+
+pub fn read_price(feed: &AccountInfo) -> Result<OraclePrice> {
+    require_keys_eq!(*feed.owner, PYTH_RECEIVER_ID, ErrorCode::InvalidOraclePrice);
+    let data = feed.try_borrow_data()?;
+    require!(
+        data.len() >= PRICE_AT + 28
+            && data[..8] == hash(b"account:PriceUpdateV2").to_bytes()[..8]
+            && data[VERIFICATION_AT] == FULL_VERIFICATION,
+        ErrorCode::InvalidOraclePrice
+    );
+    let field = |at: usize, len: usize| &data[PRICE_AT + at..PRICE_AT + at + len];
+    Ok(OraclePrice {
+        feed_id: data[FEED_ID_AT..PRICE_AT].try_into().unwrap(),
+        price: i64::from_le_bytes(field(0, 8).try_into().unwrap()),
+        conf: u64::from_le_bytes(field(8, 8).try_into().unwrap()),
+        exponent: i32::from_le_bytes(field(16, 4).try_into().unwrap()),
+        publish_time: i64::from_le_bytes(field(20, 8).try_into().unwrap()),
+    })
+}
+
+impl OraclePrice {
+    // width of the confidence interval relative to the price, in basis points
+    pub fn conf_bps(&self) -> Result<u64> {
+        require!(self.price > 0, ErrorCode::InvalidOraclePrice);
+        let bps = checked_div_ceil(self.conf as u128 * 10_000, self.price as u128)?;
+        Ok(u64::try_from(bps).unwrap_or(u64::MAX))
+    }
+
+    // mint_b for `amount` of mint_a, with the price quoted in whole mint_b per whole mint_a.
+    // the spread is added on top and the result rounds up, both in the maker's favour
+    pub fn payment(
+        &self,
+        amount: u64,
+        decimals_a: u8,
+        decimals_b: u8,
+        spread_bps: u16,
+    ) -> Result<u64> {
+        require!(self.price > 0, ErrorCode::InvalidOraclePrice);
+        let scale = |exponent: i32| {
+            10u128
+                .checked_pow(exponent.unsigned_abs())
+                .ok_or(ErrorCode::ArithmeticOverflow)
+        };
+        let mut numerator = (amount as u128)
+            .checked_mul(self.price as u128)
+            .and_then(|value| value.checked_mul(10_000 + spread_bps as u128))
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        let mut denominator = 10_000u128;
+        let exponent = self.exponent + decimals_b as i32 - decimals_a as i32;
+        if exponent >= 0 {
+            numerator = numerator
+                .checked_mul(scale(exponent)?)
+                .ok_or(ErrorCode::ArithmeticOverflow)?;
+        } else {
+            denominator = denominator
+                .checked_mul(scale(exponent)?)
+                .ok_or(ErrorCode::ArithmeticOverflow)?;
+        }
+        u64::try_from(checked_div_ceil(numerator, denominator)?)
+            .map_err(|_| ErrorCode::ArithmeticOverflow.into())
+    }
+}
@@ -0,0 +1,19 @@
+use anchor_lang::prelude::*;
+
+// a taker's payment for amount_a of an escrow, locked at seeds [b"commitment", escrow, taker]
+// until settle swaps it for the deposit or, once the deadline has passed,
+// reclaim_commitment returns it. one per taker, settle or reclaim it to commit again
+#[account]
+#[derive(InitSpace)]
+pub struct Commitment {
+    pub escrow: Pubkey,
+    pub taker: Pubkey,
+    // mint_a the commitment buys and the mint_b it owes for it, priced at commit
+    pub amount_a: u64,
+    pub amount_b: u64,
+    // escrow.remaining at commit, settling fails once it changed
+    pub remaining: u64,
+    // unix timestamp from which settle fails and the taker can reclaim
+    pub deadline: i64,
+    pub bump: u8,
+}
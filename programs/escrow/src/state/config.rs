@@ -0,0 +1,40 @@
+use anchor_lang::prelude::*;
+
+use crate::error::ErrorCode;
+use crate::math::mul_div_floor;
+use crate::MAX_FEE_BPS;
+
+// singleton at seeds [b"config"] holding the protocol wide settings
+#[account]
+#[derive(InitSpace)]
+pub struct Config {
+    pub admin: Pubkey,
+    // owner of the mint_b ATAs the protocol fee is paid into
+    pub fee_collector: Pubkey,
+    pub bump: u8,
+    // protocol fee on every take, starts at FEE_BPS
+    pub fee_bps: u16,
+    pub paused: bool,
+    // admin proposed by propose_admin, Pubkey::default() while nothing is pending
+    pub pending_admin: Pubkey,
+}
+
+// fee taken out of a mint_b payment, rounded down so the maker keeps any dust
+pub fn protocol_fee(amount: u64, fee_bps: u16) -> Result<u64> {
+    require!(fee_bps <= MAX_FEE_BPS, ErrorCode::FeeTooHigh);
+    // fee_bps <= 10_000, so the fee is at most amount
+    mul_div_floor(amount, fee_bps as u64, 10_000)
+}
+
+// the pause as the make handlers read it off the [b"config"] PDA. a deployment that hasn't
+// initialized its config can't be paused yet, anything else there that doesn't read as a
+// Config counts as paused rather than letting makes through
+pub fn is_paused(config: &AccountInfo) -> bool {
+    if config.data_is_empty() {
+        return false;
+    }
+    match Config::try_deserialize(&mut &config.data.borrow()[..]) {
+        Ok(config) => config.paused,
+        Err(_) => true,
+    }
+}
@@ -0,0 +1,14 @@
+use anchor_lang::prelude::*;
+
+// a taker's proposed price from before offers locked their bid, at the same seeds
+// [b"offer", escrow, taker] as Offer. nothing creates these anymore, the type is kept so
+// close_counter_offer can hand the rent back and free the address for an Offer
+#[account]
+#[derive(InitSpace)]
+pub struct CounterOffer {
+    pub escrow: Pubkey,
+    pub taker: Pubkey,
+    // receive the taker would pay for the whole deposit
+    pub proposed_receive: u64,
+    pub bump: u8,
+}
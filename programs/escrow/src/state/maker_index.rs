@@ -0,0 +1,113 @@
+use anchor_lang::prelude::*;
+
+use crate::error::ErrorCode;
+use crate::{Escrow, MAX_INDEXED_ESCROWS};
+
+// the open escrows of a maker, at seeds [b"index", maker], so a wallet lists them with two
+// reads instead of a program account scan. every make adds to it, every instruction that
+// finishes an escrow drops it again through finish_escrow. like Escrow it implements the
+// account traits itself, see the AccountDeserialize impl below
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, InitSpace)]
+pub struct MakerIndex {
+    pub maker: Pubkey,
+    #[max_len(MAX_INDEXED_ESCROWS)]
+    pub entries: Vec<IndexedEscrow>,
+    pub bump: u8,
+}
+
+// what escrow_pda needs besides the maker
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub struct IndexedEscrow {
+    pub namespace: [u8; 8],
+    pub seed: u64,
+}
+
+impl MakerIndex {
+    // the size of an index from before it listed namespaces, it held bare seeds
+    pub const SEED_ONLY_SPACE: usize = 8 + 32 + 4 + 8 * MAX_INDEXED_ESCROWS + 1;
+
+    // lists the escrow, the first make also fills in the maker and bump of a new index
+    pub fn list(&mut self, maker: Pubkey, bump: u8, escrow: &Escrow) -> Result<()> {
+        require!(
+            self.entries.len() < MAX_INDEXED_ESCROWS,
+            ErrorCode::MakerIndexFull
+        );
+        self.maker = maker;
+        self.bump = bump;
+        self.entries.push(IndexedEscrow {
+            namespace: escrow.namespace,
+            seed: escrow.seed,
+        });
+        Ok(())
+    }
+
+    pub fn remove(&mut self, entry: IndexedEscrow) {
+        self.entries.retain(|listed| *listed != entry);
+    }
+}
+
+impl Discriminator for MakerIndex {
+    // sha256("account:MakerIndex")[..8], like #[account] derives it
+    const DISCRIMINATOR: &'static [u8] = &[209, 62, 24, 1, 209, 237, 19, 30];
+}
+
+impl Owner for MakerIndex {
+    fn owner() -> Pubkey {
+        crate::ID
+    }
+}
+
+impl AccountSerialize for MakerIndex {
+    fn try_serialize<W: std::io::Write>(&self, writer: &mut W) -> Result<()> {
+        if writer.write_all(Self::DISCRIMINATOR).is_err()
+            || AnchorSerialize::serialize(self, writer).is_err()
+        {
+            return Err(anchor_lang::error::ErrorCode::AccountDidNotSerialize.into());
+        }
+        Ok(())
+    }
+}
+
+impl AccountDeserialize for MakerIndex {
+    // the seed only layout would parse as entries of the wrong size, so it fails with
+    // MakerIndexOutdated before it is read. compact_maker_index grows it
+    fn try_deserialize(buf: &mut &[u8]) -> Result<Self> {
+        if buf.len() < Self::DISCRIMINATOR.len() {
+            return Err(anchor_lang::error::ErrorCode::AccountDiscriminatorNotFound.into());
+        }
+        if buf[..Self::DISCRIMINATOR.len()] != *Self::DISCRIMINATOR {
+            return Err(
+                error!(anchor_lang::error::ErrorCode::AccountDiscriminatorMismatch)
+                    .with_account_name("MakerIndex"),
+            );
+        }
+        require!(
+            buf.len() == 8 + Self::INIT_SPACE,
+            ErrorCode::MakerIndexOutdated
+        );
+        Self::try_deserialize_unchecked(buf)
+    }
+
+    fn try_deserialize_unchecked(buf: &mut &[u8]) -> Result<Self> {
+        let mut data: &[u8] = &buf[Self::DISCRIMINATOR.len()..];
+        AnchorDeserialize::deserialize(&mut data)
+            .map_err(|_| anchor_lang::error::ErrorCode::AccountDidNotDeserialize.into())
+    }
+}
+
+// drops a finished escrow from the maker's index, which the caller checked against its
+// seeds. a maker who made all their open escrows before the index was required may have
+// none, and an index of the seed only layout is left to compact_maker_index, so both are
+// skipped
+pub fn unlist_escrow(maker_index: &AccountInfo, escrow: &Escrow) -> Result<()> {
+    if maker_index.owner != &crate::ID || maker_index.data_len() != 8 + MakerIndex::INIT_SPACE {
+        return Ok(());
+    }
+    let mut data = maker_index.try_borrow_mut_data()?;
+    let mut index = MakerIndex::try_deserialize(&mut &data[..])?;
+    index.remove(IndexedEscrow {
+        namespace: escrow.namespace,
+        seed: escrow.seed,
+    });
+    index.try_serialize(&mut &mut data[..])
+}
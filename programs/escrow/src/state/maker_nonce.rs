@@ -0,0 +1,11 @@
+use anchor_lang::prelude::*;
+
+// the seed make_auto gives the next escrow of `maker`, at seeds [b"nonce", maker]. created
+// by the first make_auto and counted up by every one after it
+#[account]
+#[derive(InitSpace)]
+pub struct MakerNonce {
+    pub maker: Pubkey,
+    pub next_seed: u64,
+    pub bump: u8,
+}
@@ -1,8 +1,32 @@
 use anchor_lang::prelude::*;
+use anchor_lang::system_program::{
+    allocate, assign, create_account, transfer, Allocate, Assign, CreateAccount, Transfer,
+};
 
-#[account]
+use crate::error::ErrorCode;
+use crate::math::{mul_div_ceil, mul_div_floor};
+use crate::{COOLDOWN_SLOTS, ESCROW_VERSION, MAX_BASKET_LEGS, MAX_PAYMENT_OPTIONS, MAX_WHITELIST};
+
+pub mod commitment;
+pub mod config;
+pub mod counter_offer;
+pub mod maker_index;
+pub mod maker_nonce;
+pub mod offer;
+pub mod stats;
+pub use commitment::*;
+pub use config::*;
+pub use counter_offer::*;
+pub use maker_index::*;
+pub use maker_nonce::*;
+pub use offer::*;
+pub use stats::*;
+
+// what #[account] would derive, apart from the layout check in try_deserialize. see the
+// AccountDeserialize impl below
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
 // Implements a Space trait on the given struct or enum.
-#[derive(InitSpace)]
+#[derive(InitSpace, Default)]
 // https://docs.rs/anchor-lang/latest/anchor_lang/prelude/derive.InitSpace.html
 pub struct Escrow {
     pub seed: u64,
@@ -11,9 +35,1047 @@ pub struct Escrow {
     pub mint_b: Pubkey,
     pub receive: u64,
     pub bump: u8,
+    // unix timestamp after which the escrow can no longer be taken. 0 means no expiry
+    pub expiry: i64,
+    // total amount of mint_a deposited by the maker
+    pub deposit: u64,
+    // amount of mint_a still left in the vault to be filled
+    pub remaining: u64,
+    // amount of mint_b still owed for `remaining`
+    pub remaining_receive: u64,
+    // the maker can take the escrow off the market without refunding
+    pub active: bool,
+    // only this wallet can take the escrow. Pubkey::default() means anyone can
+    pub allowed_taker: Pubkey,
+    // takers allowed to fill the escrow, only the first whitelist_len entries are used.
+    // an empty whitelist means anyone can take
+    pub whitelist: [Pubkey; MAX_WHITELIST],
+    pub whitelist_len: u8,
+    // the deposit is native SOL held by the sol_vault PDA instead of a mint_a vault
+    pub deposit_is_sol: bool,
+    // dutch auction terms for the whole deposit, decay_duration == 0 means a fixed price
+    pub start_receive: u64,
+    pub floor_receive: u64,
+    pub start_time: i64,
+    pub decay_duration: i64,
+    // smallest amount of mint_a a fill can release, unless it takes everything that is left
+    pub min_fill: u64,
+    pub created_slot: u64,
+    // the taker covers the mint_b transfer fee so the maker nets exactly what is owed.
+    // otherwise the fee comes out of the maker's proceeds
+    pub gross_up_transfer_fee: bool,
+    // alternative payment mint for take_alt, which pays receive_c for the whole deposit.
+    // top_up and withdraw scale receive_c with the deposit like receive. Pubkey::default()
+    // means only mint_b is accepted
+    pub mint_c: Pubkey,
+    pub receive_c: u64,
+    // mint_b is wrapped SOL and take_native also accepts the payment in plain lamports
+    pub receive_is_sol: bool,
+    // unix timestamp before which the deposit can't go back to the maker. 0 means no lock
+    pub refund_locked_until: i64,
+    // Open until the escrow is taken or refunded. only escrows made with keep_record stay on
+    // chain afterwards, with the status they ended in. sits at OFFSET_STATUS
+    pub status: EscrowStatus,
+    pub keep_record: bool,
+    // lets clients render single NFT listings differently, see MakeOptions::nft
+    pub kind: EscrowKind,
+    // only holders of an NFT from this verified collection can take.
+    // Pubkey::default() means anyone can
+    pub gate_collection: Pubkey,
+    // only takers holding at least gate_min_balance of this mint can take.
+    // Pubkey::default() means anyone can
+    pub gate_mint: Pubkey,
+    pub gate_min_balance: u64,
+    // receive the maker offered terms_taker through propose_terms, good until the slot
+    // reaches terms_expiry_slot. 0 means no terms are pending
+    pub terms_taker: Pubkey,
+    pub terms_receive: u64,
+    pub terms_expiry_slot: u64,
+    // second deposit mint of a basket escrow made before make took it as a basket leg.
+    // take and refund fold it into the legs, see fold_second_mint. new escrows leave it at
+    // Pubkey::default()
+    pub mint_a2: Pubkey,
+    pub deposit_2: u64,
+    // the deposit vests to vest_taker linearly over vest_duration seconds from vest_start,
+    // the time of the take. vest_duration == 0 hands the deposit over in the take
+    pub vest_start: i64,
+    pub vest_duration: i64,
+    pub vest_taker: Pubkey,
+    // mint_a vest_taker has claimed so far
+    pub claimed_amount: u64,
+    // the maker may take their own escrow, e.g. a market maker unwinding a quote
+    pub allow_self_fill: bool,
+    // gets the rent of the escrow and its vaults when take or refund close them.
+    // Pubkey::default() means the maker
+    pub rent_recipient: Pubkey,
+    // PerUnit escrows are priced at price_numerator / price_denominator mint_b per mint_a,
+    // both in base units. receive and remaining_receive then follow from deposit and
+    // remaining. zeroed, an escrow is priced by receive as before
+    pub price_mode: PriceMode,
+    pub price_numerator: u64,
+    pub price_denominator: u64,
+    // Pyth price feed of mint_a in mint_b. takes price the whole deposit at the feed's price
+    // plus spread_bps, as long as the price is at most max_price_age seconds old and its
+    // confidence interval within max_conf_bps of it. Pubkey::default() means no oracle
+    pub oracle: Pubkey,
+    pub spread_bps: u16,
+    pub max_price_age: u64,
+    // 0 accepts any confidence interval
+    pub max_conf_bps: u16,
+    // AMM pool of mint_a and mint_b owned by pool_program. takes price the whole deposit
+    // at the pool's spot price moved by pool_premium_bps, a discount when negative, as long
+    // as the pool holds at least min_reserve_a and min_reserve_b. Pubkey::default() means
+    // no pool
+    pub pool: Pubkey,
+    pub pool_program: Pubkey,
+    pub pool_premium_bps: i16,
+    pub min_reserve_a: u64,
+    pub min_reserve_b: u64,
+    // layout version, see ESCROW_VERSION. escrows from before versioning read as 0 once
+    // migrate has grown them
+    pub version: u8,
+    // further mints of a basket made through make_basket or make's deposit_2, each held in
+    // the escrow's ATA for it and released with the last fill. only the first basket_len
+    // entries are used
+    pub basket_mints: [Pubkey; MAX_BASKET_LEGS],
+    pub basket_amounts: [u64; MAX_BASKET_LEGS],
+    pub basket_len: u8,
+    // further mints take_with_payment accepts, each at its own receive for the whole
+    // deposit, scaled like receive_c. only the first payment_len entries are used
+    pub payment_mints: [Pubkey; MAX_PAYMENT_OPTIONS],
+    pub payment_receives: [u64; MAX_PAYMENT_OPTIONS],
+    pub payment_len: u8,
+    // filled in on records kept through keep_record once the escrow finishes: when it did
+    // and who took it last. Pubkey::default() for refunds
+    pub settled_at: i64,
+    pub counterparty: Pubkey,
+    // takes pay the maker's share into the escrow's own mint_b account instead of the
+    // maker's, for the maker to sweep with claim_payment
+    pub receive_to_vault: bool,
+    // set by make to keep a front-end's escrows apart from others at the same maker and
+    // seed. all zeros for every other make, see namespace_seed
+    pub namespace: [u8; 8],
+    // may accept offers for the maker, approved by the maker with approve_delegate.
+    // Pubkey::default() means no delegate
+    pub delegate: Pubkey,
+    // lowest receive update_receive, propose_terms and a signed quote can set, fixed by
+    // make along with mint_b. 0 means no floor
+    pub min_receive_floor: u64,
+    // label for front-ends, UTF-8 padded with trailing zeros, see memo_str
+    pub memo: [u8; 32],
+    // Pyth feed id the oracle account has to carry. its poster can write another feed to
+    // the same account, so the address alone doesn't pin the price
+    pub oracle_feed_id: [u8; 32],
+}
+
+impl Discriminator for Escrow {
+    // sha256("account:Escrow")[..8], like #[account] derives it
+    const DISCRIMINATOR: &'static [u8] = &[31, 213, 123, 187, 186, 22, 218, 155];
+}
+
+impl Owner for Escrow {
+    fn owner() -> Pubkey {
+        crate::ID
+    }
+}
+
+impl AccountSerialize for Escrow {
+    fn try_serialize<W: std::io::Write>(&self, writer: &mut W) -> Result<()> {
+        if writer.write_all(Self::DISCRIMINATOR).is_err()
+            || AnchorSerialize::serialize(self, writer).is_err()
+        {
+            return Err(anchor_lang::error::ErrorCode::AccountDidNotSerialize.into());
+        }
+        Ok(())
+    }
+}
+
+impl AccountDeserialize for Escrow {
+    // an escrow of an older layout is shorter or carries an older version. it fails with
+    // EscrowNotMigrated instead of a bare AccountDidNotDeserialize, migrate brings it up
+    // to date. migrate itself reads old escrows through try_deserialize_unchecked
+    fn try_deserialize(buf: &mut &[u8]) -> Result<Self> {
+        if buf.len() < Self::DISCRIMINATOR.len() {
+            return Err(anchor_lang::error::ErrorCode::AccountDiscriminatorNotFound.into());
+        }
+        if buf[..Self::DISCRIMINATOR.len()] != *Self::DISCRIMINATOR {
+            return Err(
+                error!(anchor_lang::error::ErrorCode::AccountDiscriminatorMismatch)
+                    .with_account_name("Escrow"),
+            );
+        }
+        require!(
+            buf.len() == 8 + Self::INIT_SPACE,
+            ErrorCode::EscrowNotMigrated
+        );
+        let escrow = Self::try_deserialize_unchecked(buf)?;
+        require!(
+            escrow.version == ESCROW_VERSION,
+            ErrorCode::EscrowNotMigrated
+        );
+        Ok(escrow)
+    }
+
+    fn try_deserialize_unchecked(buf: &mut &[u8]) -> Result<Self> {
+        let mut data: &[u8] = &buf[Self::DISCRIMINATOR.len()..];
+        AnchorDeserialize::deserialize(&mut data)
+            .map_err(|_| anchor_lang::error::ErrorCode::AccountDidNotDeserialize.into())
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace, Default)]
+pub enum EscrowKind {
+    #[default]
+    Fungible,
+    // a single token of a 0 decimal mint with a supply of 1
+    Nft,
+    // an Nft whose metadata makes it a Metaplex pNFT, moved through Token Metadata
+    ProgrammableNft,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace, Default)]
+pub enum EscrowStatus {
+    #[default]
+    Open,
+    // the whole deposit was taken
+    Completed,
+    // the maker took the deposit back
+    Refunded,
+    // a crank returned the deposit of an expired escrow
+    Cancelled,
+    // paid for, the deposit is released to the taker through claim_vested
+    Vesting,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace, Default)]
+pub enum PriceMode {
+    // receive is the price of the whole deposit, fills pay their share of it
+    #[default]
+    Total,
+    // fills pay the unit price for each base unit of mint_a
+    PerUnit,
+}
+
+// mint_b owed for `amount` of mint_a at numerator / denominator mint_b per mint_a. rounds
+// up, so any amount above zero costs at least one base unit of mint_b
+pub fn unit_payment(amount: u64, numerator: u64, denominator: u64) -> Result<u64> {
+    require!(
+        numerator > 0 && denominator > 0,
+        ErrorCode::InvalidUnitPrice
+    );
+    mul_div_ceil(amount, numerator, denominator)
+}
+
+// the namespace as it goes into the escrow's seeds, between the maker and the seed. the
+// zero namespace adds no bytes at all, so escrows without one keep the address they had
+// before namespaces existed
+pub fn namespace_seed(namespace: &[u8; 8]) -> &[u8] {
+    if *namespace == [0; 8] {
+        &[]
+    } else {
+        namespace
+    }
+}
+
+// the memo without its zero padding, None when that isn't valid UTF-8
+pub fn memo_str(memo: &[u8; 32]) -> Option<&str> {
+    let len = memo
+        .iter()
+        .rposition(|byte| *byte != 0)
+        .map_or(0, |last| last + 1);
+    std::str::from_utf8(&memo[..len]).ok()
+}
+
+// ends an escrow once its deposit is gone. it is closed into `destination` unless the maker
+// asked to keep a record, which stays behind with the final status, the time and the
+// taker of the last fill as `counterparty`. either way it leaves the maker's index
+pub fn finish_escrow<'info>(
+    escrow: &mut Account<'info, Escrow>,
+    status: EscrowStatus,
+    counterparty: Pubkey,
+    destination: AccountInfo<'info>,
+    maker_index: &AccountInfo<'info>,
+) -> Result<()> {
+    unlist_escrow(maker_index, escrow)?;
+    if escrow.keep_record {
+        escrow.status = status;
+        escrow.settled_at = Clock::get()?.unix_timestamp;
+        escrow.counterparty = counterparty;
+        Ok(())
+    } else {
+        escrow.close(destination)
+    }
+}
+
+// where take and refund send the rent of the accounts they close
+pub fn rent_destination<'info>(
+    escrow: &Escrow,
+    maker: AccountInfo<'info>,
+    rent_recipient: Option<&UncheckedAccount<'info>>,
+) -> Result<AccountInfo<'info>> {
+    if escrow.rent_recipient == Pubkey::default() {
+        return Ok(maker);
+    }
+    rent_recipient
+        .map(|recipient| recipient.to_account_info())
+        .ok_or(ErrorCode::RentRecipientMismatch.into())
+}
+
+// creates the program derived `account` like anchor's init does. create_account fails on
+// an address that already holds lamports, so one someone sent lamports to beforehand is
+// topped up to rent exemption, allocated and assigned instead
+pub fn create_pda_account<'info>(
+    payer: AccountInfo<'info>,
+    account: AccountInfo<'info>,
+    space: usize,
+    owner: &Pubkey,
+    signer_seeds: &[&[&[u8]]],
+    system_program: AccountInfo<'info>,
+) -> Result<()> {
+    let rent = Rent::get()?.minimum_balance(space);
+    let lamports = account.lamports();
+    if lamports == 0 {
+        let accounts = CreateAccount {
+            from: payer,
+            to: account,
+        };
+        let cpi_ctx = CpiContext::new_with_signer(system_program, accounts, signer_seeds);
+        return create_account(cpi_ctx, rent, space as u64, owner);
+    }
+
+    if lamports < rent {
+        let accounts = Transfer {
+            from: payer,
+            to: account.clone(),
+        };
+        transfer(
+            CpiContext::new(system_program.clone(), accounts),
+            rent - lamports,
+        )?;
+    }
+    let accounts = Allocate {
+        account_to_allocate: account.clone(),
+    };
+    allocate(
+        CpiContext::new_with_signer(system_program.clone(), accounts, signer_seeds),
+        space as u64,
+    )?;
+    let accounts = Assign {
+        account_to_assign: account,
+    };
+    assign(
+        CpiContext::new_with_signer(system_program, accounts, signer_seeds),
+        owner,
+    )
+}
+
+impl Escrow {
+    pub fn namespace_seed(&self) -> &[u8] {
+        namespace_seed(&self.namespace)
+    }
+
+    // make validates the memo, so this is only None for bytes written some other way
+    pub fn memo_str(&self) -> Option<&str> {
+        memo_str(&self.memo)
+    }
+
+    // an escrow is expired once the clock reaches the expiry, the boundary itself is expired
+    pub fn is_expired(&self, now: i64) -> bool {
+        self.expiry != 0 && now >= self.expiry
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.status == EscrowStatus::Open
+    }
+
+    pub fn is_programmable(&self) -> bool {
+        self.kind == EscrowKind::ProgrammableNft
+    }
+
+    // the lock has ended once the clock reaches refund_locked_until
+    pub fn is_refund_locked(&self, now: i64) -> bool {
+        now < self.refund_locked_until
+    }
+
+    pub fn set_whitelist(&mut self, takers: &[Pubkey]) -> Result<()> {
+        require!(takers.len() <= MAX_WHITELIST, ErrorCode::WhitelistTooLong);
+        for (i, taker) in takers.iter().enumerate() {
+            require!(
+                !takers[..i].contains(taker),
+                ErrorCode::DuplicateWhitelistEntry
+            );
+        }
+
+        self.whitelist = [Pubkey::default(); MAX_WHITELIST];
+        self.whitelist[..takers.len()].copy_from_slice(takers);
+        self.whitelist_len = takers.len() as u8;
+        Ok(())
+    }
+
+    // Pubkey::default() as allowed_taker keeps the escrow open to anyone
+    pub fn is_allowed_taker(&self, taker: &Pubkey) -> bool {
+        self.allowed_taker == Pubkey::default() || self.allowed_taker == *taker
+    }
+
+    pub fn is_whitelisted(&self, taker: &Pubkey) -> bool {
+        self.whitelist_len == 0 || self.whitelist[..self.whitelist_len as usize].contains(taker)
+    }
+
+    pub fn is_collection_gated(&self) -> bool {
+        self.gate_collection != Pubkey::default()
+    }
+
+    pub fn is_mint_gated(&self) -> bool {
+        self.gate_mint != Pubkey::default()
+    }
+
+    // a maker filling their own escrow only fakes volume, unless the escrow opted in
+    pub fn blocks_self_trade(&self, taker: &Pubkey) -> bool {
+        *taker == self.maker && !self.allow_self_fill
+    }
+
+    pub fn is_delegate(&self, key: &Pubkey) -> bool {
+        self.delegate != Pubkey::default() && self.delegate == *key
+    }
+
+    pub fn vests_deposit(&self) -> bool {
+        self.vest_duration > 0
+    }
+
+    pub fn is_vesting(&self) -> bool {
+        self.status == EscrowStatus::Vesting
+    }
+
+    // part of the deposit unlocked at `now`, rounded down so nothing unlocks early
+    pub fn vested(&self, now: i64) -> Result<u64> {
+        let elapsed = now
+            .saturating_sub(self.vest_start)
+            .clamp(0, self.vest_duration);
+        mul_div_floor(self.deposit, elapsed as u64, self.vest_duration as u64)
+    }
+
+    // basket legs, or a second mint not folded into them yet
+    pub fn is_basket(&self) -> bool {
+        self.mint_a2 != Pubkey::default() || self.basket_len > 0
+    }
+
+    // moves the second mint of an older escrow into the first basket leg, which lives in
+    // the same ATA of the escrow. it goes through the leg accounts from then on
+    pub fn fold_second_mint(&mut self) {
+        if self.mint_a2 == Pubkey::default() || self.basket_len > 0 {
+            return;
+        }
+        self.basket_mints[0] = self.mint_a2;
+        self.basket_amounts[0] = self.deposit_2;
+        self.basket_len = 1;
+        self.mint_a2 = Pubkey::default();
+        self.deposit_2 = 0;
+    }
+
+    pub fn basket_mints(&self) -> &[Pubkey] {
+        &self.basket_mints[..self.basket_len as usize]
+    }
+
+    pub fn has_pending_terms(&self) -> bool {
+        self.terms_receive > 0
+    }
+
+    // pending terms lapse once the slot reaches terms_expiry_slot, like the escrow expiry
+    pub fn terms_expired(&self, slot: u64) -> bool {
+        slot >= self.terms_expiry_slot
+    }
+
+    pub fn clear_terms(&mut self) {
+        self.terms_taker = Pubkey::default();
+        self.terms_receive = 0;
+        self.terms_expiry_slot = 0;
+    }
+
+    // true once any part of the deposit has been taken
+    pub fn is_filled(&self) -> bool {
+        self.remaining < self.deposit
+    }
+
+    // receive is the price of the whole deposit, what is still owed scales with what is left
+    pub fn reprice(&mut self, new_receive: u64) -> Result<()> {
+        self.remaining_receive = mul_div_ceil(self.remaining, new_receive, self.deposit)?;
+        self.receive = new_receive;
+        Ok(())
+    }
+
+    // adds `amount` to the deposit. with scale_receive the price per token stays the same
+    // and the extra receive rounds up, otherwise receive is fixed and the taker gets a better price.
+    // an auction reprices from its curve on every fill, so the curve scales with it
+    pub fn top_up(&mut self, amount: u64, scale_receive: bool) -> Result<()> {
+        require!(amount > 0, ErrorCode::ZeroAmount);
+
+        if scale_receive && self.is_auction() {
+            // floor_receive <= start_receive, so the scaled floor stays below the scaled start
+            self.start_receive = self
+                .start_receive
+                .checked_add(mul_div_ceil(amount, self.start_receive, self.deposit)?)
+                .ok_or(ErrorCode::ArithmeticOverflow)?;
+            self.floor_receive = self
+                .floor_receive
+                .checked_add(mul_div_ceil(amount, self.floor_receive, self.deposit)?)
+                .ok_or(ErrorCode::ArithmeticOverflow)?;
+        }
+        if scale_receive {
+            let deposit = self
+                .deposit
+                .checked_add(amount)
+                .ok_or(ErrorCode::ArithmeticOverflow)?;
+            self.scale_other_receives(deposit)?;
+            let extra = mul_div_ceil(amount, self.remaining_receive, self.remaining)?;
+            self.receive = self
+                .receive
+                .checked_add(extra)
+                .ok_or(ErrorCode::ArithmeticOverflow)?;
+            self.remaining_receive = self
+                .remaining_receive
+                .checked_add(extra)
+                .ok_or(ErrorCode::ArithmeticOverflow)?;
+        }
+        self.deposit = self
+            .deposit
+            .checked_add(amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.remaining = self
+            .remaining
+            .checked_add(amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        // a unit price scales with the deposit either way
+        self.sync_unit_price()
+    }
+
+    // takes `amount` back out of what is left at the same price per token.
+    // what is still owed rounds up, so the maker never ends up selling cheaper
+    pub fn withdraw(&mut self, amount: u64) -> Result<()> {
+        require!(amount > 0, ErrorCode::ZeroAmount);
+        require!(
+            amount <= self.remaining,
+            ErrorCode::WithdrawExceedsRemaining
+        );
+
+        let left = self.remaining - amount;
+        // whatever stays behind has to be fillable
+        require!(
+            left == 0 || left >= self.min_fill,
+            ErrorCode::BelowMinimumFill
+        );
+        // left < remaining, so the result is at most remaining_receive
+        let remaining_receive = mul_div_ceil(left, self.remaining_receive, self.remaining)?;
+        self.receive -= self.remaining_receive - remaining_receive;
+        self.remaining_receive = remaining_receive;
+        self.scale_other_receives(self.deposit - amount)?;
+        self.deposit -= amount;
+        self.remaining = left;
+        self.sync_unit_price()
+    }
+
+    // receive_c and the payment options price the whole deposit, so they follow it to
+    // `deposit` at the same price per token, rounding up like withdraw
+    fn scale_other_receives(&mut self, deposit: u64) -> Result<()> {
+        if self.mint_c != Pubkey::default() {
+            self.receive_c = mul_div_ceil(self.receive_c, deposit, self.deposit)?;
+        }
+        for index in 0..self.payment_len as usize {
+            self.payment_receives[index] =
+                mul_div_ceil(self.payment_receives[index], deposit, self.deposit)?;
+        }
+        Ok(())
+    }
+
+    pub fn is_oracle_priced(&self) -> bool {
+        self.oracle != Pubkey::default()
+    }
+
+    pub fn is_pool_priced(&self) -> bool {
+        self.pool != Pubkey::default()
+    }
+
+    pub fn is_unit_priced(&self) -> bool {
+        self.price_mode == PriceMode::PerUnit
+    }
+
+    // mint_b per mint_a as (numerator, denominator), the fixed unit price or what the
+    // whole deposit costs
+    pub fn base_unit_price(&self) -> (u64, u64) {
+        if self.is_unit_priced() {
+            (self.price_numerator, self.price_denominator)
+        } else {
+            (self.receive, self.deposit)
+        }
+    }
+
+    // mint_b per whole mint_a for front-ends to display. not used on chain, floats
+    // only approximate the price. 0 for an escrow without a deposit
+    pub fn unit_price(&self, decimals_a: u8, decimals_b: u8) -> f64 {
+        let (numerator, denominator) = self.base_unit_price();
+        if denominator == 0 {
+            return 0.0;
+        }
+        numerator as f64 / denominator as f64 * 10f64.powi(decimals_a as i32 - decimals_b as i32)
+    }
+
+    // reprices a unit priced escrow after its deposit or remaining changed
+    pub fn sync_unit_price(&mut self) -> Result<()> {
+        if self.is_unit_priced() {
+            self.receive =
+                unit_payment(self.deposit, self.price_numerator, self.price_denominator)?;
+            self.remaining_receive =
+                unit_payment(self.remaining, self.price_numerator, self.price_denominator)?;
+        }
+        Ok(())
+    }
+
+    pub fn is_cooling_down(&self, slot: u64) -> bool {
+        slot < self.created_slot.saturating_add(COOLDOWN_SLOTS)
+    }
+
+    pub fn is_auction(&self) -> bool {
+        self.decay_duration > 0
+    }
+
+    // price of the whole deposit at `now`. falls linearly from start_receive and
+    // clamps at floor_receive, the decay rounds down so the price never undercuts the curve
+    pub fn current_price(&self, now: i64) -> Result<u64> {
+        if !self.is_auction() {
+            return Ok(self.receive);
+        }
+        let elapsed = now
+            .saturating_sub(self.start_time)
+            .clamp(0, self.decay_duration);
+        // elapsed <= decay_duration, so the decay is at most start_receive - floor_receive
+        let decay = mul_div_floor(
+            self.start_receive - self.floor_receive,
+            elapsed as u64,
+            self.decay_duration as u64,
+        )?;
+        Ok(self.start_receive - decay)
+    }
+
+    // moves an auction escrow to the current point on its price curve before a fill
+    pub fn apply_auction_price(&mut self, now: i64) -> Result<()> {
+        if self.is_auction() {
+            self.reprice(self.current_price(now)?)?;
+        }
+        Ok(())
+    }
+
+    // price of everything that is left when paid in `mint`. mint_b follows the current
+    // receive, mint_c the fixed receive_c. remaining <= deposit, so the result fits in u64
+    pub fn full_payment(&self, mint: &Pubkey) -> Result<u64> {
+        if *mint == self.mint_b {
+            return Ok(self.remaining_receive);
+        }
+        require!(
+            self.mint_c != Pubkey::default() && *mint == self.mint_c,
+            ErrorCode::UnsupportedPaymentMint
+        );
+        mul_div_ceil(self.remaining, self.receive_c, self.deposit)
+    }
+
+    pub fn payment_mints(&self) -> &[Pubkey] {
+        &self.payment_mints[..self.payment_len as usize]
+    }
+
+    // fill of `amount` of mint_a paid in the payment option at `index`, which `mint` has
+    // to be. returns (mint_a out, payment in). the payment is the option's share of the
+    // whole deposit and rounds up, like a mint_b fill
+    pub fn fill_with_payment(&self, index: u8, mint: &Pubkey, amount: u64) -> Result<(u64, u64)> {
+        let index = index as usize;
+        require!(
+            index < self.payment_mints().len(),
+            ErrorCode::InvalidPaymentIndex
+        );
+        require_keys_eq!(
+            *mint,
+            self.payment_mints[index],
+            ErrorCode::UnsupportedPaymentMint
+        );
+        require!(amount > 0, ErrorCode::ZeroAmount);
+        require!(amount <= self.remaining, ErrorCode::FillExceedsRemaining);
+        if amount < self.remaining {
+            self.check_min_fill(amount)?;
+        }
+        let payment = mul_div_ceil(amount, self.payment_receives[index], self.deposit)?;
+        Ok((amount, payment))
+    }
+
+    // a fill paid in another mint leaves what is still owed in mint_b at the same price
+    // per token for what is left. rounds up like withdraw
+    pub fn release_receive(&mut self, amount_a: u64) -> Result<()> {
+        let left = self.remaining - amount_a;
+        self.remaining_receive = mul_div_ceil(left, self.remaining_receive, self.remaining)?;
+        Ok(())
+    }
+
+    // fill where the taker asks for `amount` of mint_a, returns (mint_a out, mint_b in).
+    // the payment rounds up so partial fills never underpay the maker
+    pub fn fill_for_amount_a(&self, amount: u64) -> Result<(u64, u64)> {
+        require!(amount > 0, ErrorCode::ZeroAmount);
+        require!(amount <= self.remaining, ErrorCode::FillExceedsRemaining);
+
+        if self.is_unit_priced() {
+            if amount == self.remaining {
+                return Ok((self.remaining, self.remaining_receive));
+            }
+            self.check_min_fill(amount)?;
+            // at most remaining_receive, the unit price of everything that is left
+            let payment = unit_payment(amount, self.price_numerator, self.price_denominator)?;
+            return Ok((amount, payment));
+        }
+        // amount <= remaining, so the payment is at most remaining_receive
+        let payment = mul_div_ceil(amount, self.remaining_receive, self.remaining)?;
+        // paying everything that is still owed releases everything that is left
+        if payment >= self.remaining_receive {
+            return Ok((self.remaining, self.remaining_receive));
+        }
+        self.check_min_fill(amount)?;
+        Ok((amount, payment))
+    }
+
+    // fill where the taker pays `amount` of mint_b, returns (mint_a out, mint_b in).
+    // mint_a out rounds down so any dust stays in the vault until the last fill
+    pub fn fill_for_amount_b(&self, amount: u64) -> Result<(u64, u64)> {
+        require!(amount > 0, ErrorCode::ZeroAmount);
+        require!(
+            amount <= self.remaining_receive,
+            ErrorCode::FillExceedsRemaining
+        );
+
+        if amount == self.remaining_receive {
+            return Ok((self.remaining, self.remaining_receive));
+        }
+        // amount < remaining_receive, so the result is below remaining. remaining_receive
+        // of a unit priced escrow rounds remaining up, which keeps this below remaining too
+        let release = if self.is_unit_priced() {
+            mul_div_floor(amount, self.price_denominator, self.price_numerator)?
+        } else {
+            mul_div_floor(amount, self.remaining, self.remaining_receive)?
+        };
+        require!(release > 0, ErrorCode::FillTooSmall);
+        self.check_min_fill(release)?;
+        Ok((release, amount))
+    }
+
+    // only called for fills that leave something in the vault
+    fn check_min_fill(&self, amount_a: u64) -> Result<()> {
+        require!(amount_a >= self.min_fill, ErrorCode::BelowMinimumFill);
+        Ok(())
+    }
 }
 
 // SPL Token
 // Mint Account: Creating token is executed by creating a mint account. Mint account create token to user token account.
 // if you want to find information about token, you can find it in mint account.
 // Token Account: User's token account. User can hold token in this account. Have at least one token account for each type of token you own.
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        MAX_INDEXED_ESCROWS, OFFSET_MAKER, OFFSET_MINT_A, OFFSET_MINT_B, OFFSET_NAMESPACE,
+        OFFSET_SEED, OFFSET_STATUS,
+    };
+
+    fn escrow(receive: u64, deposit: u64) -> Escrow {
+        Escrow {
+            receive,
+            deposit,
+            remaining: deposit,
+            remaining_receive: receive,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn unit_price_scales_by_decimals() {
+        // 10 of a 6 decimal mint_a for 25 of a 9 decimal mint_b
+        assert_eq!(escrow(25_000_000_000, 10_000_000).unit_price(6, 9), 2.5);
+        // the other way round, 10 of a 9 decimal mint_a for 25 of a 6 decimal mint_b
+        assert_eq!(escrow(25_000_000, 10_000_000_000).unit_price(9, 6), 2.5);
+    }
+
+    #[test]
+    fn unit_price_follows_the_unit_price_of_unit_priced_escrows() {
+        let mut escrow = escrow(1, 10_000_000);
+        escrow.price_mode = PriceMode::PerUnit;
+        escrow.price_numerator = 1_500_000_000;
+        escrow.price_denominator = 1_000_000;
+        assert_eq!(escrow.unit_price(6, 9), 1.5);
+    }
+
+    #[test]
+    fn unit_price_of_an_empty_escrow_is_zero() {
+        assert_eq!(escrow(0, 0).unit_price(6, 9), 0.0);
+        assert_eq!(escrow(500_000, 0).unit_price(0, 9), 0.0);
+    }
+
+    #[test]
+    fn proportional_math_holds_near_u64_max() {
+        let max = u64::MAX;
+        assert_eq!(mul_div_floor(max, max, max).unwrap(), max);
+        assert_eq!(mul_div_ceil(max - 1, max, max).unwrap(), max - 1);
+        // half the deposit of a near-max escrow costs half its receive, rounded up
+        let escrow = escrow(max - 1, max - 1);
+        assert_eq!(
+            escrow.fill_for_amount_a((max - 1) / 2).unwrap(),
+            ((max - 1) / 2, (max - 1) / 2)
+        );
+        assert_eq!(
+            escrow.fill_for_amount_b(max / 4).unwrap(),
+            (max / 4, max / 4)
+        );
+    }
+
+    #[test]
+    fn proportional_math_rounds_every_combination_of_edge_values() {
+        let max = u64::MAX;
+        let values = [
+            0,
+            1,
+            2,
+            3,
+            10_000,
+            max / 2,
+            max / 2 + 1,
+            max - 2,
+            max - 1,
+            max,
+        ];
+        for amount in values {
+            for numerator in values {
+                for denominator in values {
+                    let floor = mul_div_floor(amount, numerator, denominator);
+                    let ceil = mul_div_ceil(amount, numerator, denominator);
+                    if denominator == 0 {
+                        assert_eq!(floor.unwrap_err(), ErrorCode::ArithmeticOverflow.into());
+                        assert_eq!(ceil.unwrap_err(), ErrorCode::ArithmeticOverflow.into());
+                        continue;
+                    }
+                    // the exact product always fits in u128
+                    let product = amount as u128 * numerator as u128;
+                    let exact = (product / denominator as u128, product % denominator as u128);
+                    match (u64::try_from(exact.0), floor) {
+                        (Ok(expected), Ok(floor)) => assert_eq!(floor, expected),
+                        (Err(_), Err(err)) => assert_eq!(err, ErrorCode::ArithmeticOverflow.into()),
+                        (expected, floor) => panic!("floor {floor:?}, expected {expected:?}"),
+                    }
+                    let up = exact.0 + (exact.1 > 0) as u128;
+                    match (u64::try_from(up), ceil) {
+                        (Ok(expected), Ok(ceil)) => assert_eq!(ceil, expected),
+                        (Err(_), Err(err)) => assert_eq!(err, ErrorCode::ArithmeticOverflow.into()),
+                        (expected, ceil) => panic!("ceil {ceil:?}, expected {expected:?}"),
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn proportional_math_rejects_overflow() {
+        let max = u64::MAX;
+        assert_eq!(
+            mul_div_floor(max, max, 1).unwrap_err(),
+            ErrorCode::ArithmeticOverflow.into()
+        );
+        assert_eq!(
+            mul_div_ceil(max, 2, 1).unwrap_err(),
+            ErrorCode::ArithmeticOverflow.into()
+        );
+        assert_eq!(
+            mul_div_floor(1, 1, 0).unwrap_err(),
+            ErrorCode::ArithmeticOverflow.into()
+        );
+        // scaling the receive with a top up would push it past u64::MAX
+        let mut topped_up = escrow(max - 1, 1);
+        assert_eq!(
+            topped_up.top_up(2, true).unwrap_err(),
+            ErrorCode::ArithmeticOverflow.into()
+        );
+        // a unit price of u64::MAX per base unit can't be charged for two units
+        let mut unit_priced = escrow(1, 2);
+        unit_priced.price_mode = PriceMode::PerUnit;
+        unit_priced.price_numerator = max;
+        unit_priced.price_denominator = 1;
+        assert_eq!(
+            unit_priced.sync_unit_price().unwrap_err(),
+            ErrorCode::ArithmeticOverflow.into()
+        );
+    }
+
+    #[test]
+    fn top_up_scales_the_auction_curve_with_scale_receive() {
+        let mut auction = escrow(1_000, 100);
+        auction.start_receive = 1_000;
+        auction.floor_receive = 333;
+        auction.decay_duration = 10;
+
+        let mut fixed = auction.clone();
+        fixed.top_up(50, false).unwrap();
+        assert_eq!((fixed.start_receive, fixed.floor_receive), (1_000, 333));
+        assert_eq!(fixed.current_price(0).unwrap(), 1_000);
+
+        // the floor's extra rounds up like receive's
+        auction.top_up(50, true).unwrap();
+        assert_eq!((auction.start_receive, auction.floor_receive), (1_500, 500));
+        assert_eq!(auction.current_price(0).unwrap(), 1_500);
+        assert_eq!(auction.current_price(10).unwrap(), 500);
+    }
+
+    #[test]
+    fn other_payment_prices_follow_top_up_and_withdraw() {
+        let mut escrow = escrow(1_000, 100);
+        escrow.mint_c = Pubkey::new_unique();
+        escrow.receive_c = 2_000;
+        escrow.payment_len = 1;
+        escrow.payment_receives[0] = 333;
+
+        // without scale_receive every price stays one for the whole deposit
+        let mut fixed = escrow.clone();
+        fixed.top_up(50, false).unwrap();
+        assert_eq!((fixed.receive_c, fixed.payment_receives[0]), (2_000, 333));
+
+        escrow.top_up(50, true).unwrap();
+        assert_eq!((escrow.receive_c, escrow.payment_receives[0]), (3_000, 500));
+        // the price per token holds, rounding up for the maker
+        escrow.withdraw(75).unwrap();
+        assert_eq!(escrow.deposit, 75);
+        assert_eq!((escrow.receive_c, escrow.payment_receives[0]), (1_500, 250));
+        escrow.withdraw(74).unwrap();
+        assert_eq!((escrow.receive_c, escrow.payment_receives[0]), (20, 4));
+    }
+
+    #[test]
+    fn second_mint_folds_into_the_first_basket_leg() {
+        let mut basket = escrow(100, 1_000);
+        basket.mint_a2 = Pubkey::new_unique();
+        basket.deposit_2 = 300;
+        let mint_a2 = basket.mint_a2;
+        basket.fold_second_mint();
+        assert_eq!(basket.basket_mints(), &[mint_a2]);
+        assert_eq!(basket.basket_amounts[0], 300);
+        assert_eq!((basket.mint_a2, basket.deposit_2), (Pubkey::default(), 0));
+        assert!(basket.is_basket());
+        // folding again leaves the legs alone
+        basket.fold_second_mint();
+        assert_eq!(basket.basket_len, 1);
+    }
+
+    #[test]
+    fn maker_index_fails_when_full() {
+        let mut index = MakerIndex {
+            maker: Pubkey::default(),
+            entries: Vec::new(),
+            bump: 0,
+        };
+        let at = |namespace: u8, seed: u64| Escrow {
+            namespace: [namespace; 8],
+            seed,
+            ..Default::default()
+        };
+        for seed in 0..MAX_INDEXED_ESCROWS as u64 {
+            index.list(Pubkey::default(), 0, &at(0, seed)).unwrap();
+        }
+        assert_eq!(
+            index
+                .list(Pubkey::default(), 0, &at(0, MAX_INDEXED_ESCROWS as u64))
+                .unwrap_err(),
+            ErrorCode::MakerIndexFull.into()
+        );
+        // the same seed in another namespace is another escrow, it stays listed
+        index.remove(IndexedEscrow {
+            namespace: [1; 8],
+            seed: 0,
+        });
+        assert_eq!(index.entries.len(), MAX_INDEXED_ESCROWS);
+        // dropping an escrow makes room again
+        index.remove(IndexedEscrow {
+            namespace: [0; 8],
+            seed: 0,
+        });
+        index.list(Pubkey::default(), 0, &at(1, 0)).unwrap();
+        assert_eq!(index.entries.len(), MAX_INDEXED_ESCROWS);
+        assert_eq!(index.entries.last().unwrap().namespace, [1; 8]);
+    }
+
+    #[test]
+    fn offsets_match_the_serialized_escrow() {
+        let escrow = Escrow {
+            seed: 7,
+            maker: Pubkey::new_from_array([1; 32]),
+            mint_a: Pubkey::new_from_array([2; 32]),
+            mint_b: Pubkey::new_from_array([3; 32]),
+            status: EscrowStatus::Refunded,
+            namespace: [4; 8],
+            ..Default::default()
+        };
+        let mut data = Vec::new();
+        escrow.try_serialize(&mut data).unwrap();
+        assert_eq!(data.len(), 8 + Escrow::INIT_SPACE);
+        let at = |offset: u32, len: usize| &data[offset as usize..offset as usize + len];
+        assert_eq!(at(OFFSET_SEED, 8), 7u64.to_le_bytes());
+        assert_eq!(at(OFFSET_MAKER, 32), [1; 32]);
+        assert_eq!(at(OFFSET_MINT_A, 32), [2; 32]);
+        assert_eq!(at(OFFSET_MINT_B, 32), [3; 32]);
+        // a single byte, the fields around it stay zeroed
+        assert_eq!(
+            at(OFFSET_STATUS - 1, 3),
+            [0, EscrowStatus::Refunded as u8, 0]
+        );
+        assert_eq!(at(OFFSET_NAMESPACE, 8), [4; 8]);
+    }
+
+    #[test]
+    fn memo_str_trims_the_padding_and_rejects_invalid_utf8() {
+        let mut memo = [0; 32];
+        assert_eq!(memo_str(&memo), Some(""));
+        memo[..12].copy_from_slice("OTC deal #42".as_bytes());
+        assert_eq!(memo_str(&memo), Some("OTC deal #42"));
+        memo[31] = b'!';
+        assert_eq!(memo_str(&memo).unwrap().len(), 32);
+        // a lone continuation byte
+        memo[0] = 0x80;
+        assert_eq!(memo_str(&memo), None);
+    }
+
+    #[test]
+    fn unmigrated_escrows_fail_with_escrow_not_migrated() {
+        let escrow = Escrow {
+            version: ESCROW_VERSION,
+            ..Default::default()
+        };
+        let mut data = Vec::new();
+        escrow.try_serialize(&mut data).unwrap();
+        assert!(Escrow::try_deserialize(&mut &data[..]).is_ok());
+
+        // an older version at the current length
+        let mut old = data.clone();
+        old[8..].copy_from_slice(
+            &Escrow {
+                version: ESCROW_VERSION - 1,
+                ..Default::default()
+            }
+            .try_to_vec()
+            .unwrap(),
+        );
+        assert_eq!(
+            Escrow::try_deserialize(&mut &old[..]).err(),
+            Some(ErrorCode::EscrowNotMigrated.into())
+        );
+        // a shorter, older layout
+        assert_eq!(
+            Escrow::try_deserialize(&mut &data[..data.len() - 32]).err(),
+            Some(ErrorCode::EscrowNotMigrated.into())
+        );
+        // migrate still reads the older version
+        assert_eq!(
+            Escrow::try_deserialize_unchecked(&mut &old[..])
+                .unwrap()
+                .version,
+            ESCROW_VERSION - 1
+        );
+    }
+}
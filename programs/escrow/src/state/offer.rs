@@ -0,0 +1,16 @@
+use anchor_lang::prelude::*;
+
+// a taker's bid for everything left in an escrow, at seeds [b"offer", escrow, taker].
+// the bid sits in the offer's mint_b ATA until the maker accepts or the taker cancels.
+// one per taker, cancel it to bid again
+#[account]
+#[derive(InitSpace)]
+pub struct Offer {
+    pub escrow: Pubkey,
+    pub taker: Pubkey,
+    // mint_b that reached the offer vault
+    pub amount_b: u64,
+    // escrow.remaining when the bid was made, accepting fails once it changed
+    pub remaining: u64,
+    pub bump: u8,
+}
@@ -0,0 +1,65 @@
+use anchor_lang::prelude::*;
+
+use crate::error::ErrorCode;
+use crate::events::StatsUpdated;
+
+// protocol wide totals at seeds [b"stats"], created next to the config. every make, take
+// and refund variant counts into it, without the admin. the account is optional on all of
+// them, since one global writable account would serialize every transaction of the
+// protocol. instructions that leave it out aren't counted, so the totals are a lower bound
+// that only front ends passing it keep complete. volumes are raw base units summed across
+// all mints, fees included
+#[account]
+#[derive(InitSpace, Default)]
+pub struct Stats {
+    pub escrows_made: u64,
+    // escrows whose last fill went through take
+    pub escrows_taken: u64,
+    pub escrows_refunded: u64,
+    // mint_a taken out of vaults and the mint_b paid for it
+    pub volume_a: u128,
+    pub volume_b: u128,
+    pub bump: u8,
+}
+
+fn increment(counter: &mut u64) -> Result<()> {
+    *counter = counter
+        .checked_add(1)
+        .ok_or(ErrorCode::ArithmeticOverflow)?;
+    Ok(())
+}
+
+impl Stats {
+    pub fn record_make(&mut self) -> Result<()> {
+        increment(&mut self.escrows_made)
+    }
+
+    pub fn record_take(&mut self, amount_a: u64, amount_b: u64, completed: bool) -> Result<()> {
+        self.volume_a = self
+            .volume_a
+            .checked_add(amount_a as u128)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.volume_b = self
+            .volume_b
+            .checked_add(amount_b as u128)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        if completed {
+            increment(&mut self.escrows_taken)?;
+        }
+        Ok(())
+    }
+
+    pub fn record_refund(&mut self) -> Result<()> {
+        increment(&mut self.escrows_refunded)
+    }
+
+    pub fn updated(&self) -> StatsUpdated {
+        StatsUpdated {
+            escrows_made: self.escrows_made,
+            escrows_taken: self.escrows_taken,
+            escrows_refunded: self.escrows_refunded,
+            volume_a: self.volume_a,
+            volume_b: self.volume_b,
+        }
+    }
+}
@@ -3,12 +3,25 @@ import { Program, BN } from "@coral-xyz/anchor";
 import { Escrow } from "../target/types/escrow";
 import {
   TOKEN_PROGRAM_ID,
+  TOKEN_2022_PROGRAM_ID,
   ASSOCIATED_TOKEN_PROGRAM_ID,
+  ExtensionType,
   MINT_SIZE,
+  createInitializeTransferFeeConfigInstruction,
+  createInitializeTransferHookInstruction,
+  createInitializeNonTransferableMintInstruction,
+  createInitializePermanentDelegateInstruction,
+  getMintLen,
   createInitializeMint2Instruction,
   createAssociatedTokenAccountIdempotentInstruction,
   createMintToInstruction,
+  createFreezeAccountInstruction,
+  createThawAccountInstruction,
+  createApproveInstruction,
+  createSyncNativeInstruction,
+  createCloseAccountInstruction,
   getAssociatedTokenAddressSync,
+  NATIVE_MINT,
 } from "@solana/spl-token";
 import {
   Keypair,
@@ -17,10 +30,18 @@ import {
   Transaction,
   LAMPORTS_PER_SOL,
   TransactionInstruction,
+  ComputeBudgetProgram,
+  Ed25519Program,
+  SYSVAR_INSTRUCTIONS_PUBKEY,
 } from "@solana/web3.js";
 import { assert } from "chai";
-import { LiteSVM } from "litesvm";
-import { readFileSync } from "fs";
+import {
+  FailedTransactionMetadata,
+  LiteSVM,
+  TransactionMetadata,
+} from "litesvm";
+import { existsSync, readFileSync } from "fs";
+import { createHash } from "crypto";
 
 describe("escrow", () => {
   let svm: LiteSVM;
@@ -65,6 +86,10 @@ describe("escrow", () => {
   const programId = new PublicKey(
     "AFsE5ZUWMy2rNDa6rvaYjBVwM93hdpcxKiamgi5dUt8b"
   );
+  // counts every transfer of its mints, see programs/counter-hook
+  const hookProgramId = new PublicKey(
+    "34Jzj2PSWjhg4u4mySMadKULX9gH1Sncrns3ocmo9gZE"
+  );
   const [escrow] = PublicKey.findProgramAddressSync(
     [
       Buffer.from("escrow"),
@@ -81,6 +106,38 @@ describe("escrow", () => {
     TOKEN_PROGRAM_ID
   );
 
+  // Protocol config, every take pays FEE_BPS of mint B to the fee collector
+  const feeCollector = Keypair.generate();
+  const [config] = PublicKey.findProgramAddressSync(
+    [Buffer.from("config")],
+    programId
+  );
+  // Protocol totals, created along with the config
+  const [stats] = PublicKey.findProgramAddressSync(
+    [Buffer.from("stats")],
+    programId
+  );
+  const FEE_BPS = 30; // mirrors constants.rs
+  const REFERRAL_BPS = 50; // mirrors constants.rs
+  const feeOf = (amount: number) => Math.floor((amount * FEE_BPS) / 10_000);
+  // max_receive for takes that accept whatever the escrow currently asks
+  const ANY_PRICE = new BN(0);
+  // expected_receive_a and max_pay_b for takes that don't bound the fill itself
+  const NO_BOUND = new BN(0);
+  const feeCollectorAta = (mint: PublicKey, tokenProgram = TOKEN_PROGRAM_ID) =>
+    getAssociatedTokenAddressSync(
+      mint,
+      feeCollector.publicKey,
+      false,
+      tokenProgram
+    );
+  // Every make lists its escrow in the maker's index, every close drops it
+  const makerIndexOf = (maker: PublicKey) =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from("index"), maker.toBuffer()],
+      programId
+    )[0];
+
   // Helper functions
   function sendTransaction(
     instructions: TransactionInstruction[],
@@ -100,10 +157,55 @@ describe("escrow", () => {
 
     const result = svm.sendTransaction(tx);
     console.log("Transaction result:", result);
+    advanceSlots();
 
     return "mock-signature";
   }
 
+  // Sends like sendTransaction and returns the compute units the transaction
+  // used. Serializing throws when it is over the transaction size limit.
+  function sendTransactionUnits(
+    instructions: TransactionInstruction[],
+    signers: Keypair[]
+  ): number {
+    const tx = new Transaction();
+    instructions.forEach((ix) => tx.add(ix));
+
+    tx.recentBlockhash = svm.latestBlockhash();
+    tx.feePayer = payer.publicKey;
+    tx.sign(
+      payer,
+      ...signers.filter((s) => !s.publicKey.equals(payer.publicKey))
+    );
+    tx.serialize();
+
+    const result = svm.sendTransaction(tx);
+    assert.ok(
+      result instanceof TransactionMetadata,
+      `Transaction failed: ${
+        result instanceof FailedTransactionMetadata
+          ? result.meta().logs().join("\n")
+          : result
+      }`
+    );
+    advanceSlots();
+    return Number(result.computeUnitsConsumed());
+  }
+
+  // Slots don't move on their own in LiteSVM. Every transaction moves the
+  // clock past COOLDOWN_SLOTS like a live cluster would between transactions.
+  const COOLDOWN_SLOTS = 10; // mirrors constants.rs
+  const MAX_BATCH = 6; // mirrors constants.rs
+  function advanceSlots() {
+    svm.warpToSlot(svm.getClock().slot + BigInt(COOLDOWN_SLOTS));
+  }
+
+  function setUnixTimestamp(unixTimestamp: bigint) {
+    const clock = svm.getClock();
+    clock.unixTimestamp = unixTimestamp;
+    svm.setClock(clock);
+  }
+
   async function getTokenBalance(ata: PublicKey): Promise<number> {
     const account = svm.getAccount(ata);
     if (!account) return 0;
@@ -116,6 +218,697 @@ describe("escrow", () => {
     return Number(amount);
   }
 
+  function sendTransactionExpectError(
+    instructions: TransactionInstruction[],
+    signers: Keypair[],
+    errorName: string
+  ) {
+    const tx = new Transaction();
+    instructions.forEach((ix) => tx.add(ix));
+
+    tx.recentBlockhash = svm.latestBlockhash();
+    tx.feePayer = payer.publicKey;
+
+    const allSigners = [
+      payer,
+      ...signers.filter((s) => !s.publicKey.equals(payer.publicKey)),
+    ];
+    tx.sign(...allSigners);
+
+    const result = svm.sendTransaction(tx);
+    assert.ok(
+      result instanceof FailedTransactionMetadata,
+      `Transaction should fail with ${errorName}`
+    );
+    const logs = (result as FailedTransactionMetadata).meta().logs();
+    assert.ok(
+      logs.some((log) => log.includes(errorName)),
+      `Expected ${errorName} in logs:\n${logs.join("\n")}`
+    );
+  }
+
+  // The offer PDA of `taker` on the escrow and its mint B vault
+  function offerAccounts(p: EscrowParties, taker: PublicKey) {
+    const [offer] = PublicKey.findProgramAddressSync(
+      [Buffer.from("offer"), p.escrow.toBuffer(), taker.toBuffer()],
+      programId
+    );
+    const offerVault = getAssociatedTokenAddressSync(
+      p.mintB,
+      offer,
+      true,
+      p.tokenProgramB
+    );
+    return { offer, offerVault };
+  }
+
+  function makeOffer(p: EscrowParties, taker: Keypair, amountB: number) {
+    return getProgram(taker)
+      .methods.makeOffer(new BN(amountB))
+      .accountsPartial({
+        taker: taker.publicKey,
+        mintB: p.mintB,
+        escrow: p.escrow,
+        ...offerAccounts(p, taker.publicKey),
+        tokenProgram: p.tokenProgramB,
+      })
+      .instruction();
+  }
+
+  function cancelOffer(p: EscrowParties, taker: Keypair) {
+    return getProgram(taker)
+      .methods.cancelOffer()
+      .accountsPartial({
+        taker: taker.publicKey,
+        mintB: p.mintB,
+        ...offerAccounts(p, taker.publicKey),
+        tokenProgram: p.tokenProgramB,
+      })
+      .instruction();
+  }
+
+  function acceptOffer(
+    p: EscrowParties,
+    taker: PublicKey,
+    authority: Keypair = p.maker
+  ) {
+    return getProgram(authority)
+      .methods.acceptOffer()
+      .accountsPartial({
+        authority: authority.publicKey,
+        maker: p.maker.publicKey,
+        taker,
+        mintA: p.mintA,
+        mintB: p.mintB,
+        makerAtaB: p.makerAtaB,
+        escrow: p.escrow,
+        makerIndex: makerIndexOf(p.maker.publicKey),
+        stats,
+        vault: p.vault,
+        makerAtaA: null,
+        ...offerAccounts(p, taker),
+        config,
+        feeCollector: feeCollector.publicKey,
+        feeCollectorAtaB: feeCollectorAta(p.mintB, p.tokenProgramB),
+        rentRecipient: null,
+        tokenProgramA: p.tokenProgramA,
+        tokenProgramB: p.tokenProgramB,
+      })
+      .instruction();
+  }
+
+  // Sends the transaction and decodes the program events out of its logs
+  function sendTransactionEvents(
+    instructions: TransactionInstruction[],
+    signers: Keypair[]
+  ): { name: string; data: any }[] {
+    const tx = new Transaction();
+    instructions.forEach((ix) => tx.add(ix));
+
+    tx.recentBlockhash = svm.latestBlockhash();
+    tx.feePayer = payer.publicKey;
+    tx.sign(
+      payer,
+      ...signers.filter((s) => !s.publicKey.equals(payer.publicKey))
+    );
+
+    const result = svm.sendTransaction(tx);
+    assert.notOk(
+      result instanceof FailedTransactionMetadata,
+      "Transaction should succeed"
+    );
+    advanceSlots();
+    const program = getProgram(payer);
+    const parser = new anchor.EventParser(programId, program.coder);
+    return [...parser.parseLogs((result as any).logs())].map((event) => ({
+      name: event.name.toLowerCase(),
+      data: event.data,
+    }));
+  }
+
+  function getProgram(wallet: Keypair): Program<Escrow> {
+    const provider = {
+      connection: {
+        getAccountInfo: async (pubkey: PublicKey) => {
+          const account = svm.getAccount(pubkey);
+          return account
+            ? {
+                executable: account.executable,
+                owner: account.owner,
+                lamports: account.lamports,
+                data: Buffer.from(account.data),
+                rentEpoch: account.rentEpoch,
+              }
+            : null;
+        },
+      },
+      wallet: new anchor.Wallet(wallet),
+    } as any;
+
+    return new Program<Escrow>(
+      JSON.parse(readFileSync("./target/idl/escrow.json", "utf8")),
+      provider
+    );
+  }
+
+  type TransferFee = { bps: number; max: bigint };
+  // Any other mint extension, initialized before the mint itself
+  type MintExtension = {
+    type: ExtensionType;
+    init: (mint: PublicKey) => TransactionInstruction;
+  };
+
+  // Creates fresh mints and parties: the maker holds `deposit` of mint A and
+  // the taker holds `receive` of mint B. Both ATAs for the other side exist.
+  // Token-2022 mints can carry a transfer fee on either side, and each mint
+  // can live under its own token program. transferHookA sets mint A's
+  // transfer hook program, the counter hook also gets its accounts set up.
+  // extensionsA and extensionsB add further extensions to either mint.
+  function setupEscrowParties(
+    deposit: number,
+    receive: number,
+    {
+      tokenProgram = TOKEN_PROGRAM_ID,
+      tokenProgramA = tokenProgram,
+      tokenProgramB = tokenProgram,
+      transferFeeA,
+      transferFeeB,
+      transferHookA,
+      extensionsA = [],
+      extensionsB = [],
+      decimalsA = 6,
+      decimalsB = 6,
+    }: {
+      tokenProgram?: PublicKey;
+      tokenProgramA?: PublicKey;
+      tokenProgramB?: PublicKey;
+      transferFeeA?: TransferFee;
+      transferFeeB?: TransferFee;
+      transferHookA?: PublicKey;
+      extensionsA?: MintExtension[];
+      extensionsB?: MintExtension[];
+      decimalsA?: number;
+      decimalsB?: number;
+    } = {}
+  ) {
+    const maker = Keypair.generate();
+    const taker = Keypair.generate();
+    const mintA = Keypair.generate();
+    const mintB = Keypair.generate();
+    const seed = new BN(Math.floor(Math.random() * 1000000));
+
+    svm.airdrop(maker.publicKey, BigInt(10 * LAMPORTS_PER_SOL));
+    svm.airdrop(taker.publicKey, BigInt(10 * LAMPORTS_PER_SOL));
+
+    const ataA = (owner: PublicKey) =>
+      getAssociatedTokenAddressSync(
+        mintA.publicKey,
+        owner,
+        true,
+        tokenProgramA
+      );
+    const ataB = (owner: PublicKey) =>
+      getAssociatedTokenAddressSync(
+        mintB.publicKey,
+        owner,
+        true,
+        tokenProgramB
+      );
+    const makerAtaA = ataA(maker.publicKey);
+    const makerAtaB = ataB(maker.publicKey);
+    const takerAtaA = ataA(taker.publicKey);
+    const takerAtaB = ataB(taker.publicKey);
+
+    const [escrow] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("escrow"),
+        maker.publicKey.toBuffer(),
+        seed.toArrayLike(Buffer, "le", 8),
+      ],
+      programId
+    );
+    const vault = ataA(escrow);
+
+    const instructions: TransactionInstruction[] = [];
+    (
+      [
+        [
+          mintA,
+          decimalsA,
+          tokenProgramA,
+          extensionsA,
+          transferFeeA,
+          transferHookA,
+        ],
+        [mintB, decimalsB, tokenProgramB, extensionsB, transferFeeB],
+      ] as [
+        Keypair,
+        number,
+        PublicKey,
+        MintExtension[],
+        TransferFee?,
+        PublicKey?
+      ][]
+    ).forEach(([mint, decimals, tokenProgram, others, fee, hook]) => {
+      const extensions = [
+        ...(fee ? [ExtensionType.TransferFeeConfig] : []),
+        ...(hook ? [ExtensionType.TransferHook] : []),
+        ...others.map((extension) => extension.type),
+      ];
+      const space = extensions.length ? getMintLen(extensions) : MINT_SIZE;
+      instructions.push(
+        SystemProgram.createAccount({
+          fromPubkey: payer.publicKey,
+          newAccountPubkey: mint.publicKey,
+          lamports: Number(svm.minimumBalanceForRentExemption(BigInt(space))),
+          space,
+          programId: tokenProgram,
+        })
+      );
+      if (fee) {
+        instructions.push(
+          createInitializeTransferFeeConfigInstruction(
+            mint.publicKey,
+            payer.publicKey,
+            payer.publicKey,
+            fee.bps,
+            fee.max,
+            tokenProgram
+          )
+        );
+      }
+      if (hook) {
+        instructions.push(
+          createInitializeTransferHookInstruction(
+            mint.publicKey,
+            payer.publicKey,
+            hook,
+            tokenProgram
+          )
+        );
+      }
+      others.forEach((extension) =>
+        instructions.push(extension.init(mint.publicKey))
+      );
+      // the payer can also freeze accounts, to make transfers fail on purpose
+      instructions.push(
+        createInitializeMint2Instruction(
+          mint.publicKey,
+          decimals,
+          payer.publicKey,
+          payer.publicKey,
+          tokenProgram
+        )
+      );
+    });
+    [
+      [makerAtaA, maker.publicKey, mintA.publicKey, tokenProgramA],
+      [makerAtaB, maker.publicKey, mintB.publicKey, tokenProgramB],
+      [takerAtaA, taker.publicKey, mintA.publicKey, tokenProgramA],
+      [takerAtaB, taker.publicKey, mintB.publicKey, tokenProgramB],
+    ].forEach(([address, owner, mint, tokenProgram]) =>
+      instructions.push(
+        createAssociatedTokenAccountIdempotentInstruction(
+          payer.publicKey,
+          address,
+          owner,
+          mint,
+          tokenProgram
+        )
+      )
+    );
+    instructions.push(
+      createMintToInstruction(
+        mintA.publicKey,
+        makerAtaA,
+        payer.publicKey,
+        deposit,
+        [],
+        tokenProgramA
+      ),
+      createMintToInstruction(
+        mintB.publicKey,
+        takerAtaB,
+        payer.publicKey,
+        receive,
+        [],
+        tokenProgramB
+      )
+    );
+    if (transferHookA?.equals(hookProgramId)) {
+      instructions.push(initializeHookInstruction(mintA.publicKey));
+    }
+    sendTransaction(instructions, [mintA, mintB]);
+
+    return {
+      maker,
+      taker,
+      mintA: mintA.publicKey,
+      mintB: mintB.publicKey,
+      makerAtaA,
+      makerAtaB,
+      takerAtaA,
+      takerAtaB,
+      seed,
+      escrow,
+      vault,
+      tokenProgramA,
+      tokenProgramB,
+    };
+  }
+
+  type EscrowParties = ReturnType<typeof setupEscrowParties>;
+
+  // a fresh legacy mint with `amount` in the owner's ATA, the payer is
+  // its mint and freeze authority
+  function setupHolding(owner: PublicKey, amount: number, decimals: number) {
+    const mint = Keypair.generate();
+    const token = getAssociatedTokenAddressSync(mint.publicKey, owner);
+    sendTransaction(
+      [
+        SystemProgram.createAccount({
+          fromPubkey: payer.publicKey,
+          newAccountPubkey: mint.publicKey,
+          space: MINT_SIZE,
+          lamports: Number(
+            svm.minimumBalanceForRentExemption(BigInt(MINT_SIZE))
+          ),
+          programId: TOKEN_PROGRAM_ID,
+        }),
+        createInitializeMint2Instruction(
+          mint.publicKey,
+          decimals,
+          payer.publicKey,
+          payer.publicKey
+        ),
+        createAssociatedTokenAccountIdempotentInstruction(
+          payer.publicKey,
+          token,
+          owner,
+          mint.publicKey
+        ),
+        createMintToInstruction(mint.publicKey, token, payer.publicKey, amount),
+      ],
+      [mint]
+    );
+    return { mint: mint.publicKey, token };
+  }
+
+  // Metaplex Token Metadata, see pnft.rs
+  const METADATA_PROGRAM_ID = new PublicKey(
+    "metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s"
+  );
+  const metadataPda = (mint: PublicKey, ...seeds: Buffer[]) =>
+    PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("metadata"),
+        METADATA_PROGRAM_ID.toBuffer(),
+        mint.toBuffer(),
+        ...seeds,
+      ],
+      METADATA_PROGRAM_ID
+    )[0];
+  const editionPda = (mint: PublicKey) =>
+    metadataPda(mint, Buffer.from("edition"));
+  const tokenRecordPda = (mint: PublicKey, token: PublicKey) =>
+    metadataPda(mint, Buffer.from("token_record"), token.toBuffer());
+
+  // enough of a Metaplex metadata account for the program: key, update
+  // authority, mint, empty name, symbol and uri, then the token standard and
+  // collection. tokenStandard 4 makes it a pNFT
+  function setupMetadata(
+    mint: PublicKey,
+    {
+      tokenStandard,
+      collection,
+    }: {
+      tokenStandard?: number;
+      collection?: { key: PublicKey; verified: boolean };
+    } = {}
+  ) {
+    const metadata = metadataPda(mint);
+    const data = Buffer.alloc(679);
+    data.writeUInt8(4, 0);
+    payer.publicKey.toBuffer().copy(data, 1);
+    mint.toBuffer().copy(data, 33);
+    let at = 83;
+    if (tokenStandard !== undefined) {
+      data.writeUInt8(1, at);
+      data.writeUInt8(tokenStandard, at + 1);
+      at += 1;
+    }
+    at += 1;
+    if (collection) {
+      data.writeUInt8(1, at);
+      data.writeUInt8(collection.verified ? 1 : 0, at + 1);
+      collection.key.toBuffer().copy(data, at + 2);
+    }
+    svm.setAccount(metadata, {
+      lamports: Number(svm.minimumBalanceForRentExemption(BigInt(679))),
+      data,
+      owner: METADATA_PROGRAM_ID,
+      executable: false,
+    });
+    return metadata;
+  }
+
+  // wrapped SOL mint as the runtime ships it: no authorities, 9 decimals
+  function setupNativeMint() {
+    if (svm.getAccount(NATIVE_MINT)) return;
+    const data = Buffer.alloc(MINT_SIZE);
+    data.writeUInt8(9, 44);
+    data.writeUInt8(1, 45);
+    svm.setAccount(NATIVE_MINT, {
+      lamports: Number(svm.minimumBalanceForRentExemption(BigInt(MINT_SIZE))),
+      data,
+      owner: TOKEN_PROGRAM_ID,
+      executable: false,
+    });
+  }
+
+  const hookAccounts = (mint: PublicKey) => {
+    const pda = (label: string) =>
+      PublicKey.findProgramAddressSync(
+        [Buffer.from(label), mint.toBuffer()],
+        hookProgramId
+      )[0];
+    return {
+      extraAccountMetaList: pda("extra-account-metas"),
+      counter: pda("counter"),
+    };
+  };
+
+  // The counter hook has no generated client, so its one setup instruction
+  // is built by hand from the anchor discriminator
+  function initializeHookInstruction(mint: PublicKey) {
+    const { extraAccountMetaList, counter } = hookAccounts(mint);
+    return new TransactionInstruction({
+      programId: hookProgramId,
+      keys: [
+        { pubkey: payer.publicKey, isSigner: true, isWritable: true },
+        { pubkey: extraAccountMetaList, isSigner: false, isWritable: true },
+        { pubkey: mint, isSigner: false, isWritable: false },
+        { pubkey: counter, isSigner: false, isWritable: true },
+        {
+          pubkey: SystemProgram.programId,
+          isSigner: false,
+          isWritable: false,
+        },
+      ],
+      data: createHash("sha256")
+        .update("global:initialize_extra_account_meta_list")
+        .digest()
+        .subarray(0, 8),
+    });
+  }
+
+  // Remaining accounts Token-2022 needs to call the hook of `mint`
+  function hookRemainingAccounts(mint: PublicKey) {
+    const { extraAccountMetaList, counter } = hookAccounts(mint);
+    return [
+      { pubkey: extraAccountMetaList, isSigner: false, isWritable: false },
+      { pubkey: counter, isSigner: false, isWritable: true },
+      { pubkey: hookProgramId, isSigner: false, isWritable: false },
+    ];
+  }
+
+  function hookCount(mint: PublicKey): number {
+    const account = svm.getAccount(hookAccounts(mint).counter);
+    return Number(Buffer.from(account.data).readBigUInt64LE(8));
+  }
+
+  // Another taker for the same mints, holding `receive` of mint B
+  function setupExtraTaker(p: EscrowParties, receive: number) {
+    const taker = Keypair.generate();
+    svm.airdrop(taker.publicKey, BigInt(10 * LAMPORTS_PER_SOL));
+
+    const takerAtaA = getAssociatedTokenAddressSync(
+      p.mintA,
+      taker.publicKey,
+      false,
+      p.tokenProgramA
+    );
+    const takerAtaB = getAssociatedTokenAddressSync(
+      p.mintB,
+      taker.publicKey,
+      false,
+      p.tokenProgramB
+    );
+    sendTransaction(
+      [
+        createAssociatedTokenAccountIdempotentInstruction(
+          payer.publicKey,
+          takerAtaA,
+          taker.publicKey,
+          p.mintA,
+          p.tokenProgramA
+        ),
+        createAssociatedTokenAccountIdempotentInstruction(
+          payer.publicKey,
+          takerAtaB,
+          taker.publicKey,
+          p.mintB,
+          p.tokenProgramB
+        ),
+        createMintToInstruction(
+          p.mintB,
+          takerAtaB,
+          payer.publicKey,
+          receive,
+          [],
+          p.tokenProgramB
+        ),
+      ],
+      []
+    );
+
+    return { taker, takerAtaA, takerAtaB };
+  }
+
+  // Default make options: no expiry, fixed price and open to any taker
+  function makeOptions(overrides: Record<string, any> = {}) {
+    return {
+      expiry: new BN(0),
+      allowedTaker: null,
+      whitelist: [],
+      floorReceive: new BN(0),
+      startTime: new BN(0),
+      decayDuration: new BN(0),
+      minFill: new BN(0),
+      grossUpTransferFee: false,
+      mintC: null,
+      receiveC: new BN(0),
+      receiveSol: false,
+      refundLockedUntil: new BN(0),
+      keepRecord: false,
+      nft: false,
+      gateCollection: null,
+      gateMint: null,
+      gateMinBalance: new BN(0),
+      deposit2: new BN(0),
+      vestDuration: new BN(0),
+      allowSelfFill: false,
+      rentRecipient: null,
+      priceNumerator: new BN(0),
+      priceDenominator: new BN(0),
+      oracle: null,
+      spreadBps: 0,
+      maxPriceAge: new BN(0),
+      maxConfBps: 0,
+      pool: null,
+      poolProgram: PublicKey.default,
+      poolPremiumBps: 0,
+      minReserveA: new BN(0),
+      minReserveB: new BN(0),
+      paymentOptions: [],
+      receiveToVault: false,
+      namespace: Array(8).fill(0),
+      minReceiveFloor: new BN(0),
+      memo: Array(32).fill(0),
+      oracleFeedId: Array(32).fill(0),
+      ...overrides,
+    };
+  }
+
+  function makeAccounts(p: EscrowParties) {
+    return {
+      maker: p.maker.publicKey,
+      config,
+      stats,
+      mintA: p.mintA,
+      mintB: p.mintB,
+      escrow: p.escrow,
+      vault: p.vault,
+      metadata: null,
+      mintC: null,
+      makerIndex: makerIndexOf(p.maker.publicKey),
+      associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+      tokenProgramA: p.tokenProgramA,
+      tokenProgramB: p.tokenProgramB,
+      systemProgram: SystemProgram.programId,
+    };
+  }
+
+  function takeAccounts(p: EscrowParties) {
+    return {
+      taker: p.taker.publicKey,
+      maker: p.maker.publicKey,
+      mintA: p.mintA,
+      mintB: p.mintB,
+      makerAtaB: p.makerAtaB,
+      escrow: p.escrow,
+      vault: p.vault,
+      makerAtaA: null,
+      config,
+      stats,
+      feeCollector: feeCollector.publicKey,
+      feeCollectorAtaB: feeCollectorAta(p.mintB, p.tokenProgramB),
+      unwrapB: null,
+      paymentVault: null,
+      gateToken: null,
+      gateMetadata: null,
+      gateMintToken: null,
+      referrer: null,
+      referrerAtaB: null,
+      rentRecipient: null,
+      priceFeed: null,
+      pool: null,
+      poolVault0: null,
+      poolVault1: null,
+      sysvarInstructions: null,
+      makerIndex: makerIndexOf(p.maker.publicKey),
+      associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+      tokenProgramA: p.tokenProgramA,
+      tokenProgramB: p.tokenProgramB,
+      systemProgram: SystemProgram.programId,
+    };
+  }
+
+  function refundAccounts(p: EscrowParties) {
+    return {
+      maker: p.maker.publicKey,
+      mintA: p.mintA,
+      makerAtaA: p.makerAtaA,
+      escrow: p.escrow,
+      vault: p.vault,
+      stats,
+      rentRecipient: null,
+      makerIndex: makerIndexOf(p.maker.publicKey),
+      associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+      tokenProgramA: p.tokenProgramA,
+      systemProgram: SystemProgram.programId,
+    };
+  }
+
+  function isClosed(pubkey: PublicKey): boolean {
+    const account = svm.getAccount(pubkey);
+    return (
+      !account ||
+      account.owner.equals(SystemProgram.programId) ||
+      account.data.length === 0
+    );
+  }
+
   function parseEscrowAccount(account: any): any {
     if (!account || account.data.length === 0) return null;
 
@@ -128,6 +921,7 @@ describe("escrow", () => {
     //     pub mint_b: Pubkey,   // 32 bytes
     //     pub receive: u64,     // 8 bytes
     //     pub bump: u8,         // 1 byte
+    //     pub expiry: i64,      // 8 bytes
     // }
 
     if (data.length < 113) return null;
@@ -138,6 +932,7 @@ describe("escrow", () => {
     const mintB = new PublicKey(data.slice(80, 112));
     const receive = Buffer.from(data.slice(112, 120)).readBigUInt64LE(0);
     const bump = data[120];
+    const expiry = Buffer.from(data.slice(121, 129)).readBigInt64LE(0);
 
     return {
       seed: Number(seed),
@@ -146,6 +941,7 @@ describe("escrow", () => {
       mintB: mintB.toBase58(),
       receive: Number(receive),
       bump,
+      expiry: Number(expiry),
     };
   }
 
@@ -184,6 +980,7 @@ describe("escrow", () => {
     console.log("  Mint B:", escrowData.mintB);
     console.log("  Receive amount:", escrowData.receive);
     console.log("  Bump:", escrowData.bump);
+    console.log("  Expiry:", escrowData.expiry);
   }
 
   before(async () => {
@@ -194,6 +991,10 @@ describe("escrow", () => {
     const programPath = "./target/deploy/escrow.so";
     const programBuffer = readFileSync(programPath);
     svm.addProgram(programId, programBuffer);
+    svm.addProgram(
+      hookProgramId,
+      readFileSync("./target/deploy/counter_hook.so")
+    );
 
     // Airdrop SOL to accounts
     svm.airdrop(payer.publicKey, BigInt(10 * LAMPORTS_PER_SOL));
@@ -202,6 +1003,17 @@ describe("escrow", () => {
 
     // Setup mints and token accounts
     setupTokens();
+
+    const configIx = await getProgram(payer)
+      .methods.initializeConfig(feeCollector.publicKey)
+      .accountsPartial({
+        admin: payer.publicKey,
+        config,
+        stats,
+        systemProgram: SystemProgram.programId,
+      })
+      .instruction();
+    sendTransaction([configIx], []);
   });
 
   function setupTokens() {
@@ -328,15 +1140,18 @@ describe("escrow", () => {
 
     // Build make instruction with partial accounts and let Anchor resolve the rest
     const ix = await program.methods
-      .make(seed, receiveAmount, depositAmount)
+      .make(seed, receiveAmount, depositAmount, makeOptions())
       .accountsPartial({
         maker: maker.publicKey,
         mintA: mintA.publicKey,
         mintB: mintB.publicKey,
         escrow: escrow,
         vault: vault,
+        metadata: null,
+        makerIndex: makerIndexOf(maker.publicKey),
         associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
-        tokenProgram: TOKEN_PROGRAM_ID,
+        tokenProgramA: TOKEN_PROGRAM_ID,
+        tokenProgramB: TOKEN_PROGRAM_ID,
         systemProgram: SystemProgram.programId,
       })
       .instruction();
@@ -447,16 +1262,35 @@ describe("escrow", () => {
 
     // Build take instruction with partial accounts
     const ix = await program.methods
-      .take()
+      .take(depositAmount, ANY_PRICE, NO_BOUND, NO_BOUND)
       .accountsPartial({
         taker: taker.publicKey,
         maker: maker.publicKey,
         mintA: mintA.publicKey,
         mintB: mintB.publicKey,
+        makerAtaB,
         escrow: escrow,
         vault: vault,
+        config,
+        feeCollector: feeCollector.publicKey,
+        feeCollectorAtaB: feeCollectorAta(mintB.publicKey),
+        unwrapB: null,
+        paymentVault: null,
+        gateToken: null,
+        gateMetadata: null,
+        gateMintToken: null,
+        referrer: null,
+        referrerAtaB: null,
+        rentRecipient: null,
+        priceFeed: null,
+        pool: null,
+        poolVault0: null,
+        poolVault1: null,
+        sysvarInstructions: null,
+        makerIndex: makerIndexOf(maker.publicKey),
         associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
-        tokenProgram: TOKEN_PROGRAM_ID,
+        tokenProgramA: TOKEN_PROGRAM_ID,
+        tokenProgramB: TOKEN_PROGRAM_ID,
         systemProgram: SystemProgram.programId,
       })
       .instruction();
@@ -502,10 +1336,16 @@ describe("escrow", () => {
     );
 
     const makerBalanceB = await getTokenBalance(makerAtaB);
+    const fee = feeOf(receiveAmount.toNumber());
     assert.equal(
       makerBalanceB,
-      receiveAmount.toNumber(),
-      "Maker should have received requested amount"
+      receiveAmount.toNumber() - fee,
+      "Maker should have received requested amount minus the fee"
+    );
+    assert.equal(
+      await getTokenBalance(feeCollectorAta(mintB.publicKey)),
+      fee,
+      "Fee collector should have received the protocol fee"
     );
 
     // Verify escrow account is closed
@@ -639,15 +1479,18 @@ describe("escrow", () => {
 
     // Create new escrow
     const makeIx = await program.methods
-      .make(newSeed, receiveAmount, depositAmount)
+      .make(newSeed, receiveAmount, depositAmount, makeOptions())
       .accountsPartial({
         maker: newMaker.publicKey,
         mintA: newMintA.publicKey,
         mintB: newMintB.publicKey,
         escrow: newEscrow,
         vault: newVault,
+        metadata: null,
+        makerIndex: makerIndexOf(newMaker.publicKey),
         associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
-        tokenProgram: TOKEN_PROGRAM_ID,
+        tokenProgramA: TOKEN_PROGRAM_ID,
+        tokenProgramB: TOKEN_PROGRAM_ID,
         systemProgram: SystemProgram.programId,
       })
       .instruction();
@@ -677,10 +1520,13 @@ describe("escrow", () => {
       .accountsPartial({
         maker: newMaker.publicKey,
         mintA: newMintA.publicKey,
+        makerAtaA: newMakerAtaA,
         escrow: newEscrow,
         vault: newVault,
+        rentRecipient: null,
+        makerIndex: makerIndexOf(newMaker.publicKey),
         associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
-        tokenProgram: TOKEN_PROGRAM_ID,
+        tokenProgramA: TOKEN_PROGRAM_ID,
         systemProgram: SystemProgram.programId,
       })
       .instruction();
@@ -724,4 +1570,5296 @@ describe("escrow", () => {
 
     console.log("✅ Make and refund escrow test completed successfully");
   });
+
+  it("Partial take leaves dust until the last fill", async () => {
+    const p = setupEscrowParties(1_000_000, 2_000_000);
+    const makerProgram = getProgram(p.maker);
+    const takerProgram = getProgram(p.taker);
+
+    const makeIx = await makerProgram.methods
+      .make(p.seed, new BN(2_000_000), new BN(1_000_000), makeOptions())
+      .accountsPartial(makeAccounts(p))
+      .instruction();
+    sendTransaction([makeIx], [p.maker]);
+
+    // fill everything but 1 unit of mint A
+    const firstFill = await takerProgram.methods
+      .take(new BN(999_999), ANY_PRICE, NO_BOUND, NO_BOUND)
+      .accountsPartial(takeAccounts(p))
+      .instruction();
+    sendTransaction([firstFill], [p.taker]);
+
+    assert.equal(await getTokenBalance(p.vault), 1, "1 unit of dust is left");
+    assert.equal(await getTokenBalance(p.takerAtaA), 999_999);
+    const firstFee = feeOf(1_999_998);
+    assert.equal(await getTokenBalance(p.makerAtaB), 1_999_998 - firstFee);
+    assert.ok(!isClosed(p.escrow), "Escrow stays open after a partial fill");
+
+    const lastFill = await takerProgram.methods
+      .take(new BN(1), ANY_PRICE, NO_BOUND, NO_BOUND)
+      .accountsPartial(takeAccounts(p))
+      .instruction();
+    sendTransaction([lastFill], [p.taker]);
+
+    assert.equal(await getTokenBalance(p.takerAtaA), 1_000_000);
+    // 2 units are too small to carry a fee
+    assert.equal(await getTokenBalance(p.makerAtaB), 2_000_000 - firstFee);
+    assert.ok(isClosed(p.vault), "Vault should be closed once empty");
+    assert.ok(isClosed(p.escrow), "Escrow should be closed once empty");
+  });
+
+  it("Take partial rounds the released amount down", async () => {
+    const p = setupEscrowParties(1_000_000, 3);
+    const makerProgram = getProgram(p.maker);
+    const takerProgram = getProgram(p.taker);
+
+    const makeIx = await makerProgram.methods
+      .make(p.seed, new BN(3), new BN(1_000_000), makeOptions())
+      .accountsPartial(makeAccounts(p))
+      .instruction();
+    sendTransaction([makeIx], [p.maker]);
+
+    // 1 * 1_000_000 / 3 = 333_333.33, the fraction stays in the vault
+    const firstFill = await takerProgram.methods
+      .takePartial(new BN(1))
+      .accountsPartial(takeAccounts(p))
+      .instruction();
+    sendTransaction([firstFill], [p.taker]);
+
+    assert.equal(await getTokenBalance(p.takerAtaA), 333_333);
+    assert.equal(await getTokenBalance(p.vault), 666_667);
+
+    // paying the rest of the receive amount releases the dust as well
+    const lastFill = await takerProgram.methods
+      .takePartial(new BN(2))
+      .accountsPartial(takeAccounts(p))
+      .instruction();
+    sendTransaction([lastFill], [p.taker]);
+
+    assert.equal(await getTokenBalance(p.takerAtaA), 1_000_000);
+    assert.equal(await getTokenBalance(p.makerAtaB), 3);
+    assert.ok(isClosed(p.escrow), "Escrow should be closed once paid in full");
+  });
+
+  it("Take fails once the clock reaches the expiry", async () => {
+    const p = setupEscrowParties(1_000_000, 500_000);
+    const makerProgram = getProgram(p.maker);
+    const takerProgram = getProgram(p.taker);
+
+    const clock = svm.getClock();
+    const expiry = clock.unixTimestamp + BigInt(60);
+
+    const makeIx = await makerProgram.methods
+      .make(
+        p.seed,
+        new BN(500_000),
+        new BN(1_000_000),
+        makeOptions({ expiry: new BN(expiry.toString()) })
+      )
+      .accountsPartial(makeAccounts(p))
+      .instruction();
+    sendTransaction([makeIx], [p.maker]);
+
+    // boundary: unix_timestamp == expiry is already expired
+    setUnixTimestamp(expiry);
+
+    const takeIx = await takerProgram.methods
+      .take(new BN(1_000_000), ANY_PRICE, NO_BOUND, NO_BOUND)
+      .accountsPartial(takeAccounts(p))
+      .instruction();
+    sendTransactionExpectError([takeIx], [p.taker], "EscrowExpired");
+    assert.equal(await getTokenBalance(p.vault), 1_000_000);
+
+    // the maker can still refund an expired escrow
+    const refundIx = await makerProgram.methods
+      .refund()
+      .accountsPartial(refundAccounts(p))
+      .instruction();
+    sendTransaction([refundIx], [p.maker]);
+
+    assert.equal(await getTokenBalance(p.makerAtaA), 1_000_000);
+    assert.ok(isClosed(p.escrow), "Escrow should be closed after refund");
+  });
+
+  it("Crank refund returns an expired escrow for a bounty", async () => {
+    const p = setupEscrowParties(1_000_000, 500_000);
+    const cranker = Keypair.generate();
+    svm.airdrop(cranker.publicKey, BigInt(LAMPORTS_PER_SOL));
+    const makerProgram = getProgram(p.maker);
+    const crankerProgram = getProgram(cranker);
+
+    const clock = svm.getClock();
+    const expiry = clock.unixTimestamp + BigInt(60);
+
+    const makeIx = await makerProgram.methods
+      .make(
+        p.seed,
+        new BN(500_000),
+        new BN(1_000_000),
+        makeOptions({ expiry: new BN(expiry.toString()) })
+      )
+      .accountsPartial(makeAccounts(p))
+      .instruction();
+    sendTransaction([makeIx], [p.maker]);
+
+    const crankAccounts = {
+      cranker: cranker.publicKey,
+      maker: p.maker.publicKey,
+      mintA: p.mintA,
+      makerAtaA: p.makerAtaA,
+      escrow: p.escrow,
+      vault: p.vault,
+      rentRecipient: null,
+      makerIndex: makerIndexOf(p.maker.publicKey),
+      stats,
+      associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId,
+    };
+
+    const earlyCrank = await crankerProgram.methods
+      .crankRefund()
+      .accountsPartial(crankAccounts)
+      .instruction();
+    sendTransactionExpectError([earlyCrank], [cranker], "EscrowNotExpired");
+
+    // only the maker can refund during the grace period after the expiry
+    setUnixTimestamp(expiry);
+    svm.expireBlockhash();
+    sendTransactionExpectError([earlyCrank], [cranker], "GracePeriodActive");
+
+    setUnixTimestamp(expiry + BigInt(24 * 60 * 60));
+
+    const crankerLamportsBefore = svm.getBalance(cranker.publicKey);
+    const crankIx = await crankerProgram.methods
+      .crankRefund()
+      .accountsPartial(crankAccounts)
+      .instruction();
+    sendTransaction([crankIx], [cranker]);
+
+    assert.equal(await getTokenBalance(p.makerAtaA), 1_000_000);
+    assert.ok(isClosed(p.vault), "Vault should be closed after crank");
+    assert.ok(isClosed(p.escrow), "Escrow should be closed after crank");
+    // the payer covers the fee, so the cranker only gains the bounty
+    assert.equal(
+      svm.getBalance(cranker.publicKey) - crankerLamportsBefore,
+      BigInt(100_000)
+    );
+  });
+
+  it("Paused escrow can't be taken until the maker re-enables it", async () => {
+    const p = setupEscrowParties(1_000_000, 500_000);
+    const makerProgram = getProgram(p.maker);
+    const takerProgram = getProgram(p.taker);
+
+    const makeIx = await makerProgram.methods
+      .make(p.seed, new BN(500_000), new BN(1_000_000), makeOptions())
+      .accountsPartial(makeAccounts(p))
+      .instruction();
+    const pauseIx = await makerProgram.methods
+      .setActive(false)
+      .accountsPartial({ maker: p.maker.publicKey, escrow: p.escrow })
+      .instruction();
+    sendTransaction([makeIx, pauseIx], [p.maker]);
+
+    const takeIx = await takerProgram.methods
+      .take(new BN(1_000_000), ANY_PRICE, NO_BOUND, NO_BOUND)
+      .accountsPartial(takeAccounts(p))
+      .instruction();
+    sendTransactionExpectError([takeIx], [p.taker], "EscrowPaused");
+    assert.equal(await getTokenBalance(p.vault), 1_000_000);
+
+    const resumeIx = await makerProgram.methods
+      .setActive(true)
+      .accountsPartial({ maker: p.maker.publicKey, escrow: p.escrow })
+      .instruction();
+    sendTransaction([resumeIx, takeIx], [p.maker, p.taker]);
+
+    assert.equal(await getTokenBalance(p.takerAtaA), 1_000_000);
+    assert.ok(isClosed(p.escrow), "Escrow should be closed after take");
+  });
+
+  it("Designated taker escrow rejects everyone else", async () => {
+    const p = setupEscrowParties(1_000_000, 500_000);
+    const makerProgram = getProgram(p.maker);
+
+    const makeIx = await makerProgram.methods
+      .make(
+        p.seed,
+        new BN(500_000),
+        new BN(1_000_000),
+        makeOptions({ allowedTaker: p.taker.publicKey })
+      )
+      .accountsPartial(makeAccounts(p))
+      .instruction();
+    sendTransaction([makeIx], [p.maker]);
+
+    // a third party with its own mint B balance tries to snipe the escrow
+    const sniper = setupExtraTaker(p, 500_000);
+
+    const snipeIx = await getProgram(sniper.taker)
+      .methods.take(new BN(1_000_000), ANY_PRICE, NO_BOUND, NO_BOUND)
+      .accountsPartial({ ...takeAccounts(p), taker: sniper.taker.publicKey })
+      .instruction();
+    sendTransactionExpectError(
+      [snipeIx],
+      [sniper.taker],
+      "UnauthorizedTaker"
+    );
+
+    const takeIx = await getProgram(p.taker)
+      .methods.take(new BN(1_000_000), ANY_PRICE, NO_BOUND, NO_BOUND)
+      .accountsPartial(takeAccounts(p))
+      .instruction();
+    sendTransaction([takeIx], [p.taker]);
+
+    assert.equal(await getTokenBalance(p.takerAtaA), 1_000_000);
+    assert.equal(await getTokenBalance(sniper.takerAtaB), 500_000);
+  });
+
+  it("Maker can re-price an open escrow", async () => {
+    const p = setupEscrowParties(1_000_000, 500_000);
+    const makerProgram = getProgram(p.maker);
+
+    const makeIx = await makerProgram.methods
+      .make(p.seed, new BN(400_000), new BN(1_000_000), makeOptions())
+      .accountsPartial(makeAccounts(p))
+      .instruction();
+    const updateIx = await makerProgram.methods
+      .updateReceive(new BN(500_000))
+      .accountsPartial({ maker: p.maker.publicKey, escrow: p.escrow })
+      .instruction();
+    sendTransaction([makeIx, updateIx], [p.maker]);
+
+    const escrowData = parseEscrowAccount(svm.getAccount(p.escrow));
+    assert.equal(escrowData.receive, 500_000);
+    assert.equal(await getTokenBalance(p.vault), 1_000_000);
+
+    const takeIx = await getProgram(p.taker)
+      .methods.take(new BN(1_000_000), ANY_PRICE, NO_BOUND, NO_BOUND)
+      .accountsPartial(takeAccounts(p))
+      .instruction();
+    sendTransaction([takeIx], [p.taker]);
+
+    assert.equal(
+      await getTokenBalance(p.makerAtaB),
+      500_000 - feeOf(500_000)
+    );
+  });
+
+  it("Whitelisted escrow only accepts listed takers", async () => {
+    const p = setupEscrowParties(1_000_000, 500_000);
+    const makerProgram = getProgram(p.maker);
+    const listed = setupExtraTaker(p, 0);
+    const outsider = setupExtraTaker(p, 500_000);
+
+    const makeIx = await makerProgram.methods
+      .make(
+        p.seed,
+        new BN(500_000),
+        new BN(1_000_000),
+        makeOptions({ whitelist: [listed.taker.publicKey] })
+      )
+      .accountsPartial(makeAccounts(p))
+      .instruction();
+    sendTransaction([makeIx], [p.maker]);
+
+    const outsiderIx = await getProgram(outsider.taker)
+      .methods.take(new BN(1_000_000), ANY_PRICE, NO_BOUND, NO_BOUND)
+      .accountsPartial({
+        ...takeAccounts(p),
+        taker: outsider.taker.publicKey,
+      })
+      .instruction();
+    sendTransactionExpectError(
+      [outsiderIx],
+      [outsider.taker],
+      "TakerNotWhitelisted"
+    );
+
+    const duplicateIx = await makerProgram.methods
+      .setWhitelist([p.taker.publicKey, p.taker.publicKey])
+      .accountsPartial({ maker: p.maker.publicKey, escrow: p.escrow })
+      .instruction();
+    sendTransactionExpectError(
+      [duplicateIx],
+      [p.maker],
+      "DuplicateWhitelistEntry"
+    );
+
+    const updateIx = await makerProgram.methods
+      .setWhitelist([listed.taker.publicKey, p.taker.publicKey])
+      .accountsPartial({ maker: p.maker.publicKey, escrow: p.escrow })
+      .instruction();
+    sendTransaction([updateIx], [p.maker]);
+
+    const takeIx = await getProgram(p.taker)
+      .methods.take(new BN(1_000_000), ANY_PRICE, NO_BOUND, NO_BOUND)
+      .accountsPartial(takeAccounts(p))
+      .instruction();
+    sendTransaction([takeIx], [p.taker]);
+
+    assert.equal(await getTokenBalance(p.takerAtaA), 1_000_000);
+  });
+
+  it("Re-pricing is rejected for zero or once partially filled", async () => {
+    const p = setupEscrowParties(1_000_000, 500_000);
+    const makerProgram = getProgram(p.maker);
+
+    const makeIx = await makerProgram.methods
+      .make(p.seed, new BN(500_000), new BN(1_000_000), makeOptions())
+      .accountsPartial(makeAccounts(p))
+      .instruction();
+    sendTransaction([makeIx], [p.maker]);
+
+    const zeroIx = await makerProgram.methods
+      .updateReceive(new BN(0))
+      .accountsPartial({ maker: p.maker.publicKey, escrow: p.escrow })
+      .instruction();
+    sendTransactionExpectError([zeroIx], [p.maker], "ZeroReceive");
+
+    const takeIx = await getProgram(p.taker)
+      .methods.take(new BN(500_000), ANY_PRICE, NO_BOUND, NO_BOUND)
+      .accountsPartial(takeAccounts(p))
+      .instruction();
+    sendTransaction([takeIx], [p.taker]);
+
+    const updateIx = await makerProgram.methods
+      .updateReceive(new BN(1))
+      .accountsPartial({ maker: p.maker.publicKey, escrow: p.escrow })
+      .instruction();
+    sendTransactionExpectError([updateIx], [p.maker], "EscrowAlreadyFilled");
+    assert.equal(parseEscrowAccount(svm.getAccount(p.escrow)).receive, 500_000);
+  });
+
+  it("Maker can fix mint_b until the first fill", async () => {
+    const p = setupEscrowParties(1_000_000, 500_000);
+    const wrong = setupEscrowParties(0, 0);
+    const makerProgram = getProgram(p.maker);
+    const updateMintB = (mint: PublicKey) =>
+      makerProgram.methods
+        .updateMintB()
+        .accountsPartial({
+          maker: p.maker.publicKey,
+          escrow: p.escrow,
+          newMintB: mint,
+        })
+        .instruction();
+
+    const makeIx = await makerProgram.methods
+      .make(p.seed, new BN(500_000), new BN(1_000_000), makeOptions())
+      .accountsPartial({ ...makeAccounts(p), mintB: wrong.mintB })
+      .instruction();
+    sendTransaction([makeIx], [p.maker]);
+
+    sendTransactionExpectError(
+      [await updateMintB(p.mintA)],
+      [p.maker],
+      "IdenticalMints"
+    );
+
+    const [updated] = sendTransactionEvents(
+      [await updateMintB(p.mintB)],
+      [p.maker]
+    );
+    assert.equal(updated.name, "escrowmintupdated");
+    assert.ok(updated.data.oldMintB.equals(wrong.mintB));
+    assert.ok(updated.data.newMintB.equals(p.mintB));
+    assert.equal(
+      parseEscrowAccount(svm.getAccount(p.escrow)).mintB,
+      p.mintB.toBase58()
+    );
+
+    const takeIx = await getProgram(p.taker)
+      .methods.take(new BN(500_000), ANY_PRICE, NO_BOUND, NO_BOUND)
+      .accountsPartial(takeAccounts(p))
+      .instruction();
+    sendTransaction([takeIx], [p.taker]);
+    assert.equal(await getTokenBalance(p.takerAtaB), 250_000);
+
+    sendTransactionExpectError(
+      [await updateMintB(wrong.mintB)],
+      [p.maker],
+      "EscrowAlreadyFilled"
+    );
+  });
+
+  it("SOL escrow sends lamports to the taker", async () => {
+    const p = setupEscrowParties(0, 500_000);
+    const makerProgram = getProgram(p.maker);
+    const [solVault] = PublicKey.findProgramAddressSync(
+      [Buffer.from("sol_vault"), p.escrow.toBuffer()],
+      programId
+    );
+    const deposit = LAMPORTS_PER_SOL;
+
+    const makeIx = await makerProgram.methods
+      .makeSol(p.seed, new BN(500_000), new BN(deposit), makeOptions())
+      .accountsPartial({
+        maker: p.maker.publicKey,
+        mintB: p.mintB,
+        escrow: p.escrow,
+        makerIndex: makerIndexOf(p.maker.publicKey),
+        stats,
+        solVault,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .instruction();
+    sendTransaction([makeIx], [p.maker]);
+    assert.equal(Number(svm.getBalance(solVault)), deposit);
+
+    const takerBefore = svm.getBalance(p.taker.publicKey);
+    const takeIx = await getProgram(p.taker)
+      .methods.takeSol()
+      .accountsPartial({
+        taker: p.taker.publicKey,
+        maker: p.maker.publicKey,
+        mintB: p.mintB,
+        escrow: p.escrow,
+        makerIndex: makerIndexOf(p.maker.publicKey),
+        stats,
+        solVault,
+        config,
+        feeCollector: feeCollector.publicKey,
+        feeCollectorAtaB: feeCollectorAta(p.mintB),
+        rentRecipient: null,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .instruction();
+    sendTransaction([takeIx], [p.taker]);
+
+    const takerAfter = Number(svm.getBalance(p.taker.publicKey));
+    assert.equal(takerAfter - takerBefore, deposit);
+    assert.equal(
+      await getTokenBalance(p.makerAtaB),
+      500_000 - feeOf(500_000)
+    );
+    assert.isTrue(isClosed(p.escrow));
+  });
+
+  it("Top up grows the deposit and optionally the receive", async () => {
+    const p = setupEscrowParties(2_000_000, 1_500_000);
+    const makerProgram = getProgram(p.maker);
+    const topUpAccounts = {
+      maker: p.maker.publicKey,
+      mintA: p.mintA,
+      escrow: p.escrow,
+      vault: p.vault,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    };
+
+    const makeIx = await makerProgram.methods
+      .make(p.seed, new BN(500_000), new BN(1_000_000), makeOptions())
+      .accountsPartial(makeAccounts(p))
+      .instruction();
+    const scaledIx = await makerProgram.methods
+      .topUp(new BN(500_000), true)
+      .accountsPartial(topUpAccounts)
+      .instruction();
+    const fixedIx = await makerProgram.methods
+      .topUp(new BN(500_000), false)
+      .accountsPartial(topUpAccounts)
+      .instruction();
+    sendTransaction([makeIx, scaledIx, fixedIx], [p.maker]);
+
+    const escrowData = parseEscrowAccount(svm.getAccount(p.escrow));
+    assert.equal(escrowData.receive, 750_000);
+    assert.equal(await getTokenBalance(p.vault), 2_000_000);
+
+    const zeroIx = await makerProgram.methods
+      .topUp(new BN(0), false)
+      .accountsPartial(topUpAccounts)
+      .instruction();
+    sendTransactionExpectError([zeroIx], [p.maker], "ZeroAmount");
+
+    const takeIx = await getProgram(p.taker)
+      .methods.take(new BN(2_000_000), ANY_PRICE, NO_BOUND, NO_BOUND)
+      .accountsPartial(takeAccounts(p))
+      .instruction();
+    sendTransaction([takeIx], [p.taker]);
+
+    assert.equal(await getTokenBalance(p.takerAtaA), 2_000_000);
+    assert.equal(
+      await getTokenBalance(p.makerAtaB),
+      750_000 - feeOf(750_000)
+    );
+  });
+
+  it("Maker withdraws part of the deposit, all of it closes", async () => {
+    const p = setupEscrowParties(1_000_000, 500_000);
+    const makerProgram = getProgram(p.maker);
+    const withdraw = (amount: number) =>
+      makerProgram.methods
+        .withdraw(new BN(amount))
+        .accountsPartial({
+          maker: p.maker.publicKey,
+          mintA: p.mintA,
+          makerAtaA: p.makerAtaA,
+          escrow: p.escrow,
+          makerIndex: makerIndexOf(p.maker.publicKey),
+          stats,
+          vault: p.vault,
+          rentRecipient: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .instruction();
+
+    const makeIx = await makerProgram.methods
+      .make(p.seed, new BN(500_000), new BN(1_000_000), makeOptions())
+      .accountsPartial(makeAccounts(p))
+      .instruction();
+    sendTransaction([makeIx, await withdraw(200_000)], [p.maker]);
+
+    const escrowData = parseEscrowAccount(svm.getAccount(p.escrow));
+    assert.equal(escrowData.receive, 400_000, "Price per token is kept");
+    assert.equal(await getTokenBalance(p.vault), 800_000);
+
+    // a take lands first, the withdrawal signed against the old balance fails
+    const takeIx = await getProgram(p.taker)
+      .methods.take(new BN(500_000), ANY_PRICE, NO_BOUND, NO_BOUND)
+      .accountsPartial(takeAccounts(p))
+      .instruction();
+    sendTransaction([takeIx], [p.taker]);
+    sendTransactionExpectError(
+      [await withdraw(800_000)],
+      [p.maker],
+      "WithdrawExceedsRemaining"
+    );
+
+    // dust sent to the vault from outside doesn't block the close
+    const vault = svm.getAccount(p.vault);
+    const data = Buffer.from(vault.data);
+    data.writeBigUInt64LE(BigInt(300_007), 64);
+    svm.setAccount(p.vault, { ...vault, data });
+
+    sendTransaction([await withdraw(300_000)], [p.maker]);
+    assert.equal(await getTokenBalance(p.makerAtaA), 500_007);
+    assert.ok(isClosed(p.vault), "Vault should be closed once empty");
+    assert.ok(isClosed(p.escrow), "Escrow should be closed once empty");
+  });
+
+  it("Dutch auction price decays to the floor", async () => {
+    const p = setupEscrowParties(1_000_000, 1_000_000);
+    const makerProgram = getProgram(p.maker);
+    const clock = svm.getClock();
+    const start = clock.unixTimestamp;
+
+    const makeIx = await makerProgram.methods
+      .make(
+        p.seed,
+        new BN(1_000_000),
+        new BN(1_000_000),
+        makeOptions({
+          floorReceive: new BN(400_000),
+          startTime: new BN(start.toString()),
+          decayDuration: new BN(1_000),
+        })
+      )
+      .accountsPartial(makeAccounts(p))
+      .instruction();
+    sendTransaction([makeIx], [p.maker]);
+
+    const repriceIx = await makerProgram.methods
+      .updateReceive(new BN(900_000))
+      .accountsPartial({ maker: p.maker.publicKey, escrow: p.escrow })
+      .instruction();
+    sendTransactionExpectError([repriceIx], [p.maker], "AuctionPriced");
+
+    // at the start nothing has decayed, so a taker capped below the start
+    // price is turned away
+    setUnixTimestamp(start);
+    const cappedIx = await getProgram(p.taker)
+      .methods.take(new BN(500_000), new BN(999_999), NO_BOUND, NO_BOUND)
+      .accountsPartial(takeAccounts(p))
+      .instruction();
+    sendTransactionExpectError([cappedIx], [p.taker], "SlippageExceeded");
+
+    // halfway through the decay the whole deposit costs 700_000, which the
+    // same cap now covers
+    setUnixTimestamp(start + BigInt(500));
+    const takeIx = await getProgram(p.taker)
+      .methods.take(new BN(500_000), new BN(999_999), NO_BOUND, NO_BOUND)
+      .accountsPartial(takeAccounts(p))
+      .instruction();
+    sendTransaction([takeIx], [p.taker]);
+    assert.equal(await getTokenBalance(p.takerAtaB), 1_000_000 - 350_000);
+
+    // long after the decay the price stays at the floor
+    setUnixTimestamp(start + BigInt(10_000));
+    const lastIx = await getProgram(p.taker)
+      .methods.take(new BN(500_000), ANY_PRICE, NO_BOUND, NO_BOUND)
+      .accountsPartial(takeAccounts(p))
+      .instruction();
+    sendTransaction([lastIx], [p.taker]);
+    assert.equal(await getTokenBalance(p.takerAtaB), 1_000_000 - 550_000);
+    assert.ok(isClosed(p.escrow), "Escrow should be closed once empty");
+  });
+
+  it("Make, take and refund emit events with the moved amounts", async () => {
+    const p = setupEscrowParties(2_000_000, 500_000);
+    const makerProgram = getProgram(p.maker);
+
+    const makeIx = await makerProgram.methods
+      .make(p.seed, new BN(500_000), new BN(1_000_000), makeOptions())
+      .accountsPartial(makeAccounts(p))
+      .instruction();
+    const [made] = sendTransactionEvents([makeIx], [p.maker]);
+    assert.equal(made.name, "escrowmade");
+    assert.ok(made.data.escrow.equals(p.escrow));
+    assert.ok(made.data.maker.equals(p.maker.publicKey));
+    assert.ok(made.data.mintA.equals(p.mintA));
+    assert.ok(made.data.mintB.equals(p.mintB));
+    assert.equal(made.data.seed.toString(), p.seed.toString());
+    assert.equal(made.data.deposit.toNumber(), 1_000_000);
+    assert.equal(made.data.receive.toNumber(), 500_000);
+
+    const takeIx = await getProgram(p.taker)
+      .methods.take(new BN(400_000), ANY_PRICE, NO_BOUND, NO_BOUND)
+      .accountsPartial(takeAccounts(p))
+      .instruction();
+    const [taken] = sendTransactionEvents([takeIx], [p.taker]);
+    assert.equal(taken.name, "escrowtaken");
+    assert.ok(taken.data.taker.equals(p.taker.publicKey));
+    assert.equal(taken.data.amountA.toNumber(), 400_000);
+    assert.equal(taken.data.amountB.toNumber(), 200_000);
+    assert.equal(taken.data.fee.toNumber(), feeOf(200_000));
+    assert.equal(taken.data.remaining.toNumber(), 600_000);
+    assert.equal(
+      await getTokenBalance(p.makerAtaB),
+      taken.data.amountB.toNumber() - taken.data.fee.toNumber()
+    );
+
+    const refundIx = await makerProgram.methods
+      .refund()
+      .accountsPartial(refundAccounts(p))
+      .instruction();
+    const [refunded] = sendTransactionEvents([refundIx], [p.maker]);
+    assert.equal(refunded.name, "escrowrefunded");
+    assert.ok(refunded.data.escrow.equals(p.escrow));
+    assert.equal(refunded.data.amount.toNumber(), 600_000);
+  });
+
+  it("Quote returns the price and vault balance as return data", async () => {
+    const p = setupEscrowParties(1_000_000, 500_000);
+    const makerProgram = getProgram(p.maker);
+
+    const makeIx = await makerProgram.methods
+      .make(
+        p.seed,
+        new BN(500_000),
+        new BN(1_000_000),
+        makeOptions({ allowedTaker: p.taker.publicKey })
+      )
+      .accountsPartial(makeAccounts(p))
+      .instruction();
+    sendTransaction([makeIx], [p.maker]);
+
+    const quote = async (taker: PublicKey) => {
+      const quoteIx = await makerProgram.methods
+        .quote(taker)
+        .accountsPartial({ escrow: p.escrow, vault: p.vault })
+        .instruction();
+      const tx = new Transaction().add(quoteIx);
+      tx.recentBlockhash = svm.latestBlockhash();
+      tx.feePayer = payer.publicKey;
+      tx.sign(payer);
+      const result = svm.simulateTransaction(tx);
+      assert.notOk(result instanceof FailedTransactionMetadata);
+      return Buffer.from((result as any).meta().returnData().data());
+    };
+
+    // EscrowQuote { receive, remaining_receive, vault_balance, takeable }
+    const data = await quote(p.taker.publicKey);
+    assert.equal(data.readBigUInt64LE(0), BigInt(500_000));
+    assert.equal(data.readBigUInt64LE(8), BigInt(500_000));
+    assert.equal(data.readBigUInt64LE(16), BigInt(1_000_000));
+    assert.equal(data[24], 1, "The allowed taker can take");
+    const stranger = Keypair.generate().publicKey;
+    assert.equal((await quote(stranger))[24], 0, "Anyone else can't");
+
+    const pauseIx = await makerProgram.methods
+      .setActive(false)
+      .accountsPartial({ maker: p.maker.publicKey, escrow: p.escrow })
+      .instruction();
+    sendTransaction([pauseIx], [p.maker]);
+    const paused = await quote(p.taker.publicKey);
+    assert.equal(paused[24], 0, "Paused escrow is not takeable");
+  });
+
+  it("Take also emits its event through a self CPI", async () => {
+    const p = setupEscrowParties(1_000_000, 500_000);
+
+    const makeIx = await getProgram(p.maker)
+      .methods.make(p.seed, new BN(500_000), new BN(1_000_000), makeOptions())
+      .accountsPartial(makeAccounts(p))
+      .instruction();
+    sendTransaction([makeIx], [p.maker]);
+
+    const program = getProgram(p.taker);
+    const takeIx = await program.methods
+      .take(new BN(1_000_000), ANY_PRICE, NO_BOUND, NO_BOUND)
+      .accountsPartial(takeAccounts(p))
+      .instruction();
+    const tx = new Transaction().add(takeIx);
+    tx.recentBlockhash = svm.latestBlockhash();
+    tx.feePayer = payer.publicKey;
+    tx.sign(payer, p.taker);
+    const result = svm.sendTransaction(tx) as any;
+    assert.notOk(result instanceof FailedTransactionMetadata);
+
+    // inner instruction data is the 8 byte event CPI tag followed by the event
+    const message = tx.compileMessage();
+    const events = result
+      .innerInstructions()
+      .flat()
+      .map((inner: any) => inner.instruction())
+      .filter((ix: any) =>
+        message.accountKeys[ix.programIdIndex()].equals(programId)
+      )
+      .map((ix: any) =>
+        program.coder.events.decode(
+          Buffer.from(ix.data()).subarray(8).toString("base64")
+        )
+      );
+    assert.equal(events.length, 2);
+    assert.equal(events[0].name.toLowerCase(), "escrowtaken");
+    assert.equal(events[0].data.amountA.toNumber(), 1_000_000);
+    assert.equal(events[1].name.toLowerCase(), "statsupdated");
+  });
+
+  it("Take checks the vault holds at least the recorded deposit", async () => {
+    const p = setupEscrowParties(1_000_000, 500_000);
+
+    const makeIx = await getProgram(p.maker)
+      .methods.make(p.seed, new BN(500_000), new BN(1_000_000), makeOptions())
+      .accountsPartial(makeAccounts(p))
+      .instruction();
+    sendTransaction([makeIx], [p.maker]);
+
+    // rewrite the token account amount (offset 64) behind the program's back
+    const setVaultAmount = (amount: number) => {
+      const account = svm.getAccount(p.vault);
+      const data = Buffer.from(account.data);
+      data.writeBigUInt64LE(BigInt(amount), 64);
+      svm.setAccount(p.vault, { ...account, data });
+    };
+    const takeIx = (makerAtaA: PublicKey | null) =>
+      getProgram(p.taker)
+        .methods.take(new BN(1_000_000), ANY_PRICE, NO_BOUND, NO_BOUND)
+        .accountsPartial({ ...takeAccounts(p), makerAtaA })
+        .instruction();
+
+    // a drained vault no longer covers the deposit. an empty vault fails the
+    // same way before the taker pays anything
+    for (const amount of [0, 999_999]) {
+      setVaultAmount(amount);
+      sendTransactionExpectError(
+        [await takeIx(null)],
+        [p.taker],
+        "VaultBalanceMismatch"
+      );
+    }
+    assert.equal(await getTokenBalance(p.takerAtaB), 500_000);
+
+    // tokens sent to the vault from outside go back to the maker, which
+    // needs the maker's account
+    setVaultAmount(1_000_005);
+    sendTransactionExpectError(
+      [await takeIx(null)],
+      [p.taker],
+      "MakerAtaRequired"
+    );
+    sendTransaction([await takeIx(p.makerAtaA)], [p.taker]);
+    assert.equal(await getTokenBalance(p.takerAtaA), 1_000_000);
+    assert.equal(await getTokenBalance(p.makerAtaA), 5);
+    assert.ok(isClosed(p.vault), "Vault should be closed once empty");
+  });
+
+  it("Make rejects the same mint on both sides", async () => {
+    const p = setupEscrowParties(1_000_000, 500_000);
+
+    const makeIx = await getProgram(p.maker)
+      .methods.make(p.seed, new BN(500_000), new BN(1_000_000), makeOptions())
+      .accountsPartial({ ...makeAccounts(p), mintB: p.mintA })
+      .instruction();
+    sendTransactionExpectError([makeIx], [p.maker], "IdenticalMints");
+  });
+
+  it("Config is created once and only the admin can update it", async () => {
+    const configIx = await getProgram(payer)
+      .methods.initializeConfig(payer.publicKey)
+      .accountsPartial({ admin: payer.publicKey, config })
+      .instruction();
+    sendTransactionExpectError([configIx], [], "already in use");
+
+    const update = (wallet: Keypair, feeBps: number) =>
+      getProgram(wallet)
+        .methods.updateConfig({ feeBps, feeCollector: null })
+        .accountsPartial({ admin: wallet.publicKey, config })
+        .instruction();
+    const outsider = Keypair.generate();
+    sendTransactionExpectError(
+      [await update(outsider, 0)],
+      [outsider],
+      "UnauthorizedAdmin"
+    );
+    sendTransactionExpectError([await update(payer, 1_001)], [], "FeeTooHigh");
+
+    // with the fee switched off the maker receives the whole payment and
+    // the fee accounts can be left out
+    const p = setupEscrowParties(1_000_000, 500_000);
+    const makeIx = await getProgram(p.maker)
+      .methods.make(p.seed, new BN(500_000), new BN(1_000_000), makeOptions())
+      .accountsPartial(makeAccounts(p))
+      .instruction();
+    sendTransaction([makeIx, await update(payer, 0)], [p.maker]);
+
+    const takeIx = await getProgram(p.taker)
+      .methods.take(new BN(1_000_000), ANY_PRICE, NO_BOUND, NO_BOUND)
+      .accountsPartial({
+        ...takeAccounts(p),
+        feeCollector: null,
+        feeCollectorAtaB: null,
+      })
+      .instruction();
+    sendTransaction([takeIx, await update(payer, FEE_BPS)], [p.taker]);
+    assert.equal(await getTokenBalance(p.makerAtaB), 500_000);
+  });
+
+  it("Make rejects zero amounts and deposits it can't cover", async () => {
+    const p = setupEscrowParties(1_000_000, 500_000);
+    const make = (receive: number, deposit: number) =>
+      getProgram(p.maker)
+        .methods.make(p.seed, new BN(receive), new BN(deposit), makeOptions())
+        .accountsPartial(makeAccounts(p))
+        .instruction();
+
+    sendTransactionExpectError(
+      [await make(500_000, 0)],
+      [p.maker],
+      "ZeroDeposit"
+    );
+    sendTransactionExpectError(
+      [await make(0, 1_000_000)],
+      [p.maker],
+      "ZeroReceive"
+    );
+    // the maker holds 1_000_000 of mint_a
+    sendTransactionExpectError(
+      [await make(500_000, 1_000_001)],
+      [p.maker],
+      "InsufficientMakerFunds"
+    );
+  });
+
+  it("Transfer fee mint A escrows offer what reached the vault", async () => {
+    const p = setupEscrowParties(1_000_000, 500_000, {
+      tokenProgram: TOKEN_2022_PROGRAM_ID,
+      transferFeeA: { bps: 100, max: BigInt(1_000_000) },
+    });
+
+    const makeIx = await getProgram(p.maker)
+      .methods.make(p.seed, new BN(500_000), new BN(1_000_000), makeOptions())
+      .accountsPartial(makeAccounts(p))
+      .instruction();
+    const [made] = sendTransactionEvents([makeIx], [p.maker]);
+
+    // 1% of the deposit is withheld on the way into the vault
+    assert.equal(made.data.deposit.toNumber(), 990_000);
+    assert.equal(await getTokenBalance(p.vault), 990_000);
+    const data = Buffer.from(svm.getAccount(p.escrow).data);
+    assert.equal(data.readBigUInt64LE(137), BigInt(990_000), "remaining");
+
+    const takeIx = await getProgram(p.taker)
+      .methods.take(new BN(990_000), ANY_PRICE, NO_BOUND, NO_BOUND)
+      .accountsPartial(takeAccounts(p))
+      .instruction();
+    sendTransaction([takeIx], [p.taker]);
+
+    assert.equal(await getTokenBalance(p.takerAtaA), 990_000 - 9_900);
+    assert.ok(isClosed(p.vault), "Vault should be closed once empty");
+    assert.ok(isClosed(p.escrow), "Escrow should be closed once empty");
+  });
+
+  it("Fee splits the payment exactly and needs the fee accounts", async () => {
+    const p = setupEscrowParties(1_000_000, 333_333);
+
+    const makeIx = await getProgram(p.maker)
+      .methods.make(p.seed, new BN(333_333), new BN(1_000_000), makeOptions())
+      .accountsPartial(makeAccounts(p))
+      .instruction();
+    sendTransaction([makeIx], [p.maker]);
+
+    const takeIx = (accounts: Record<string, any>) =>
+      getProgram(p.taker)
+        .methods.take(new BN(1_000_000), ANY_PRICE, NO_BOUND, NO_BOUND)
+        .accountsPartial({ ...takeAccounts(p), ...accounts })
+        .instruction();
+    sendTransactionExpectError(
+      [await takeIx({ feeCollector: null, feeCollectorAtaB: null })],
+      [p.taker],
+      "MissingFeeAccount"
+    );
+
+    const [taken] = sendTransactionEvents([await takeIx({})], [p.taker]);
+    // 333_333 * 30 / 10_000 = 999.99, the fee rounds down
+    assert.equal(taken.data.fee.toNumber(), 999);
+    const makerB = await getTokenBalance(p.makerAtaB);
+    const feeB = await getTokenBalance(feeCollectorAta(p.mintB));
+    assert.equal(makerB + feeB, 333_333);
+    assert.equal(feeB, 999);
+  });
+
+  it("Partial fills below the minimum fill are rejected", async () => {
+    const p = setupEscrowParties(1_000_000, 500_000);
+    const makerProgram = getProgram(p.maker);
+    const takerProgram = getProgram(p.taker);
+
+    const makeIx = await makerProgram.methods
+      .make(
+        p.seed,
+        new BN(500_000),
+        new BN(1_000_000),
+        makeOptions({ minFill: new BN(100_000) })
+      )
+      .accountsPartial(makeAccounts(p))
+      .instruction();
+    sendTransaction([makeIx], [p.maker]);
+
+    // 10_000 of mint B only releases 20_000 of mint A
+    const smallIx = await takerProgram.methods
+      .takePartial(new BN(10_000))
+      .accountsPartial(takeAccounts(p))
+      .instruction();
+    sendTransactionExpectError([smallIx], [p.taker], "BelowMinimumFill");
+
+    const withdrawIx = await makerProgram.methods
+      .withdraw(new BN(950_000))
+      .accountsPartial({
+        maker: p.maker.publicKey,
+        mintA: p.mintA,
+        makerAtaA: p.makerAtaA,
+        escrow: p.escrow,
+        makerIndex: makerIndexOf(p.maker.publicKey),
+        stats,
+        vault: p.vault,
+        rentRecipient: null,
+        tokenProgram: p.tokenProgramA,
+      })
+      .instruction();
+    sendTransactionExpectError([withdrawIx], [p.maker], "BelowMinimumFill");
+
+    // a fill may leave less than the minimum, the rest can still be taken
+    const bigIx = await takerProgram.methods
+      .take(new BN(950_000), ANY_PRICE, NO_BOUND, NO_BOUND)
+      .accountsPartial(takeAccounts(p))
+      .instruction();
+    sendTransaction([bigIx], [p.taker]);
+    const restIx = await takerProgram.methods
+      .take(new BN(50_000), ANY_PRICE, NO_BOUND, NO_BOUND)
+      .accountsPartial(takeAccounts(p))
+      .instruction();
+    sendTransaction([restIx], [p.taker]);
+
+    assert.equal(await getTokenBalance(p.takerAtaA), 1_000_000);
+    assert.ok(isClosed(p.escrow), "Escrow should be closed once empty");
+  });
+
+  it("Admin authority moves in two steps", async () => {
+    const newAdmin = Keypair.generate();
+    const stranger = Keypair.generate();
+    const propose = (admin: Keypair, pending: PublicKey) =>
+      getProgram(admin)
+        .methods.proposeAdmin(pending)
+        .accountsPartial({ admin: admin.publicKey, config })
+        .instruction();
+    const accept = (signer: Keypair) =>
+      getProgram(signer)
+        .methods.acceptAdmin()
+        .accountsPartial({ pendingAdmin: signer.publicKey, config })
+        .instruction();
+    const cancelIx = await getProgram(payer)
+      .methods.cancelPendingAdmin()
+      .accountsPartial({ admin: payer.publicKey, config })
+      .instruction();
+    // Config: discriminator, admin, fee_collector, bump, fee_bps, paused
+    const pendingAdmin = () =>
+      new PublicKey(svm.getAccount(config).data.slice(76, 108));
+    const admin = () => new PublicKey(svm.getAccount(config).data.slice(8, 40));
+
+    sendTransaction([await propose(payer, newAdmin.publicKey)], []);
+    assert.ok(pendingAdmin().equals(newAdmin.publicKey));
+    sendTransactionExpectError(
+      [await accept(stranger)],
+      [stranger],
+      "NotPendingAdmin"
+    );
+
+    // proposing the current admin again clears the pending slot
+    sendTransaction([await propose(payer, payer.publicKey)], []);
+    assert.ok(pendingAdmin().equals(PublicKey.default));
+    sendTransactionExpectError(
+      [await accept(newAdmin)],
+      [newAdmin],
+      "NotPendingAdmin"
+    );
+
+    sendTransaction([await propose(payer, newAdmin.publicKey), cancelIx], []);
+    assert.ok(pendingAdmin().equals(PublicKey.default));
+
+    sendTransaction([await propose(payer, newAdmin.publicKey)], []);
+    const [accepted] = sendTransactionEvents(
+      [await accept(newAdmin)],
+      [newAdmin]
+    );
+    assert.equal(accepted.name, "adminaccepted");
+    assert.ok(accepted.data.oldAdmin.equals(payer.publicKey));
+    assert.ok(admin().equals(newAdmin.publicKey));
+
+    // hand the config back so the remaining tests keep the payer as admin
+    sendTransaction(
+      [await propose(newAdmin, payer.publicKey), await accept(payer)],
+      [newAdmin]
+    );
+    assert.ok(admin().equals(payer.publicKey));
+  });
+
+  it("Protocol pause blocks make and take but not refund", async () => {
+    const p = setupEscrowParties(2_000_000, 500_000);
+    const makerProgram = getProgram(p.maker);
+    const setPaused = (paused: boolean) =>
+      getProgram(payer)
+        .methods.setPaused(paused)
+        .accountsPartial({ admin: payer.publicKey, config })
+        .instruction();
+
+    const makeIx = await makerProgram.methods
+      .make(p.seed, new BN(500_000), new BN(1_000_000), makeOptions())
+      .accountsPartial(makeAccounts(p))
+      .instruction();
+    sendTransaction([makeIx], [p.maker]);
+    const [toggled] = sendTransactionEvents([await setPaused(true)], []);
+    assert.equal(toggled.name, "pausetoggled");
+    assert.equal(toggled.data.paused, true);
+
+    const takeIx = await getProgram(p.taker)
+      .methods.take(new BN(1_000_000), ANY_PRICE, NO_BOUND, NO_BOUND)
+      .accountsPartial(takeAccounts(p))
+      .instruction();
+    sendTransactionExpectError([takeIx], [p.taker], "ProtocolPaused");
+
+    const other = setupEscrowParties(1_000_000, 500_000);
+    const otherMakeIx = await getProgram(other.maker)
+      .methods.make(other.seed, new BN(1), new BN(1), makeOptions())
+      .accountsPartial(makeAccounts(other))
+      .instruction();
+    sendTransactionExpectError([otherMakeIx], [other.maker], "ProtocolPaused");
+
+    const refundIx = await makerProgram.methods
+      .refund()
+      .accountsPartial(refundAccounts(p))
+      .instruction();
+    sendTransaction([refundIx, await setPaused(false)], [p.maker]);
+    assert.equal(await getTokenBalance(p.makerAtaA), 2_000_000);
+    assert.ok(isClosed(p.escrow), "Escrow should be closed after refund");
+  });
+
+  it("Top up reports the new totals in its event", async () => {
+    const p = setupEscrowParties(1_500_000, 500_000);
+    const makerProgram = getProgram(p.maker);
+
+    const makeIx = await makerProgram.methods
+      .make(p.seed, new BN(500_000), new BN(1_000_000), makeOptions())
+      .accountsPartial(makeAccounts(p))
+      .instruction();
+    sendTransaction([makeIx], [p.maker]);
+
+    const topUpIx = await makerProgram.methods
+      .topUp(new BN(500_000), true)
+      .accountsPartial({
+        maker: p.maker.publicKey,
+        mintA: p.mintA,
+        escrow: p.escrow,
+        vault: p.vault,
+        tokenProgram: p.tokenProgramA,
+      })
+      .instruction();
+    const [toppedUp] = sendTransactionEvents([topUpIx], [p.maker]);
+    assert.equal(toppedUp.name, "escrowtoppedup");
+    assert.equal(toppedUp.data.amount.toNumber(), 500_000);
+    assert.equal(toppedUp.data.scaledReceive, true);
+    assert.equal(toppedUp.data.deposit.toNumber(), 1_500_000);
+    assert.equal(toppedUp.data.remaining.toNumber(), 1_500_000);
+    assert.equal(toppedUp.data.receive.toNumber(), 750_000);
+    assert.equal(toppedUp.data.remainingReceive.toNumber(), 750_000);
+  });
+
+  it("Take in the same slot as make hits the cooldown", async () => {
+    const p = setupEscrowParties(1_000_000, 500_000);
+
+    const makeIx = await getProgram(p.maker)
+      .methods.make(p.seed, new BN(500_000), new BN(1_000_000), makeOptions())
+      .accountsPartial(makeAccounts(p))
+      .instruction();
+    const takeIx = await getProgram(p.taker)
+      .methods.take(new BN(1_000_000), ANY_PRICE, NO_BOUND, NO_BOUND)
+      .accountsPartial(takeAccounts(p))
+      .instruction();
+    sendTransactionExpectError(
+      [makeIx, takeIx],
+      [p.maker, p.taker],
+      "CooldownNotElapsed"
+    );
+
+    // one slot short of the cooldown is still too early
+    const madeSlot = svm.getClock().slot;
+    sendTransaction([makeIx], [p.maker]);
+    svm.warpToSlot(madeSlot + BigInt(COOLDOWN_SLOTS - 1));
+    sendTransactionExpectError([takeIx], [p.taker], "CooldownNotElapsed");
+
+    svm.warpToSlot(madeSlot + BigInt(COOLDOWN_SLOTS));
+    svm.expireBlockhash();
+    sendTransaction([takeIx], [p.taker]);
+    assert.equal(await getTokenBalance(p.takerAtaA), 1_000_000);
+  });
+
+  it("Escrows can pair a legacy mint with a Token-2022 mint", async () => {
+    const p = setupEscrowParties(1_000_000, 500_000, {
+      tokenProgramA: TOKEN_PROGRAM_ID,
+      tokenProgramB: TOKEN_2022_PROGRAM_ID,
+    });
+
+    const makeIx = (accounts: Record<string, any>) =>
+      getProgram(p.maker)
+        .methods.make(p.seed, new BN(500_000), new BN(1_000_000), makeOptions())
+        .accountsPartial({ ...makeAccounts(p), ...accounts })
+        .instruction();
+    // each mint is checked against its own token program
+    sendTransactionExpectError(
+      [await makeIx({ tokenProgramB: TOKEN_PROGRAM_ID })],
+      [p.maker],
+      "ConstraintMintTokenProgram"
+    );
+    sendTransaction([await makeIx({})], [p.maker]);
+    assert.equal(await getTokenBalance(p.vault), 1_000_000);
+
+    const takeIx = await getProgram(p.taker)
+      .methods.take(new BN(1_000_000), ANY_PRICE, NO_BOUND, NO_BOUND)
+      .accountsPartial(takeAccounts(p))
+      .instruction();
+    sendTransaction([takeIx], [p.taker]);
+
+    const fee = feeOf(500_000);
+    assert.equal(await getTokenBalance(p.takerAtaA), 1_000_000);
+    assert.equal(await getTokenBalance(p.makerAtaB), 500_000 - fee);
+    assert.equal(
+      await getTokenBalance(feeCollectorAta(p.mintB, p.tokenProgramB)),
+      fee
+    );
+    assert.ok(isClosed(p.vault), "Vault should be closed once empty");
+    assert.ok(isClosed(p.escrow), "Escrow should be closed once empty");
+  });
+
+  it("Maker accepts one of several locked offers", async () => {
+    const p = setupEscrowParties(1_000_000, 400_000);
+    const rival = setupExtraTaker(p, 350_000);
+    const makerProgram = getProgram(p.maker);
+
+    const makeIx = await makerProgram.methods
+      .make(p.seed, new BN(500_000), new BN(1_000_000), makeOptions())
+      .accountsPartial(makeAccounts(p))
+      .instruction();
+    sendTransaction([makeIx], [p.maker]);
+
+    const taker = offerAccounts(p, p.taker.publicKey);
+    const [made] = sendTransactionEvents(
+      [await makeOffer(p, p.taker, 400_000)],
+      [p.taker]
+    );
+    assert.equal(made.name, "offermade");
+    assert.equal(made.data.amountB.toNumber(), 400_000);
+    assert.equal(made.data.remaining.toNumber(), 1_000_000);
+    assert.equal(await getTokenBalance(taker.offerVault), 400_000);
+    assert.equal(await getTokenBalance(p.takerAtaB), 0);
+    sendTransaction([await makeOffer(p, rival.taker, 350_000)], [rival.taker]);
+
+    const rent =
+      svm.getAccount(taker.offer).lamports +
+      svm.getAccount(taker.offerVault).lamports;
+    const takerLamportsBefore = svm.getBalance(p.taker.publicKey);
+    const [accepted] = sendTransactionEvents(
+      [await acceptOffer(p, p.taker.publicKey)],
+      [p.maker]
+    );
+    assert.equal(accepted.name, "offeraccepted");
+    assert.equal(accepted.data.amountA.toNumber(), 1_000_000);
+    assert.equal(accepted.data.amountB.toNumber(), 400_000);
+    assert.equal(accepted.data.fee.toNumber(), feeOf(400_000));
+    assert.equal(await getTokenBalance(p.takerAtaA), 1_000_000);
+    assert.equal(await getTokenBalance(p.makerAtaB), 400_000 - feeOf(400_000));
+    assert.ok(isClosed(p.escrow), "Escrow should be closed once accepted");
+    assert.ok(isClosed(p.vault), "Vault should be closed once accepted");
+    assert.ok(isClosed(taker.offer), "Offer should be closed");
+    assert.ok(isClosed(taker.offerVault), "Offer vault should be closed");
+    assert.equal(
+      Number(svm.getBalance(p.taker.publicKey) - takerLamportsBefore),
+      rent
+    );
+
+    // the losing bid stays refundable after the escrow is gone
+    const [cancelled] = sendTransactionEvents(
+      [await cancelOffer(p, rival.taker)],
+      [rival.taker]
+    );
+    assert.equal(cancelled.name, "offercancelled");
+    assert.equal(cancelled.data.amountB.toNumber(), 350_000);
+    assert.equal(await getTokenBalance(rival.takerAtaB), 350_000);
+    assert.ok(isClosed(offerAccounts(p, rival.taker.publicKey).offer));
+  });
+
+  it("Offers can't be accepted once cancelled or outdated", async () => {
+    const p = setupEscrowParties(1_000_000, 1_000_000);
+    const rival = setupExtraTaker(p, 600_000);
+    const makeIx = await getProgram(p.maker)
+      .methods.make(p.seed, new BN(1_000_000), new BN(1_000_000), makeOptions())
+      .accountsPartial(makeAccounts(p))
+      .instruction();
+    sendTransaction([makeIx], [p.maker]);
+
+    sendTransactionExpectError(
+      [await makeOffer(p, rival.taker, 700_000)],
+      [rival.taker],
+      "InsufficientTakerFunds"
+    );
+    sendTransaction([await makeOffer(p, rival.taker, 600_000)], [rival.taker]);
+    sendTransaction([await cancelOffer(p, rival.taker)], [rival.taker]);
+    sendTransactionExpectError(
+      [await acceptOffer(p, rival.taker.publicKey)],
+      [p.maker],
+      "AccountNotInitialized"
+    );
+    assert.equal(await getTokenBalance(rival.takerAtaB), 600_000);
+
+    // a partial take after the bid changes what it would buy
+    sendTransaction([await makeOffer(p, rival.taker, 600_000)], [rival.taker]);
+    const takeIx = await getProgram(p.taker)
+      .methods.take(new BN(500_000), ANY_PRICE, NO_BOUND, NO_BOUND)
+      .accountsPartial(takeAccounts(p))
+      .instruction();
+    sendTransaction([takeIx], [p.taker]);
+    sendTransactionExpectError(
+      [await acceptOffer(p, rival.taker.publicKey)],
+      [p.maker],
+      "OfferOutdated"
+    );
+    sendTransaction([await cancelOffer(p, rival.taker)], [rival.taker]);
+    assert.equal(await getTokenBalance(rival.takerAtaB), 600_000);
+    assert.equal(await getTokenBalance(p.vault), 500_000);
+  });
+
+  it("Transfer fee mint B is charged to the maker or grossed up", async () => {
+    // 1% capped at 2_000, the maker's share hits the cap
+    const transferFeeB = { bps: 100, max: BigInt(2_000) };
+    const takeWith = async (grossUpTransferFee: boolean, balance: number) => {
+      const p = setupEscrowParties(1_000_000, balance, {
+        tokenProgramB: TOKEN_2022_PROGRAM_ID,
+        transferFeeB,
+      });
+      const makeIx = await getProgram(p.maker)
+        .methods.make(
+          p.seed,
+          new BN(500_000),
+          new BN(1_000_000),
+          makeOptions({ grossUpTransferFee })
+        )
+        .accountsPartial(makeAccounts(p))
+        .instruction();
+      sendTransaction([makeIx], [p.maker]);
+
+      const takeIx = await getProgram(p.taker)
+        .methods.take(new BN(1_000_000), ANY_PRICE, NO_BOUND, NO_BOUND)
+        .accountsPartial(takeAccounts(p))
+        .instruction();
+      sendTransaction([takeIx], [p.taker]);
+      assert.equal(await getTokenBalance(p.takerAtaA), 1_000_000);
+      return p;
+    };
+
+    const fee = feeOf(500_000);
+    const share = 500_000 - fee;
+    // the collector's own transfer is charged 1% in both modes
+    const collectorFee = fee - Math.floor(fee / 100);
+
+    const collected = (p: EscrowParties) =>
+      getTokenBalance(feeCollectorAta(p.mintB, p.tokenProgramB));
+
+    const charged = await takeWith(false, 500_000);
+    assert.equal(await getTokenBalance(charged.makerAtaB), share - 2_000);
+    assert.equal(await getTokenBalance(charged.takerAtaB), 0);
+    assert.equal(await collected(charged), collectorFee);
+
+    // the taker sends share + 2_000 so the maker nets the whole share
+    const grossedUp = await takeWith(true, 502_000);
+    assert.equal(await getTokenBalance(grossedUp.makerAtaB), share);
+    assert.equal(await getTokenBalance(grossedUp.takerAtaB), 0);
+    assert.equal(await collected(grossedUp), collectorFee);
+  });
+
+  it("Transfer hook mints run the hook on make, take and refund", async () => {
+    const setup = (transferHookA = hookProgramId) =>
+      setupEscrowParties(1_000_000, 500_000, {
+        tokenProgramA: TOKEN_2022_PROGRAM_ID,
+        transferHookA,
+      });
+    const make = (p: EscrowParties) =>
+      getProgram(p.maker)
+        .methods.make(p.seed, new BN(500_000), new BN(1_000_000), makeOptions())
+        .accountsPartial(makeAccounts(p))
+        .remainingAccounts(hookRemainingAccounts(p.mintA))
+        .instruction();
+
+    const taken = setup();
+    // without the hook accounts the extra account meta list can't be found
+    // (TransferHookError::IncorrectAccount)
+    const bareMakeIx = await getProgram(taken.maker)
+      .methods.make(
+        taken.seed,
+        new BN(500_000),
+        new BN(1_000_000),
+        makeOptions()
+      )
+      .accountsPartial(makeAccounts(taken))
+      .instruction();
+    sendTransactionExpectError([bareMakeIx], [taken.maker], "0x7dc8348c");
+    assert.equal(hookCount(taken.mintA), 0);
+
+    sendTransaction([await make(taken)], [taken.maker]);
+    assert.equal(hookCount(taken.mintA), 1);
+    assert.equal(await getTokenBalance(taken.vault), 1_000_000);
+
+    const takeIx = await getProgram(taken.taker)
+      .methods.take(new BN(1_000_000), ANY_PRICE, NO_BOUND, NO_BOUND)
+      .accountsPartial(takeAccounts(taken))
+      .remainingAccounts(hookRemainingAccounts(taken.mintA))
+      .instruction();
+    sendTransaction([takeIx], [taken.taker]);
+    assert.equal(hookCount(taken.mintA), 2);
+    assert.equal(await getTokenBalance(taken.takerAtaA), 1_000_000);
+
+    const refunded = setup();
+    sendTransaction([await make(refunded)], [refunded.maker]);
+    const refundIx = await getProgram(refunded.maker)
+      .methods.refund()
+      .accountsPartial(refundAccounts(refunded))
+      .remainingAccounts(hookRemainingAccounts(refunded.mintA))
+      .instruction();
+    sendTransaction([refundIx], [refunded.maker]);
+    assert.equal(hookCount(refunded.mintA), 2);
+    assert.equal(await getTokenBalance(refunded.makerAtaA), 1_000_000);
+
+    // a hook pointing back at the escrow is rejected before any transfer
+    const reentrant = setup(programId);
+    sendTransactionExpectError(
+      [await make(reentrant)],
+      [reentrant.maker],
+      "ReentrantTransferHook"
+    );
+  });
+
+  it("Take alt settles in mint B or the alternative mint C", async () => {
+    const p = setupEscrowParties(1_000_000, 500_000);
+    // a plain mint with `amount` held by the taker
+    const takerMint = (amount: number) => {
+      const mint = Keypair.generate();
+      const takerAta = getAssociatedTokenAddressSync(
+        mint.publicKey,
+        p.taker.publicKey
+      );
+      sendTransaction(
+        [
+          SystemProgram.createAccount({
+            fromPubkey: payer.publicKey,
+            newAccountPubkey: mint.publicKey,
+            lamports: Number(
+              svm.minimumBalanceForRentExemption(BigInt(MINT_SIZE))
+            ),
+            space: MINT_SIZE,
+            programId: TOKEN_PROGRAM_ID,
+          }),
+          createInitializeMint2Instruction(
+            mint.publicKey,
+            6,
+            payer.publicKey,
+            null
+          ),
+          createAssociatedTokenAccountIdempotentInstruction(
+            payer.publicKey,
+            takerAta,
+            p.taker.publicKey,
+            mint.publicKey
+          ),
+          createMintToInstruction(
+            mint.publicKey,
+            takerAta,
+            payer.publicKey,
+            amount
+          ),
+        ],
+        [mint]
+      );
+      return mint.publicKey;
+    };
+    const mintC = takerMint(300_000);
+    const mintD = takerMint(300_000);
+
+    const makeIx = await getProgram(p.maker)
+      .methods.make(
+        p.seed,
+        new BN(500_000),
+        new BN(1_000_000),
+        makeOptions({ mintC, receiveC: new BN(300_000) })
+      )
+      .accountsPartial({ ...makeAccounts(p), mintC })
+      .instruction();
+    sendTransaction([makeIx], [p.maker]);
+
+    const payIn = (mint: PublicKey) => ({
+      ...takeAccounts(p),
+      mintB: mint,
+      feeCollectorAtaB: feeCollectorAta(mint),
+    });
+    const takerProgram = getProgram(p.taker);
+    // a plain take still has to pay in mint B
+    const takeInC = await takerProgram.methods
+      .take(new BN(1_000_000), ANY_PRICE, NO_BOUND, NO_BOUND)
+      .accountsPartial(payIn(mintC))
+      .instruction();
+    sendTransactionExpectError([takeInC], [p.taker], "UnsupportedPaymentMint");
+    const takeAltInD = await takerProgram.methods
+      .takeAlt()
+      .accountsPartial(payIn(mintD))
+      .instruction();
+    sendTransactionExpectError(
+      [takeAltInD],
+      [p.taker],
+      "UnsupportedPaymentMint"
+    );
+
+    const takeAltInC = await takerProgram.methods
+      .takeAlt()
+      .accountsPartial(payIn(mintC))
+      .instruction();
+    const [taken] = sendTransactionEvents([takeAltInC], [p.taker]);
+    assert.ok(taken.data.mintB.equals(mintC));
+    assert.equal(taken.data.amountB.toNumber(), 300_000);
+
+    const makerAtaC = getAssociatedTokenAddressSync(mintC, p.maker.publicKey);
+    assert.equal(await getTokenBalance(p.takerAtaA), 1_000_000);
+    assert.equal(await getTokenBalance(makerAtaC), 300_000 - feeOf(300_000));
+    assert.equal(await getTokenBalance(feeCollectorAta(mintC)), feeOf(300_000));
+    assert.equal(await getTokenBalance(p.makerAtaB), 0);
+    assert.ok(isClosed(p.escrow), "Escrow should be closed after take alt");
+  });
+
+  it("Failed maker payment leaves the vault and escrow intact", async () => {
+    const p = setupEscrowParties(1_000_000, 500_000);
+
+    const makeIx = await getProgram(p.maker)
+      .methods.make(p.seed, new BN(500_000), new BN(1_000_000), makeOptions())
+      .accountsPartial(makeAccounts(p))
+      .instruction();
+    sendTransaction(
+      [
+        makeIx,
+        createFreezeAccountInstruction(p.makerAtaB, p.mintB, payer.publicKey),
+      ],
+      [p.maker]
+    );
+    const escrowBefore = Buffer.from(svm.getAccount(p.escrow).data);
+
+    // the frozen maker account is named before any transfer is attempted
+    const takeIx = await getProgram(p.taker)
+      .methods.take(new BN(1_000_000), ANY_PRICE, NO_BOUND, NO_BOUND)
+      .accountsPartial(takeAccounts(p))
+      .instruction();
+    sendTransactionExpectError([takeIx], [p.taker], "AccountFrozen");
+
+    assert.equal(await getTokenBalance(p.vault), 1_000_000);
+    assert.equal(await getTokenBalance(p.takerAtaA), 0);
+    assert.equal(await getTokenBalance(p.takerAtaB), 500_000);
+    assert.ok(
+      Buffer.from(svm.getAccount(p.escrow).data).equals(escrowBefore),
+      "Escrow should be unchanged"
+    );
+  });
+
+  it("Take names a frozen taker account before paying", async () => {
+    const p = setupEscrowParties(1_000_000, 500_000);
+    const makeIx = await getProgram(p.maker)
+      .methods.make(p.seed, new BN(500_000), new BN(1_000_000), makeOptions())
+      .accountsPartial(makeAccounts(p))
+      .instruction();
+    const freezeIx = (account: PublicKey, mint: PublicKey) =>
+      createFreezeAccountInstruction(account, mint, payer.publicKey);
+    sendTransaction([makeIx, freezeIx(p.takerAtaA, p.mintA)], [p.maker]);
+
+    const takeIx = await getProgram(p.taker)
+      .methods.take(new BN(1_000_000), ANY_PRICE, NO_BOUND, NO_BOUND)
+      .accountsPartial(takeAccounts(p))
+      .instruction();
+    sendTransactionExpectError([takeIx], [p.taker], "AccountFrozen");
+    assert.equal(await getTokenBalance(p.takerAtaB), 500_000);
+
+    sendTransaction(
+      [
+        createThawAccountInstruction(p.takerAtaA, p.mintA, payer.publicKey),
+        freezeIx(p.makerAtaB, p.mintB),
+      ],
+      []
+    );
+    svm.expireBlockhash();
+    sendTransactionExpectError([takeIx], [p.taker], "AccountFrozen");
+
+    sendTransaction(
+      [createThawAccountInstruction(p.makerAtaB, p.mintB, payer.publicKey)],
+      []
+    );
+    svm.expireBlockhash();
+    sendTransaction([takeIx], [p.taker]);
+    assert.equal(await getTokenBalance(p.takerAtaA), 1_000_000);
+  });
+
+  it("Mints with extensions that break the escrow are rejected", async () => {
+    const nonTransferable: MintExtension = {
+      type: ExtensionType.NonTransferable,
+      init: (mint) =>
+        createInitializeNonTransferableMintInstruction(
+          mint,
+          TOKEN_2022_PROGRAM_ID
+        ),
+    };
+    const permanentDelegate: MintExtension = {
+      type: ExtensionType.PermanentDelegate,
+      init: (mint) =>
+        createInitializePermanentDelegateInstruction(
+          mint,
+          payer.publicKey,
+          TOKEN_2022_PROGRAM_ID
+        ),
+    };
+    // spl-token has no builder for this one. ConfidentialTransferExtension
+    // (27) InitializeMint (0) with no authority, no auto approve and no
+    // auditor
+    const confidentialTransfer: MintExtension = {
+      type: ExtensionType.ConfidentialTransferMint,
+      init: (mint) =>
+        new TransactionInstruction({
+          programId: TOKEN_2022_PROGRAM_ID,
+          keys: [{ pubkey: mint, isSigner: false, isWritable: true }],
+          data: Buffer.concat([Buffer.from([27, 0]), Buffer.alloc(65)]),
+        }),
+    };
+
+    const cases: [Record<string, MintExtension[]>, string][] = [
+      [{ extensionsA: [nonTransferable] }, "NonTransferableMint"],
+      [{ extensionsA: [permanentDelegate] }, "PermanentDelegateMint"],
+      [{ extensionsB: [permanentDelegate] }, "PermanentDelegateMint"],
+      [{ extensionsA: [confidentialTransfer] }, "ConfidentialTransferMint"],
+    ];
+    for (const [extensions, errorName] of cases) {
+      const p = setupEscrowParties(1_000_000, 500_000, {
+        tokenProgram: TOKEN_2022_PROGRAM_ID,
+        ...extensions,
+      });
+      const makeIx = await getProgram(p.maker)
+        .methods.make(p.seed, new BN(500_000), new BN(1_000_000), makeOptions())
+        .accountsPartial(makeAccounts(p))
+        .instruction();
+      sendTransactionExpectError([makeIx], [p.maker], errorName);
+      assert.ok(isClosed(p.escrow), "Escrow should not be created");
+    }
+
+    // mint C goes through the same check, and has to be passed to make
+    const p = setupEscrowParties(1_000_000, 500_000);
+    const mintC = setupEscrowParties(1, 1, {
+      tokenProgram: TOKEN_2022_PROGRAM_ID,
+      extensionsB: [permanentDelegate],
+    }).mintB;
+    const makeInC = (accounts: { mintC: PublicKey | null }) =>
+      getProgram(p.maker)
+        .methods.make(
+          p.seed,
+          new BN(500_000),
+          new BN(1_000_000),
+          makeOptions({ mintC, receiveC: new BN(300_000) })
+        )
+        .accountsPartial({ ...makeAccounts(p), ...accounts })
+        .instruction();
+    sendTransactionExpectError(
+      [await makeInC({ mintC })],
+      [p.maker],
+      "PermanentDelegateMint"
+    );
+    sendTransactionExpectError(
+      [await makeInC({ mintC: null })],
+      [p.maker],
+      "MintCMismatch"
+    );
+    assert.ok(isClosed(p.escrow), "Escrow should not be created");
+
+    // and so does a new mint B
+    const makeIx = await getProgram(p.maker)
+      .methods.make(p.seed, new BN(500_000), new BN(1_000_000), makeOptions())
+      .accountsPartial(makeAccounts(p))
+      .instruction();
+    sendTransaction([makeIx], [p.maker]);
+    const updateIx = await getProgram(p.maker)
+      .methods.updateMintB()
+      .accountsPartial({
+        maker: p.maker.publicKey,
+        escrow: p.escrow,
+        newMintB: mintC,
+      })
+      .instruction();
+    sendTransactionExpectError([updateIx], [p.maker], "PermanentDelegateMint");
+  });
+
+  it("Take native pays a wrapped SOL escrow in lamports", async () => {
+    setupNativeMint();
+    const receive = 2 * LAMPORTS_PER_SOL;
+    const tokens = setupEscrowParties(1_000_000, receive);
+    const p = { ...tokens, mintB: NATIVE_MINT };
+
+    // receive_sol needs mint B to be wrapped SOL
+    const makeInTokens = await getProgram(tokens.maker)
+      .methods.make(
+        tokens.seed,
+        new BN(receive),
+        new BN(1_000_000),
+        makeOptions({ receiveSol: true })
+      )
+      .accountsPartial(makeAccounts(tokens))
+      .instruction();
+    sendTransactionExpectError(
+      [makeInTokens],
+      [tokens.maker],
+      "MintBNotNative"
+    );
+
+    const makeIx = await getProgram(p.maker)
+      .methods.make(
+        p.seed,
+        new BN(receive),
+        new BN(1_000_000),
+        makeOptions({ receiveSol: true })
+      )
+      .accountsPartial(makeAccounts(p))
+      .instruction();
+    sendTransaction([makeIx], [p.maker]);
+
+    const takeNative = (
+      taker: Keypair,
+      collector: PublicKey | null = feeCollector.publicKey
+    ) =>
+      getProgram(taker)
+        .methods.takeNative()
+        .accountsPartial({
+          taker: taker.publicKey,
+          maker: p.maker.publicKey,
+          mintA: p.mintA,
+          takerAtaA: getAssociatedTokenAddressSync(p.mintA, taker.publicKey),
+          escrow: p.escrow,
+          makerIndex: makerIndexOf(p.maker.publicKey),
+          stats,
+          vault: p.vault,
+          makerAtaA: null,
+          config,
+          feeCollector: collector,
+          rentRecipient: null,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          tokenProgramA: p.tokenProgramA,
+          systemProgram: SystemProgram.programId,
+        })
+        .instruction();
+
+    // the fee collector can only be left out while there is no fee
+    sendTransactionExpectError(
+      [await takeNative(p.taker, null)],
+      [p.taker],
+      "MissingFeeAccount"
+    );
+
+    // the system program's ResultWithNegativeLamports
+    const poorTaker = Keypair.generate();
+    svm.airdrop(poorTaker.publicKey, BigInt(LAMPORTS_PER_SOL));
+    sendTransactionExpectError(
+      [await takeNative(poorTaker)],
+      [poorTaker],
+      "custom program error: 0x1"
+    );
+    assert.equal(await getTokenBalance(p.vault), 1_000_000);
+
+    // hand the maker to a program, as if it were a PDA, lamports still land
+    const makerAccount = svm.getAccount(p.maker.publicKey);
+    svm.setAccount(p.maker.publicKey, {
+      ...makerAccount,
+      data: Buffer.alloc(8),
+      owner: hookProgramId,
+    });
+    const makerBefore = Number(svm.getBalance(p.maker.publicKey));
+    const closedRent =
+      Number(svm.getBalance(p.vault)) + Number(svm.getBalance(p.escrow));
+
+    const [taken] = sendTransactionEvents(
+      [await takeNative(p.taker)],
+      [p.taker]
+    );
+    assert.ok(taken.data.mintB.equals(NATIVE_MINT));
+    assert.equal(taken.data.amountB.toNumber(), receive);
+
+    assert.equal(
+      Number(svm.getBalance(p.maker.publicKey)) - makerBefore,
+      receive - feeOf(receive) + closedRent
+    );
+    assert.equal(await getTokenBalance(p.takerAtaA), 1_000_000);
+    assert.ok(isClosed(p.vault), "Vault should be closed after take native");
+    assert.ok(isClosed(p.escrow), "Escrow should be closed after take native");
+  });
+
+  it("Refund waits for the refund lock to end", async () => {
+    const p = setupEscrowParties(1_000_000, 500_000);
+    const makerProgram = getProgram(p.maker);
+    const lockedUntil = svm.getClock().unixTimestamp + BigInt(60);
+
+    const makeIx = await makerProgram.methods
+      .make(
+        p.seed,
+        new BN(500_000),
+        new BN(1_000_000),
+        makeOptions({ refundLockedUntil: new BN(lockedUntil.toString()) })
+      )
+      .accountsPartial(makeAccounts(p))
+      .instruction();
+    sendTransaction([makeIx], [p.maker]);
+
+    const refundIx = await makerProgram.methods
+      .refund()
+      .accountsPartial(refundAccounts(p))
+      .instruction();
+    sendTransactionExpectError([refundIx], [p.maker], "RefundLocked");
+    assert.equal(await getTokenBalance(p.vault), 1_000_000);
+
+    // boundary: unix_timestamp == refund_locked_until is already unlocked
+    setUnixTimestamp(lockedUntil);
+    svm.expireBlockhash();
+    sendTransaction([refundIx], [p.maker]);
+
+    assert.equal(await getTokenBalance(p.makerAtaA), 1_000_000);
+    assert.ok(isClosed(p.escrow), "Escrow should be closed after refund");
+  });
+
+  it("Make with SOL wraps lamports into the vault", async () => {
+    setupNativeMint();
+    const tokens = setupEscrowParties(1, 500_000);
+    const p = {
+      ...tokens,
+      mintA: NATIVE_MINT,
+      vault: getAssociatedTokenAddressSync(NATIVE_MINT, tokens.escrow, true),
+    };
+    const lamports = LAMPORTS_PER_SOL;
+    const vaultRent = Number(svm.minimumBalanceForRentExemption(BigInt(165)));
+    const makerProgram = getProgram(p.maker);
+
+    const makeIx = await makerProgram.methods
+      .makeWithSol(p.seed, new BN(500_000), new BN(lamports), makeOptions())
+      .accountsPartial(makeAccounts(p))
+      .instruction();
+    const makerBefore = Number(svm.getBalance(p.maker.publicKey));
+    sendTransaction([makeIx], [p.maker]);
+
+    // the vault keeps exactly its rent exempt minimum on top of the deposit
+    assert.equal(Number(svm.getBalance(p.vault)), vaultRent + lamports);
+    assert.equal(await getTokenBalance(p.vault), lamports);
+
+    // refund unwraps into the maker's wallet, no wSOL account needed
+    const refundIx = await makerProgram.methods
+      .refund()
+      .accountsPartial({ ...refundAccounts(p), makerAtaA: null })
+      .instruction();
+    sendTransaction([refundIx], [p.maker]);
+    assert.equal(Number(svm.getBalance(p.maker.publicKey)), makerBefore);
+    assert.ok(isClosed(p.vault), "Vault should be closed after refund");
+    assert.ok(isClosed(p.escrow), "Escrow should be closed after refund");
+
+    // other mints still need the maker's token account
+    const makeTokensIx = await makerProgram.methods
+      .make(p.seed, new BN(500_000), new BN(1), makeOptions())
+      .accountsPartial(makeAccounts(tokens))
+      .instruction();
+    sendTransaction([makeTokensIx], [p.maker]);
+    const refundTokensIx = await makerProgram.methods
+      .refund()
+      .accountsPartial({ ...refundAccounts(tokens), makerAtaA: null })
+      .instruction();
+    sendTransactionExpectError(
+      [refundTokensIx],
+      [p.maker],
+      "MakerAtaRequired"
+    );
+  });
+
+  it("Make batch creates and funds several escrows at once", async () => {
+    const p = setupEscrowParties(600_000, 500_000);
+    const seeds = [1, 2, 3].map((i) => p.seed.addn(i));
+    const receives = [100_000, 200_000, 300_000].map((n) => new BN(n));
+    const deposits = [100_000, 200_000, 300_000].map((n) => new BN(n));
+    const batch = seeds.map((seed) => {
+      const [escrow] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("escrow"),
+          p.maker.publicKey.toBuffer(),
+          seed.toArrayLike(Buffer, "le", 8),
+        ],
+        programId
+      );
+      const vault = getAssociatedTokenAddressSync(p.mintA, escrow, true);
+      return { escrow, vault };
+    });
+    const remainingAccounts = batch.flatMap(({ escrow, vault }) =>
+      [escrow, vault].map((pubkey) => ({
+        pubkey,
+        isSigner: false,
+        isWritable: true,
+      }))
+    );
+    const makeBatch = (receives: BN[], batchSeeds = seeds) =>
+      getProgram(p.maker)
+        .methods.makeBatch(batchSeeds, receives, deposits)
+        .accountsPartial({
+          maker: p.maker.publicKey,
+          config,
+          mintA: p.mintA,
+          mintB: p.mintB,
+          makerAtaA: p.makerAtaA,
+          makerIndex: makerIndexOf(p.maker.publicKey),
+          stats,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          tokenProgramA: p.tokenProgramA,
+          tokenProgramB: p.tokenProgramB,
+          systemProgram: SystemProgram.programId,
+        })
+        .remainingAccounts(remainingAccounts)
+        .instruction();
+
+    sendTransactionExpectError(
+      [await makeBatch(receives.slice(1))],
+      [p.maker],
+      "BatchLengthMismatch"
+    );
+    sendTransactionExpectError(
+      [await makeBatch(receives, [seeds[0], seeds[0], seeds[2]])],
+      [p.maker],
+      "DuplicateBatchSeed"
+    );
+
+    // lamports sent to an escrow address up front don't block its creation
+    svm.airdrop(batch[0].escrow, BigInt(1_000));
+
+    // every escrow costs an ATA creation and a transfer, well past the default
+    const computeIx = ComputeBudgetProgram.setComputeUnitLimit({
+      units: 600_000,
+    });
+    const events = sendTransactionEvents(
+      [computeIx, await makeBatch(receives)],
+      [p.maker]
+    );
+    assert.deepEqual(
+      events.map((event) => [event.name, event.data.seed.toNumber()]),
+      seeds.map((seed) => ["escrowmade", seed.toNumber()])
+    );
+    for (const [i, { vault }] of batch.entries()) {
+      assert.equal(await getTokenBalance(vault), deposits[i].toNumber());
+    }
+    assert.equal(await getTokenBalance(p.makerAtaA), 0);
+
+    // each one is an ordinary escrow
+    const takeIx = await getProgram(p.taker)
+      .methods.take(deposits[1], ANY_PRICE, NO_BOUND, NO_BOUND)
+      .accountsPartial({ ...takeAccounts(p), ...batch[1] })
+      .instruction();
+    sendTransaction([takeIx], [p.taker]);
+    assert.equal(await getTokenBalance(p.takerAtaA), 200_000);
+    assert.ok(isClosed(batch[1].escrow), "Taken escrow should be closed");
+    assert.equal(await getTokenBalance(batch[2].vault), 300_000);
+    // the batch listed every escrow, the take dropped its own
+    const listed = getProgram(p.maker).coder.accounts.decode(
+      "makerIndex",
+      Buffer.from(svm.getAccount(makerIndexOf(p.maker.publicKey)).data)
+    );
+    assert.deepEqual(
+      listed.entries.map((entry: { seed: BN }) => entry.seed.toNumber()),
+      [seeds[0], seeds[2]].map((seed) => seed.toNumber())
+    );
+  });
+
+  it("Make batch of MAX_BATCH escrows fits one transaction", async () => {
+    const p = setupEscrowParties(1_000_000, 500_000);
+    const seeds = [...Array(MAX_BATCH + 1).keys()].map((i) => p.seed.addn(i));
+    const makeBatch = (batchSeeds: BN[]) => {
+      const amounts = batchSeeds.map(() => new BN(100_000));
+      return getProgram(p.maker)
+        .methods.makeBatch(batchSeeds, amounts, amounts)
+        .accountsPartial({
+          maker: p.maker.publicKey,
+          config,
+          mintA: p.mintA,
+          mintB: p.mintB,
+          makerAtaA: p.makerAtaA,
+          makerIndex: makerIndexOf(p.maker.publicKey),
+          stats,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          tokenProgramA: p.tokenProgramA,
+          tokenProgramB: p.tokenProgramB,
+          systemProgram: SystemProgram.programId,
+        })
+        .remainingAccounts(
+          batchSeeds.flatMap((seed) => {
+            const [escrow] = PublicKey.findProgramAddressSync(
+              [
+                Buffer.from("escrow"),
+                p.maker.publicKey.toBuffer(),
+                seed.toArrayLike(Buffer, "le", 8),
+              ],
+              programId
+            );
+            const vault = getAssociatedTokenAddressSync(p.mintA, escrow, true);
+            return [escrow, vault].map((pubkey) => ({
+              pubkey,
+              isSigner: false,
+              isWritable: true,
+            }));
+          })
+        )
+        .instruction();
+    };
+    const computeIx = ComputeBudgetProgram.setComputeUnitLimit({
+      units: 1_400_000,
+    });
+
+    const one = sendTransactionUnits(
+      [computeIx, await makeBatch(seeds.slice(0, 1))],
+      [p.maker]
+    );
+    const full = sendTransactionUnits(
+      [computeIx, await makeBatch(seeds.slice(1))],
+      [p.maker]
+    );
+    const perEscrow = (full - one) / (MAX_BATCH - 1);
+    console.log(`make_batch: ${perEscrow} compute units per escrow`);
+    assert.isAtMost(full, 1_400_000);
+    assert.equal(await getTokenBalance(p.makerAtaA), 100_000);
+  });
+
+  it("Take batch fills escrows across makers and mints", async () => {
+    const p = setupEscrowParties(1_000_000, 1_000_000);
+    const other = setupEscrowParties(1_000_000, 0);
+    // p's taker also takes other's escrow
+    const takerAtaA3 = getAssociatedTokenAddressSync(
+      other.mintA,
+      p.taker.publicKey
+    );
+    const takerAtaB3 = getAssociatedTokenAddressSync(
+      other.mintB,
+      p.taker.publicKey
+    );
+    sendTransaction(
+      [
+        createAssociatedTokenAccountIdempotentInstruction(
+          payer.publicKey,
+          takerAtaA3,
+          p.taker.publicKey,
+          other.mintA
+        ),
+        createAssociatedTokenAccountIdempotentInstruction(
+          payer.publicKey,
+          takerAtaB3,
+          p.taker.publicKey,
+          other.mintB
+        ),
+        createMintToInstruction(
+          other.mintB,
+          takerAtaB3,
+          payer.publicKey,
+          700_000
+        ),
+        ...[p.mintB, other.mintB].map((mint) =>
+          createAssociatedTokenAccountIdempotentInstruction(
+            payer.publicKey,
+            feeCollectorAta(mint),
+            feeCollector.publicKey,
+            mint
+          )
+        ),
+      ],
+      []
+    );
+
+    for (const [parties, receive] of [
+      [p, 500_000],
+      [other, 700_000],
+    ] as [EscrowParties, number][]) {
+      const makeIx = await getProgram(parties.maker)
+        .methods.make(
+          parties.seed,
+          new BN(receive),
+          new BN(1_000_000),
+          makeOptions()
+        )
+        .accountsPartial(makeAccounts(parties))
+        .instruction();
+      sendTransaction([makeIx], [parties.maker]);
+    }
+    // dust sent to a vault from outside doesn't block the batch
+    const vault = svm.getAccount(other.vault);
+    const data = Buffer.from(vault.data);
+    data.writeBigUInt64LE(BigInt(1_000_007), 64);
+    svm.setAccount(other.vault, { ...vault, data });
+
+    // [escrow, vault, maker, mint_a, mint_b, maker_ata_a, maker_ata_b,
+    // taker_ata_a, taker_ata_b, fee_collector_ata_b, rent_recipient,
+    // maker_index] per escrow, the mints are read only. without a rent
+    // recipient the maker stands in for it
+    const leg = (
+      parties: EscrowParties,
+      takerAtaA: PublicKey,
+      takerAtaB: PublicKey
+    ) =>
+      [
+        parties.escrow,
+        parties.vault,
+        parties.maker.publicKey,
+        parties.mintA,
+        parties.mintB,
+        parties.makerAtaA,
+        parties.makerAtaB,
+        takerAtaA,
+        takerAtaB,
+        feeCollectorAta(parties.mintB),
+        parties.maker.publicKey,
+        makerIndexOf(parties.maker.publicKey),
+      ].map((pubkey, i) => ({
+        pubkey,
+        isSigner: false,
+        isWritable: i !== 3 && i !== 4,
+      }));
+    const takeBatch = (maxPayB: number[]) =>
+      getProgram(p.taker)
+        .methods.takeBatch(
+          [400_000, 1_000_000].map((n) => new BN(n)),
+          maxPayB.map((n) => new BN(n))
+        )
+        .accountsPartial({
+          taker: p.taker.publicKey,
+          config,
+          stats,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts([
+          ...leg(p, p.takerAtaA, p.takerAtaB),
+          ...leg(other, takerAtaA3, takerAtaB3),
+        ])
+        .instruction();
+    const computeIx = ComputeBudgetProgram.setComputeUnitLimit({
+      units: 600_000,
+    });
+
+    // the last leg's bound fails the whole batch, the first one included
+    sendTransactionExpectError(
+      [computeIx, await takeBatch([0, 600_000])],
+      [p.taker],
+      "SlippageExceeded"
+    );
+    assert.equal(await getTokenBalance(p.vault), 1_000_000);
+    assert.equal(await getTokenBalance(p.takerAtaB), 1_000_000);
+
+    const taken = () =>
+      getProgram(payer)
+        .coder.accounts.decode("stats", Buffer.from(svm.getAccount(stats).data))
+        .escrowsTaken.toNumber();
+    const takenBefore = taken();
+    const events = sendTransactionEvents(
+      [computeIx, await takeBatch([200_000, 700_000])],
+      [p.taker]
+    );
+    assert.equal(events.pop().name, "statsupdated");
+    assert.deepEqual(
+      events.map((event) => [
+        event.name,
+        event.data.amountA.toNumber(),
+        event.data.amountB.toNumber(),
+        event.data.remaining.toNumber(),
+      ]),
+      [
+        ["escrowtaken", 400_000, 200_000, 600_000],
+        ["escrowtaken", 1_000_000, 700_000, 0],
+      ]
+    );
+    assert.equal(await getTokenBalance(p.takerAtaA), 400_000);
+    assert.equal(await getTokenBalance(p.takerAtaB), 800_000);
+    assert.equal(await getTokenBalance(p.makerAtaB), 200_000 - feeOf(200_000));
+    assert.equal(await getTokenBalance(takerAtaA3), 1_000_000);
+    assert.equal(
+      await getTokenBalance(other.makerAtaB),
+      700_000 - feeOf(700_000)
+    );
+    assert.equal(await getTokenBalance(p.vault), 600_000);
+    // the dust goes back to the maker with the last fill
+    assert.equal(await getTokenBalance(other.makerAtaA), 7);
+    assert.ok(isClosed(other.escrow), "Fully taken escrow should be closed");
+    assert.equal(taken(), takenBefore + 1);
+    const listed = (parties: EscrowParties) =>
+      getProgram(payer)
+        .coder.accounts.decode(
+          "makerIndex",
+          Buffer.from(
+            svm.getAccount(makerIndexOf(parties.maker.publicKey)).data
+          )
+        )
+        .entries.map((entry: { seed: BN }) => entry.seed.toNumber());
+    // the partial fill stays listed
+    assert.deepEqual(listed(p), [p.seed.toNumber()]);
+    assert.deepEqual(listed(other), []);
+  });
+
+  it("Take unwraps a wrapped SOL payment into the maker's wallet", async () => {
+    setupNativeMint();
+    const tokens = setupEscrowParties(1_000_000, 1);
+    const wsol = (owner: PublicKey) =>
+      getAssociatedTokenAddressSync(NATIVE_MINT, owner);
+    const p = {
+      ...tokens,
+      mintB: NATIVE_MINT,
+      makerAtaB: wsol(tokens.maker.publicKey),
+      takerAtaB: wsol(tokens.taker.publicKey),
+    };
+    const receive = LAMPORTS_PER_SOL;
+    const [unwrapB] = PublicKey.findProgramAddressSync(
+      [Buffer.from("unwrap"), p.escrow.toBuffer()],
+      programId
+    );
+
+    const makeIx = await getProgram(p.maker)
+      .methods.make(p.seed, new BN(receive), new BN(1_000_000), makeOptions())
+      .accountsPartial(makeAccounts(p))
+      .instruction();
+    sendTransaction(
+      [
+        makeIx,
+        createAssociatedTokenAccountIdempotentInstruction(
+          payer.publicKey,
+          p.takerAtaB,
+          p.taker.publicKey,
+          NATIVE_MINT
+        ),
+        SystemProgram.transfer({
+          fromPubkey: p.taker.publicKey,
+          toPubkey: p.takerAtaB,
+          lamports: receive,
+        }),
+        createSyncNativeInstruction(p.takerAtaB),
+      ],
+      [p.maker, p.taker]
+    );
+    assert.isNull(svm.getAccount(p.makerAtaB));
+
+    // neither do lamports sent to the unwrap account's address
+    svm.airdrop(unwrapB, BigInt(1_000));
+
+    const makerBefore = Number(svm.getBalance(p.maker.publicKey));
+    const closedRent =
+      Number(svm.getBalance(p.vault)) + Number(svm.getBalance(p.escrow));
+    const takeIx = await getProgram(p.taker)
+      .methods.take(new BN(1_000_000), ANY_PRICE, NO_BOUND, NO_BOUND)
+      .accountsPartial({ ...takeAccounts(p), makerAtaB: null, unwrapB })
+      .instruction();
+    const [taken] = sendTransactionEvents([takeIx], [p.taker]);
+
+    const delivered = receive - feeOf(receive);
+    assert.equal(taken.data.lamports.toNumber(), delivered);
+    assert.equal(
+      Number(svm.getBalance(p.maker.publicKey)) - makerBefore,
+      delivered + closedRent
+    );
+    assert.isNull(svm.getAccount(p.makerAtaB));
+    assert.ok(isClosed(unwrapB), "Unwrap account should not outlive the take");
+    assert.equal(await getTokenBalance(p.takerAtaA), 1_000_000);
+
+    // only wrapped SOL can be unwrapped
+    const other = setupEscrowParties(1_000_000, 500_000);
+    const otherMakeIx = await getProgram(other.maker)
+      .methods.make(
+        other.seed,
+        new BN(500_000),
+        new BN(1_000_000),
+        makeOptions()
+      )
+      .accountsPartial(makeAccounts(other))
+      .instruction();
+    sendTransaction([otherMakeIx], [other.maker]);
+    const [otherUnwrapB] = PublicKey.findProgramAddressSync(
+      [Buffer.from("unwrap"), other.escrow.toBuffer()],
+      programId
+    );
+    const otherTakeIx = await getProgram(other.taker)
+      .methods.take(new BN(1_000_000), ANY_PRICE, NO_BOUND, NO_BOUND)
+      .accountsPartial({
+        ...takeAccounts(other),
+        makerAtaB: null,
+        unwrapB: otherUnwrapB,
+      })
+      .instruction();
+    sendTransactionExpectError([otherTakeIx], [other.taker], "MintBNotNative");
+  });
+
+  it("Escrows made with keep record stay behind with a status", async () => {
+    const p = setupEscrowParties(1_000_000, 500_000);
+    const makerProgram = getProgram(p.maker);
+    const record = () =>
+      makerProgram.coder.accounts.decode(
+        "escrow",
+        Buffer.from(svm.getAccount(p.escrow).data)
+      );
+    const status = () => Object.keys(record().status)[0];
+
+    const makeIx = await makerProgram.methods
+      .make(
+        p.seed,
+        new BN(500_000),
+        new BN(1_000_000),
+        makeOptions({ keepRecord: true })
+      )
+      .accountsPartial(makeAccounts(p))
+      .instruction();
+    sendTransaction([makeIx], [p.maker]);
+    assert.equal(status(), "open");
+    // no one settled an open escrow yet
+    assert.ok(record().counterparty.equals(PublicKey.default));
+
+    const closeRecordIx = await makerProgram.methods
+      .closeRecord()
+      .accountsPartial({ maker: p.maker.publicKey, escrow: p.escrow })
+      .instruction();
+    sendTransactionExpectError([closeRecordIx], [p.maker], "EscrowStillOpen");
+
+    const takeIx = await getProgram(p.taker)
+      .methods.take(new BN(1_000_000), ANY_PRICE, NO_BOUND, NO_BOUND)
+      .accountsPartial(takeAccounts(p))
+      .instruction();
+    sendTransaction([takeIx], [p.taker]);
+    assert.ok(isClosed(p.vault), "Vault should be closed after take");
+    assert.equal(status(), "completed");
+    assert.ok(record().counterparty.equals(p.taker.publicKey));
+    assert.equal(
+      record().settledAt.toString(),
+      svm.getClock().unixTimestamp.toString()
+    );
+
+    // the record is read only
+    const pauseIx = await makerProgram.methods
+      .setActive(false)
+      .accountsPartial({ maker: p.maker.publicKey, escrow: p.escrow })
+      .instruction();
+    sendTransactionExpectError([pauseIx], [p.maker], "EscrowNotOpen");
+    const quoteIx = await makerProgram.methods
+      .quote(p.taker.publicKey)
+      .accountsPartial({ escrow: p.escrow, vault: p.vault })
+      .instruction();
+    sendTransactionExpectError([quoteIx], [p.maker], "EscrowNotOpen");
+
+    svm.expireBlockhash();
+    sendTransaction([closeRecordIx], [p.maker]);
+    assert.ok(isClosed(p.escrow), "Record should be closed");
+  });
+
+  it("Take creates missing token accounts for both parties", async () => {
+    const p = setupEscrowParties(1_000_000, 500_000);
+
+    const makeIx = await getProgram(p.maker)
+      .methods.make(p.seed, new BN(500_000), new BN(1_000_000), makeOptions())
+      .accountsPartial(makeAccounts(p))
+      .instruction();
+    // both accounts are still empty, closing them leaves nothing behind
+    sendTransaction(
+      [
+        makeIx,
+        createCloseAccountInstruction(
+          p.makerAtaB,
+          p.maker.publicKey,
+          p.maker.publicKey
+        ),
+        createCloseAccountInstruction(
+          p.takerAtaA,
+          p.taker.publicKey,
+          p.taker.publicKey
+        ),
+      ],
+      [p.maker, p.taker]
+    );
+    assert.isNull(svm.getAccount(p.makerAtaB));
+    assert.isNull(svm.getAccount(p.takerAtaA));
+
+    // an existing account has to belong to the maker
+    const takeIntoTakerIx = await getProgram(p.taker)
+      .methods.take(new BN(1_000_000), ANY_PRICE, NO_BOUND, NO_BOUND)
+      .accountsPartial({ ...takeAccounts(p), makerAtaB: p.takerAtaB })
+      .instruction();
+    sendTransactionExpectError(
+      [takeIntoTakerIx],
+      [p.taker],
+      "ConstraintTokenOwner"
+    );
+
+    const takerBefore = svm.getBalance(p.taker.publicKey);
+    const takeIx = await getProgram(p.taker)
+      .methods.take(new BN(1_000_000), ANY_PRICE, NO_BOUND, NO_BOUND)
+      .accountsPartial(takeAccounts(p))
+      .instruction();
+    sendTransaction([takeIx], [p.taker]);
+
+    assert.equal(await getTokenBalance(p.takerAtaA), 1_000_000);
+    assert.equal(
+      await getTokenBalance(p.makerAtaB),
+      500_000 - feeOf(500_000)
+    );
+    // the taker paid the rent of both, and of the fee collector's new account
+    const ataRent = Number(svm.minimumBalanceForRentExemption(BigInt(165)));
+    assert.equal(
+      takerBefore - Number(svm.getBalance(p.taker.publicKey)),
+      3 * ataRent
+    );
+  });
+
+  it("NFT escrows sell a single token of a 0 decimal mint", async () => {
+    const make = (p: EscrowParties, metadata: PublicKey) =>
+      getProgram(p.maker)
+        .methods.make(
+          p.seed,
+          new BN(500_000),
+          new BN(1),
+          makeOptions({ nft: true })
+        )
+        .accountsPartial({ ...makeAccounts(p), metadata })
+        .instruction();
+
+    // a supply of 2 is not a single NFT
+    const editions = setupEscrowParties(2, 500_000, { decimalsA: 0 });
+    sendTransactionExpectError(
+      [await make(editions, setupMetadata(editions.mintA))],
+      [editions.maker],
+      "NotAnNft"
+    );
+
+    const p = setupEscrowParties(1, 500_000, { decimalsA: 0 });
+    sendTransactionExpectError(
+      [await make(p, setupMetadata(editions.mintA))],
+      [p.maker],
+      "InvalidMetadata"
+    );
+    sendTransaction([await make(p, setupMetadata(p.mintA))], [p.maker]);
+    const escrow = getProgram(p.maker).coder.accounts.decode(
+      "escrow",
+      Buffer.from(svm.getAccount(p.escrow).data)
+    );
+    assert.deepEqual(escrow.kind, { nft: {} });
+    assert.equal(await getTokenBalance(p.vault), 1);
+
+    const takeIx = await getProgram(p.taker)
+      .methods.take(new BN(1), ANY_PRICE, NO_BOUND, NO_BOUND)
+      .accountsPartial(takeAccounts(p))
+      .instruction();
+    sendTransaction([takeIx], [p.taker]);
+    assert.equal(await getTokenBalance(p.takerAtaA), 1);
+    assert.equal(await getTokenBalance(p.makerAtaB), 500_000 - feeOf(500_000));
+  });
+
+  it("Make wraps lamports when the maker's wSOL account is short", async () => {
+    setupNativeMint();
+    const tokens = setupEscrowParties(1, 500_000);
+    const p = {
+      ...tokens,
+      mintA: NATIVE_MINT,
+      makerAtaA: getAssociatedTokenAddressSync(
+        NATIVE_MINT,
+        tokens.maker.publicKey
+      ),
+      takerAtaA: getAssociatedTokenAddressSync(
+        NATIVE_MINT,
+        tokens.taker.publicKey
+      ),
+      vault: getAssociatedTokenAddressSync(NATIVE_MINT, tokens.escrow, true),
+    };
+    // the maker's wSOL account exists but holds nothing
+    sendTransaction(
+      [
+        createAssociatedTokenAccountIdempotentInstruction(
+          payer.publicKey,
+          p.makerAtaA,
+          p.maker.publicKey,
+          NATIVE_MINT
+        ),
+      ],
+      []
+    );
+    assert.equal(await getTokenBalance(p.makerAtaA), 0);
+
+    const lamports = LAMPORTS_PER_SOL;
+    const makeIx = await getProgram(p.maker)
+      .methods.make(p.seed, new BN(500_000), new BN(lamports), makeOptions())
+      .accountsPartial(makeAccounts(p))
+      .instruction();
+    const makerBefore = Number(svm.getBalance(p.maker.publicKey));
+    sendTransaction([makeIx], [p.maker]);
+
+    assert.equal(await getTokenBalance(p.vault), lamports);
+    assert.equal(await getTokenBalance(p.makerAtaA), 0);
+    const rent =
+      Number(svm.getBalance(p.escrow)) +
+      Number(svm.minimumBalanceForRentExemption(BigInt(165)));
+    assert.equal(
+      Number(svm.getBalance(p.maker.publicKey)),
+      makerBefore - lamports - rent
+    );
+
+    const takeIx = await getProgram(p.taker)
+      .methods.take(new BN(lamports), ANY_PRICE, NO_BOUND, NO_BOUND)
+      .accountsPartial(takeAccounts(p))
+      .instruction();
+    sendTransaction([takeIx], [p.taker]);
+    assert.equal(await getTokenBalance(p.takerAtaA), lamports);
+  });
+
+  it("Take rejects a price above the taker's max receive", async () => {
+    const p = setupEscrowParties(1_000_000, 600_000);
+    const makeIx = await getProgram(p.maker)
+      .methods.make(p.seed, new BN(500_000), new BN(1_000_000), makeOptions())
+      .accountsPartial(makeAccounts(p))
+      .instruction();
+    sendTransaction([makeIx], [p.maker]);
+
+    // the maker reprices after the taker agreed to 500_000
+    const updateIx = await getProgram(p.maker)
+      .methods.updateReceive(new BN(600_000))
+      .accountsPartial({ maker: p.maker.publicKey, escrow: p.escrow })
+      .instruction();
+    sendTransaction([updateIx], [p.maker]);
+
+    const take = (maxReceive: number) =>
+      getProgram(p.taker)
+        .methods.take(new BN(1_000_000), new BN(maxReceive), NO_BOUND, NO_BOUND)
+        .accountsPartial(takeAccounts(p))
+        .instruction();
+    sendTransactionExpectError(
+      [await take(500_000)],
+      [p.taker],
+      "SlippageExceeded"
+    );
+    assert.equal(await getTokenBalance(p.vault), 1_000_000);
+    assert.equal(await getTokenBalance(p.takerAtaB), 600_000);
+
+    sendTransaction([await take(600_000)], [p.taker]);
+    assert.equal(await getTokenBalance(p.takerAtaA), 1_000_000);
+    assert.equal(await getTokenBalance(p.takerAtaB), 0);
+  });
+
+  it("Take rejects a fill outside the taker's bounds", async () => {
+    const p = setupEscrowParties(1_000_000, 600_000);
+    const makeIx = await getProgram(p.maker)
+      .methods.make(p.seed, new BN(500_000), new BN(1_000_000), makeOptions())
+      .accountsPartial(makeAccounts(p))
+      .instruction();
+    sendTransaction([makeIx], [p.maker]);
+
+    // the taker signs for half the deposit at 250_000, then the maker's
+    // update lands first and doubles the price of that half
+    const takeIx = await getProgram(p.taker)
+      .methods.take(
+        new BN(500_000),
+        ANY_PRICE,
+        new BN(500_000),
+        new BN(250_000)
+      )
+      .accountsPartial(takeAccounts(p))
+      .instruction();
+    const updateIx = await getProgram(p.maker)
+      .methods.updateReceive(new BN(1_000_000))
+      .accountsPartial({ maker: p.maker.publicKey, escrow: p.escrow })
+      .instruction();
+    sendTransaction([updateIx], [p.maker]);
+    sendTransactionExpectError([takeIx], [p.taker], "SlippageExceeded");
+    assert.equal(await getTokenBalance(p.vault), 1_000_000);
+    assert.equal(await getTokenBalance(p.takerAtaB), 600_000);
+
+    // a mint_a transfer fee leaves the taker short of the expected amount
+    const fee = setupEscrowParties(1_000_000, 500_000, {
+      tokenProgram: TOKEN_2022_PROGRAM_ID,
+      transferFeeA: { bps: 100, max: BigInt(1_000_000) },
+    });
+    const feeMakeIx = await getProgram(fee.maker)
+      .methods.make(fee.seed, new BN(500_000), new BN(1_000_000), makeOptions())
+      .accountsPartial(makeAccounts(fee))
+      .instruction();
+    sendTransaction([feeMakeIx], [fee.maker]);
+    const take = (expectedReceiveA: number) =>
+      getProgram(fee.taker)
+        .methods.take(
+          new BN(990_000),
+          ANY_PRICE,
+          new BN(expectedReceiveA),
+          new BN(500_000)
+        )
+        .accountsPartial(takeAccounts(fee))
+        .instruction();
+    sendTransactionExpectError(
+      [await take(990_000)],
+      [fee.taker],
+      "SlippageExceeded"
+    );
+    sendTransaction([await take(990_000 - 9_900)], [fee.taker]);
+    assert.equal(await getTokenBalance(fee.takerAtaA), 990_000 - 9_900);
+  });
+
+  it("pNFT escrows need the Token Metadata accounts", async () => {
+    const p = setupEscrowParties(1, 500_000, { decimalsA: 0 });
+    const metadata = setupMetadata(p.mintA, { tokenStandard: 4 });
+    const make = (options: any) =>
+      getProgram(p.maker)
+        .methods.make(p.seed, new BN(500_000), new BN(1), makeOptions(options))
+        .accountsPartial({ ...makeAccounts(p), metadata })
+        .instruction();
+
+    // take_native only moves plain tokens
+    sendTransactionExpectError(
+      [await make({ nft: true, receiveSol: true })],
+      [p.maker],
+      "ProgrammableNftUnsupported"
+    );
+    sendTransactionExpectError(
+      [await make({ nft: true })],
+      [p.maker],
+      "MissingPnftAccounts"
+    );
+  });
+
+  it("pNFT escrows move the token through Token Metadata", async function () {
+    // Token Metadata dumped from mainnet, `solana program dump -um
+    // metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s` into the fixture path
+    const fixture = "./tests/fixtures/mpl_token_metadata.so";
+    if (!existsSync(fixture)) this.skip();
+    svm.addProgram(METADATA_PROGRAM_ID, readFileSync(fixture));
+
+    const meta = (pubkey: PublicKey, isWritable = false, isSigner = false) => ({
+      pubkey,
+      isSigner,
+      isWritable,
+    });
+    const str = (value: string) => {
+      const len = Buffer.alloc(4);
+      len.writeUInt32LE(value.length);
+      return Buffer.concat([len, Buffer.from(value)]);
+    };
+    const u64 = (value: number) => new BN(value).toArrayLike(Buffer, "le", 8);
+    const computeIx = ComputeBudgetProgram.setComputeUnitLimit({
+      units: 1_000_000,
+    });
+
+    // Token Auth Rules, dumped the same way from
+    // auth9SigNpDKz4sJJ1DfCTuZrZNSAgh9sFD3rboVmgg. only the rule set case
+    // needs it
+    const RULES_PROGRAM_ID = new PublicKey(
+      "auth9SigNpDKz4sJJ1DfCTuZrZNSAgh9sFD3rboVmgg"
+    );
+    const rulesFixture = "./tests/fixtures/mpl_token_auth_rules.so";
+    // just enough MessagePack for a RuleSetV1, an array of the lib version,
+    // the owner's bytes, the name and the map of operations to rules
+    const fixstr = (value: string) =>
+      Buffer.concat([Buffer.from([0xa0 | value.length]), Buffer.from(value)]);
+    const createRuleSet = (name: string) => {
+      const [ruleSet] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("rule_set"),
+          payer.publicKey.toBuffer(),
+          Buffer.from(name),
+        ],
+        RULES_PROGRAM_ID
+      );
+      const owner = [...payer.publicKey.toBytes()].map((byte) =>
+        Buffer.from(byte < 0x80 ? [byte] : [0xcc, byte])
+      );
+      // every transfer passes, whoever owns either side
+      const operations = [
+        "Transfer",
+        "Transfer:Owner",
+        "Transfer:WalletToWallet",
+      ];
+      const serialized = Buffer.concat([
+        Buffer.from([0x94, 1, 0xdc, 0, 32]),
+        ...owner,
+        fixstr(name),
+        Buffer.from([0x80 | operations.length]),
+        ...operations.flatMap((operation) => [
+          fixstr(operation),
+          fixstr("Pass"),
+        ]),
+      ]);
+      const len = Buffer.alloc(4);
+      len.writeUInt32LE(serialized.length);
+      const create = new TransactionInstruction({
+        programId: RULES_PROGRAM_ID,
+        keys: [
+          meta(payer.publicKey, true, true),
+          meta(ruleSet, true),
+          meta(SystemProgram.programId),
+          meta(RULES_PROGRAM_ID),
+        ],
+        // CreateOrUpdate, CreateOrUpdateArgs::V1
+        data: Buffer.concat([Buffer.from([0, 0]), len, serialized]),
+      });
+      sendTransaction([create], []);
+      return ruleSet;
+    };
+
+    // CreateV1 then MintV1 of a pNFT owned by `owner`, with the rule set if
+    // one is given
+    const setupPnft = (owner: PublicKey, ruleSet?: PublicKey) => {
+      const mint = Keypair.generate();
+      const metadata = metadataPda(mint.publicKey);
+      const edition = editionPda(mint.publicKey);
+      const token = getAssociatedTokenAddressSync(mint.publicKey, owner);
+      const tokenRecord = tokenRecordPda(mint.publicKey, token);
+      const create = new TransactionInstruction({
+        programId: METADATA_PROGRAM_ID,
+        keys: [
+          meta(metadata, true),
+          meta(edition, true),
+          meta(mint.publicKey, true, true),
+          meta(payer.publicKey, false, true),
+          meta(payer.publicKey, true, true),
+          meta(payer.publicKey, false, true),
+          meta(SystemProgram.programId),
+          meta(SYSVAR_INSTRUCTIONS_PUBKEY),
+          meta(TOKEN_PROGRAM_ID),
+        ],
+        data: Buffer.concat([
+          // Create, CreateArgs::V1
+          Buffer.from([42, 0]),
+          str("Escrow pNFT"),
+          str("EPNFT"),
+          str(""),
+          // seller fee, no creators, primary sale, mutable, pNFT standard
+          Buffer.from([0, 0, 0, 0, 1, 4]),
+          // no collection, uses or collection details
+          Buffer.from([0, 0, 0]),
+          ruleSet
+            ? Buffer.concat([Buffer.from([1]), ruleSet.toBuffer()])
+            : Buffer.from([0]),
+          // decimals 0, PrintSupply::Zero
+          Buffer.from([1, 0, 1, 0]),
+        ]),
+      });
+      const mintTo = new TransactionInstruction({
+        programId: METADATA_PROGRAM_ID,
+        keys: [
+          meta(token, true),
+          meta(owner),
+          meta(metadata, true),
+          meta(edition, true),
+          meta(tokenRecord, true),
+          meta(mint.publicKey, true),
+          meta(payer.publicKey, false, true),
+          meta(METADATA_PROGRAM_ID),
+          meta(payer.publicKey, true, true),
+          meta(SystemProgram.programId),
+          meta(SYSVAR_INSTRUCTIONS_PUBKEY),
+          meta(TOKEN_PROGRAM_ID),
+          meta(ASSOCIATED_TOKEN_PROGRAM_ID),
+          meta(ruleSet ? RULES_PROGRAM_ID : METADATA_PROGRAM_ID),
+          meta(ruleSet ?? METADATA_PROGRAM_ID),
+        ],
+        // Mint, MintArgs::V1 { amount: 1, authorization_data: None }
+        data: Buffer.concat([Buffer.from([43, 0]), u64(1), Buffer.from([0])]),
+      });
+      sendTransaction([computeIx, create, mintTo], [mint]);
+      return { mint: mint.publicKey, metadata, edition, ruleSet };
+    };
+
+    // the pNFT moves from `from` to `to`, each account has a token record
+    const pnftAccounts = (
+      nft: ReturnType<typeof setupPnft>,
+      from: PublicKey,
+      to: PublicKey
+    ) => [
+      meta(nft.metadata, true),
+      meta(nft.edition),
+      meta(tokenRecordPda(nft.mint, from), true),
+      meta(tokenRecordPda(nft.mint, to), true),
+      meta(SYSVAR_INSTRUCTIONS_PUBKEY),
+      meta(METADATA_PROGRAM_ID),
+      // a rule set adds the rules program and the rule set, see pnft.rs
+      ...(nft.ruleSet ? [meta(RULES_PROGRAM_ID), meta(nft.ruleSet)] : []),
+    ];
+    const setup = (ruleSet?: PublicKey) => {
+      const tokens = setupEscrowParties(1, 500_000);
+      const nft = setupPnft(tokens.maker.publicKey, ruleSet);
+      const ata = (owner: PublicKey) =>
+        getAssociatedTokenAddressSync(nft.mint, owner, true);
+      const p = {
+        ...tokens,
+        mintA: nft.mint,
+        makerAtaA: ata(tokens.maker.publicKey),
+        takerAtaA: ata(tokens.taker.publicKey),
+        vault: ata(tokens.escrow),
+      };
+      return { p, nft };
+    };
+    const make = async ({ p, nft }: ReturnType<typeof setup>) => {
+      const makeIx = await getProgram(p.maker)
+        .methods.make(
+          p.seed,
+          new BN(500_000),
+          new BN(1),
+          makeOptions({ nft: true })
+        )
+        .accountsPartial({ ...makeAccounts(p), metadata: nft.metadata })
+        .remainingAccounts(pnftAccounts(nft, p.makerAtaA, p.vault))
+        .instruction();
+      sendTransaction([computeIx, makeIx], [p.maker]);
+      const escrow = getProgram(p.maker).coder.accounts.decode(
+        "escrow",
+        Buffer.from(svm.getAccount(p.escrow).data)
+      );
+      assert.deepEqual(escrow.kind, { programmableNft: {} });
+      assert.equal(await getTokenBalance(p.vault), 1);
+      assert.equal(await getTokenBalance(p.makerAtaA), 0);
+    };
+
+    const refunded = setup();
+    await make(refunded);
+    const refundIx = await getProgram(refunded.p.maker)
+      .methods.refund()
+      .accountsPartial(refundAccounts(refunded.p))
+      .remainingAccounts(
+        pnftAccounts(refunded.nft, refunded.p.vault, refunded.p.makerAtaA)
+      )
+      .instruction();
+    sendTransaction([computeIx, refundIx], [refunded.p.maker]);
+    assert.equal(await getTokenBalance(refunded.p.makerAtaA), 1);
+    assert.ok(isClosed(refunded.p.escrow), "Escrow should be closed");
+
+    const taken = setup();
+    await make(taken);
+    const takeIx = await getProgram(taken.p.taker)
+      .methods.take(new BN(1), ANY_PRICE, NO_BOUND, NO_BOUND)
+      .accountsPartial(takeAccounts(taken.p))
+      .remainingAccounts(
+        pnftAccounts(taken.nft, taken.p.vault, taken.p.takerAtaA)
+      )
+      .instruction();
+    sendTransaction([computeIx, takeIx], [taken.p.taker]);
+    assert.equal(await getTokenBalance(taken.p.takerAtaA), 1);
+    assert.equal(
+      await getTokenBalance(taken.p.makerAtaB),
+      500_000 - feeOf(500_000)
+    );
+    assert.ok(isClosed(taken.p.escrow), "Escrow should be closed");
+
+    // Token Metadata checks the transfers of a pNFT with a rule set against
+    // it, the escrow passes the rules accounts through
+    if (!existsSync(rulesFixture)) return;
+    svm.addProgram(RULES_PROGRAM_ID, readFileSync(rulesFixture));
+    const ruled = setup(createRuleSet("escrow"));
+    await make(ruled);
+    const ruledTakeIx = await getProgram(ruled.p.taker)
+      .methods.take(new BN(1), ANY_PRICE, NO_BOUND, NO_BOUND)
+      .accountsPartial(takeAccounts(ruled.p))
+      .remainingAccounts(
+        pnftAccounts(ruled.nft, ruled.p.vault, ruled.p.takerAtaA)
+      )
+      .instruction();
+    sendTransaction([computeIx, ruledTakeIx], [ruled.p.taker]);
+    assert.equal(await getTokenBalance(ruled.p.takerAtaA), 1);
+    assert.ok(isClosed(ruled.p.escrow), "Escrow should be closed");
+  });
+
+  it("Collection gated escrows go to verified holders only", async () => {
+    const collection = Keypair.generate().publicKey;
+    const p = setupEscrowParties(1_000_000, 500_000);
+    const makeIx = await getProgram(p.maker)
+      .methods.make(
+        p.seed,
+        new BN(500_000),
+        new BN(1_000_000),
+        makeOptions({ gateCollection: collection })
+      )
+      .accountsPartial(makeAccounts(p))
+      .instruction();
+    sendTransaction([makeIx], [p.maker]);
+
+    // a single token of a 0 decimal mint in the taker's account
+    const holding = (metadata: { key: PublicKey; verified: boolean }) => {
+      const { mint, token } = setupHolding(p.taker.publicKey, 1, 0);
+      return {
+        mint,
+        gateToken: token,
+        gateMetadata: setupMetadata(mint, { collection: metadata }),
+      };
+    };
+    const take = (gate: { gateToken: PublicKey; gateMetadata: PublicKey }) =>
+      getProgram(p.taker)
+        .methods.take(new BN(1_000_000), ANY_PRICE, NO_BOUND, NO_BOUND)
+        .accountsPartial({ ...takeAccounts(p), ...gate })
+        .instruction();
+
+    sendTransactionExpectError(
+      [await take({ gateToken: null, gateMetadata: null })],
+      [p.taker],
+      "TakerNotCollectionHolder"
+    );
+    // named in the metadata but not verified by the collection authority
+    const unverified = holding({ key: collection, verified: false });
+    sendTransactionExpectError(
+      [await take(unverified)],
+      [p.taker],
+      "TakerNotCollectionHolder"
+    );
+    const otherCollection = holding({
+      key: Keypair.generate().publicKey,
+      verified: true,
+    });
+    sendTransactionExpectError(
+      [await take(otherCollection)],
+      [p.taker],
+      "TakerNotCollectionHolder"
+    );
+    // the metadata of another mint doesn't prove the holding
+    const holder = holding({ key: collection, verified: true });
+    sendTransactionExpectError(
+      [await take({ ...holder, gateMetadata: unverified.gateMetadata })],
+      [p.taker],
+      "TakerNotCollectionHolder"
+    );
+
+    // a frozen and delegated holding still belongs to the taker
+    sendTransaction(
+      [
+        createApproveInstruction(
+          holder.gateToken,
+          Keypair.generate().publicKey,
+          p.taker.publicKey,
+          1
+        ),
+        createFreezeAccountInstruction(
+          holder.gateToken,
+          holder.mint,
+          payer.publicKey
+        ),
+      ],
+      [p.taker]
+    );
+    sendTransaction([await take(holder)], [p.taker]);
+    assert.equal(await getTokenBalance(p.takerAtaA), 1_000_000);
+    assert.equal(await getTokenBalance(holder.gateToken), 1);
+  });
+
+  it("Mint gated escrows need a minimum gate token balance", async () => {
+    const p = setupEscrowParties(1_000_000, 500_000);
+    const gate = setupHolding(p.taker.publicKey, 9_999, 6);
+    const makeIx = await getProgram(p.maker)
+      .methods.make(
+        p.seed,
+        new BN(500_000),
+        new BN(1_000_000),
+        makeOptions({ gateMint: gate.mint, gateMinBalance: new BN(10_000) })
+      )
+      .accountsPartial(makeAccounts(p))
+      .instruction();
+    sendTransaction([makeIx], [p.maker]);
+
+    const take = (gateMintToken: PublicKey | null) =>
+      getProgram(p.taker)
+        .methods.take(new BN(1_000_000), ANY_PRICE, NO_BOUND, NO_BOUND)
+        .accountsPartial({ ...takeAccounts(p), gateMintToken })
+        .instruction();
+    sendTransactionExpectError(
+      [await take(null)],
+      [p.taker],
+      "InvalidGateAccount"
+    );
+    // the taker's account of another mint
+    sendTransactionExpectError(
+      [await take(p.takerAtaB)],
+      [p.taker],
+      "InvalidGateAccount"
+    );
+    // one unit short of the minimum
+    sendTransactionExpectError(
+      [await take(gate.token)],
+      [p.taker],
+      "GateBalanceTooLow"
+    );
+
+    sendTransaction(
+      [createMintToInstruction(gate.mint, gate.token, payer.publicKey, 1)],
+      []
+    );
+    sendTransaction([await take(gate.token)], [p.taker]);
+    assert.equal(await getTokenBalance(p.takerAtaA), 1_000_000);
+    assert.equal(await getTokenBalance(gate.token), 10_000);
+  });
+
+  it("Takes with a referrer split the maker's share with them", async () => {
+    const p = setupEscrowParties(1_000_000, 1_000_000);
+    const referrer = Keypair.generate();
+    const referrerAtaB = getAssociatedTokenAddressSync(
+      p.mintB,
+      referrer.publicKey
+    );
+    const makeIx = await getProgram(p.maker)
+      .methods.make(p.seed, new BN(1_000_000), new BN(1_000_000), makeOptions())
+      .accountsPartial(makeAccounts(p))
+      .instruction();
+    sendTransaction(
+      [
+        makeIx,
+        createAssociatedTokenAccountIdempotentInstruction(
+          p.maker.publicKey,
+          referrerAtaB,
+          referrer.publicKey,
+          p.mintB
+        ),
+      ],
+      [p.maker]
+    );
+
+    // the taker can't refer themselves
+    const selfReferral = await getProgram(p.taker)
+      .methods.take(new BN(500_000), ANY_PRICE, NO_BOUND, NO_BOUND)
+      .accountsPartial({
+        ...takeAccounts(p),
+        referrer: p.taker.publicKey,
+        referrerAtaB: p.takerAtaB,
+      })
+      .instruction();
+    sendTransactionExpectError([selfReferral], [p.taker], "SelfReferral");
+
+    // half with a referrer, half without
+    const takeWithReferrer = await getProgram(p.taker)
+      .methods.take(new BN(500_000), ANY_PRICE, NO_BOUND, NO_BOUND)
+      .accountsPartial({
+        ...takeAccounts(p),
+        referrer: referrer.publicKey,
+        referrerAtaB,
+      })
+      .instruction();
+    sendTransaction([takeWithReferrer], [p.taker]);
+    const referral = Math.floor((500_000 * REFERRAL_BPS) / 10_000);
+    assert.equal(await getTokenBalance(referrerAtaB), referral);
+    assert.equal(
+      await getTokenBalance(p.makerAtaB),
+      500_000 - feeOf(500_000) - referral
+    );
+
+    const takeIx = await getProgram(p.taker)
+      .methods.take(new BN(500_000), ANY_PRICE, NO_BOUND, NO_BOUND)
+      .accountsPartial(takeAccounts(p))
+      .instruction();
+    sendTransaction([takeIx], [p.taker]);
+    assert.equal(await getTokenBalance(referrerAtaB), referral);
+    assert.equal(
+      await getTokenBalance(p.makerAtaB),
+      1_000_000 - 2 * feeOf(500_000) - referral
+    );
+    assert.ok(isClosed(p.escrow), "Escrow should be closed once empty");
+  });
+
+  it("Take rejects bad escrows, mints and short funds by name", async () => {
+    // the taker holds only half of what the escrow asks
+    const p = setupEscrowParties(1_000_000, 500_000);
+    const makeIx = await getProgram(p.maker)
+      .methods.make(p.seed, new BN(1_000_000), new BN(1_000_000), makeOptions())
+      .accountsPartial(makeAccounts(p))
+      .instruction();
+    sendTransaction([makeIx], [p.maker]);
+    const take = (accounts: Record<string, any> = {}) =>
+      getProgram(p.taker)
+        .methods.take(new BN(1_000_000), ANY_PRICE, NO_BOUND, NO_BOUND)
+        .accountsPartial({ ...takeAccounts(p), ...accounts })
+        .instruction();
+
+    sendTransactionExpectError(
+      [await take()],
+      [p.taker],
+      "InsufficientTakerFunds"
+    );
+
+    // paying in a mint the taker does hold, but not the escrow's mint_b
+    const other = setupHolding(p.taker.publicKey, 1_000_000, 6);
+    sendTransactionExpectError(
+      [
+        await take({
+          mintB: other.mint,
+          makerAtaB: getAssociatedTokenAddressSync(
+            other.mint,
+            p.maker.publicKey
+          ),
+          feeCollectorAtaB: feeCollectorAta(other.mint),
+        }),
+      ],
+      [p.taker],
+      "UnsupportedPaymentMint"
+    );
+
+    // an escrow that was never made
+    const missing = setupEscrowParties(1_000_000, 1_000_000);
+    sendTransactionExpectError(
+      [
+        await getProgram(missing.taker)
+          .methods.take(new BN(1_000_000), ANY_PRICE, NO_BOUND, NO_BOUND)
+          .accountsPartial(takeAccounts(missing))
+          .instruction(),
+      ],
+      [missing.taker],
+      "AccountNotInitialized"
+    );
+
+    const refundIx = await getProgram(p.maker)
+      .methods.refund()
+      .accountsPartial(refundAccounts(p))
+      .instruction();
+    sendTransaction([refundIx], [p.maker]);
+    sendTransactionExpectError(
+      [await take()],
+      [p.taker],
+      "AccountNotInitialized"
+    );
+    assert.equal(await getTokenBalance(p.makerAtaA), 1_000_000);
+    assert.equal(await getTokenBalance(p.takerAtaB), 500_000);
+  });
+
+  it("Taker accepts the maker's proposed terms by repeating them", async () => {
+    const p = setupEscrowParties(1_000_000, 1_000_000);
+    const rival = setupExtraTaker(p, 1_000_000);
+    const makerProgram = getProgram(p.maker);
+    const makeIx = await makerProgram.methods
+      .make(p.seed, new BN(1_000_000), new BN(1_000_000), makeOptions())
+      .accountsPartial(makeAccounts(p))
+      .instruction();
+    sendTransaction([makeIx], [p.maker]);
+
+    const update = { maker: p.maker.publicKey, escrow: p.escrow };
+    const propose = (validSlots: number) =>
+      makerProgram.methods
+        .proposeTerms(new BN(800_000), p.taker.publicKey, new BN(validSlots))
+        .accountsPartial(update)
+        .instruction();
+    const clear = () =>
+      makerProgram.methods.clearTerms().accountsPartial(update).instruction();
+    const takeWithTerms = (taker: Keypair, receive: number) =>
+      getProgram(taker)
+        .methods.takeWithTerms(new BN(receive))
+        .accountsPartial({ ...takeAccounts(p), taker: taker.publicKey })
+        .instruction();
+
+    const [proposed] = sendTransactionEvents([await propose(1_000)], [p.maker]);
+    assert.equal(proposed.name, "termsproposed");
+    assert.ok(proposed.data.taker.equals(p.taker.publicKey));
+    assert.equal(proposed.data.receive.toNumber(), 800_000);
+    sendTransactionExpectError(
+      [await takeWithTerms(rival.taker, 800_000)],
+      [rival.taker],
+      "UnauthorizedTaker"
+    );
+    // the maker can't slip different terms past a taker who signed for others
+    sendTransactionExpectError(
+      [await takeWithTerms(p.taker, 700_000)],
+      [p.taker],
+      "TermsMismatch"
+    );
+
+    const [cleared] = sendTransactionEvents([await clear()], [p.maker]);
+    assert.equal(cleared.name, "termscleared");
+    sendTransactionExpectError(
+      [await takeWithTerms(p.taker, 800_000)],
+      [p.taker],
+      "NoPendingTerms"
+    );
+
+    // sending a transaction moves the clock past a single slot of validity.
+    // repeated transactions need a new blockhash
+    sendTransaction([await propose(1)], [p.maker]);
+    svm.expireBlockhash();
+    sendTransactionExpectError(
+      [await takeWithTerms(p.taker, 800_000)],
+      [p.taker],
+      "TermsExpired"
+    );
+    svm.expireBlockhash();
+    const [expired] = sendTransactionEvents([await clear()], [p.maker]);
+    assert.equal(expired.name, "termsexpired");
+
+    svm.expireBlockhash();
+    sendTransaction([await propose(1_000)], [p.maker]);
+    const events = sendTransactionEvents(
+      [await takeWithTerms(p.taker, 800_000)],
+      [p.taker]
+    );
+    assert.deepEqual(
+      events.map((event) => event.name),
+      ["termsaccepted", "escrowtaken", "statsupdated"]
+    );
+    assert.equal(await getTokenBalance(p.takerAtaA), 1_000_000);
+    assert.equal(await getTokenBalance(p.makerAtaB), 800_000 - feeOf(800_000));
+    assert.ok(isClosed(p.escrow), "Escrow should be closed once taken");
+  });
+
+  it("Basket escrows sell two mints together, only as a whole", async () => {
+    const basket = async (p: EscrowParties, withLeg: boolean) => {
+      const second = setupHolding(p.maker.publicKey, 300_000, 6);
+      const vault2 = getAssociatedTokenAddressSync(second.mint, p.escrow, true);
+      // the second mint is a basket leg, [mint, vault, ATA of the maker or
+      // taker], see basket.rs
+      const legAccounts = (owner: PublicKey) =>
+        [
+          second.mint,
+          vault2,
+          getAssociatedTokenAddressSync(second.mint, owner),
+        ].map((pubkey, i) => ({ pubkey, isSigner: false, isWritable: i > 0 }));
+      const makeIx = await getProgram(p.maker)
+        .methods.make(
+          p.seed,
+          new BN(1_000_000),
+          new BN(1_000_000),
+          makeOptions({ deposit2: new BN(300_000) })
+        )
+        .accountsPartial(makeAccounts(p))
+        .remainingAccounts(withLeg ? legAccounts(p.maker.publicKey) : [])
+        .instruction();
+      return { ...second, vault2, legAccounts, makeIx };
+    };
+
+    const p = setupEscrowParties(1_000_000, 1_000_000);
+    const { makeIx: withoutLeg } = await basket(p, false);
+    sendTransactionExpectError([withoutLeg], [p.maker], "BasketLegMismatch");
+    const { mint: mintA2, vault2, legAccounts, makeIx } = await basket(p, true);
+    // a vault created ahead of the make doesn't block it
+    sendTransaction(
+      [
+        createAssociatedTokenAccountIdempotentInstruction(
+          payer.publicKey,
+          vault2,
+          p.escrow,
+          mintA2
+        ),
+      ],
+      []
+    );
+    sendTransaction([makeIx], [p.maker]);
+    assert.equal(await getTokenBalance(vault2), 300_000);
+
+    const take = (amount: number, withLeg: boolean) =>
+      getProgram(p.taker)
+        .methods.take(new BN(amount), ANY_PRICE, NO_BOUND, NO_BOUND)
+        .accountsPartial(takeAccounts(p))
+        .remainingAccounts(withLeg ? legAccounts(p.taker.publicKey) : [])
+        .instruction();
+    sendTransactionExpectError(
+      [await take(500_000, true)],
+      [p.taker],
+      "BelowMinimumFill"
+    );
+    sendTransactionExpectError(
+      [await take(1_000_000, false)],
+      [p.taker],
+      "BasketLegMismatch"
+    );
+    // tokens sent to the second vault from outside go along with the leg
+    const vault = svm.getAccount(vault2);
+    const data = Buffer.from(vault.data);
+    data.writeBigUInt64LE(BigInt(300_007), 64);
+    svm.setAccount(vault2, { ...vault, data });
+    sendTransaction([await take(1_000_000, true)], [p.taker]);
+    assert.equal(await getTokenBalance(p.takerAtaA), 1_000_000);
+    const takerAtaA2 = getAssociatedTokenAddressSync(mintA2, p.taker.publicKey);
+    assert.equal(await getTokenBalance(takerAtaA2), 300_007);
+    assert.ok(isClosed(vault2), "Second vault should be closed");
+    assert.ok(isClosed(p.escrow), "Escrow should be closed once taken");
+
+    // a refund returns both mints
+    const q = setupEscrowParties(1_000_000, 1_000_000);
+    const refunded = await basket(q, true);
+    sendTransaction([refunded.makeIx], [q.maker]);
+    const refundIx = await getProgram(q.maker)
+      .methods.refund()
+      .accountsPartial(refundAccounts(q))
+      .remainingAccounts(refunded.legAccounts(q.maker.publicKey))
+      .instruction();
+    sendTransaction([refundIx], [q.maker]);
+    assert.equal(await getTokenBalance(q.makerAtaA), 1_000_000);
+    assert.equal(await getTokenBalance(refunded.token), 300_000);
+    assert.ok(isClosed(refunded.vault2), "Second vault should be closed");
+  });
+
+  it("Vesting escrows release the deposit to the taker over time", async () => {
+    const p = setupEscrowParties(1_000_000, 500_000);
+    const start = svm.getClock().unixTimestamp;
+    const makeIx = await getProgram(p.maker)
+      .methods.make(
+        p.seed,
+        new BN(500_000),
+        new BN(1_000_000),
+        makeOptions({ vestDuration: new BN(1_000) })
+      )
+      .accountsPartial(makeAccounts(p))
+      .instruction();
+    sendTransaction([makeIx], [p.maker]);
+
+    const take = (amount: number) =>
+      getProgram(p.taker)
+        .methods.take(new BN(amount), ANY_PRICE, NO_BOUND, NO_BOUND)
+        .accountsPartial(takeAccounts(p))
+        .instruction();
+    sendTransactionExpectError(
+      [await take(500_000)],
+      [p.taker],
+      "BelowMinimumFill"
+    );
+    setUnixTimestamp(start);
+    // a taker naming themselves as the maker would pay themselves and still
+    // become the vest taker
+    const selfPaidIx = await getProgram(p.taker)
+      .methods.take(new BN(1_000_000), ANY_PRICE, NO_BOUND, NO_BOUND)
+      .accountsPartial({
+        ...takeAccounts(p),
+        maker: p.taker.publicKey,
+        makerAtaB: p.takerAtaB,
+      })
+      .instruction();
+    sendTransactionExpectError([selfPaidIx], [p.taker], "ConstraintHasOne");
+    sendTransaction([await take(1_000_000)], [p.taker]);
+    // paid in full, but nothing has vested yet
+    assert.equal(await getTokenBalance(p.makerAtaB), 500_000 - feeOf(500_000));
+    assert.equal(await getTokenBalance(p.takerAtaA), 0);
+    assert.equal(await getTokenBalance(p.vault), 1_000_000);
+
+    const claim = (taker: Keypair) =>
+      getProgram(taker)
+        .methods.claimVested()
+        .accountsPartial({
+          taker: taker.publicKey,
+          maker: p.maker.publicKey,
+          mintA: p.mintA,
+          escrow: p.escrow,
+          makerIndex: makerIndexOf(p.maker.publicKey),
+          vault: p.vault,
+          rentRecipient: null,
+          tokenProgram: p.tokenProgramA,
+        })
+        .instruction();
+    sendTransactionExpectError(
+      [await claim(p.taker)],
+      [p.taker],
+      "NothingToClaim"
+    );
+    sendTransactionExpectError(
+      [await claim(p.maker)],
+      [p.maker],
+      "UnauthorizedTaker"
+    );
+    const refundIx = await getProgram(p.maker)
+      .methods.refund()
+      .accountsPartial(refundAccounts(p))
+      .instruction();
+    sendTransactionExpectError([refundIx], [p.maker], "EscrowNotOpen");
+
+    setUnixTimestamp(start + BigInt(250));
+    svm.expireBlockhash();
+    const [claimed] = sendTransactionEvents([await claim(p.taker)], [p.taker]);
+    assert.equal(claimed.name, "vestedclaimed");
+    assert.equal(claimed.data.amount.toNumber(), 250_000);
+    assert.equal(await getTokenBalance(p.takerAtaA), 250_000);
+
+    // long after the vesting ends the rest is claimed and the escrow closes
+    setUnixTimestamp(start + BigInt(10_000));
+    sendTransaction([await claim(p.taker)], [p.taker]);
+    assert.equal(await getTokenBalance(p.takerAtaA), 1_000_000);
+    assert.ok(isClosed(p.vault), "Vault should be closed once claimed");
+    assert.ok(isClosed(p.escrow), "Escrow should be closed once claimed");
+  });
+
+  it("Makers can only take their own escrow after opting in", async () => {
+    const selfFill = async (allowSelfFill: boolean) => {
+      const p = setupEscrowParties(1_000_000, 500_000);
+      // the maker pays themselves, on top of the protocol fee
+      sendTransaction(
+        [
+          createMintToInstruction(
+            p.mintB,
+            p.makerAtaB,
+            payer.publicKey,
+            500_000
+          ),
+        ],
+        []
+      );
+      const makeIx = await getProgram(p.maker)
+        .methods.make(
+          p.seed,
+          new BN(500_000),
+          new BN(1_000_000),
+          makeOptions({ allowSelfFill })
+        )
+        .accountsPartial(makeAccounts(p))
+        .instruction();
+      sendTransaction([makeIx], [p.maker]);
+      const takeIx = await getProgram(p.maker)
+        .methods.take(new BN(1_000_000), ANY_PRICE, NO_BOUND, NO_BOUND)
+        .accountsPartial({ ...takeAccounts(p), taker: p.maker.publicKey })
+        .instruction();
+      return { p, takeIx };
+    };
+
+    const rejected = await selfFill(false);
+    sendTransactionExpectError(
+      [rejected.takeIx],
+      [rejected.p.maker],
+      "SelfTradeNotAllowed"
+    );
+    assert.equal(await getTokenBalance(rejected.p.vault), 1_000_000);
+
+    const allowed = await selfFill(true);
+    sendTransaction([allowed.takeIx], [allowed.p.maker]);
+    assert.equal(await getTokenBalance(allowed.p.makerAtaA), 1_000_000);
+    assert.equal(
+      await getTokenBalance(allowed.p.makerAtaB),
+      500_000 - feeOf(500_000)
+    );
+    assert.ok(isClosed(allowed.p.escrow));
+  });
+
+  it("Closing escrows pays the rent to the rent recipient", async () => {
+    const treasury = Keypair.generate().publicKey;
+    const makeWithRecipient = async (p: EscrowParties) => {
+      const makeIx = await getProgram(p.maker)
+        .methods.make(
+          p.seed,
+          new BN(500_000),
+          new BN(1_000_000),
+          makeOptions({ rentRecipient: treasury })
+        )
+        .accountsPartial(makeAccounts(p))
+        .instruction();
+      sendTransaction([makeIx], [p.maker]);
+      return Number(
+        svm.getAccount(p.escrow).lamports + svm.getAccount(p.vault).lamports
+      );
+    };
+
+    const taken = setupEscrowParties(1_000_000, 500_000);
+    const rent = await makeWithRecipient(taken);
+    const take = (rentRecipient: PublicKey | null) =>
+      getProgram(taken.taker)
+        .methods.take(new BN(1_000_000), ANY_PRICE, NO_BOUND, NO_BOUND)
+        .accountsPartial({ ...takeAccounts(taken), rentRecipient })
+        .instruction();
+    sendTransactionExpectError(
+      [await take(null)],
+      [taken.taker],
+      "RentRecipientMismatch"
+    );
+    const makerBefore = svm.getBalance(taken.maker.publicKey);
+    sendTransaction([await take(treasury)], [taken.taker]);
+    assert.equal(Number(svm.getBalance(treasury)), rent);
+    assert.equal(svm.getBalance(taken.maker.publicKey), makerBefore);
+    assert.ok(isClosed(taken.escrow));
+
+    const refunded = setupEscrowParties(1_000_000, 500_000);
+    const refundRent = await makeWithRecipient(refunded);
+    const refundIx = await getProgram(refunded.maker)
+      .methods.refund()
+      .accountsPartial({ ...refundAccounts(refunded), rentRecipient: treasury })
+      .instruction();
+    sendTransaction([refundIx], [refunded.maker]);
+    assert.equal(Number(svm.getBalance(treasury)), rent + refundRent);
+    assert.equal(await getTokenBalance(refunded.makerAtaA), 1_000_000);
+
+    // withdrawing the whole deposit closes the escrow like a refund
+    const withdrawn = setupEscrowParties(1_000_000, 500_000);
+    const withdrawRent = await makeWithRecipient(withdrawn);
+    const withdrawIx = await getProgram(withdrawn.maker)
+      .methods.withdraw(new BN(1_000_000))
+      .accountsPartial({
+        maker: withdrawn.maker.publicKey,
+        mintA: withdrawn.mintA,
+        makerAtaA: withdrawn.makerAtaA,
+        escrow: withdrawn.escrow,
+        makerIndex: makerIndexOf(withdrawn.maker.publicKey),
+        stats,
+        vault: withdrawn.vault,
+        rentRecipient: treasury,
+        tokenProgram: withdrawn.tokenProgramA,
+      })
+      .instruction();
+    sendTransaction([withdrawIx], [withdrawn.maker]);
+    assert.equal(
+      Number(svm.getBalance(treasury)),
+      rent + refundRent + withdrawRent
+    );
+    assert.ok(isClosed(withdrawn.escrow));
+
+    // SOL escrows route their rent the same way when taken or refunded
+    const makeSolWithRecipient = async (p: EscrowParties) => {
+      const [solVault] = PublicKey.findProgramAddressSync(
+        [Buffer.from("sol_vault"), p.escrow.toBuffer()],
+        programId
+      );
+      const makeIx = await getProgram(p.maker)
+        .methods.makeSol(
+          p.seed,
+          new BN(500_000),
+          new BN(LAMPORTS_PER_SOL),
+          makeOptions({ rentRecipient: treasury })
+        )
+        .accountsPartial({
+          maker: p.maker.publicKey,
+          mintB: p.mintB,
+          escrow: p.escrow,
+          makerIndex: makerIndexOf(p.maker.publicKey),
+          stats,
+          solVault,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .instruction();
+      sendTransaction([makeIx], [p.maker]);
+      return solVault;
+    };
+    let paid = Number(svm.getBalance(treasury));
+
+    const takenSol = setupEscrowParties(0, 500_000);
+    const takenVault = await makeSolWithRecipient(takenSol);
+    const solRent = Number(svm.getAccount(takenSol.escrow).lamports);
+    const takeSol = (rentRecipient: PublicKey | null) =>
+      getProgram(takenSol.taker)
+        .methods.takeSol()
+        .accountsPartial({
+          taker: takenSol.taker.publicKey,
+          maker: takenSol.maker.publicKey,
+          mintB: takenSol.mintB,
+          escrow: takenSol.escrow,
+          makerIndex: makerIndexOf(takenSol.maker.publicKey),
+          stats,
+          solVault: takenVault,
+          config,
+          feeCollector: feeCollector.publicKey,
+          feeCollectorAtaB: feeCollectorAta(takenSol.mintB),
+          rentRecipient,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .instruction();
+    sendTransactionExpectError(
+      [await takeSol(null)],
+      [takenSol.taker],
+      "RentRecipientMismatch"
+    );
+    sendTransaction([await takeSol(treasury)], [takenSol.taker]);
+    paid += solRent;
+    assert.equal(Number(svm.getBalance(treasury)), paid);
+    assert.ok(isClosed(takenSol.escrow));
+
+    const refundedSol = setupEscrowParties(0, 500_000);
+    const refundedVault = await makeSolWithRecipient(refundedSol);
+    const refundSolIx = await getProgram(refundedSol.maker)
+      .methods.refundSol()
+      .accountsPartial({
+        maker: refundedSol.maker.publicKey,
+        escrow: refundedSol.escrow,
+        solVault: refundedVault,
+        stats,
+        rentRecipient: treasury,
+        makerIndex: makerIndexOf(refundedSol.maker.publicKey),
+        systemProgram: SystemProgram.programId,
+      })
+      .instruction();
+    sendTransaction([refundSolIx], [refundedSol.maker]);
+    paid += solRent;
+    assert.equal(Number(svm.getBalance(treasury)), paid);
+    assert.ok(isClosed(refundedSol.escrow));
+  });
+
+  it("Take with quote fills at a price the maker signed", async () => {
+    const p = setupEscrowParties(1_000_000, 500_000);
+    const makeIx = await getProgram(p.maker)
+      .methods.make(
+        p.seed,
+        new BN(500_000),
+        new BN(1_000_000),
+        makeOptions({ minReceiveFloor: new BN(350_000) })
+      )
+      .accountsPartial(makeAccounts(p))
+      .instruction();
+    sendTransaction([makeIx], [p.maker]);
+
+    const now = Number(svm.getClock().unixTimestamp);
+    // the Ed25519 instruction carries the signature, which the take repeats.
+    // the quote is over the escrow as it is when the maker signs
+    const quote = (signer: Keypair, price: number, expiry: number) => {
+      const escrow = getProgram(p.maker).coder.accounts.decode(
+        "escrow",
+        Buffer.from(svm.getAccount(p.escrow).data)
+      );
+      const message = Buffer.concat([
+        p.escrow.toBuffer(),
+        escrow.mintB.toBuffer(),
+        escrow.createdSlot.toArrayLike(Buffer, "le", 8),
+        escrow.remaining.toArrayLike(Buffer, "le", 8),
+        new BN(price).toArrayLike(Buffer, "le", 8),
+        new BN(expiry).toArrayLike(Buffer, "le", 8),
+      ]);
+      const verifyIx = Ed25519Program.createInstructionWithPrivateKey({
+        privateKey: signer.secretKey,
+        message,
+      });
+      // signature after the 16 byte header and the public key
+      return { verifyIx, signature: [...verifyIx.data.subarray(48, 112)] };
+    };
+    const takeWithQuote = async (
+      signer: Keypair,
+      price: number,
+      expiry: number
+    ) => {
+      const { verifyIx, signature } = quote(signer, price, expiry);
+      const takeIx = await getProgram(p.taker)
+        .methods.takeWithQuote(new BN(price), new BN(expiry), signature)
+        .accountsPartial({
+          ...takeAccounts(p),
+          sysvarInstructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+        })
+        .instruction();
+      return [verifyIx, takeIx];
+    };
+
+    sendTransactionExpectError(
+      await takeWithQuote(p.taker, 400_000, now + 60),
+      [p.taker],
+      "InvalidQuoteSignature"
+    );
+    sendTransactionExpectError(
+      await takeWithQuote(p.maker, 400_000, now),
+      [p.taker],
+      "QuoteExpired"
+    );
+    // the taker can't lower the price the maker signed
+    const [verifyIx] = await takeWithQuote(p.maker, 400_000, now + 60);
+    const [, lowered] = await takeWithQuote(p.maker, 300_000, now + 60);
+    sendTransactionExpectError(
+      [verifyIx, lowered],
+      [p.taker],
+      "InvalidQuoteSignature"
+    );
+    sendTransactionExpectError([lowered], [p.taker], "InvalidQuoteSignature");
+    // nor can the maker's key sign one below the floor
+    sendTransactionExpectError(
+      await takeWithQuote(p.maker, 349_999, now + 60),
+      [p.taker],
+      "BelowPriceFloor"
+    );
+
+    // a fill since the maker signed makes the quote stale
+    const stale = await takeWithQuote(p.maker, 400_000, now + 60);
+    const partialIx = await getProgram(p.taker)
+      .methods.take(new BN(100_000), ANY_PRICE, NO_BOUND, NO_BOUND)
+      .accountsPartial(takeAccounts(p))
+      .instruction();
+    sendTransaction([partialIx], [p.taker]);
+    sendTransactionExpectError(stale, [p.taker], "InvalidQuoteSignature");
+
+    // 400_000 for the whole deposit is 360_000 for the 900_000 left
+    const events = sendTransactionEvents(
+      await takeWithQuote(p.maker, 400_000, now + 60),
+      [p.taker]
+    );
+    assert.deepEqual(
+      events.map((event) => event.name),
+      ["quoteaccepted", "escrowtaken", "statsupdated"]
+    );
+    assert.equal(await getTokenBalance(p.takerAtaA), 1_000_000);
+    assert.equal(await getTokenBalance(p.takerAtaB), 90_000);
+    assert.equal(
+      await getTokenBalance(p.makerAtaB),
+      410_000 - feeOf(50_000) - feeOf(360_000)
+    );
+    assert.ok(isClosed(p.escrow));
+  });
+
+  it("Unit priced escrows charge every fill at the unit price", async () => {
+    const unitPriced = async (
+      p: EscrowParties,
+      deposit: number,
+      numerator: number,
+      denominator: number
+    ) => {
+      const makeIx = await getProgram(p.maker)
+        .methods.make(
+          p.seed,
+          new BN(1),
+          new BN(deposit),
+          makeOptions({
+            priceNumerator: new BN(numerator),
+            priceDenominator: new BN(denominator),
+          })
+        )
+        .accountsPartial(makeAccounts(p))
+        .instruction();
+      const [made] = sendTransactionEvents([makeIx], [p.maker]);
+      return made;
+    };
+    const take = (p: EscrowParties, amount: number) =>
+      getProgram(p.taker)
+        .methods.take(new BN(amount), ANY_PRICE, NO_BOUND, NO_BOUND)
+        .accountsPartial(takeAccounts(p))
+        .instruction();
+
+    // 2.5 of a 9 decimal mint_b for each token of a 0 decimal mint_a
+    const tickets = setupEscrowParties(10, 25_000_000_000, {
+      decimalsA: 0,
+      decimalsB: 9,
+    });
+    const made = await unitPriced(tickets, 10, 2_500_000_000, 1);
+    assert.equal(made.data.receive.toString(), "25000000000");
+    assert.equal(made.data.priceNumerator.toNumber(), 2_500_000_000);
+    assert.equal(made.data.priceDenominator.toNumber(), 1);
+    sendTransaction([await take(tickets, 3)], [tickets.taker]);
+    assert.equal(await getTokenBalance(tickets.takerAtaA), 3);
+    assert.equal(await getTokenBalance(tickets.takerAtaB), 17_500_000_000);
+
+    // the maker can't put a total price over the unit price
+    const updateIx = await getProgram(tickets.maker)
+      .methods.updateReceive(new BN(1))
+      .accountsPartial({
+        maker: tickets.maker.publicKey,
+        escrow: tickets.escrow,
+      })
+      .instruction();
+    sendTransactionExpectError([updateIx], [tickets.maker], "UnitPriced");
+
+    // 3 of a 0 decimal mint_b for each whole token of a 9 decimal mint_a
+    const dust = setupEscrowParties(10_000_000_000, 100, {
+      decimalsA: 9,
+      decimalsB: 0,
+    });
+    await unitPriced(dust, 10_000_000_000, 3, 1_000_000_000);
+    // a single base unit of mint_a still costs a whole base unit of mint_b
+    sendTransaction([await take(dust, 1)], [dust.taker]);
+    assert.equal(await getTokenBalance(dust.takerAtaB), 99);
+    sendTransaction([await take(dust, 1_000_000_000)], [dust.taker]);
+    assert.equal(await getTokenBalance(dust.takerAtaB), 96);
+    // what is left costs 26.999999997, rounded up
+    sendTransaction([await take(dust, 8_999_999_999)], [dust.taker]);
+    assert.equal(await getTokenBalance(dust.takerAtaA), 10_000_000_000);
+    assert.equal(await getTokenBalance(dust.takerAtaB), 69);
+    assert.ok(isClosed(dust.escrow));
+  });
+
+  it("Oracle priced escrows charge the feed price plus spread", async () => {
+    const PYTH_RECEIVER_ID = new PublicKey(
+      "rec5EKMGg6MxZYaMdyBfgwp4d5rB9T1VQH5pJv5LtFJ"
+    );
+    const now = svm.getClock().unixTimestamp;
+    const feedId = Buffer.alloc(32, 7);
+    // a fully verified PriceUpdateV2 of the Pyth receiver
+    const setPrice = (
+      feed: PublicKey,
+      price: number,
+      exponent: number,
+      {
+        conf = 0,
+        age = 0,
+        id = feedId,
+      }: { conf?: number; age?: number; id?: Buffer } = {}
+    ) => {
+      const data = Buffer.alloc(133);
+      createHash("sha256")
+        .update("account:PriceUpdateV2")
+        .digest()
+        .copy(data, 0, 0, 8);
+      data.writeUInt8(1, 40);
+      id.copy(data, 41);
+      data.writeBigInt64LE(BigInt(price), 73);
+      data.writeBigUInt64LE(BigInt(conf), 81);
+      data.writeInt32LE(exponent, 89);
+      data.writeBigInt64LE(now - BigInt(age), 93);
+      svm.setAccount(feed, {
+        lamports: Number(svm.minimumBalanceForRentExemption(BigInt(133))),
+        data,
+        owner: PYTH_RECEIVER_ID,
+        executable: false,
+      });
+    };
+    const makeOracle = async (
+      p: EscrowParties,
+      deposit: number,
+      feed: PublicKey,
+      spreadBps: number
+    ) => {
+      const makeIx = await getProgram(p.maker)
+        .methods.make(
+          p.seed,
+          new BN(1),
+          new BN(deposit),
+          makeOptions({
+            oracle: feed,
+            oracleFeedId: [...feedId],
+            spreadBps,
+            maxPriceAge: new BN(60),
+            maxConfBps: 100,
+          })
+        )
+        .accountsPartial(makeAccounts(p))
+        .instruction();
+      sendTransaction([makeIx], [p.maker]);
+    };
+    const take = (p: EscrowParties, amount: number, priceFeed: PublicKey) =>
+      getProgram(p.taker)
+        .methods.take(new BN(amount), ANY_PRICE, NO_BOUND, NO_BOUND)
+        .accountsPartial({ ...takeAccounts(p), priceFeed })
+        .instruction();
+
+    // $1.50 with a negative exponent, 2 of a 9 decimal mint_a for a 6 decimal
+    // mint_b, plus a 1% spread
+    const p = setupEscrowParties(2_000_000_000, 4_000_000, {
+      decimalsA: 9,
+      decimalsB: 6,
+    });
+    const feed = Keypair.generate().publicKey;
+    await makeOracle(p, 2_000_000_000, feed, 100);
+
+    setPrice(feed, 150_000_000, -8, { age: 61 });
+    sendTransactionExpectError(
+      [await take(p, 2_000_000_000, feed)],
+      [p.taker],
+      "StaleOraclePrice"
+    );
+    // the same take again, against a new price
+    svm.expireBlockhash();
+    setPrice(feed, 150_000_000, -8, { conf: 3_000_000 });
+    sendTransactionExpectError(
+      [await take(p, 2_000_000_000, feed)],
+      [p.taker],
+      "OracleConfidenceTooWide"
+    );
+    const other = Keypair.generate().publicKey;
+    setPrice(other, 150_000_000, -8);
+    sendTransactionExpectError(
+      [await take(p, 2_000_000_000, other)],
+      [p.taker],
+      "OracleMismatch"
+    );
+    // the right account carrying another feed
+    svm.expireBlockhash();
+    setPrice(feed, 150_000_000, -8, { id: Buffer.alloc(32, 8) });
+    sendTransactionExpectError(
+      [await take(p, 2_000_000_000, feed)],
+      [p.taker],
+      "OracleMismatch"
+    );
+
+    svm.expireBlockhash();
+    setPrice(feed, 150_000_000, -8, { conf: 1_000_000, age: 60 });
+    sendTransaction([await take(p, 2_000_000_000, feed)], [p.taker]);
+    assert.equal(await getTokenBalance(p.takerAtaA), 2_000_000_000);
+    assert.equal(await getTokenBalance(p.takerAtaB), 4_000_000 - 3_030_000);
+
+    // $300 with a positive exponent, a 0 decimal mint_a filled in halves
+    const q = setupEscrowParties(2, 600_000_000, { decimalsA: 0 });
+    const qFeed = Keypair.generate().publicKey;
+    await makeOracle(q, 2, qFeed, 0);
+    setPrice(qFeed, 3, 2);
+    sendTransaction([await take(q, 1, qFeed)], [q.taker]);
+    assert.equal(await getTokenBalance(q.takerAtaB), 300_000_000);
+    // the price moved before the second half
+    svm.expireBlockhash();
+    setPrice(qFeed, 25, 1);
+    sendTransaction([await take(q, 1, qFeed)], [q.taker]);
+    assert.equal(await getTokenBalance(q.takerAtaB), 50_000_000);
+    assert.ok(isClosed(q.escrow));
+  });
+
+  it("Pool priced escrows take the pool's price with the premium", async () => {
+    const poolProgram = Keypair.generate().publicKey;
+    // a constant product pool laid out like Raydium CP-Swap's PoolState
+    const setupPool = (
+      p: EscrowParties,
+      reserveA: number,
+      reserveB: number,
+      aFirst: boolean,
+      mintA = p.mintA
+    ) => {
+      const owner = Keypair.generate().publicKey;
+      const vaultA = getAssociatedTokenAddressSync(
+        p.mintA,
+        owner,
+        false,
+        p.tokenProgramA
+      );
+      const vaultB = getAssociatedTokenAddressSync(
+        p.mintB,
+        owner,
+        false,
+        p.tokenProgramB
+      );
+      sendTransaction(
+        [
+          createAssociatedTokenAccountIdempotentInstruction(
+            payer.publicKey,
+            vaultA,
+            owner,
+            p.mintA,
+            p.tokenProgramA
+          ),
+          createAssociatedTokenAccountIdempotentInstruction(
+            payer.publicKey,
+            vaultB,
+            owner,
+            p.mintB,
+            p.tokenProgramB
+          ),
+          createMintToInstruction(p.mintA, vaultA, payer.publicKey, reserveA),
+          createMintToInstruction(p.mintB, vaultB, payer.publicKey, reserveB),
+        ],
+        []
+      );
+      const [vault0, vault1] = aFirst ? [vaultA, vaultB] : [vaultB, vaultA];
+      const [mint0, mint1] = aFirst ? [mintA, p.mintB] : [p.mintB, mintA];
+      const data = Buffer.alloc(637);
+      vault0.toBuffer().copy(data, 72);
+      vault1.toBuffer().copy(data, 104);
+      mint0.toBuffer().copy(data, 168);
+      mint1.toBuffer().copy(data, 200);
+      const pool = Keypair.generate().publicKey;
+      svm.setAccount(pool, {
+        lamports: Number(svm.minimumBalanceForRentExemption(BigInt(637))),
+        data,
+        owner: poolProgram,
+        executable: false,
+      });
+      return { pool, poolVault0: vault0, poolVault1: vault1 };
+    };
+    const poolPriced = async (
+      receive: number,
+      premiumBps: number,
+      reserves: [number, number],
+      aFirst: boolean
+    ) => {
+      const p = setupEscrowParties(1_000_000, receive);
+      const pool = setupPool(p, ...reserves, aFirst);
+      const makeIx = await getProgram(p.maker)
+        .methods.make(
+          p.seed,
+          new BN(1),
+          new BN(1_000_000),
+          makeOptions({
+            pool: pool.pool,
+            poolProgram,
+            poolPremiumBps: premiumBps,
+            minReserveA: new BN(1_000_000_000),
+            minReserveB: new BN(1_000_000_000),
+          })
+        )
+        .accountsPartial(makeAccounts(p))
+        .instruction();
+      sendTransaction([makeIx], [p.maker]);
+      const take = async (accounts = pool) =>
+        getProgram(p.taker)
+          .methods.take(new BN(1_000_000), ANY_PRICE, NO_BOUND, NO_BOUND)
+          .accountsPartial({ ...takeAccounts(p), ...accounts })
+          .instruction();
+      return { p, pool, take };
+    };
+
+    // 2 mint_b per mint_a, with mint_a as the pool's token 0 and a 1% discount
+    const discounted = await poolPriced(
+      2_000_000,
+      -100,
+      [10_000_000_000, 20_000_000_000],
+      true
+    );
+    sendTransactionExpectError(
+      [
+        await discounted.take({
+          ...setupPool(discounted.p, 10_000_000_000, 20_000_000_000, true),
+          pool: discounted.pool.pool,
+        }),
+      ],
+      [discounted.p.taker],
+      "PoolMismatch"
+    );
+    sendTransaction([await discounted.take()], [discounted.p.taker]);
+    assert.equal(await getTokenBalance(discounted.p.takerAtaB), 20_000);
+    assert.ok(isClosed(discounted.p.escrow));
+
+    // the same price with the mints the other way round and a 1% premium
+    const marked = await poolPriced(
+      2_100_000,
+      100,
+      [10_000_000_000, 20_000_000_000],
+      false
+    );
+    sendTransaction([await marked.take()], [marked.p.taker]);
+    assert.equal(await getTokenBalance(marked.p.takerAtaB), 80_000);
+
+    // a pool thinner than the escrow's minimums
+    const thin = await poolPriced(
+      2_000_000,
+      0,
+      [500_000_000, 1_000_000_000],
+      true
+    );
+    sendTransactionExpectError(
+      [await thin.take()],
+      [thin.p.taker],
+      "PoolReservesTooLow"
+    );
+    // nor can the maker put a fixed price over the pool's
+    const updateIx = await getProgram(thin.p.maker)
+      .methods.updateReceive(new BN(1_000_000))
+      .accountsPartial({ maker: thin.p.maker.publicKey, escrow: thin.p.escrow })
+      .instruction();
+    sendTransactionExpectError([updateIx], [thin.p.maker], "PoolPriced");
+
+    // a pool of another mint against mint_b
+    const other = await poolPriced(
+      2_000_000,
+      0,
+      [10_000_000_000, 20_000_000_000],
+      true
+    );
+    const wrongMint = setupPool(
+      other.p,
+      10_000_000_000,
+      20_000_000_000,
+      true,
+      Keypair.generate().publicKey
+    );
+    svm.setAccount(other.pool.pool, svm.getAccount(wrongMint.pool)!);
+    sendTransactionExpectError(
+      [await other.take({ ...wrongMint, pool: other.pool.pool })],
+      [other.p.taker],
+      "PoolMismatch"
+    );
+  });
+
+  it("Migrate grows an escrow of the first layout", async () => {
+    const p = setupEscrowParties(1_000_000, 500_000);
+    const makeIx = await getProgram(p.maker)
+      .methods.make(p.seed, new BN(500_000), new BN(1_000_000), makeOptions())
+      .accountsPartial(makeAccounts(p))
+      .instruction();
+    sendTransaction([makeIx], [p.maker]);
+
+    // the first layout ended with the bump, 121 bytes with the discriminator
+    const account = svm.getAccount(p.escrow)!;
+    svm.setAccount(p.escrow, {
+      ...account,
+      data: account.data.slice(0, 121),
+      lamports: Number(svm.minimumBalanceForRentExemption(BigInt(121))),
+    });
+    const takeIx = await getProgram(p.taker)
+      .methods.take(new BN(1_000_000), ANY_PRICE, NO_BOUND, NO_BOUND)
+      .accountsPartial(takeAccounts(p))
+      .instruction();
+    sendTransactionExpectError([takeIx], [p.taker], "EscrowNotMigrated");
+
+    const migrate = (maker: Keypair, vault: PublicKey | null) =>
+      getProgram(maker)
+        .methods.migrate()
+        .accountsPartial({
+          maker: maker.publicKey,
+          escrow: p.escrow,
+          vault,
+          systemProgram: SystemProgram.programId,
+        })
+        .instruction();
+    sendTransactionExpectError(
+      [await migrate(p.taker, p.vault)],
+      [p.taker],
+      "ConstraintHasOne"
+    );
+    // the deposit of the first layout is read from the vault
+    sendTransactionExpectError(
+      [await migrate(p.maker, null)],
+      [p.maker],
+      "InvalidEscrowLayout"
+    );
+
+    const [migrated] = sendTransactionEvents(
+      [await migrate(p.maker, p.vault)],
+      [p.maker]
+    );
+    assert.equal(migrated.name, "escrowmigrated");
+    assert.equal(migrated.data.oldVersion, 0);
+    assert.equal(migrated.data.version, 10);
+    assert.equal(svm.getAccount(p.escrow)!.data.length, account.data.length);
+
+    // running it again leaves the escrow as it is
+    svm.expireBlockhash();
+    assert.deepEqual(
+      sendTransactionEvents([await migrate(p.maker, p.vault)], [p.maker]),
+      []
+    );
+
+    sendTransaction([takeIx], [p.taker]);
+    assert.equal(await getTokenBalance(p.takerAtaA), 1_000_000);
+    assert.equal(
+      await getTokenBalance(p.makerAtaB),
+      500_000 - feeOf(500_000)
+    );
+    assert.ok(isClosed(p.escrow));
+  });
+
+  it("Migrate keeps the namespace of a version 6 escrow", async () => {
+    const p = setupEscrowParties(1_000_000, 500_000);
+    const namespace = [...Buffer.from("front-1!")];
+    const [escrow] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("escrow"),
+        p.maker.publicKey.toBuffer(),
+        Buffer.from(namespace),
+        p.seed.toArrayLike(Buffer, "le", 8),
+      ],
+      programId
+    );
+    const q = {
+      ...p,
+      escrow,
+      vault: getAssociatedTokenAddressSync(p.mintA, escrow, true),
+    };
+    const makeIx = await getProgram(p.maker)
+      .methods.make(
+        p.seed,
+        new BN(500_000),
+        new BN(1_000_000),
+        makeOptions({ namespace })
+      )
+      .accountsPartial(makeAccounts(q))
+      .instruction();
+    sendTransaction([makeIx], [p.maker]);
+
+    // version 6 ended with the namespace, the version byte sits at 841
+    const account = svm.getAccount(escrow)!;
+    const data = account.data.slice(0, 1173);
+    data[841] = 6;
+    svm.setAccount(escrow, {
+      ...account,
+      data,
+      lamports: Number(svm.minimumBalanceForRentExemption(BigInt(1173))),
+    });
+
+    const migrateIx = await getProgram(p.maker)
+      .methods.migrate()
+      .accountsPartial({
+        maker: p.maker.publicKey,
+        escrow,
+        vault: null,
+        systemProgram: SystemProgram.programId,
+      })
+      .instruction();
+    const [migrated] = sendTransactionEvents([migrateIx], [p.maker]);
+    assert.equal(migrated.name, "escrowmigrated");
+    assert.equal(migrated.data.oldVersion, 6);
+    assert.equal(svm.getAccount(escrow)!.data.length, account.data.length);
+
+    const takeIx = await getProgram(p.taker)
+      .methods.take(new BN(1_000_000), ANY_PRICE, NO_BOUND, NO_BOUND)
+      .accountsPartial(takeAccounts(q))
+      .instruction();
+    sendTransaction([takeIx], [p.taker]);
+    assert.equal(await getTokenBalance(p.takerAtaA), 1_000_000);
+    assert.ok(isClosed(escrow));
+  });
+
+  it("Cross matches two escrows and pays the matcher the surplus", async () => {
+    const p = setupEscrowParties(1_000_000, 0);
+    // maker_2 holds mint_b and sells it for mint_a
+    const other = setupExtraTaker(p, 600_000);
+    const seed2 = new BN(Math.floor(Math.random() * 1000000));
+    const [escrow2] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("escrow"),
+        other.taker.publicKey.toBuffer(),
+        seed2.toArrayLike(Buffer, "le", 8),
+      ],
+      programId
+    );
+    const q = {
+      ...p,
+      maker: other.taker,
+      mintA: p.mintB,
+      mintB: p.mintA,
+      makerAtaB: other.takerAtaA,
+      seed: seed2,
+      escrow: escrow2,
+      vault: getAssociatedTokenAddressSync(p.mintB, escrow2, true),
+    };
+    const matcher = Keypair.generate();
+    svm.airdrop(matcher.publicKey, BigInt(LAMPORTS_PER_SOL));
+
+    const makeIx = await getProgram(p.maker)
+      .methods.make(p.seed, new BN(500_000), new BN(1_000_000), makeOptions())
+      .accountsPartial(makeAccounts(p))
+      .instruction();
+    const makeIx2 = await getProgram(q.maker)
+      .methods.make(q.seed, new BN(1_200_000), new BN(600_000), makeOptions())
+      .accountsPartial(makeAccounts(q))
+      .instruction();
+    sendTransaction([makeIx], [p.maker]);
+    sendTransaction([makeIx2], [q.maker]);
+
+    const crossAccounts = {
+      matcher: matcher.publicKey,
+      maker1: p.maker.publicKey,
+      maker2: q.maker.publicKey,
+      mintA: p.mintA,
+      mintB: p.mintB,
+      escrow1: p.escrow,
+      escrow2: q.escrow,
+      vault1: p.vault,
+      vault2: q.vault,
+      maker1AtaB: p.makerAtaB,
+      maker2AtaA: q.makerAtaB,
+      maker1AtaA: null,
+      maker2AtaB: null,
+      config,
+      feeCollector: feeCollector.publicKey,
+      feeCollectorAtaA: feeCollectorAta(p.mintA),
+      feeCollectorAtaB: feeCollectorAta(p.mintB),
+      rentRecipient1: null,
+      rentRecipient2: null,
+      makerIndex1: makerIndexOf(p.maker.publicKey),
+      makerIndex2: makerIndexOf(q.maker.publicKey),
+      stats,
+      associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+      tokenProgramA: TOKEN_PROGRAM_ID,
+      tokenProgramB: TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId,
+    };
+    const cross = (accounts = {}) =>
+      getProgram(matcher)
+        .methods.cross()
+        .accountsPartial({ ...crossAccounts, ...accounts })
+        .instruction();
+
+    // 600_000 mint_b for 1_200_000 mint_a is more than escrow_1 holds
+    sendTransactionExpectError([await cross()], [matcher], "PricesDontCross");
+
+    const updateIx = await getProgram(q.maker)
+      .methods.updateReceive(new BN(800_000))
+      .accountsPartial({ maker: q.maker.publicKey, escrow: q.escrow })
+      .instruction();
+    sendTransaction([updateIx], [q.maker]);
+
+    // a maker is only ever paid into their own ATA
+    sendTransactionExpectError(
+      [await cross({ maker1AtaB: p.takerAtaB })],
+      [matcher],
+      "ConstraintTokenOwner"
+    );
+
+    // dust sent to vault_1 from outside goes back to maker_1, not the matcher
+    const vault1 = svm.getAccount(p.vault);
+    const data = Buffer.from(vault1.data);
+    data.writeBigUInt64LE(BigInt(500_007), 64);
+    svm.setAccount(p.vault, { ...vault1, data });
+    sendTransactionExpectError([await cross()], [matcher], "MakerAtaRequired");
+
+    const [crossed] = sendTransactionEvents(
+      [await cross({ maker1AtaA: p.makerAtaA })],
+      [matcher]
+    );
+    assert.equal(crossed.name, "escrowscrossed");
+    assert.equal(crossed.data.amountA.toNumber(), 800_000);
+    assert.equal(crossed.data.amountB.toNumber(), 500_000);
+    assert.equal(crossed.data.surplusA.toNumber(), 200_000);
+    assert.equal(crossed.data.surplusB.toNumber(), 100_000);
+
+    assert.equal(
+      await getTokenBalance(p.makerAtaB),
+      500_000 - feeOf(500_000)
+    );
+    assert.equal(
+      await getTokenBalance(q.makerAtaB),
+      800_000 - feeOf(800_000)
+    );
+    assert.equal(
+      await getTokenBalance(
+        getAssociatedTokenAddressSync(p.mintA, matcher.publicKey)
+      ),
+      200_000
+    );
+    assert.equal(
+      await getTokenBalance(
+        getAssociatedTokenAddressSync(p.mintB, matcher.publicKey)
+      ),
+      100_000
+    );
+    assert.equal(await getTokenBalance(p.makerAtaA), 500_007);
+    assert.ok(isClosed(p.escrow), "Escrow 1 should be closed after cross");
+    assert.ok(isClosed(q.escrow), "Escrow 2 should be closed after cross");
+    assert.ok(isClosed(p.vault) && isClosed(q.vault));
+  });
+
+  it("Make basket escrows sell their legs only as a whole", async () => {
+    const MAX_BASKET_LEGS = 4; // mirrors constants.rs
+    const basket = async (legCount: number) => {
+      const p = setupEscrowParties(1_000_000, 500_000);
+      const legs = Array.from({ length: legCount }, (_, i) =>
+        setupHolding(p.maker.publicKey, 100_000 * (i + 1), 6)
+      );
+      // [mint, vault, ATA of the maker or taker] per leg, see basket.rs
+      const legAccounts = (owner: PublicKey) =>
+        legs.flatMap(({ mint }) =>
+          [
+            mint,
+            getAssociatedTokenAddressSync(mint, p.escrow, true),
+            getAssociatedTokenAddressSync(mint, owner),
+          ].map((pubkey, i) => ({ pubkey, isSigner: false, isWritable: i > 0 }))
+        );
+      const makeIx = await getProgram(p.maker)
+        .methods.makeBasket(
+          p.seed,
+          new BN(500_000),
+          new BN(1_000_000),
+          makeOptions(),
+          legs.map((_, i) => new BN(100_000 * (i + 1)))
+        )
+        .accountsPartial(makeAccounts(p))
+        .remainingAccounts(legAccounts(p.maker.publicKey))
+        .instruction();
+      return { p, legs, legAccounts, makeIx };
+    };
+
+    const none = await basket(0);
+    sendTransactionExpectError([none.makeIx], [none.p.maker], "BasketTooLarge");
+    const tooMany = await basket(MAX_BASKET_LEGS + 1);
+    sendTransactionExpectError(
+      [tooMany.makeIx],
+      [tooMany.p.maker],
+      "BasketTooLarge"
+    );
+    const repeated = await basket(2);
+    repeated.legs[1] = repeated.legs[0];
+    const repeatedIx = await getProgram(repeated.p.maker)
+      .methods.makeBasket(
+        repeated.p.seed,
+        new BN(500_000),
+        new BN(1_000_000),
+        makeOptions(),
+        [new BN(100_000), new BN(100_000)]
+      )
+      .accountsPartial(makeAccounts(repeated.p))
+      .remainingAccounts(repeated.legAccounts(repeated.p.maker.publicKey))
+      .instruction();
+    sendTransactionExpectError(
+      [repeatedIx],
+      [repeated.p.maker],
+      "IdenticalMints"
+    );
+
+    for (const legCount of [1, 3, MAX_BASKET_LEGS]) {
+      const { p, legs, legAccounts, makeIx } = await basket(legCount);
+      const [made, basketMade] = sendTransactionEvents([makeIx], [p.maker]);
+      assert.equal(made.name, "escrowmade");
+      assert.equal(basketMade.data.mints.length, legCount);
+      for (const [i, { mint }] of legs.entries()) {
+        const vault = getAssociatedTokenAddressSync(mint, p.escrow, true);
+        assert.equal(await getTokenBalance(vault), 100_000 * (i + 1));
+      }
+
+      const take = (amount: number, withLegs: boolean) =>
+        getProgram(p.taker)
+          .methods.take(new BN(amount), ANY_PRICE, NO_BOUND, NO_BOUND)
+          .accountsPartial(takeAccounts(p))
+          .remainingAccounts(withLegs ? legAccounts(p.taker.publicKey) : [])
+          .instruction();
+      sendTransactionExpectError(
+        [await take(500_000, true)],
+        [p.taker],
+        "BelowMinimumFill"
+      );
+      sendTransactionExpectError(
+        [await take(1_000_000, false)],
+        [p.taker],
+        "BasketLegMismatch"
+      );
+      // the taker's accounts for the legs are created in the take
+      sendTransaction([await take(1_000_000, true)], [p.taker]);
+      assert.equal(await getTokenBalance(p.takerAtaA), 1_000_000);
+      for (const [i, { mint }] of legs.entries()) {
+        assert.equal(
+          await getTokenBalance(
+            getAssociatedTokenAddressSync(mint, p.taker.publicKey)
+          ),
+          100_000 * (i + 1)
+        );
+        assert.ok(
+          isClosed(getAssociatedTokenAddressSync(mint, p.escrow, true)),
+          "Leg vaults should be closed"
+        );
+      }
+      assert.ok(isClosed(p.escrow), "Escrow should be closed once taken");
+    }
+
+    // a refund returns every leg to the maker
+    const { p, legs, legAccounts, makeIx } = await basket(3);
+    sendTransaction([makeIx], [p.maker]);
+    const refundIx = await getProgram(p.maker)
+      .methods.refund()
+      .accountsPartial(refundAccounts(p))
+      .remainingAccounts(legAccounts(p.maker.publicKey))
+      .instruction();
+    sendTransaction([refundIx], [p.maker]);
+    assert.equal(await getTokenBalance(p.makerAtaA), 1_000_000);
+    for (const [i, { mint, token }] of legs.entries()) {
+      assert.equal(await getTokenBalance(token), 100_000 * (i + 1));
+    }
+    assert.ok(isClosed(p.escrow), "Escrow should be closed once refunded");
+  });
+
+  it("Commitments settle before their deadline or go back", async () => {
+    const p = setupEscrowParties(1_000_000, 500_000);
+    const rival = setupExtraTaker(p, 100_000);
+    const makeIx = await getProgram(p.maker)
+      .methods.make(p.seed, new BN(500_000), new BN(1_000_000), makeOptions())
+      .accountsPartial(makeAccounts(p))
+      .instruction();
+    sendTransaction([makeIx], [p.maker]);
+
+    const [commitment] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("commitment"),
+        p.escrow.toBuffer(),
+        p.taker.publicKey.toBuffer(),
+      ],
+      programId
+    );
+    const commitmentVault = getAssociatedTokenAddressSync(
+      p.mintB,
+      commitment,
+      true,
+      p.tokenProgramB
+    );
+    const commit = (amount: number) =>
+      getProgram(p.taker)
+        .methods.commit(new BN(amount))
+        .accountsPartial({
+          taker: p.taker.publicKey,
+          mintB: p.mintB,
+          escrow: p.escrow,
+          config,
+          commitment,
+          commitmentVault,
+          tokenProgram: p.tokenProgramB,
+        })
+        .instruction();
+    const settle = (settler: Keypair) =>
+      getProgram(settler)
+        .methods.settle()
+        .accountsPartial({
+          settler: settler.publicKey,
+          maker: p.maker.publicKey,
+          taker: p.taker.publicKey,
+          mintA: p.mintA,
+          mintB: p.mintB,
+          makerAtaB: p.makerAtaB,
+          escrow: p.escrow,
+          makerIndex: makerIndexOf(p.maker.publicKey),
+          vault: p.vault,
+          makerAtaA: null,
+          commitment,
+          commitmentVault,
+          config,
+          stats,
+          feeCollector: feeCollector.publicKey,
+          feeCollectorAtaB: feeCollectorAta(p.mintB, p.tokenProgramB),
+          rentRecipient: null,
+          tokenProgramA: p.tokenProgramA,
+          tokenProgramB: p.tokenProgramB,
+        })
+        .instruction();
+    // the escrow is only needed to reclaim before the deadline
+    const reclaim = (escrow: PublicKey | null = null) =>
+      getProgram(p.taker)
+        .methods.reclaimCommitment()
+        .accountsPartial({
+          taker: p.taker.publicKey,
+          mintB: p.mintB,
+          takerAtaB: p.takerAtaB,
+          commitment,
+          commitmentVault,
+          escrow,
+          tokenProgram: p.tokenProgramB,
+        })
+        .instruction();
+    const setPaused = (paused: boolean) =>
+      getProgram(payer)
+        .methods.setPaused(paused)
+        .accountsPartial({ admin: payer.publicKey, config })
+        .instruction();
+
+    svm.expireBlockhash();
+    sendTransaction([await setPaused(true)], []);
+    sendTransactionExpectError(
+      [await commit(400_000)],
+      [p.taker],
+      "ProtocolPaused"
+    );
+    sendTransaction([await setPaused(false)], []);
+    svm.expireBlockhash();
+
+    const [committed] = sendTransactionEvents(
+      [await commit(400_000)],
+      [p.taker]
+    );
+    assert.equal(committed.name, "commitmentmade");
+    assert.equal(committed.data.amountB.toNumber(), 200_000);
+    assert.equal(await getTokenBalance(commitmentVault), 200_000);
+    sendTransactionExpectError(
+      [await reclaim(p.escrow)],
+      [p.taker],
+      "CommitmentActive"
+    );
+    sendTransactionExpectError(
+      [await settle(rival.taker)],
+      [rival.taker],
+      "UnauthorizedSettler"
+    );
+
+    // the maker settles, the taker doesn't have to be around
+    const [settled, totals] = sendTransactionEvents(
+      [await settle(p.maker)],
+      [p.maker]
+    );
+    assert.equal(settled.name, "commitmentsettled");
+    assert.equal(totals.name, "statsupdated");
+    assert.equal(settled.data.remaining.toNumber(), 600_000);
+    assert.equal(await getTokenBalance(p.takerAtaA), 400_000);
+    assert.equal(await getTokenBalance(p.makerAtaB), 200_000 - feeOf(200_000));
+    assert.equal(await getTokenBalance(p.vault), 600_000);
+    assert.ok(isClosed(commitment) && isClosed(commitmentVault));
+
+    // a take after the commit changes what it would buy
+    sendTransaction([await commit(200_000)], [p.taker]);
+    const rivalTake = await getProgram(rival.taker)
+      .methods.take(new BN(200_000), ANY_PRICE, NO_BOUND, NO_BOUND)
+      .accountsPartial({ ...takeAccounts(p), taker: rival.taker.publicKey })
+      .instruction();
+    sendTransaction([rivalTake], [rival.taker]);
+    sendTransactionExpectError(
+      [await settle(p.taker)],
+      [p.taker],
+      "CommitmentOutdated"
+    );
+    // the outdated commitment comes back before its deadline
+    const [reclaimed] = sendTransactionEvents(
+      [await reclaim(p.escrow)],
+      [p.taker]
+    );
+    assert.equal(reclaimed.name, "commitmentreclaimed");
+    assert.equal(await getTokenBalance(p.takerAtaB), 300_000);
+    assert.ok(isClosed(commitment));
+
+    // settling fails from the deadline on
+    svm.expireBlockhash();
+    sendTransaction([await commit(200_000)], [p.taker]);
+    setUnixTimestamp(svm.getClock().unixTimestamp + BigInt(60 * 60));
+    sendTransactionExpectError(
+      [await settle(p.taker)],
+      [p.taker],
+      "CommitmentExpired"
+    );
+    svm.expireBlockhash();
+    sendTransaction([await reclaim()], [p.taker]);
+    assert.equal(await getTokenBalance(p.takerAtaB), 300_000);
+    assert.equal(await getTokenBalance(p.vault), 400_000);
+  });
+
+  it("Payment options price fills in each listed mint", async () => {
+    const p = setupEscrowParties(1_000_000, 500_000);
+    const usdc = setupHolding(p.taker.publicKey, 300_000, 6);
+    const usdt = setupHolding(p.taker.publicKey, 300_000, 6);
+    const other = setupHolding(p.taker.publicKey, 300_000, 6);
+    const paymentOptions = [
+      { mint: usdc.mint, receive: new BN(250_000) },
+      { mint: usdt.mint, receive: new BN(300_000) },
+    ];
+    const makeIx = await getProgram(p.maker)
+      .methods.make(
+        p.seed,
+        new BN(500_000),
+        new BN(1_000_000),
+        makeOptions({ paymentOptions })
+      )
+      .accountsPartial(makeAccounts(p))
+      .instruction();
+    sendTransaction([makeIx], [p.maker]);
+
+    const takerProgram = getProgram(p.taker);
+    const payIn = (index: number, mint: PublicKey, amount: number) =>
+      takerProgram.methods
+        .takeWithPayment(index, new BN(amount))
+        .accountsPartial({
+          ...takeAccounts(p),
+          mintB: mint,
+          feeCollectorAtaB: feeCollectorAta(mint),
+        })
+        .instruction();
+    sendTransactionExpectError(
+      [await payIn(0, other.mint, 400_000)],
+      [p.taker],
+      "UnsupportedPaymentMint"
+    );
+    sendTransactionExpectError(
+      [await payIn(2, other.mint, 400_000)],
+      [p.taker],
+      "InvalidPaymentIndex"
+    );
+
+    // 400_000 of the deposit at 300_000 for all of it
+    const [partial] = sendTransactionEvents(
+      [await payIn(1, usdt.mint, 400_000)],
+      [p.taker]
+    );
+    assert.ok(partial.data.mintB.equals(usdt.mint));
+    assert.equal(partial.data.amountB.toNumber(), 120_000);
+    const makerAtaUsdt = getAssociatedTokenAddressSync(
+      usdt.mint,
+      p.maker.publicKey
+    );
+    assert.equal(await getTokenBalance(makerAtaUsdt), 120_000 - feeOf(120_000));
+    assert.equal(await getTokenBalance(p.takerAtaA), 400_000);
+
+    // mint B still pays its own price for what is left
+    const takeIx = await takerProgram.methods
+      .take(new BN(300_000), ANY_PRICE, NO_BOUND, NO_BOUND)
+      .accountsPartial(takeAccounts(p))
+      .instruction();
+    const [taken] = sendTransactionEvents([takeIx], [p.taker]);
+    assert.equal(taken.data.amountB.toNumber(), 150_000);
+
+    const [last] = sendTransactionEvents(
+      [await payIn(0, usdc.mint, 300_000)],
+      [p.taker]
+    );
+    assert.equal(last.data.amountB.toNumber(), 75_000);
+    assert.equal(await getTokenBalance(usdc.token), 225_000);
+    assert.equal(await getTokenBalance(p.takerAtaA), 1_000_000);
+    assert.ok(isClosed(p.escrow), "Escrow should be closed");
+  });
+
+  it("Receive to vault escrows hold payments until claimed", async () => {
+    const p = setupEscrowParties(1_000_000, 500_000);
+    const makerProgram = getProgram(p.maker);
+    const makeIx = await makerProgram.methods
+      .make(
+        p.seed,
+        new BN(500_000),
+        new BN(1_000_000),
+        makeOptions({ receiveToVault: true })
+      )
+      .accountsPartial(makeAccounts(p))
+      .instruction();
+    sendTransaction([makeIx], [p.maker]);
+
+    const paymentVault = getAssociatedTokenAddressSync(
+      p.mintB,
+      p.escrow,
+      true,
+      p.tokenProgramB
+    );
+    const take = (amount: number, vault: PublicKey | null) =>
+      getProgram(p.taker)
+        .methods.take(new BN(amount), ANY_PRICE, NO_BOUND, NO_BOUND)
+        .accountsPartial({ ...takeAccounts(p), paymentVault: vault })
+        .instruction();
+    const claim = () =>
+      makerProgram.methods
+        .claimPayment(p.seed)
+        .accountsPartial({
+          maker: p.maker.publicKey,
+          mintB: p.mintB,
+          escrow: p.escrow,
+          paymentVault,
+          makerAtaB: p.makerAtaB,
+          tokenProgram: p.tokenProgramB,
+        })
+        .instruction();
+
+    sendTransactionExpectError(
+      [await take(400_000, null)],
+      [p.taker],
+      "PaymentVaultRequired"
+    );
+    sendTransaction([await take(400_000, paymentVault)], [p.taker]);
+    assert.equal(await getTokenBalance(paymentVault), 200_000 - feeOf(200_000));
+    assert.equal(await getTokenBalance(p.makerAtaB), 0);
+
+    const [claimed] = sendTransactionEvents([await claim()], [p.maker]);
+    assert.equal(claimed.name, "paymentclaimed");
+    assert.equal(claimed.data.amountB.toNumber(), 200_000 - feeOf(200_000));
+    assert.equal(await getTokenBalance(p.makerAtaB), 200_000 - feeOf(200_000));
+    assert.ok(isClosed(paymentVault), "Payment vault should be closed");
+
+    // the last take closes the escrow, its payments can still be claimed
+    sendTransaction([await take(600_000, paymentVault)], [p.taker]);
+    assert.ok(isClosed(p.escrow), "Escrow should be closed after take");
+    svm.expireBlockhash();
+    sendTransaction([await claim()], [p.maker]);
+    assert.equal(
+      await getTokenBalance(p.makerAtaB),
+      500_000 - feeOf(200_000) - feeOf(300_000)
+    );
+  });
+
+  it("Make takes a vault that already exists", async () => {
+    const p = setupEscrowParties(1_000_000, 500_000);
+    // anyone can create an escrow's ATA ahead of the make, and send it dust
+    sendTransaction(
+      [
+        createAssociatedTokenAccountIdempotentInstruction(
+          payer.publicKey,
+          p.vault,
+          p.escrow,
+          p.mintA,
+          p.tokenProgramA
+        ),
+        createMintToInstruction(p.mintA, p.vault, payer.publicKey, 1),
+      ],
+      []
+    );
+
+    const makeIx = await getProgram(p.maker)
+      .methods.make(p.seed, new BN(500_000), new BN(1_000_000), makeOptions())
+      .accountsPartial(makeAccounts(p))
+      .instruction();
+    const [made] = sendTransactionEvents([makeIx], [p.maker]);
+    assert.equal(made.name, "escrowmade");
+    assert.equal(made.data.deposit.toNumber(), 1_000_000);
+    assert.equal(await getTokenBalance(p.vault), 1_000_001);
+
+    const takeIx = await getProgram(p.taker)
+      .methods.take(new BN(1_000_000), ANY_PRICE, NO_BOUND, NO_BOUND)
+      .accountsPartial(takeAccounts(p))
+      .instruction();
+    sendTransaction([takeIx], [p.taker]);
+    assert.equal(await getTokenBalance(p.takerAtaA), 1_000_000);
+    // the dust goes back to the maker with the last take
+    assert.equal(await getTokenBalance(p.makerAtaA), 1);
+    assert.ok(isClosed(p.escrow), "Escrow should be closed after take");
+  });
+
+  it("Make auto gives each escrow the maker's next seed", async () => {
+    const p = setupEscrowParties(300_000, 300_000);
+    const [makerNonce] = PublicKey.findProgramAddressSync(
+      [Buffer.from("nonce"), p.maker.publicKey.toBuffer()],
+      programId
+    );
+    const made: PublicKey[] = [];
+    for (let i = 0; i < 3; i++) {
+      const [escrow] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("escrow"),
+          p.maker.publicKey.toBuffer(),
+          new BN(i).toArrayLike(Buffer, "le", 8),
+        ],
+        programId
+      );
+      const ix = await getProgram(p.maker)
+        .methods.makeAuto(new BN(100_000), new BN(100_000))
+        .accountsPartial({
+          maker: p.maker.publicKey,
+          config,
+          makerNonce,
+          mintA: p.mintA,
+          mintB: p.mintB,
+          makerAtaA: p.makerAtaA,
+          escrow,
+          makerIndex: makerIndexOf(p.maker.publicKey),
+          stats,
+          vault: getAssociatedTokenAddressSync(p.mintA, escrow, true),
+          tokenProgramA: p.tokenProgramA,
+          tokenProgramB: p.tokenProgramB,
+        })
+        .instruction();
+      const [event] = sendTransactionEvents([ix], [p.maker]);
+      assert.equal(event.name, "escrowmade");
+      assert.equal(event.data.seed.toNumber(), i);
+      assert.ok(event.data.escrow.equals(escrow));
+      const state = getProgram(p.maker).coder.accounts.decode(
+        "escrow",
+        Buffer.from(svm.getAccount(escrow).data)
+      );
+      assert.equal(state.seed.toNumber(), i);
+      made.push(escrow);
+    }
+    assert.equal(new Set(made.map((e) => e.toBase58())).size, 3);
+    const nonce = getProgram(p.maker).coder.accounts.decode(
+      "makerNonce",
+      Buffer.from(svm.getAccount(makerNonce).data)
+    );
+    assert.equal(nonce.nextSeed.toNumber(), 3);
+    assert.equal(await getTokenBalance(p.makerAtaA), 0);
+  });
+
+  it("Maker index lists the maker's open escrows", async () => {
+    const p = setupEscrowParties(1_000_000, 1_000_000);
+    const makerIndex = makerIndexOf(p.maker.publicKey);
+    const seeds = [0, 1, 2, 3].map((i) => p.seed.addn(i));
+    const zero = Array(8).fill(0);
+    const namespace = [...Buffer.from("front-1!")];
+    const at = (seed: BN, ns: number[] = zero) => {
+      const [escrow] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("escrow"),
+          p.maker.publicKey.toBuffer(),
+          ...(ns === zero ? [] : [Buffer.from(ns)]),
+          seed.toArrayLike(Buffer, "le", 8),
+        ],
+        programId
+      );
+      const vault = getAssociatedTokenAddressSync(p.mintA, escrow, true);
+      return { escrow, vault };
+    };
+    const listed = () =>
+      getProgram(p.maker)
+        .coder.accounts.decode(
+          "makerIndex",
+          Buffer.from(svm.getAccount(makerIndex).data)
+        )
+        .entries.map((entry: { namespace: number[]; seed: BN }) => [
+          entry.namespace,
+          entry.seed.toNumber(),
+        ]);
+    const make = (seed: BN, ns: number[] = zero) =>
+      getProgram(p.maker)
+        .methods.make(
+          seed,
+          new BN(250_000),
+          new BN(250_000),
+          makeOptions({ namespace: ns })
+        )
+        .accountsPartial({ ...makeAccounts(p), ...at(seed, ns) })
+        .instruction();
+    const take = (seed: BN, index = makerIndex) =>
+      getProgram(p.taker)
+        .methods.take(new BN(250_000), ANY_PRICE, NO_BOUND, NO_BOUND)
+        .accountsPartial({ ...takeAccounts(p), ...at(seed), makerIndex: index })
+        .instruction();
+    const refund = (seed: BN, ns: number[] = zero) =>
+      getProgram(p.maker)
+        .methods.refund()
+        .accountsPartial({ ...refundAccounts(p), ...at(seed, ns) })
+        .instruction();
+    const compact = (dropped: BN[]) =>
+      getProgram(p.maker)
+        .methods.compactMakerIndex(
+          dropped.map((seed) => ({ namespace: zero, seed }))
+        )
+        .accountsPartial({
+          maker: p.maker.publicKey,
+          makerIndex,
+          systemProgram: SystemProgram.programId,
+        })
+        .remainingAccounts(
+          dropped.map((seed) => ({
+            pubkey: at(seed).escrow,
+            isSigner: false,
+            isWritable: false,
+          }))
+        )
+        .instruction();
+
+    // a namespaced escrow is listed with its namespace
+    sendTransaction([await make(seeds[0]), await make(seeds[1])], [p.maker]);
+    sendTransaction([await make(seeds[0], namespace)], [p.maker]);
+    assert.deepEqual(listed(), [
+      [zero, seeds[0].toNumber()],
+      [zero, seeds[1].toNumber()],
+      [namespace, seeds[0].toNumber()],
+    ]);
+    // the index can't be left out or swapped for another account
+    sendTransactionExpectError(
+      [await take(seeds[0], p.maker.publicKey)],
+      [p.taker],
+      "ConstraintSeeds"
+    );
+    sendTransaction([await take(seeds[0])], [p.taker]);
+    sendTransaction([await refund(seeds[0], namespace)], [p.maker]);
+    assert.deepEqual(listed(), [[zero, seeds[1].toNumber()]]);
+
+    // an index of the seed only layout, still listing the taken seeds[0]
+    sendTransaction([await make(seeds[2])], [p.maker]);
+    const old = Buffer.alloc(8 + 32 + 4 + 8 * 32 + 1);
+    Buffer.from(svm.getAccount(makerIndex).data).copy(old, 0, 0, 40);
+    old.writeUInt32LE(3, 40);
+    [seeds[0], seeds[1], seeds[2]].forEach((seed, i) =>
+      old.writeBigUInt64LE(BigInt(seed.toString()), 44 + 8 * i)
+    );
+    old[44 + 8 * 3] = PublicKey.findProgramAddressSync(
+      [Buffer.from("index"), p.maker.publicKey.toBuffer()],
+      programId
+    )[1];
+    svm.setAccount(makerIndex, {
+      ...svm.getAccount(makerIndex),
+      data: old,
+      lamports: Number(svm.minimumBalanceForRentExemption(BigInt(old.length))),
+    });
+    sendTransactionExpectError(
+      [await make(seeds[3])],
+      [p.maker],
+      "MakerIndexOutdated"
+    );
+    sendTransactionExpectError(
+      [await compact([seeds[1]])],
+      [p.maker],
+      "EscrowStillIndexed"
+    );
+    // compacting grows it to the current layout first
+    sendTransaction([await compact([seeds[0]])], [p.maker]);
+    assert.deepEqual(listed(), [
+      [zero, seeds[1].toNumber()],
+      [zero, seeds[2].toNumber()],
+    ]);
+    sendTransaction([await make(seeds[3])], [p.maker]);
+    for (const seed of [seeds[1], seeds[2], seeds[3]]) {
+      sendTransaction([await refund(seed)], [p.maker]);
+    }
+    assert.deepEqual(listed(), []);
+    sendTransaction([await compact([])], [p.maker]);
+    assert.ok(isClosed(makerIndex), "Empty index should be closed");
+  });
+
+  it("Namespaces keep escrows at the same maker and seed apart", async () => {
+    const p = setupEscrowParties(1_000_000, 500_000);
+    const namespace = [...Buffer.from("front-1!")];
+    const [escrow] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("escrow"),
+        p.maker.publicKey.toBuffer(),
+        Buffer.from(namespace),
+        p.seed.toArrayLike(Buffer, "le", 8),
+      ],
+      programId
+    );
+    const vault = getAssociatedTokenAddressSync(p.mintA, escrow, true);
+    const q = { ...p, escrow, vault };
+    const make = (r: EscrowParties, options: object) =>
+      getProgram(r.maker)
+        .methods.make(
+          r.seed,
+          new BN(250_000),
+          new BN(500_000),
+          makeOptions(options)
+        )
+        .accountsPartial(makeAccounts(r))
+        .instruction();
+
+    // the zero namespace keeps the address escrows had without one
+    sendTransaction([await make(p, {})], [p.maker]);
+    sendTransaction([await make(q, { namespace })], [p.maker]);
+    assert.notOk(escrow.equals(p.escrow));
+    const made = getProgram(p.maker).coder.accounts.decode(
+      "escrow",
+      Buffer.from(svm.getAccount(escrow).data)
+    );
+    assert.deepEqual(made.namespace, namespace);
+    assert.equal(await getTokenBalance(vault), 500_000);
+
+    const takeIx = await getProgram(p.taker)
+      .methods.take(new BN(500_000), ANY_PRICE, NO_BOUND, NO_BOUND)
+      .accountsPartial(takeAccounts(q))
+      .instruction();
+    sendTransaction([takeIx], [p.taker]);
+    assert.ok(isClosed(escrow), "Namespaced escrow should be closed");
+    const refundIx = await getProgram(p.maker)
+      .methods.refund()
+      .accountsPartial(refundAccounts(p))
+      .instruction();
+    sendTransaction([refundIx], [p.maker]);
+    assert.equal(await getTokenBalance(p.makerAtaA), 500_000);
+    assert.equal(await getTokenBalance(p.takerAtaA), 500_000);
+  });
+
+  it("Stats count makes, takes, refunds and the volume taken", async () => {
+    const totals = () => {
+      const data = getProgram(payer).coder.accounts.decode(
+        "stats",
+        Buffer.from(svm.getAccount(stats).data)
+      );
+      return [
+        data.escrowsMade,
+        data.escrowsTaken,
+        data.escrowsRefunded,
+        data.volumeA,
+        data.volumeB,
+      ].map((total: BN) => total.toNumber());
+    };
+    const make = (p: EscrowParties) =>
+      getProgram(p.maker)
+        .methods.make(p.seed, new BN(500_000), new BN(1_000_000), makeOptions())
+        .accountsPartial(makeAccounts(p))
+        .instruction();
+    const [made, taken, refunded, volumeA, volumeB] = totals();
+
+    const p = setupEscrowParties(1_000_000, 500_000);
+    sendTransaction([await make(p)], [p.maker]);
+    const takeIx = await getProgram(p.taker)
+      .methods.take(new BN(1_000_000), ANY_PRICE, NO_BOUND, NO_BOUND)
+      .accountsPartial(takeAccounts(p))
+      .instruction();
+    const events = sendTransactionEvents([takeIx], [p.taker]);
+    const expected = [
+      made + 1,
+      taken + 1,
+      refunded,
+      volumeA + 1_000_000,
+      volumeB + 500_000,
+    ];
+    assert.deepEqual(totals(), expected);
+    const updated = events.find((event) => event.name === "statsupdated");
+    assert.equal(updated.data.volumeB.toNumber(), volumeB + 500_000);
+
+    const q = setupEscrowParties(1_000_000, 500_000);
+    sendTransaction([await make(q)], [q.maker]);
+    const refundIx = await getProgram(q.maker)
+      .methods.refund()
+      .accountsPartial(refundAccounts(q))
+      .instruction();
+    sendTransaction([refundIx], [q.maker]);
+    expected[0] += 1;
+    expected[2] += 1;
+    assert.deepEqual(totals(), expected);
+
+    // withdrawing the whole deposit counts as a refund
+    const r = setupEscrowParties(1_000_000, 500_000);
+    sendTransaction([await make(r)], [r.maker]);
+    const withdrawIx = await getProgram(r.maker)
+      .methods.withdraw(new BN(1_000_000))
+      .accountsPartial({
+        maker: r.maker.publicKey,
+        mintA: r.mintA,
+        makerAtaA: r.makerAtaA,
+        escrow: r.escrow,
+        makerIndex: makerIndexOf(r.maker.publicKey),
+        stats,
+        vault: r.vault,
+        rentRecipient: null,
+        tokenProgram: r.tokenProgramA,
+      })
+      .instruction();
+    sendTransaction([withdrawIx], [r.maker]);
+    expected[0] += 1;
+    expected[2] += 1;
+    assert.deepEqual(totals(), expected);
+
+    // stats is optional everywhere, escrows that leave it out aren't counted
+    const u = setupEscrowParties(1_000_000, 500_000);
+    const uncounted = await getProgram(u.maker)
+      .methods.make(u.seed, new BN(500_000), new BN(1_000_000), makeOptions())
+      .accountsPartial({ ...makeAccounts(u), stats: null })
+      .instruction();
+    sendTransaction([uncounted], [u.maker]);
+    const uncountedTake = await getProgram(u.taker)
+      .methods.take(new BN(1_000_000), ANY_PRICE, NO_BOUND, NO_BOUND)
+      .accountsPartial({ ...takeAccounts(u), stats: null })
+      .instruction();
+    const untracked = sendTransactionEvents([uncountedTake], [u.taker]);
+    assert.notOk(untracked.some((event) => event.name === "statsupdated"));
+    assert.deepEqual(totals(), expected);
+  });
+
+  it("Only the approved delegate accepts offers for the maker", async () => {
+    const p = setupEscrowParties(1_000_000, 1_000_000);
+    const rival = setupExtraTaker(p, 1_000_000);
+    const bot = Keypair.generate();
+    svm.airdrop(bot.publicKey, BigInt(LAMPORTS_PER_SOL));
+    const makerProgram = getProgram(p.maker);
+    const makeIx = await makerProgram.methods
+      .make(p.seed, new BN(1_000_000), new BN(1_000_000), makeOptions())
+      .accountsPartial(makeAccounts(p))
+      .instruction();
+    sendTransaction([makeIx], [p.maker]);
+    sendTransaction([await makeOffer(p, p.taker, 400_000)], [p.taker]);
+
+    const approve = (delegate: PublicKey) =>
+      makerProgram.methods
+        .approveDelegate(delegate)
+        .accountsPartial({ maker: p.maker.publicKey, escrow: p.escrow })
+        .instruction();
+    const accept = (authority: Keypair) =>
+      acceptOffer(p, p.taker.publicKey, authority);
+
+    sendTransactionExpectError([await accept(bot)], [bot], "NotDelegate");
+    const [approved] = sendTransactionEvents(
+      [await approve(bot.publicKey)],
+      [p.maker]
+    );
+    assert.equal(approved.name, "delegateapproved");
+    assert.ok(approved.data.oldDelegate.equals(PublicKey.default));
+    assert.ok(approved.data.delegate.equals(bot.publicKey));
+    sendTransactionExpectError(
+      [await accept(rival.taker)],
+      [rival.taker],
+      "NotDelegate"
+    );
+
+    // revoking takes the delegate's say away again
+    sendTransaction([await approve(PublicKey.default)], [p.maker]);
+    svm.expireBlockhash();
+    sendTransactionExpectError([await accept(bot)], [bot], "NotDelegate");
+    sendTransaction([await approve(bot.publicKey)], [p.maker]);
+
+    // the delegate settles, the maker is paid
+    const [accepted] = sendTransactionEvents([await accept(bot)], [bot]);
+    assert.equal(accepted.name, "offeraccepted");
+    assert.ok(accepted.data.maker.equals(p.maker.publicKey));
+    assert.equal(await getTokenBalance(p.takerAtaA), 1_000_000);
+    assert.equal(await getTokenBalance(p.makerAtaB), 400_000 - feeOf(400_000));
+    assert.ok(isClosed(p.escrow));
+  });
+
+  it("Maker can't reprice below the floor set at make", async () => {
+    const p = setupEscrowParties(1_000_000, 1_000_000);
+    const makerProgram = getProgram(p.maker);
+    const make = (floor: number) =>
+      makerProgram.methods
+        .make(
+          p.seed,
+          new BN(1_000_000),
+          new BN(1_000_000),
+          makeOptions({ minReceiveFloor: new BN(floor) })
+        )
+        .accountsPartial(makeAccounts(p))
+        .instruction();
+    sendTransactionExpectError(
+      [await make(1_000_001)],
+      [p.maker],
+      "BelowPriceFloor"
+    );
+    sendTransaction([await make(600_000)], [p.maker]);
+
+    const update = { maker: p.maker.publicKey, escrow: p.escrow };
+    const updateReceive = (receive: number) =>
+      makerProgram.methods
+        .updateReceive(new BN(receive))
+        .accountsPartial(update)
+        .instruction();
+    sendTransactionExpectError(
+      [await updateReceive(1)],
+      [p.maker],
+      "BelowPriceFloor"
+    );
+    // pending terms would otherwise sell the whole deposit below the floor
+    const proposeIx = await makerProgram.methods
+      .proposeTerms(new BN(599_999), p.taker.publicKey, new BN(1_000))
+      .accountsPartial(update)
+      .instruction();
+    sendTransactionExpectError([proposeIx], [p.maker], "BelowPriceFloor");
+
+    // the floor is in mint_b's units, so mint_b stays
+    const updateMintBIx = await makerProgram.methods
+      .updateMintB()
+      .accountsPartial({ ...update, newMintB: setupEscrowParties(0, 0).mintB })
+      .instruction();
+    sendTransactionExpectError([updateMintBIx], [p.maker], "PriceFloorSet");
+
+    sendTransaction([await updateReceive(600_000)], [p.maker]);
+    assert.equal(parseEscrowAccount(svm.getAccount(p.escrow)).receive, 600_000);
+  });
+
+  it("Maker recovers the vault an escrow was closed without", async () => {
+    const p = setupEscrowParties(1_000_000, 500_000);
+    const makeIx = await getProgram(p.maker)
+      .methods.make(p.seed, new BN(500_000), new BN(1_000_000), makeOptions())
+      .accountsPartial(makeAccounts(p))
+      .instruction();
+    sendTransaction([makeIx], [p.maker]);
+    const recoverIx = await getProgram(p.maker)
+      .methods.recoverOrphanVault(p.seed, Array(8).fill(0))
+      .accountsPartial({
+        maker: p.maker.publicKey,
+        mintA: p.mintA,
+        escrow: p.escrow,
+        vault: p.vault,
+        makerAtaA: p.makerAtaA,
+        tokenProgram: p.tokenProgramA,
+      })
+      .instruction();
+
+    sendTransactionExpectError([recoverIx], [p.maker], "EscrowNotOrphaned");
+
+    // the escrow disappears behind the program's back, leaving its vault
+    svm.setAccount(p.escrow, {
+      lamports: 0,
+      data: Buffer.alloc(0),
+      owner: SystemProgram.programId,
+      executable: false,
+    });
+    svm.expireBlockhash();
+    const [recovered] = sendTransactionEvents([recoverIx], [p.maker]);
+    assert.equal(recovered.name, "orphanvaultrecovered");
+    assert.equal(recovered.data.amount.toNumber(), 1_000_000);
+    assert.equal(await getTokenBalance(p.makerAtaA), 1_000_000);
+    assert.ok(isClosed(p.vault), "Vault should be closed once recovered");
+  });
+
+  it("Escrows carry the memo they were made with", async () => {
+    const p = setupEscrowParties(1_000_000, 500_000);
+    const make = (memo: Buffer) =>
+      getProgram(p.maker)
+        .methods.make(
+          p.seed,
+          new BN(500_000),
+          new BN(1_000_000),
+          makeOptions({ memo: Array.from(memo) })
+        )
+        .accountsPartial(makeAccounts(p))
+        .instruction();
+    const padded = (bytes: Buffer) =>
+      Buffer.concat([bytes, Buffer.alloc(32 - bytes.length)]);
+
+    sendTransactionExpectError(
+      [await make(padded(Buffer.from([0xc3, 0x28])))],
+      [p.maker],
+      "InvalidMemo"
+    );
+    const label = padded(Buffer.from("OTC deal #42"));
+    sendTransaction([await make(label)], [p.maker]);
+    const escrow = getProgram(p.maker).coder.accounts.decode(
+      "escrow",
+      Buffer.from(svm.getAccount(p.escrow).data)
+    );
+    const memo = Buffer.from(escrow.memo);
+    assert.equal(memo.subarray(0, memo.indexOf(0)).toString(), "OTC deal #42");
+  });
+
+  it("Crank refund sol returns an expired SOL deposit", async () => {
+    const p = setupEscrowParties(0, 500_000);
+    const cranker = Keypair.generate();
+    svm.airdrop(cranker.publicKey, BigInt(LAMPORTS_PER_SOL));
+    const [solVault] = PublicKey.findProgramAddressSync(
+      [Buffer.from("sol_vault"), p.escrow.toBuffer()],
+      programId
+    );
+    const expiry = svm.getClock().unixTimestamp + BigInt(60);
+    const makeIx = await getProgram(p.maker)
+      .methods.makeSol(
+        p.seed,
+        new BN(500_000),
+        new BN(LAMPORTS_PER_SOL),
+        makeOptions({ expiry: new BN(expiry.toString()) })
+      )
+      .accountsPartial({
+        maker: p.maker.publicKey,
+        mintB: p.mintB,
+        escrow: p.escrow,
+        makerIndex: makerIndexOf(p.maker.publicKey),
+        stats,
+        solVault,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .instruction();
+    sendTransaction([makeIx], [p.maker]);
+
+    const crankIx = await getProgram(cranker)
+      .methods.crankRefundSol()
+      .accountsPartial({
+        cranker: cranker.publicKey,
+        maker: p.maker.publicKey,
+        escrow: p.escrow,
+        makerIndex: makerIndexOf(p.maker.publicKey),
+        stats,
+        solVault,
+        rentRecipient: null,
+      })
+      .instruction();
+    sendTransactionExpectError([crankIx], [cranker], "EscrowNotExpired");
+
+    setUnixTimestamp(expiry + BigInt(24 * 60 * 60));
+    svm.expireBlockhash();
+    const makerBefore = svm.getBalance(p.maker.publicKey);
+    const crankerBefore = svm.getBalance(cranker.publicKey);
+    const escrowRent = svm.getBalance(p.escrow);
+    const [cranked] = sendTransactionEvents([crankIx], [cranker]);
+    assert.equal(cranked.name, "escrowcranked");
+    assert.equal(cranked.data.amount.toNumber(), LAMPORTS_PER_SOL);
+    assert.equal(
+      svm.getBalance(cranker.publicKey) - crankerBefore,
+      BigInt(100_000)
+    );
+    assert.equal(
+      svm.getBalance(p.maker.publicKey) - makerBefore,
+      BigInt(LAMPORTS_PER_SOL) + escrowRent - BigInt(100_000)
+    );
+    assert.isTrue(isClosed(p.escrow));
+  });
+
+  it("Taker closes a counter offer left from before locked bids", async () => {
+    const p = setupEscrowParties(1_000_000, 400_000);
+    const makeIx = await getProgram(p.maker)
+      .methods.make(p.seed, new BN(500_000), new BN(1_000_000), makeOptions())
+      .accountsPartial(makeAccounts(p))
+      .instruction();
+    sendTransaction([makeIx], [p.maker]);
+
+    // a counter offer the old propose left at the offer's address
+    const { offer } = offerAccounts(p, p.taker.publicKey);
+    const [, bump] = PublicKey.findProgramAddressSync(
+      [Buffer.from("offer"), p.escrow.toBuffer(), p.taker.publicKey.toBuffer()],
+      programId
+    );
+    const program = getProgram(p.taker);
+    const data = await program.coder.accounts.encode("counterOffer", {
+      escrow: p.escrow,
+      taker: p.taker.publicKey,
+      proposedReceive: new BN(450_000),
+      bump,
+    });
+    const rent = svm.minimumBalanceForRentExemption(BigInt(data.length));
+    svm.setAccount(offer, {
+      lamports: Number(rent),
+      data,
+      owner: programId,
+      executable: false,
+    });
+
+    const takerBefore = svm.getBalance(p.taker.publicKey);
+    const closeIx = await program.methods
+      .closeCounterOffer()
+      .accountsPartial({ taker: p.taker.publicKey, counterOffer: offer })
+      .instruction();
+    sendTransaction([closeIx], [p.taker]);
+    assert.ok(isClosed(offer), "Counter offer should be closed");
+    assert.equal(
+      Number(svm.getBalance(p.taker.publicKey) - takerBefore),
+      Number(rent)
+    );
+
+    // the address is free for a locked bid again
+    sendTransaction([await makeOffer(p, p.taker, 400_000)], [p.taker]);
+    assert.equal(await getTokenBalance(p.takerAtaB), 0);
+  });
 });